@@ -0,0 +1,150 @@
+/* Generated by cbindgen from bindings/src/c.rs; do not edit. */
+
+#ifndef OPHELIA_H
+#define OPHELIA_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+// What kind of error an `OpheliaError` is.
+typedef enum OpheliaErrorKind {
+  // A pointer was null, or a string was not UTF-8.
+  OPHELIA_ERROR_KIND_INVALID_ARGUMENT,
+  // A template could not be parsed.
+  OPHELIA_ERROR_KIND_SYNTAX,
+  // The context passed to `ophelia_render_json` was not a JSON object.
+  OPHELIA_ERROR_KIND_INVALID_JSON,
+  // A template which was imported or included could not be found.
+  OPHELIA_ERROR_KIND_TEMPLATE_NOT_FOUND,
+  // Rendering failed.
+  OPHELIA_ERROR_KIND_RENDER,
+  // Ophelia panicked (which is a bug).
+  OPHELIA_ERROR_KIND_PANIC,
+} OpheliaErrorKind;
+
+// Where templates which are imported or included are loaded from.
+typedef struct OpheliaEnv OpheliaEnv;
+
+// Why a function failed.
+typedef struct OpheliaError OpheliaError;
+
+// A parsed template.
+typedef struct OpheliaTemplate OpheliaTemplate;
+
+// Where in a template's source an error is.
+typedef struct OpheliaSpan {
+  // The byte offset at which the error starts.
+  size_t start;
+  // The byte offset at which the error ends.
+  size_t end;
+  // The (one-based) line on which the error starts.
+  size_t line;
+  // The (one-based) column at which the error starts, in characters.
+  size_t column;
+} OpheliaSpan;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Makes an environment, which has no templates to load.
+struct OpheliaEnv *ophelia_env_new(void);
+
+// Frees an environment.
+//
+// # Safety
+//
+// `env` must be null, or have come from `ophelia_env_new` (and not have been freed).
+void ophelia_env_free(struct OpheliaEnv *env);
+
+// Adds a template, which can be imported or included under `name`. Templates added this way take
+// precedence over those in the environment's directory.
+//
+// # Safety
+//
+// `env` must be a live environment, `name` and `source` must be nul-terminated strings, and
+// `error` must be null or valid to write to.
+bool ophelia_env_add_template(struct OpheliaEnv *env,
+                              const char *name,
+                              const char *source,
+                              struct OpheliaError **error);
+
+// Loads templates which are imported or included from files inside `directory` (template names
+// are paths relative to it).
+//
+// # Safety
+//
+// `env` must be a live environment, `directory` must be a nul-terminated string, and `error`
+// must be null or valid to write to.
+bool ophelia_env_set_directory(struct OpheliaEnv *env,
+                               const char *directory,
+                               struct OpheliaError **error);
+
+// Parses a template. Returns null (and sets `error`, which has a span) if it cannot be parsed.
+//
+// # Safety
+//
+// `source` must be a nul-terminated string, and `error` must be null or valid to write to.
+struct OpheliaTemplate *ophelia_template_parse(const char *source, struct OpheliaError **error);
+
+// Frees a template.
+//
+// # Safety
+//
+// `template` must be null, or have come from `ophelia_template_parse` (and not have been freed).
+void ophelia_template_free(struct OpheliaTemplate *template_);
+
+// Renders `template`, with the variables in `json` (an object, or null for no variables), and
+// templates which it imports or includes loaded from `env` (which may be null). Returns the
+// output, which has to be freed with `ophelia_string_free`, or null if rendering failed.
+//
+// # Safety
+//
+// `env` must be null or a live environment, `template` must be a live template, `json` must be
+// null or a nul-terminated string, and `error` must be null or valid to write to.
+char *ophelia_render_json(const struct OpheliaEnv *env,
+                          const struct OpheliaTemplate *template_,
+                          const char *json,
+                          struct OpheliaError **error);
+
+// Frees a string which the API returned.
+//
+// # Safety
+//
+// `string` must be null, or have come from the API (and not have been freed).
+void ophelia_string_free(char *string);
+
+// What kind of error `error` is.
+//
+// # Safety
+//
+// `error` must be a live error.
+enum OpheliaErrorKind ophelia_error_kind(const struct OpheliaError *error);
+
+// A description of `error`, which lives as long as it does.
+//
+// # Safety
+//
+// `error` must be a live error.
+const char *ophelia_error_message(const struct OpheliaError *error);
+
+// Where in the template's source `error` is. Returns false (and leaves `span` alone) if the error
+// is not at a particular place (only syntax errors are).
+//
+// # Safety
+//
+// `error` must be a live error, and `span` must be valid to write to.
+bool ophelia_error_span(const struct OpheliaError *error, struct OpheliaSpan *span);
+
+// Frees an error.
+//
+// # Safety
+//
+// `error` must be null, or have come from the API (and not have been freed).
+void ophelia_error_free(struct OpheliaError *error);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* OPHELIA_H */
@@ -0,0 +1,378 @@
+//! A C API (its header is `include/ophelia.h`).
+//!
+//! Everything which the API hands out is owned by the caller, and has to be given back to the
+//! matching `_free` function. Functions which can fail take an `OpheliaError **error`; if it is
+//! not null, it is set to null when the function succeeds, and to an error (which the caller
+//! owns) when it fails. Strings passed to the API must be UTF-8, and are copied if they are kept.
+//!
+//! ```c
+//! OpheliaError *error = NULL;
+//! OpheliaTemplate *template = ophelia_template_parse("hi {{ name }}", &error);
+//! char *output = ophelia_render_json(NULL, template, "{\"name\": \"ann\"}", &error);
+//! /* ... */
+//! ophelia_string_free(output);
+//! ophelia_template_free(template);
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::PathBuf,
+    ptr,
+};
+
+use ophelia_logic::{
+    parse::{Parse, Template},
+    render::{Context, FileSystemLoader, Loader, Render, RenderError, Value},
+};
+
+/// Where templates which are imported or included are loaded from.
+#[derive(Debug, Clone, Default)]
+pub struct OpheliaEnv {
+    templates: HashMap<String, String>,
+    directory: Option<PathBuf>,
+}
+
+impl Loader for OpheliaEnv {
+    fn load(&self, name: &str) -> Option<String> {
+        self.templates.get(name).cloned().or_else(|| {
+            let directory = self.directory.as_ref()?;
+            FileSystemLoader::new(directory.clone()).load(name)
+        })
+    }
+}
+
+/// A parsed template.
+#[derive(Debug)]
+pub struct OpheliaTemplate {
+    template: Template<'static>,
+}
+
+/// What kind of error an `OpheliaError` is.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpheliaErrorKind {
+    /// A pointer was null, or a string was not UTF-8.
+    InvalidArgument,
+    /// A template could not be parsed.
+    Syntax,
+    /// The context passed to `ophelia_render_json` was not a JSON object.
+    InvalidJson,
+    /// A template which was imported or included could not be found.
+    TemplateNotFound,
+    /// Rendering failed.
+    Render,
+    /// Ophelia panicked (which is a bug).
+    Panic,
+}
+
+/// Where in a template's source an error is.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpheliaSpan {
+    /// The byte offset at which the error starts.
+    pub start: usize,
+    /// The byte offset at which the error ends.
+    pub end: usize,
+    /// The (one-based) line on which the error starts.
+    pub line: usize,
+    /// The (one-based) column at which the error starts, in characters.
+    pub column: usize,
+}
+
+/// Why a function failed.
+#[derive(Debug)]
+pub struct OpheliaError {
+    kind: OpheliaErrorKind,
+    message: CString,
+    span: Option<OpheliaSpan>,
+}
+
+impl OpheliaError {
+    fn new(kind: OpheliaErrorKind, message: impl Into<String>) -> Self {
+        let message = message.into().replace('\0', "\u{FFFD}");
+        Self {
+            kind,
+            message: CString::new(message).unwrap(),
+            span: None,
+        }
+    }
+}
+
+impl From<RenderError> for OpheliaError {
+    fn from(error: RenderError) -> Self {
+        let kind = match error {
+            RenderError::TemplateNotFound(_) => OpheliaErrorKind::TemplateNotFound,
+            RenderError::InvalidTemplate { .. } => OpheliaErrorKind::Syntax,
+            _ => OpheliaErrorKind::Render,
+        };
+        Self::new(kind, error.to_string())
+    }
+}
+
+/// Runs `f`, storing the error it fails with (or a panic) in `error`.
+///
+/// # Safety
+///
+/// `error` must be null, or valid to write to.
+unsafe fn guard<T>(
+    error: *mut *mut OpheliaError,
+    failed: T,
+    f: impl FnOnce() -> Result<T, OpheliaError>,
+) -> T {
+    let result = catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        Err(OpheliaError::new(
+            OpheliaErrorKind::Panic,
+            "ophelia panicked",
+        ))
+    });
+    let (value, raised) = match result {
+        Ok(value) => (value, ptr::null_mut()),
+        Err(raised) => (failed, Box::into_raw(Box::new(raised))),
+    };
+    if !error.is_null() {
+        *error = raised;
+    } else if !raised.is_null() {
+        drop(Box::from_raw(raised));
+    }
+    value
+}
+
+/// The string which `string` points to.
+///
+/// # Safety
+///
+/// `string` must be null, or point to a nul-terminated string which outlives `'a`.
+unsafe fn string<'a>(string: *const c_char, what: &str) -> Result<&'a str, OpheliaError> {
+    if string.is_null() {
+        return Err(OpheliaError::new(
+            OpheliaErrorKind::InvalidArgument,
+            format!("`{}` is null", what),
+        ));
+    }
+    CStr::from_ptr(string).to_str().map_err(|_| {
+        OpheliaError::new(
+            OpheliaErrorKind::InvalidArgument,
+            format!("`{}` is not UTF-8", what),
+        )
+    })
+}
+
+/// Makes an environment, which has no templates to load.
+#[no_mangle]
+pub extern "C" fn ophelia_env_new() -> *mut OpheliaEnv {
+    Box::into_raw(Box::default())
+}
+
+/// Frees an environment.
+///
+/// # Safety
+///
+/// `env` must be null, or have come from `ophelia_env_new` (and not have been freed).
+#[no_mangle]
+pub unsafe extern "C" fn ophelia_env_free(env: *mut OpheliaEnv) {
+    if !env.is_null() {
+        drop(Box::from_raw(env));
+    }
+}
+
+/// Adds a template, which can be imported or included under `name`. Templates added this way take
+/// precedence over those in the environment's directory.
+///
+/// # Safety
+///
+/// `env` must be a live environment, `name` and `source` must be nul-terminated strings, and
+/// `error` must be null or valid to write to.
+#[no_mangle]
+pub unsafe extern "C" fn ophelia_env_add_template(
+    env: *mut OpheliaEnv,
+    name: *const c_char,
+    source: *const c_char,
+    error: *mut *mut OpheliaError,
+) -> bool {
+    guard(error, false, || {
+        let env = env
+            .as_mut()
+            .ok_or_else(|| OpheliaError::new(OpheliaErrorKind::InvalidArgument, "`env` is null"))?;
+        let name = string(name, "name")?;
+        let source = string(source, "source")?;
+        env.templates.insert(name.to_string(), source.to_string());
+        Ok(true)
+    })
+}
+
+/// Loads templates which are imported or included from files inside `directory` (template names
+/// are paths relative to it).
+///
+/// # Safety
+///
+/// `env` must be a live environment, `directory` must be a nul-terminated string, and `error`
+/// must be null or valid to write to.
+#[no_mangle]
+pub unsafe extern "C" fn ophelia_env_set_directory(
+    env: *mut OpheliaEnv,
+    directory: *const c_char,
+    error: *mut *mut OpheliaError,
+) -> bool {
+    guard(error, false, || {
+        let env = env
+            .as_mut()
+            .ok_or_else(|| OpheliaError::new(OpheliaErrorKind::InvalidArgument, "`env` is null"))?;
+        env.directory = Some(string(directory, "directory")?.into());
+        Ok(true)
+    })
+}
+
+/// Parses a template. Returns null (and sets `error`, which has a span) if it cannot be parsed.
+///
+/// # Safety
+///
+/// `source` must be a nul-terminated string, and `error` must be null or valid to write to.
+#[no_mangle]
+pub unsafe extern "C" fn ophelia_template_parse(
+    source: *const c_char,
+    error: *mut *mut OpheliaError,
+) -> *mut OpheliaTemplate {
+    guard(error, ptr::null_mut(), || {
+        let source = string(source, "source")?;
+        match Template::parse(source) {
+            Ok((template, _)) => Ok(Box::into_raw(Box::new(OpheliaTemplate {
+                template: template.into_owned(),
+            }))),
+            Err(parse_error) => {
+                let start = parse_error.offset(source);
+                let before = &source[..start];
+                let end = start + source[start..].chars().next().map_or(0, char::len_utf8);
+                let mut error =
+                    OpheliaError::new(OpheliaErrorKind::Syntax, parse_error.to_string());
+                error.span = Some(OpheliaSpan {
+                    start,
+                    end,
+                    line: before.matches('\n').count() + 1,
+                    column: before.rsplit('\n').next().unwrap_or("").chars().count() + 1,
+                });
+                Err(error)
+            }
+        }
+    })
+}
+
+/// Frees a template.
+///
+/// # Safety
+///
+/// `template` must be null, or have come from `ophelia_template_parse` (and not have been freed).
+#[no_mangle]
+pub unsafe extern "C" fn ophelia_template_free(template: *mut OpheliaTemplate) {
+    if !template.is_null() {
+        drop(Box::from_raw(template));
+    }
+}
+
+/// Renders `template`, with the variables in `json` (an object, or null for no variables), and
+/// templates which it imports or includes loaded from `env` (which may be null). Returns the
+/// output, which has to be freed with `ophelia_string_free`, or null if rendering failed.
+///
+/// # Safety
+///
+/// `env` must be null or a live environment, `template` must be a live template, `json` must be
+/// null or a nul-terminated string, and `error` must be null or valid to write to.
+#[no_mangle]
+pub unsafe extern "C" fn ophelia_render_json(
+    env: *const OpheliaEnv,
+    template: *const OpheliaTemplate,
+    json: *const c_char,
+    error: *mut *mut OpheliaError,
+) -> *mut c_char {
+    guard(error, ptr::null_mut(), || {
+        let template = template.as_ref().ok_or_else(|| {
+            OpheliaError::new(OpheliaErrorKind::InvalidArgument, "`template` is null")
+        })?;
+
+        let mut ctx = Context::new();
+        if let Some(env) = env.as_ref() {
+            ctx.set_loader(env.clone());
+        }
+        if !json.is_null() {
+            let variables: BTreeMap<String, Value> = serde_json::from_str(string(json, "json")?)
+                .map_err(|error| {
+                    OpheliaError::new(OpheliaErrorKind::InvalidJson, error.to_string())
+                })?;
+            for (name, value) in variables {
+                ctx.insert(name, value);
+            }
+        }
+
+        let mut output = String::new();
+        template.template.render(&mut ctx, &mut output)?;
+        CString::new(output)
+            .map(CString::into_raw)
+            .map_err(|_| OpheliaError::new(OpheliaErrorKind::Render, "the output contains a nul"))
+    })
+}
+
+/// Frees a string which the API returned.
+///
+/// # Safety
+///
+/// `string` must be null, or have come from the API (and not have been freed).
+#[no_mangle]
+pub unsafe extern "C" fn ophelia_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// What kind of error `error` is.
+///
+/// # Safety
+///
+/// `error` must be a live error.
+#[no_mangle]
+pub unsafe extern "C" fn ophelia_error_kind(error: *const OpheliaError) -> OpheliaErrorKind {
+    (*error).kind
+}
+
+/// A description of `error`, which lives as long as it does.
+///
+/// # Safety
+///
+/// `error` must be a live error.
+#[no_mangle]
+pub unsafe extern "C" fn ophelia_error_message(error: *const OpheliaError) -> *const c_char {
+    (*error).message.as_ptr()
+}
+
+/// Where in the template's source `error` is. Returns false (and leaves `span` alone) if the error
+/// is not at a particular place (only syntax errors are).
+///
+/// # Safety
+///
+/// `error` must be a live error, and `span` must be valid to write to.
+#[no_mangle]
+pub unsafe extern "C" fn ophelia_error_span(
+    error: *const OpheliaError,
+    span: *mut OpheliaSpan,
+) -> bool {
+    match (*error).span {
+        Some(error_span) => {
+            *span = error_span;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Frees an error.
+///
+/// # Safety
+///
+/// `error` must be null, or have come from the API (and not have been freed).
+#[no_mangle]
+pub unsafe extern "C" fn ophelia_error_free(error: *mut OpheliaError) {
+    if !error.is_null() {
+        drop(Box::from_raw(error));
+    }
+}
@@ -1 +1,15 @@
+//! Bindings which let ophelia be used from other languages.
+//!
+//! - [`python`] is a Python module (built into a wheel with `maturin build`), which follows
+//!   Jinja's API closely enough to be swapped in for it.
+//! - [`c`] is a C API, for which `include/ophelia.h` is the header.
+//! - [`wasm`] is a JavaScript API, for `wasm32-unknown-unknown` (build it with `wasm-pack`).
 
+#[cfg(feature = "c")]
+pub mod c;
+
+#[cfg(feature = "python")]
+pub mod python;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,193 @@
+//! Converting between Python objects and the values which templates work with.
+
+use std::{cell::RefCell, collections::HashMap};
+
+use ophelia_logic::render::{Function, RenderError, Value};
+use pyo3::{
+    prelude::*,
+    types::{PyBool, PyDict, PyFloat, PyFrozenSet, PyInt, PyList, PySet, PyString, PyTuple},
+};
+
+thread_local! {
+    /// The exception which the last Python function called by a template raised, so that it can
+    /// be raised again (rather than turned into a generic error) once rendering stops.
+    static RAISED: RefCell<Option<PyErr>> = const { RefCell::new(None) };
+}
+
+/// The exception which was raised while rendering, if it was raised by Python code.
+pub(crate) fn take_raised() -> Option<PyErr> {
+    RAISED.with(|raised| raised.borrow_mut().take())
+}
+
+/// Converts a Python object into a value.
+///
+/// Objects which are not one of Python's builtin types are converted into namespaces of their
+/// (public) attributes, so that templates can read them as in Jinja. Each object is only
+/// converted once, so objects which appear in several places are shared; an object which
+/// contains itself is `Undefined` inside itself.
+pub(crate) fn to_value(object: &Bound<'_, PyAny>) -> PyResult<Value> {
+    Converter::default().convert(object)
+}
+
+#[derive(Default)]
+struct Converter {
+    /// The objects which have been converted, by their `id`.
+    done: HashMap<usize, Value>,
+    /// The objects which are being converted.
+    converting: Vec<usize>,
+}
+
+impl Converter {
+    fn convert(&mut self, object: &Bound<'_, PyAny>) -> PyResult<Value> {
+        if object.is_none() {
+            return Ok(Value::None);
+        }
+        // `bool` is a subclass of `int`, so it has to be checked for first
+        if let Ok(b) = object.cast::<PyBool>() {
+            return Ok(Value::Bool(b.is_true()));
+        }
+        if object.is_instance_of::<PyInt>() {
+            return Ok(match object.extract::<i32>() {
+                Ok(int) => Value::Integer(int),
+                // integers are 32 bits wide, so larger ones lose some precision
+                Err(_) => Value::Float(object.extract::<f64>()? as f32),
+            });
+        }
+        if object.is_instance_of::<PyFloat>() {
+            return Ok(Value::Float(object.extract::<f64>()? as f32));
+        }
+        if let Ok(string) = object.cast::<PyString>() {
+            return Ok(Value::String(string.to_cow()?.into_owned()));
+        }
+
+        let id = object.as_ptr() as usize;
+        if let Some(value) = self.done.get(&id) {
+            return Ok(value.clone());
+        }
+        if self.converting.contains(&id) {
+            return Ok(Value::Undefined);
+        }
+
+        self.converting.push(id);
+        let value = self.convert_container(object);
+        self.converting.pop();
+
+        let value = value?;
+        self.done.insert(id, value.clone());
+        Ok(value)
+    }
+
+    fn convert_container(&mut self, object: &Bound<'_, PyAny>) -> PyResult<Value> {
+        let py = object.py();
+
+        if object.is_instance_of::<PyList>()
+            || object.is_instance_of::<PyTuple>()
+            || object.is_instance_of::<PySet>()
+            || object.is_instance_of::<PyFrozenSet>()
+        {
+            return self.convert_items(object);
+        }
+
+        let mapping = py.import("collections.abc")?.getattr("Mapping")?;
+        if object.is_instance(&mapping)? {
+            let mut pairs = vec![];
+            for item in object.call_method0("items")?.try_iter()? {
+                let (key, value): (Bound<PyAny>, Bound<PyAny>) = item?.extract()?;
+                pairs.push((self.convert(&key)?, self.convert(&value)?));
+            }
+            return Ok(Value::dict(pairs));
+        }
+
+        if object.is_callable() {
+            return Ok(Value::Function(function(object.clone().unbind())));
+        }
+
+        if object.hasattr("__dict__")? {
+            let mut attrs = vec![];
+            for (name, value) in object.getattr("__dict__")?.cast::<PyDict>()?.iter() {
+                let name = name.extract::<String>()?;
+                if !name.starts_with('_') {
+                    attrs.push((name, self.convert(&value)?));
+                }
+            }
+            return Ok(Value::namespace(attrs));
+        }
+
+        if object.try_iter().is_ok() {
+            return self.convert_items(object);
+        }
+
+        Ok(Value::String(object.str()?.to_cow()?.into_owned()))
+    }
+
+    fn convert_items(&mut self, object: &Bound<'_, PyAny>) -> PyResult<Value> {
+        let items = object
+            .try_iter()?
+            .map(|item| self.convert(&item?))
+            .collect::<PyResult<_>>()?;
+        Ok(Value::list(items))
+    }
+}
+
+/// Converts a value into a Python object.
+pub(crate) fn to_python<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        Value::Undefined | Value::None | Value::Function(_) => py.None().into_bound(py),
+        Value::Bool(b) => PyBool::new(py, *b).to_owned().into_any(),
+        Value::Integer(int) => int.into_pyobject(py)?.into_any(),
+        Value::Float(float) => float.into_pyobject(py)?.into_any(),
+        Value::String(string) => PyString::new(py, string).into_any(),
+        Value::List(items) => {
+            let items = items
+                .borrow()
+                .iter()
+                .map(|item| to_python(py, item))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, items)?.into_any()
+        }
+        Value::Dict(pairs) => {
+            let dict = PyDict::new(py);
+            for (key, value) in pairs.borrow().iter() {
+                dict.set_item(to_python(py, key)?, to_python(py, value)?)?;
+            }
+            dict.into_any()
+        }
+        Value::Namespace(attrs) => {
+            let dict = PyDict::new(py);
+            for (name, value) in attrs.borrow().iter() {
+                dict.set_item(name, to_python(py, value)?)?;
+            }
+            dict.into_any()
+        }
+    })
+}
+
+/// A function which calls `callable`.
+///
+/// If `callable` raises an exception, the function fails, and the exception is kept to be raised
+/// again by whatever started rendering.
+pub(crate) fn function(callable: Py<PyAny>) -> Function {
+    Function::new(move |args, kwargs| {
+        Python::attach(|py| {
+            let call = || -> PyResult<Value> {
+                let args = args
+                    .iter()
+                    .map(|arg| to_python(py, arg))
+                    .collect::<PyResult<Vec<_>>>()?;
+                let named = PyDict::new(py);
+                for (name, value) in &kwargs {
+                    named.set_item(name, to_python(py, value)?)?;
+                }
+                let result = callable
+                    .bind(py)
+                    .call(PyTuple::new(py, args)?, Some(&named))?;
+                to_value(&result)
+            };
+            call().map_err(|error| {
+                let message = error.to_string();
+                RAISED.with(|raised| *raised.borrow_mut() = Some(error));
+                RenderError::InvalidOperation(message)
+            })
+        })
+    })
+}
@@ -0,0 +1,297 @@
+//! The `ophelia` Python module.
+//!
+//! It mirrors the parts of Jinja's API which most code uses, so that switching is mostly a matter
+//! of changing imports:
+//!
+//! ```python
+//! from ophelia import Environment, FileSystemLoader
+//!
+//! env = Environment(loader=FileSystemLoader("templates"))
+//! env.filters["shout"] = lambda value, times=1: value.upper() + "!" * times
+//! env.tests["short"] = lambda value: len(value) < 5
+//! print(env.get_template("page.html").render(user=user, items=[1, 2, 3]))
+//! ```
+//!
+//! Values passed to templates are converted when rendering starts (see [`convert::to_value`]).
+//! Exceptions raised by Python filters, tests and functions propagate out of `render`.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use ophelia_logic::{
+    parse::{Parse, Template as ParsedTemplate},
+    render::{self, Context, Render, RenderError},
+};
+use pyo3::{
+    create_exception,
+    exceptions::PyException,
+    prelude::*,
+    types::{PyDict, PyTuple},
+};
+
+mod convert;
+
+create_exception!(
+    ophelia,
+    TemplateError,
+    PyException,
+    "The base class of the errors raised while loading or rendering templates."
+);
+create_exception!(
+    ophelia,
+    TemplateSyntaxError,
+    TemplateError,
+    "A template could not be parsed."
+);
+create_exception!(
+    ophelia,
+    TemplateNotFound,
+    TemplateError,
+    "A template could not be found by the loader."
+);
+
+/// Loads templates from files inside a directory.
+#[pyclass(module = "ophelia", frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+struct FileSystemLoader {
+    #[pyo3(get)]
+    searchpath: PathBuf,
+}
+
+#[pymethods]
+impl FileSystemLoader {
+    #[new]
+    fn new(searchpath: PathBuf) -> Self {
+        Self { searchpath }
+    }
+}
+
+/// Loads templates from a dict of names to sources.
+#[pyclass(module = "ophelia", frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+struct DictLoader {
+    #[pyo3(get)]
+    mapping: HashMap<String, String>,
+}
+
+#[pymethods]
+impl DictLoader {
+    #[new]
+    fn new(mapping: HashMap<String, String>) -> Self {
+        Self { mapping }
+    }
+}
+
+/// The loaders which an environment can use.
+#[derive(Debug, Clone)]
+enum Loader {
+    FileSystem(FileSystemLoader),
+    Dict(DictLoader),
+}
+
+impl Loader {
+    fn extract(loader: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(loader) = loader.cast::<FileSystemLoader>() {
+            Ok(Loader::FileSystem(loader.get().clone()))
+        } else if let Ok(loader) = loader.cast::<DictLoader>() {
+            Ok(Loader::Dict(loader.get().clone()))
+        } else {
+            Err(pyo3::exceptions::PyTypeError::new_err(
+                "the loader should be a FileSystemLoader or a DictLoader",
+            ))
+        }
+    }
+
+    fn to_loader(&self) -> Box<dyn render::Loader> {
+        match self {
+            Loader::FileSystem(loader) => {
+                Box::new(render::FileSystemLoader::new(loader.searchpath.clone()))
+            }
+            Loader::Dict(loader) => {
+                let mut dict = render::DictLoader::new();
+                for (name, source) in &loader.mapping {
+                    dict.insert(name.clone(), source.clone());
+                }
+                Box::new(dict)
+            }
+        }
+    }
+}
+
+/// Where templates are loaded from, and the filters, tests and globals which they can use.
+///
+/// `filters`, `tests` and `globals` are dicts, which can be changed at any time (changes apply to
+/// templates which were already loaded, too).
+#[pyclass(module = "ophelia", frozen)]
+struct Environment {
+    loader: Option<Loader>,
+    #[pyo3(get)]
+    filters: Py<PyDict>,
+    #[pyo3(get)]
+    tests: Py<PyDict>,
+    #[pyo3(get)]
+    globals: Py<PyDict>,
+}
+
+#[pymethods]
+impl Environment {
+    #[new]
+    #[pyo3(signature = (loader=None))]
+    fn new(py: Python<'_>, loader: Option<&Bound<'_, PyAny>>) -> PyResult<Self> {
+        Ok(Self {
+            loader: loader.map(Loader::extract).transpose()?,
+            filters: PyDict::new(py).unbind(),
+            tests: PyDict::new(py).unbind(),
+            globals: PyDict::new(py).unbind(),
+        })
+    }
+
+    /// Loads the template called `name` through the loader.
+    fn get_template(slf: &Bound<'_, Self>, name: &str) -> PyResult<Template> {
+        let source = slf
+            .get()
+            .loader
+            .as_ref()
+            .and_then(|loader| loader.to_loader().load(name))
+            .ok_or_else(|| TemplateNotFound::new_err(name.to_string()))?;
+        Template::parse(&source, Some(name.to_string()), Some(slf.clone().unbind()))
+    }
+
+    /// Parses `source` into a template (which is rendered with this environment).
+    fn from_string(slf: &Bound<'_, Self>, source: &str) -> PyResult<Template> {
+        Template::parse(source, None, Some(slf.clone().unbind()))
+    }
+}
+
+impl Environment {
+    /// A context with the environment's loader, filters, tests and globals.
+    fn context(&self, py: Python<'_>) -> PyResult<Context> {
+        let mut ctx = Context::new();
+        if let Some(loader) = &self.loader {
+            ctx.set_loader(LoaderBox(loader.to_loader()));
+        }
+        for (name, filter) in self.filters.bind(py).iter() {
+            ctx.add_filter(
+                name.extract::<String>()?,
+                convert::function(filter.unbind()),
+            );
+        }
+        for (name, test) in self.tests.bind(py).iter() {
+            ctx.add_test(name.extract::<String>()?, convert::function(test.unbind()));
+        }
+        for (name, value) in self.globals.bind(py).iter() {
+            ctx.insert(name.extract::<String>()?, convert::to_value(&value)?);
+        }
+        Ok(ctx)
+    }
+}
+
+/// Lets a boxed loader be given to [`Context::set_loader`].
+#[derive(Debug)]
+struct LoaderBox(Box<dyn render::Loader>);
+
+impl render::Loader for LoaderBox {
+    fn load(&self, name: &str) -> Option<String> {
+        self.0.load(name)
+    }
+}
+
+/// A parsed template.
+#[pyclass(module = "ophelia", frozen)]
+struct Template {
+    template: ParsedTemplate<'static>,
+    /// The name which the template was loaded under, if it was loaded.
+    #[pyo3(get)]
+    name: Option<String>,
+    environment: Option<Py<Environment>>,
+}
+
+#[pymethods]
+impl Template {
+    /// Parses `source` into a template, which is rendered without a loader (as in Jinja,
+    /// `Template(source)` is a shortcut for `Environment().from_string(source)`).
+    #[new]
+    fn new(source: &str) -> PyResult<Self> {
+        Self::parse(source, None, None)
+    }
+
+    /// Renders the template. It takes the same arguments as `dict` (e.g. `render(name="x")` or
+    /// `render({"name": "x"})`), which become the template's variables.
+    #[pyo3(signature = (*args, **kwargs))]
+    fn render(
+        &self,
+        py: Python<'_>,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<String> {
+        let mut ctx = match &self.environment {
+            Some(environment) => environment.get().context(py)?,
+            None => Context::new(),
+        };
+        let variables = py.get_type::<PyDict>().call(args, kwargs)?;
+        for (name, value) in variables.cast::<PyDict>()?.iter() {
+            ctx.insert(name.extract::<String>()?, convert::to_value(&value)?);
+        }
+
+        // anything left over from a render which failed in the middle is stale
+        convert::take_raised();
+        let mut output = String::new();
+        match self.template.render(&mut ctx, &mut output) {
+            Ok(()) => Ok(output),
+            Err(error) => Err(convert::take_raised().unwrap_or_else(|| render_error(error))),
+        }
+    }
+}
+
+impl Template {
+    fn parse(
+        source: &str,
+        name: Option<String>,
+        environment: Option<Py<Environment>>,
+    ) -> PyResult<Self> {
+        let template = match ParsedTemplate::parse(source) {
+            Ok((template, _)) => template.into_owned(),
+            Err(error) => {
+                let before = &source[..error.offset(source)];
+                let line = before.matches('\n').count() + 1;
+                return Err(TemplateSyntaxError::new_err(format!(
+                    "{} (in {}, line {})",
+                    error,
+                    name.as_deref().unwrap_or("<template>"),
+                    line
+                )));
+            }
+        };
+        Ok(Self {
+            template,
+            name,
+            environment,
+        })
+    }
+}
+
+fn render_error(error: RenderError) -> PyErr {
+    match error {
+        RenderError::TemplateNotFound(name) => TemplateNotFound::new_err(name),
+        RenderError::InvalidTemplate { .. } => TemplateSyntaxError::new_err(error.to_string()),
+        error => TemplateError::new_err(error.to_string()),
+    }
+}
+
+#[pymodule]
+fn ophelia(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add_class::<Environment>()?;
+    m.add_class::<Template>()?;
+    m.add_class::<FileSystemLoader>()?;
+    m.add_class::<DictLoader>()?;
+    m.add("TemplateError", py.get_type::<TemplateError>())?;
+    m.add("TemplateSyntaxError", py.get_type::<TemplateSyntaxError>())?;
+    m.add("TemplateNotFound", py.get_type::<TemplateNotFound>())?;
+    Ok(())
+}
+
+/// Makes `import ophelia` work in an interpreter which is embedded in a Rust program (rather than
+/// one which loaded the module from a wheel). It has to be called before the interpreter starts.
+pub fn register() {
+    pyo3::append_to_inittab!(ophelia);
+}
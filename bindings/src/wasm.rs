@@ -0,0 +1,182 @@
+//! A JavaScript API, for previewing templates in the browser (build it with `wasm-pack`).
+//!
+//! Templates are rendered exactly as they are on the server, except that there is no file system
+//! to load templates from, so templates which are imported or included have to be added to an
+//! [`Environment`] first.
+//!
+//! ```js
+//! import { Environment, parse, render } from "ophelia";
+//!
+//! for (const diagnostic of parse(source)) {
+//!     editor.mark(diagnostic.start, diagnostic.end, diagnostic.message);
+//! }
+//! preview.innerHTML = render(source, JSON.stringify({ user: { name: "ann" } }));
+//!
+//! const env = new Environment();
+//! env.addTemplate("header.html", "<h1>{{ title }}</h1>");
+//! preview.innerHTML = env.render("{% include 'header.html' %}", '{"title": "Home"}');
+//! ```
+
+use std::collections::BTreeMap;
+
+use ophelia_logic::{
+    cst,
+    parse::{Parse, ParseError, Template},
+    render::{Context, DictLoader, Render, Value},
+};
+use wasm_bindgen::prelude::*;
+
+/// Something which is wrong with a template.
+///
+/// Offsets are in UTF-16 code units (as JavaScript strings are indexed), so
+/// `source.slice(diagnostic.start, diagnostic.end)` is the text which is wrong.
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    message: String,
+    start: usize,
+    end: usize,
+    line: usize,
+    column: usize,
+}
+
+#[wasm_bindgen]
+impl Diagnostic {
+    /// What is wrong.
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    /// The offset at which the problem starts.
+    #[wasm_bindgen(getter)]
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The offset at which the problem ends.
+    #[wasm_bindgen(getter)]
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// The (one-based) line on which the problem starts.
+    #[wasm_bindgen(getter)]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The (one-based) column at which the problem starts, in characters.
+    #[wasm_bindgen(getter)]
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+impl Diagnostic {
+    /// A diagnostic for the bytes `start..end` of `source`.
+    fn new(source: &str, start: usize, end: usize, message: impl Into<String>) -> Self {
+        let before = &source[..start];
+        Self {
+            message: message.into(),
+            start: before.encode_utf16().count(),
+            end: source[..end].encode_utf16().count(),
+            line: before.matches('\n').count() + 1,
+            column: before.rsplit('\n').next().unwrap_or("").chars().count() + 1,
+        }
+    }
+
+    /// A diagnostic for the error which the parser failed with.
+    fn from_parse_error(source: &str, error: &ParseError) -> Self {
+        let start = error.offset(source);
+        let end = start + source[start..].chars().next().map_or(0, char::len_utf8);
+        Self::new(source, start, end, error.to_string())
+    }
+}
+
+/// Everything which is wrong with `source` (which is empty if it can be rendered).
+#[wasm_bindgen]
+pub fn parse(source: &str) -> Vec<Diagnostic> {
+    // the lossless tree finds (and recovers from) more errors at once, but accepts some templates
+    // which the parser does not, so those are checked with the parser too
+    let tree = cst::parse(source);
+    match tree.errors() {
+        [] => match Template::parse(source) {
+            Ok(_) => vec![],
+            Err(error) => vec![Diagnostic::from_parse_error(source, &error)],
+        },
+        errors => errors
+            .iter()
+            .map(|error| {
+                let range = error.range();
+                Diagnostic::new(
+                    source,
+                    range.start().into(),
+                    range.end().into(),
+                    error.message(),
+                )
+            })
+            .collect(),
+    }
+}
+
+/// Renders `template` with the variables in `json_context` (a JSON object, or `undefined` for no
+/// variables). Throws an `Error` if the template cannot be parsed or rendered.
+#[wasm_bindgen]
+pub fn render(template: &str, json_context: Option<String>) -> Result<String, JsError> {
+    Environment::new().render(template, json_context)
+}
+
+/// The templates which templates that are rendered can import or include.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    loader: DictLoader,
+}
+
+#[wasm_bindgen]
+impl Environment {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a template, which can be imported or included under `name`.
+    #[wasm_bindgen(js_name = addTemplate)]
+    pub fn add_template(&mut self, name: String, source: String) {
+        self.loader.insert(name, source);
+    }
+
+    /// Renders `template` (see [`render`]), loading the templates which it imports or includes
+    /// from this environment.
+    pub fn render(&self, template: &str, json_context: Option<String>) -> Result<String, JsError> {
+        let parsed = match Template::parse(template) {
+            Ok((parsed, _)) => parsed,
+            Err(error) => {
+                let diagnostic = Diagnostic::from_parse_error(template, &error);
+                return Err(JsError::new(&format!(
+                    "{}:{}: {}",
+                    diagnostic.line, diagnostic.column, diagnostic.message
+                )));
+            }
+        };
+
+        let mut ctx = Context::new();
+        ctx.set_loader(self.loader.clone());
+        if let Some(json) = json_context {
+            let variables: BTreeMap<String, Value> =
+                serde_json::from_str(&json).map_err(|error| {
+                    JsError::new(&format!("the context is not a JSON object: {}", error))
+                })?;
+            for (name, value) in variables {
+                ctx.insert(name, value);
+            }
+        }
+
+        let mut output = String::new();
+        parsed
+            .render(&mut ctx, &mut output)
+            .map_err(|error| JsError::new(&error.to_string()))?;
+        Ok(output)
+    }
+}
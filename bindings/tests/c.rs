@@ -0,0 +1,234 @@
+//! Calls the C API through its exported symbols, as a C program would.
+
+use std::{
+    ffi::{CStr, CString},
+    fs,
+    os::raw::c_char,
+    path::Path,
+    ptr,
+};
+
+use bindings::c::{OpheliaErrorKind, OpheliaSpan};
+
+// the types which the header only declares, as C sees them
+#[repr(C)]
+struct OpheliaEnv {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct OpheliaTemplate {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct OpheliaError {
+    _private: [u8; 0],
+}
+
+extern "C" {
+    fn ophelia_env_new() -> *mut OpheliaEnv;
+    fn ophelia_env_free(env: *mut OpheliaEnv);
+    fn ophelia_env_add_template(
+        env: *mut OpheliaEnv,
+        name: *const c_char,
+        source: *const c_char,
+        error: *mut *mut OpheliaError,
+    ) -> bool;
+    fn ophelia_env_set_directory(
+        env: *mut OpheliaEnv,
+        directory: *const c_char,
+        error: *mut *mut OpheliaError,
+    ) -> bool;
+    fn ophelia_template_parse(
+        source: *const c_char,
+        error: *mut *mut OpheliaError,
+    ) -> *mut OpheliaTemplate;
+    fn ophelia_template_free(template: *mut OpheliaTemplate);
+    fn ophelia_render_json(
+        env: *const OpheliaEnv,
+        template: *const OpheliaTemplate,
+        json: *const c_char,
+        error: *mut *mut OpheliaError,
+    ) -> *mut c_char;
+    fn ophelia_string_free(string: *mut c_char);
+    fn ophelia_error_kind(error: *const OpheliaError) -> OpheliaErrorKind;
+    fn ophelia_error_message(error: *const OpheliaError) -> *const c_char;
+    fn ophelia_error_span(error: *const OpheliaError, span: *mut OpheliaSpan) -> bool;
+    fn ophelia_error_free(error: *mut OpheliaError);
+}
+
+fn c(string: &str) -> CString {
+    CString::new(string).unwrap()
+}
+
+/// Takes the error which a call failed with, checking that there is one.
+unsafe fn take_error(
+    error: &mut *mut OpheliaError,
+) -> (OpheliaErrorKind, String, Option<OpheliaSpan>) {
+    assert!(!error.is_null(), "the call should have failed");
+    let kind = ophelia_error_kind(*error);
+    let message = CStr::from_ptr(ophelia_error_message(*error))
+        .to_str()
+        .unwrap()
+        .to_string();
+    let mut span = OpheliaSpan {
+        start: 0,
+        end: 0,
+        line: 0,
+        column: 0,
+    };
+    let span = ophelia_error_span(*error, &mut span).then_some(span);
+    ophelia_error_free(*error);
+    *error = ptr::null_mut();
+    (kind, message, span)
+}
+
+/// Parses and renders `source`, returning the output.
+unsafe fn render(env: *const OpheliaEnv, source: &str, json: &str) -> String {
+    let mut error = ptr::null_mut();
+    let template = ophelia_template_parse(c(source).as_ptr(), &mut error);
+    assert!(error.is_null() && !template.is_null());
+
+    let output = ophelia_render_json(env, template, c(json).as_ptr(), &mut error);
+    assert!(error.is_null() && !output.is_null());
+    let rendered = CStr::from_ptr(output).to_str().unwrap().to_string();
+
+    ophelia_string_free(output);
+    ophelia_template_free(template);
+    rendered
+}
+
+#[test]
+fn templates_render_with_json() {
+    unsafe {
+        assert_eq!(
+            render(
+                ptr::null(),
+                "{% for x in items %}{{ x }},{% endfor %}{{ user.name | upper }}{{ nothing }}",
+                r#"{"items": [1, 2.5, "a", true], "user": {"name": "ann"}, "nothing": null}"#,
+            ),
+            "1,2.5,a,True,ANNNone"
+        );
+
+        // the context may be null
+        let mut error = ptr::null_mut();
+        let template = ophelia_template_parse(c("hi {{ name }}").as_ptr(), &mut error);
+        let output = ophelia_render_json(ptr::null(), template, ptr::null(), &mut error);
+        assert_eq!(CStr::from_ptr(output).to_str().unwrap(), "hi ");
+        ophelia_string_free(output);
+        ophelia_template_free(template);
+    }
+}
+
+#[test]
+fn environments_load_templates() {
+    let dir = std::env::temp_dir().join(format!("ophelia-c-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("header.html"), "<h1>{{ title }}</h1>").unwrap();
+
+    unsafe {
+        let env = ophelia_env_new();
+        let mut error = ptr::null_mut();
+        assert!(ophelia_env_add_template(
+            env,
+            c("macros.html").as_ptr(),
+            c("{% macro b(x) %}<b>{{ x }}</b>{% endmacro %}").as_ptr(),
+            &mut error,
+        ));
+        assert!(ophelia_env_set_directory(
+            env,
+            c(dir.to_str().unwrap()).as_ptr(),
+            &mut error
+        ));
+        assert!(error.is_null());
+
+        assert_eq!(
+            render(
+                env,
+                "{% include 'header.html' %}{% from 'macros.html' import b %}{{ b(title) }}",
+                r#"{"title": "Home"}"#,
+            ),
+            "<h1>Home</h1><b>Home</b>"
+        );
+        ophelia_env_free(env);
+    }
+}
+
+#[test]
+fn errors_say_what_went_wrong() {
+    unsafe {
+        let mut error = ptr::null_mut();
+
+        let template = ophelia_template_parse(c("{% if x %}\nhi {{ 1 + }}").as_ptr(), &mut error);
+        assert!(template.is_null());
+        let (kind, message, span) = take_error(&mut error);
+        assert_eq!(kind, OpheliaErrorKind::Syntax);
+        assert!(!message.is_empty());
+        let span = span.expect("syntax errors have a span");
+        assert_eq!((span.line, span.column), (2, 11));
+        assert_eq!((span.start, span.end), (21, 22));
+
+        let template =
+            ophelia_template_parse(c("{{ x | nope }}{% include 'a' %}").as_ptr(), &mut error);
+        assert!(error.is_null());
+        for (json, expected) in &[
+            ("[1, 2]", OpheliaErrorKind::InvalidJson),
+            ("{", OpheliaErrorKind::InvalidJson),
+            (r#"{"x": 1}"#, OpheliaErrorKind::Render),
+        ] {
+            let output = ophelia_render_json(ptr::null(), template, c(json).as_ptr(), &mut error);
+            assert!(output.is_null());
+            let (kind, message, span) = take_error(&mut error);
+            assert_eq!(kind, *expected, "{}", message);
+            assert!(span.is_none());
+        }
+        ophelia_template_free(template);
+
+        let template = ophelia_template_parse(c("{% include 'a' %}").as_ptr(), &mut error);
+        let output = ophelia_render_json(ptr::null(), template, ptr::null(), &mut error);
+        assert!(output.is_null());
+        assert_eq!(take_error(&mut error).0, OpheliaErrorKind::TemplateNotFound);
+        ophelia_template_free(template);
+
+        assert!(ophelia_template_parse(ptr::null(), &mut error).is_null());
+        assert_eq!(take_error(&mut error).0, OpheliaErrorKind::InvalidArgument);
+        let invalid = [0xffu8, 0];
+        assert!(ophelia_template_parse(invalid.as_ptr() as *const c_char, &mut error).is_null());
+        assert_eq!(take_error(&mut error).0, OpheliaErrorKind::InvalidArgument);
+
+        // errors can be ignored by passing null
+        assert!(ophelia_template_parse(ptr::null(), ptr::null_mut()).is_null());
+
+        // freeing null does nothing
+        ophelia_env_free(ptr::null_mut());
+        ophelia_template_free(ptr::null_mut());
+        ophelia_string_free(ptr::null_mut());
+        ophelia_error_free(ptr::null_mut());
+    }
+}
+
+#[test]
+fn the_header_is_up_to_date() {
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+    let mut header = vec![];
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(crate_dir.join("src/c.rs"))
+        .generate()
+        .unwrap()
+        .write(&mut header);
+    let header = String::from_utf8(header).unwrap();
+
+    let path = crate_dir.join("include/ophelia.h");
+    if std::env::var_os("OPHELIA_UPDATE_HEADER").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &header).unwrap();
+    }
+    assert_eq!(
+        fs::read_to_string(&path).unwrap_or_default(),
+        header,
+        "the header is out of date; run `OPHELIA_UPDATE_HEADER=1 cargo test -p bindings --test c`"
+    );
+}
@@ -0,0 +1,138 @@
+use std::{ffi::CString, fs, sync::Once};
+
+use pyo3::{prelude::*, types::PyDict};
+
+/// Runs `code` in an interpreter which can `import ophelia`, failing if it raises.
+fn run(code: &str) {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(bindings::python::register);
+
+    Python::attach(|py| {
+        let globals = PyDict::new(py);
+        let code = CString::new(format!("import ophelia\n{}", code)).unwrap();
+        if let Err(error) = py.run(&code, Some(&globals), None) {
+            error.display(py);
+            panic!("the Python code raised {}", error);
+        }
+    })
+}
+
+#[test]
+fn templates_render_with_keyword_arguments_or_a_dict() {
+    run(r#"
+t = ophelia.Template("{% for x in items %}{{ x }},{% endfor %}{{ name | upper }}")
+assert t.render(items=[1, 2.5, "a"], name="ophelia") == "1,2.5,a,OPHELIA"
+assert t.render({"items": (1,), "name": "x"}) == "1,X"
+assert t.render({"items": []}, name="y") == "Y"
+"#);
+}
+
+#[test]
+fn python_values_are_converted() {
+    run(r#"
+class User:
+    def __init__(self, name, friends):
+        self.name = name
+        self.friends = friends
+        self._secret = "hidden"
+
+ann = User("ann", [])
+bob = User("bob", [ann])
+ann.friends.append(bob)
+
+t = ophelia.Template(
+    "{{ user.name }}:{% for f in user.friends %}{{ f.name }}{{ f.friends | length }}{% endfor %}"
+    "{{ user._secret }}|{{ data.a }}{{ flag }}{{ nothing }}{{ big }}|{{ shout('hi', times=2) }}"
+)
+out = t.render(
+    user=bob,
+    data={"a": [1, {"b": None}]},
+    flag=True,
+    nothing=None,
+    big=2 ** 40,
+    shout=lambda s, times: s.upper() * times,
+)
+assert out == "bob:ann1|[1, {'b': None}]TrueNone1099511627776.0|HIHI", out
+"#);
+}
+
+#[test]
+fn environments_load_templates() {
+    let dir = std::env::temp_dir().join(format!("ophelia-python-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("page.html"),
+        "{% include 'header.html' %}{{ body }}",
+    )
+    .unwrap();
+    fs::write(dir.join("header.html"), "<h1>{{ title }}</h1>").unwrap();
+
+    run(&format!(
+        r#"
+env = ophelia.Environment(loader=ophelia.FileSystemLoader({:?}))
+env.globals["title"] = "Home"
+t = env.get_template("page.html")
+assert t.name == "page.html"
+assert t.render(body="hi") == "<h1>Home</h1>hi"
+
+try:
+    env.get_template("missing.html")
+    raise AssertionError("the template should not have been found")
+except ophelia.TemplateNotFound:
+    pass
+
+env = ophelia.Environment(loader=ophelia.DictLoader({{"a.html": "{{% import 'b.html' as b %}}{{{{ b.x }}}}", "b.html": "{{% set x = 2 %}}"}}))
+assert env.get_template("a.html").render() == "2"
+assert env.from_string("{{% include 'b.html' %}}ok").render() == "ok"
+"#,
+        dir.display().to_string()
+    ));
+}
+
+#[test]
+fn filters_and_tests_can_be_registered() {
+    run(r#"
+env = ophelia.Environment()
+env.filters["shout"] = lambda value, times=1: value.upper() + "!" * times
+env.tests["short"] = lambda value: len(value) < 5
+t = env.from_string("{{ name | shout }} {{ name | shout(2) }} {{ name is short }} {{ 'ab' is short }}")
+assert t.render(name="ophelia") == "OPHELIA! OPHELIA!! False True"
+
+# filters which are added later are seen by templates which were already made
+env.filters["shout"] = lambda value, times=1: value.lower()
+assert t.render(name="ABC") == "abc abc True True"
+"#);
+}
+
+#[test]
+fn errors_become_exceptions() {
+    run(r#"
+try:
+    ophelia.Template("{% if x %}\n{{ 1 + }}")
+    raise AssertionError("the template should not have parsed")
+except ophelia.TemplateSyntaxError as error:
+    assert "line 2" in str(error), str(error)
+
+try:
+    ophelia.Template("{{ x | nope }}").render(x=1)
+    raise AssertionError("the filter should not exist")
+except ophelia.TemplateError as error:
+    assert "nope" in str(error)
+
+class Oops(Exception):
+    pass
+
+def fail(value):
+    raise Oops(value)
+
+env = ophelia.Environment()
+env.filters["fail"] = fail
+try:
+    env.from_string("{{ 1 | fail }}").render()
+    raise AssertionError("the filter should have raised")
+except Oops as error:
+    assert error.args == (1,)
+
+assert issubclass(ophelia.TemplateNotFound, ophelia.TemplateError)
+"#);
+}
@@ -0,0 +1,74 @@
+//! Runs the JavaScript API under Node (with `cargo test --target wasm32-unknown-unknown
+//! --no-default-features --features wasm`).
+
+#![cfg(target_arch = "wasm32")]
+
+use bindings::wasm::{parse, render, Environment};
+use wasm_bindgen::{JsCast, JsError, JsValue};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// The message of the `Error` which a function threw.
+fn message(error: JsError) -> String {
+    JsValue::from(error)
+        .dyn_into::<js_sys::Error>()
+        .unwrap()
+        .message()
+        .into()
+}
+
+#[wasm_bindgen_test]
+fn templates_render_with_json() {
+    assert_eq!(
+        render(
+            "{% for x in items %}{{ x }},{% endfor %}{{ user.name | upper }}",
+            Some(r#"{"items": [1, 2.5, "a", true], "user": {"name": "ann"}}"#.to_string()),
+        )
+        .unwrap(),
+        "1,2.5,a,True,ANN"
+    );
+    assert_eq!(render("hi {{ name }}", None).unwrap(), "hi ");
+
+    let error = render("{{ x }}", Some("[1]".to_string())).unwrap_err();
+    assert!(message(error).starts_with("the context is not a JSON object"));
+    let error = render("{{ x | nope }}", None).unwrap_err();
+    assert_eq!(message(error), "there is no filter called `nope`");
+    let error = render("{% if x %}\nhi {{ 1 + }}", None).unwrap_err();
+    assert!(message(error).starts_with("2:11: "));
+}
+
+#[wasm_bindgen_test]
+fn environments_hold_the_templates_which_are_loaded() {
+    let mut env = Environment::new();
+    env.add_template("header.html".into(), "<h1>{{ title }}</h1>".into());
+    env.add_template(
+        "macros.html".into(),
+        "{% macro b(x) %}<b>{{ x }}</b>{% endmacro %}".into(),
+    );
+    assert_eq!(
+        env.render(
+            "{% include 'header.html' %}{% from 'macros.html' import b %}{{ b(title) }}",
+            Some(r#"{"title": "Home"}"#.to_string()),
+        )
+        .unwrap(),
+        "<h1>Home</h1><b>Home</b>"
+    );
+
+    let error = env.render("{% include 'footer.html' %}", None).unwrap_err();
+    assert_eq!(message(error), "could not find the template `footer.html`");
+}
+
+#[wasm_bindgen_test]
+fn diagnostics_say_where_errors_are() {
+    assert!(parse("{% if x %}{{ x }}{% endif %}").is_empty());
+
+    // offsets count UTF-16 code units, as JavaScript does
+    let diagnostics = parse("é😀\n{{ 1 + }}");
+    assert_eq!(diagnostics.len(), 1);
+    let diagnostic = &diagnostics[0];
+    assert!(!diagnostic.message().is_empty());
+    assert_eq!((diagnostic.line(), diagnostic.column()), (2, 8));
+    assert_eq!((diagnostic.start(), diagnostic.end()), (11, 13));
+
+    // the lossless tree recovers from errors, so it can find several at once
+    assert!(parse("{% if %}{{ }}{% for %}").len() > 1);
+}
@@ -0,0 +1,26 @@
+//! Reading the variables which templates are rendered with.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use ophelia_logic::render::{Context, Value};
+
+/// Reads the file at `path` (as JSON, YAML or TOML, depending on its extension), and puts each
+/// of its top-level keys into `ctx`.
+pub fn load(path: &Path, ctx: &mut Context) -> Result<(), String> {
+    let source = fs::read_to_string(path)
+        .map_err(|error| format!("could not read `{}`: {}", path.display(), error))?;
+
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    let variables: Result<BTreeMap<String, Value>, String> = match extension {
+        Some("yaml" | "yml") => serde_yaml::from_str(&source).map_err(|error| error.to_string()),
+        Some("toml") => toml::from_str(&source).map_err(|error| error.to_string()),
+        _ => serde_json::from_str(&source).map_err(|error| error.to_string()),
+    };
+    let variables =
+        variables.map_err(|error| format!("could not parse `{}`: {}", path.display(), error))?;
+
+    for (name, value) in variables {
+        ctx.insert(name, value);
+    }
+    Ok(())
+}
@@ -0,0 +1,333 @@
+//! The `ophelia` command, which renders, checks, lints and formats templates.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Parser, Subcommand};
+use ophelia_logic::{
+    cst,
+    format::{Formatter, Quote},
+    lint::{Linter, Rule, Severity},
+    parse::{ast::Template, Parse, ParseError},
+    render::{Context, FileSystemLoader, IoOutput, Render, Undefined},
+};
+
+mod data;
+
+#[derive(Debug, Parser)]
+#[command(name = "ophelia", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Renders a template, and prints the result.
+    Render {
+        template: PathBuf,
+        /// A JSON, YAML or TOML file with the variables to render the template with.
+        #[arg(long)]
+        data: Option<PathBuf>,
+        /// The directory which imports and includes are looked up in (by default, the one the
+        /// template is in).
+        #[arg(long)]
+        templates: Option<PathBuf>,
+        /// Makes using a variable which is not defined an error.
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Checks that templates parse, and reports where they do not.
+    Check {
+        /// Templates, or directories to look for templates in.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Formats templates in place.
+    Fmt {
+        /// Templates, or directories to look for templates in.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Lists the templates which are not formatted, rather than formatting them.
+        #[arg(long)]
+        check: bool,
+        /// Indents the bodies of blocks by this many spaces.
+        #[arg(long)]
+        indent: Option<usize>,
+        /// Quotes strings with `"` rather than `'`.
+        #[arg(long)]
+        double_quotes: bool,
+    },
+    /// Reports mistakes (and likely mistakes) in templates.
+    Lint {
+        /// Templates, or directories to look for templates in.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Stops checking a rule (e.g. `unused-variable`).
+        #[arg(long, value_name = "RULE")]
+        allow: Vec<String>,
+        /// Reports a rule's lints as errors.
+        #[arg(long, value_name = "RULE")]
+        deny: Vec<String>,
+        /// A filter which templates are rendered with, besides the builtin ones.
+        #[arg(long = "filter", value_name = "NAME")]
+        filters: Vec<String>,
+        /// A test which templates are rendered with, besides the builtin ones.
+        #[arg(long = "test", value_name = "NAME")]
+        tests: Vec<String>,
+    },
+    /// Prints the tree which a template is parsed into.
+    Ast {
+        template: PathBuf,
+        /// Prints the tree as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+fn main() -> ExitCode {
+    let result = match Cli::parse().command {
+        Command::Render {
+            template,
+            data,
+            templates,
+            strict,
+        } => render(&template, data.as_deref(), templates, strict),
+        Command::Check { paths } => check(&paths),
+        Command::Fmt {
+            paths,
+            check,
+            indent,
+            double_quotes,
+        } => {
+            let mut formatter = Formatter::new();
+            if let Some(width) = indent {
+                formatter = formatter.indent(width);
+            }
+            if double_quotes {
+                formatter = formatter.quote(Quote::Double);
+            }
+            fmt(&paths, &formatter, check)
+        }
+        Command::Lint {
+            paths,
+            allow,
+            deny,
+            filters,
+            tests,
+        } => linter(&allow, &deny, filters, tests).and_then(|linter| lint(&paths, &linter)),
+        Command::Ast { template, json } => ast(&template, json),
+    };
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// What the subcommands return: `Ok(false)` if they ran but found problems (which they have
+/// already reported), and `Err` if they could not run.
+type CommandResult = Result<bool, String>;
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path)
+        .map_err(|error| format!("could not read `{}`: {}", path.display(), error))
+}
+
+fn parse<'i>(path: &Path, source: &'i str) -> Result<Template<'i>, String> {
+    Template::parse(source)
+        .map(|(template, _)| template)
+        .map_err(|error| {
+            let (line, column, message) = describe(source, &error);
+            format!("{}:{}:{}: {}", path.display(), line, column, message)
+        })
+}
+
+/// Where `offset` is in `source`, as a (one-based) line and column.
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+/// Where a parse error is (as a line and column), and what it is.
+fn describe(source: &str, error: &ParseError) -> (usize, usize, String) {
+    let (line, column) = position(source, error.offset(source));
+    (line, column, error.to_string())
+}
+
+/// The templates at `paths`, with directories replaced by the files inside them.
+fn files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    fn walk(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+        if !path.is_dir() {
+            files.push(path.to_path_buf());
+            return Ok(());
+        }
+        let entries = fs::read_dir(path)
+            .map_err(|error| format!("could not read `{}`: {}", path.display(), error))?;
+        let mut entries = entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| format!("could not read `{}`: {}", path.display(), error))?;
+        entries.sort();
+        for entry in entries {
+            walk(&entry, files)?;
+        }
+        Ok(())
+    }
+
+    let mut files = vec![];
+    for path in paths {
+        walk(path, &mut files)?;
+    }
+    Ok(files)
+}
+
+fn render(
+    template: &Path,
+    data: Option<&Path>,
+    templates: Option<PathBuf>,
+    strict: bool,
+) -> CommandResult {
+    let source = read(template)?;
+    let parsed = parse(template, &source)?;
+
+    let mut ctx = Context::new();
+    let root =
+        templates.unwrap_or_else(|| template.parent().map(Path::to_path_buf).unwrap_or_default());
+    ctx.set_loader(FileSystemLoader::new(root));
+    if strict {
+        ctx.set_undefined(Undefined::Strict);
+    }
+    if let Some(data) = data {
+        data::load(data, &mut ctx)?;
+    }
+
+    // the output is written out as it is rendered, rather than all at once at the end
+    let mut output = IoOutput::new(io::stdout().lock());
+    parsed
+        .render(&mut ctx, &mut output)
+        .and_then(|_| output.flush())
+        .map_err(|error| match error.span(&source) {
+            Some(span) => {
+                let (line, column) = position(&source, span.start);
+                format!("{}:{}:{}: {}", template.display(), line, column, error)
+            }
+            None => format!("{}: {}", template.display(), error),
+        })?;
+    Ok(true)
+}
+
+fn check(paths: &[PathBuf]) -> CommandResult {
+    let mut ok = true;
+    for path in files(paths)? {
+        let source = read(&path)?;
+        // the lossless tree finds (and recovers from) more errors at once, but accepts some
+        // templates which the parser does not, so those are checked with the parser too
+        let tree = cst::parse(&source);
+        let errors: Vec<_> = match tree.errors() {
+            [] => match Template::parse(&source) {
+                Ok(_) => continue,
+                Err(error) => vec![describe(&source, &error)],
+            },
+            errors => errors
+                .iter()
+                .map(|error| {
+                    let (line, column) = position(&source, error.range().start().into());
+                    (line, column, error.message().to_string())
+                })
+                .collect(),
+        };
+        for (line, column, message) in errors {
+            println!("{}:{}:{}: error: {}", path.display(), line, column, message);
+        }
+        ok = false;
+    }
+    Ok(ok)
+}
+
+fn fmt(paths: &[PathBuf], formatter: &Formatter, check: bool) -> CommandResult {
+    let mut ok = true;
+    for path in files(paths)? {
+        let source = read(&path)?;
+        let formatted = formatter.format(&parse(&path, &source)?);
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{}", path.display());
+            ok = false;
+        } else {
+            fs::write(&path, formatted)
+                .map_err(|error| format!("could not write `{}`: {}", path.display(), error))?;
+        }
+    }
+    Ok(ok)
+}
+
+fn linter(
+    allow: &[String],
+    deny: &[String],
+    filters: Vec<String>,
+    tests: Vec<String>,
+) -> Result<Linter, String> {
+    let rule = |id: &String| Rule::from_id(id).ok_or_else(|| format!("there is no rule `{}`", id));
+    let mut linter = Linter::new();
+    for id in allow {
+        linter = linter.allow(rule(id)?);
+    }
+    for id in deny {
+        linter = linter.severity(rule(id)?, Severity::Error);
+    }
+    for name in filters {
+        linter = linter.filter(name);
+    }
+    for name in tests {
+        linter = linter.test(name);
+    }
+    Ok(linter)
+}
+
+/// Reports every lint, but only fails if some of them are errors.
+fn lint(paths: &[PathBuf], linter: &Linter) -> CommandResult {
+    let mut ok = true;
+    for path in files(paths)? {
+        let source = read(&path)?;
+        let template = parse(&path, &source)?;
+        for lint in linter.lint(&source, &template) {
+            let place = match &lint.span {
+                Some(span) => {
+                    let (line, column) = position(&source, span.start);
+                    format!("{}:{}:{}", path.display(), line, column)
+                }
+                None => path.display().to_string(),
+            };
+            println!(
+                "{}: {}[{}]: {}",
+                place, lint.severity, lint.rule, lint.message
+            );
+            ok = ok && lint.severity != Severity::Error;
+        }
+    }
+    Ok(ok)
+}
+
+fn ast(template: &Path, json: bool) -> CommandResult {
+    let source = read(template)?;
+    let parsed = parse(template, &source)?;
+    if json {
+        let json = serde_json::to_string_pretty(&parsed).map_err(|error| error.to_string())?;
+        println!("{}", json);
+    } else {
+        println!("{:#?}", parsed);
+    }
+    Ok(true)
+}
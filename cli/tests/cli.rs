@@ -0,0 +1,177 @@
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+/// A fresh directory for a test to put templates in.
+fn directory(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("ophelia-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn ophelia(args: &[&str], dir: &PathBuf) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ophelia"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn render_uses_data_files_and_includes() {
+    let dir = directory("render");
+    fs::write(
+        dir.join("page.html"),
+        "{% for item in items %}{{ item }},{% endfor %}{% include 'footer.html' %}",
+    )
+    .unwrap();
+    fs::write(dir.join("footer.html"), "by {{ author.name }}").unwrap();
+
+    let data = [
+        (
+            "data.json",
+            r#"{"items": [1, 2.5], "author": {"name": "ann"}}"#,
+        ),
+        ("data.yaml", "items: [1, 2.5]\nauthor:\n  name: ann\n"),
+        ("data.toml", "items = [1, 2.5]\n[author]\nname = 'ann'\n"),
+    ];
+    for (name, contents) in data.iter() {
+        fs::write(dir.join(name), contents).unwrap();
+        let output = ophelia(&["render", "page.html", "--data", name], &dir);
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(stdout(&output), "1,2.5,by ann");
+    }
+
+    // variables which are not defined are only an error when rendering strictly
+    let output = ophelia(&["render", "page.html"], &dir);
+    assert_eq!(stdout(&output), "by ");
+    let output = ophelia(&["render", "page.html", "--strict"], &dir);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr).trim(),
+        "error: page.html:1:16: `items` is undefined"
+    );
+
+    let output = ophelia(&["render", "missing.html"], &dir);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("missing.html"));
+}
+
+#[test]
+fn check_reports_where_errors_are() {
+    let dir = directory("check");
+    fs::create_dir(dir.join("nested")).unwrap();
+    fs::write(dir.join("good.html"), "{% if x %}{{ x }}{% endif %}").unwrap();
+    fs::write(dir.join("nested/bad.html"), "fine\n{{ 1 + }}").unwrap();
+
+    let output = ophelia(&["check", "good.html"], &dir);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(stdout(&output), "");
+
+    let output = ophelia(&["check", "."], &dir);
+    assert!(!output.status.success());
+    let stdout = stdout(&output);
+    assert!(
+        stdout.starts_with(&format!(
+            "{}:2:8: error: ",
+            PathBuf::from(".").join("nested").join("bad.html").display()
+        )),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("good.html"));
+}
+
+#[test]
+fn fmt_rewrites_files_or_lists_them() {
+    let dir = directory("fmt");
+    fs::write(dir.join("page.html"), "{%if x%}{{x+1}}{%endif%}").unwrap();
+
+    let output = ophelia(&["fmt", "--check", "page.html"], &dir);
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "page.html\n");
+
+    let output = ophelia(&["fmt", "page.html"], &dir);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read_to_string(dir.join("page.html")).unwrap(),
+        "{% if x %}{{ x + 1 }}{% endif %}"
+    );
+
+    let output = ophelia(&["fmt", "--check", "page.html"], &dir);
+    assert!(output.status.success(), "{:?}", output);
+}
+
+#[test]
+fn lint_reports_mistakes() {
+    let dir = directory("lint");
+    fs::write(
+        dir.join("page.html"),
+        "{% set unused = 1 %}\n{{ name | shout }}",
+    )
+    .unwrap();
+
+    let output = ophelia(&["lint", "page.html"], &dir);
+    assert!(!output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "page.html:1:8: warning[unused-variable]: `unused` is set, but never used\n\
+         page.html:2:11: error[unknown-filter]: there is no filter called `shout`\n"
+    );
+
+    // warnings alone do not fail
+    let output = ophelia(&["lint", "page.html", "--filter", "shout"], &dir);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(stdout(&output).lines().count(), 1);
+
+    let args = [
+        "lint",
+        "page.html",
+        "--filter",
+        "shout",
+        "--deny",
+        "unused-variable",
+    ];
+    let output = ophelia(&args, &dir);
+    assert!(!output.status.success());
+    assert!(stdout(&output).starts_with("page.html:1:8: error[unused-variable]"));
+
+    let args = [
+        "lint",
+        "page.html",
+        "--allow",
+        "unused-variable",
+        "--allow",
+        "unknown-filter",
+    ];
+    let output = ophelia(&args, &dir);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(stdout(&output), "");
+
+    let output = ophelia(&["lint", "page.html", "--allow", "unused"], &dir);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("there is no rule `unused`"));
+}
+
+#[test]
+fn ast_prints_the_tree() {
+    let dir = directory("ast");
+    fs::write(dir.join("page.html"), "hi {{ name }}").unwrap();
+
+    let output = ophelia(&["ast", "page.html"], &dir);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(stdout(&output).contains("RawText"));
+
+    let output = ophelia(&["ast", "--json", "page.html"], &dir);
+    assert!(output.status.success(), "{:?}", output);
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json["expressions"][0]["RawText"], "hi ");
+    assert_eq!(json["expressions"][1]["Expr"]["Ident"]["name"], "name");
+}
@@ -1,11 +1,16 @@
 use honggfuzz::fuzz;
-use ophelia_logic::parse::{Parse, Template};
+use ophelia_logic::{
+    cst,
+    parse::{Parse, Template},
+};
 use std::str;
 
 fn main() {
     loop {
         fuzz!(|input: &[u8]| {
             if let Ok(input) = str::from_utf8(input) {
+                assert_eq!(cst::parse(input).syntax().to_string(), input);
+
                 if let Ok((template, _)) = Template::parse(input) {
                     let output = template.to_string();
 
@@ -0,0 +1,66 @@
+//! Compares rendering by walking the tree with running the compiled program (which currently
+//! take about as long as each other; see the `compile` module).
+//!
+//! Run with `cargo bench -p ophelia_logic`.
+
+#![feature(test)]
+
+extern crate test;
+
+use ophelia_logic::{
+    compile::compile,
+    parse::{Parse, Template},
+    render::{Context, Render, Value},
+};
+use test::{black_box, Bencher};
+
+const TEMPLATE: &str = "<h1>Hello {{ user.name | title }}!</h1>\
+    <ul>{% for item in items %}<li>{{ item.name }}: {{ item.price * 2 }}</li>{% endfor %}</ul>\
+    {% set ns = namespace(total=0) %}\
+    {% for item in items %}{% set ns.total = ns.total + item.price %}{% endfor %}\
+    <p>Total: {{ ns.total }}</p>";
+
+fn context() -> Context {
+    let item = |name: &str, price: i32| {
+        Value::dict(vec![
+            (Value::from("name"), Value::from(name)),
+            (Value::from("price"), Value::from(price)),
+        ])
+    };
+
+    let mut ctx = Context::new();
+    ctx.insert(
+        "user",
+        Value::dict(vec![(Value::from("name"), Value::from("ophelia"))]),
+    );
+    ctx.insert(
+        "items",
+        Value::list((0..50).map(|i| item("widget", i)).collect()),
+    );
+    ctx
+}
+
+#[bench]
+fn tree_walk(b: &mut Bencher) {
+    let (template, _) = Template::parse(TEMPLATE).unwrap();
+    let mut ctx = context();
+
+    b.iter(|| {
+        let mut output = String::new();
+        template.render(&mut ctx, &mut output).unwrap();
+        black_box(output)
+    });
+}
+
+#[bench]
+fn compiled(b: &mut Bencher) {
+    let (template, _) = Template::parse(TEMPLATE).unwrap();
+    let program = compile(&template);
+    let mut ctx = context();
+
+    b.iter(|| {
+        let mut output = String::new();
+        program.render(&mut ctx, &mut output).unwrap();
+        black_box(output)
+    });
+}
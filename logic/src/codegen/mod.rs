@@ -0,0 +1,163 @@
+//! Turning templates into Rust code.
+//!
+//! This is how the `template!` macro (from `ophelia_macros`) renders templates without parsing
+//! them at runtime: the template is parsed and optimised while the crate which uses it is being
+//! compiled, and every node is then written out as the Rust code which renders it. The code does
+//! exactly what [`Render`] would, and calls into [`runtime`] for anything which is not worth
+//! writing out in full (operators, filters, imports and includes). Given a struct as well, it
+//! generates a [`TypedTemplate`] instead, which takes the template's variables from the struct's
+//! fields, so that Rust checks that there is one for each of them.
+//!
+//! Generating code needs the `codegen` feature; running it does not.
+
+#[doc(hidden)]
+pub mod runtime;
+
+use crate::render::{Context, Output, Render, RenderResult};
+
+#[cfg(feature = "codegen")]
+use crate::parse::Template;
+
+/// A template which was turned into Rust code at build time (see the `template!` macro).
+#[derive(Debug, Clone, Copy)]
+pub struct GeneratedTemplate {
+    render: fn(&mut Context, &mut dyn Output) -> RenderResult<()>,
+}
+
+impl GeneratedTemplate {
+    #[doc(hidden)]
+    pub const fn new(render: fn(&mut Context, &mut dyn Output) -> RenderResult<()>) -> Self {
+        Self { render }
+    }
+}
+
+impl Render for GeneratedTemplate {
+    fn render(&self, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()> {
+        (self.render)(ctx, output)
+    }
+}
+
+/// A [`GeneratedTemplate`] whose variables are the fields of a struct `C` (see the `template!`
+/// macro), so that rendering it with a `C` which is missing one of them is a compile error.
+pub struct TypedTemplate<C> {
+    template: GeneratedTemplate,
+    fill: fn(&C, &mut Context),
+}
+
+impl<C> TypedTemplate<C> {
+    #[doc(hidden)]
+    pub const fn new(template: GeneratedTemplate, fill: fn(&C, &mut Context)) -> Self {
+        Self { template, fill }
+    }
+
+    /// Renders the template with the fields of `data` as its variables, in a new scope of `ctx`
+    /// (which still provides everything else, such as the loader and the sandbox).
+    pub fn render(&self, data: &C, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()> {
+        ctx.push_scope();
+        (self.fill)(data, ctx);
+        let result = self.template.render(ctx, output);
+        ctx.pop_scope();
+        result
+    }
+}
+
+// (not derived, which would only implement them where `C` does)
+impl<C> Clone for TypedTemplate<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C> Copy for TypedTemplate<C> {}
+
+impl<C> std::fmt::Debug for TypedTemplate<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TypedTemplate")
+            .field("template", &self.template)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Writes a node out as Rust code.
+///
+/// For an expression, this is a Rust expression which evaluates to its [`Value`]; for anything
+/// else, it is a list of statements which render it. Either way, the code refers to the context
+/// as `ctx` (a `&mut Context`) and the output as `output` (a `&mut dyn Output`), and uses `?` to
+/// return [`RenderError`]s.
+///
+/// [`Value`]: crate::render::Value
+/// [`RenderError`]: crate::render::RenderError
+#[cfg(feature = "codegen")]
+pub trait Generate {
+    fn generate(&self) -> proc_macro2::TokenStream;
+}
+
+/// Generates the code of a [`GeneratedTemplate`] which renders `template`.
+///
+/// The template is optimised first.
+#[cfg(feature = "codegen")]
+pub fn generate(template: Template<'_>) -> proc_macro2::TokenStream {
+    let body = crate::optimise::optimise(template).generate();
+    quote::quote! {{
+        // each node is written out on its own, so (for example) the code after a node which
+        // always fails is unreachable
+        #[allow(unreachable_code, unused_mut, unused_variables, clippy::all)]
+        fn render(
+            ctx: &mut ::ophelia_logic::render::Context,
+            output: &mut dyn ::ophelia_logic::render::Output,
+        ) -> ::ophelia_logic::render::RenderResult<()> {
+            #[allow(unused_imports)]
+            use ::ophelia_logic::{
+                codegen::runtime,
+                render::{Context, RenderError, RenderResult, Value},
+            };
+            #body
+            Ok(())
+        }
+        ::ophelia_logic::codegen::GeneratedTemplate::new(render)
+    }}
+}
+
+/// Generates the code of a [`TypedTemplate`] which renders `template` with the fields of
+/// `context` (a Rust type).
+///
+/// Every variable which the template reads without binding it itself (see
+/// [`meta::undeclared_variables`]) is copied from the field of the same name, which has to be
+/// [`Clone`] and convert [`Into`] a [`Value`], so a template which reads a variable which
+/// `context` does not have (or cannot provide) does not compile. Variables which only the
+/// templates it imports or includes read are not checked, since those are loaded while rendering.
+///
+/// [`meta::undeclared_variables`]: crate::meta::undeclared_variables
+/// [`Value`]: crate::render::Value
+#[cfg(feature = "codegen")]
+pub fn generate_typed(
+    template: Template<'_>,
+    context: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    // errors about the fields point at the type
+    let span = context
+        .clone()
+        .into_iter()
+        .next()
+        .map_or_else(proc_macro2::Span::call_site, |token| token.span());
+    let fields = crate::meta::undeclared_variables(&template)
+        .into_iter()
+        .map(|name| {
+            let field = proc_macro2::Ident::new(name, span);
+            quote::quote_spanned! {span=>
+                ctx.insert(
+                    #name,
+                    ::ophelia_logic::render::Value::from(::std::clone::Clone::clone(&data.#field)),
+                );
+            }
+        })
+        .collect::<Vec<_>>();
+    let generated = generate(template);
+    quote::quote! {{
+        #[allow(unused_variables)]
+        fn fill(data: &#context, ctx: &mut ::ophelia_logic::render::Context) {
+            #(#fields)*
+        }
+        ::ophelia_logic::codegen::TypedTemplate::new(#generated, fill)
+    }}
+}
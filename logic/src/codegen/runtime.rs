@@ -0,0 +1,144 @@
+//! What the code which [`super::generate`] writes out calls into. None of this is meant to be used
+//! directly.
+
+use crate::{
+    parse::{
+        expr::op,
+        import, include,
+        r#macro::{bind_args, defining_context},
+    },
+    render::{Context, Function, Kwargs, Origin, Output, RenderResult, Value},
+};
+
+pub use crate::parse::expr::op::{BinOp, UnaryOp};
+
+pub fn apply(ctx: &Context, op: BinOp, lhs: Value, rhs: Value) -> RenderResult<Value> {
+    ctx.apply(op, lhs, rhs)
+}
+
+pub fn apply_unary(op: UnaryOp, value: Value) -> RenderResult<Value> {
+    op::apply_unary(op, value)
+}
+
+pub fn call_method(
+    ctx: &Context,
+    value: &Value,
+    name: &str,
+    args: Vec<Value>,
+    kwargs: Kwargs,
+) -> RenderResult<Value> {
+    ctx.check_method(name)?;
+    op::call_method(value, name, args, kwargs)
+}
+
+pub fn filter(
+    ctx: &Context,
+    name: &str,
+    value: Value,
+    args: Vec<Value>,
+    kwargs: Kwargs,
+) -> RenderResult<Value> {
+    ctx.apply_filter(name, value, args, kwargs)
+}
+
+pub fn test(
+    ctx: &Context,
+    name: &str,
+    value: Value,
+    args: Vec<Value>,
+    kwargs: Kwargs,
+) -> RenderResult<Value> {
+    ctx.apply_test(name, value, args, kwargs).map(Value::Bool)
+}
+
+pub fn write(output: &mut dyn Output, text: &str) -> RenderResult<()> {
+    output.write(text)
+}
+
+pub fn emit(output: &mut dyn Output, value: Value) -> RenderResult<()> {
+    write!(output, "{}", value)
+}
+
+/// Writes `value`, the value of the expression `expr`.
+pub fn emit_expr(
+    ctx: &Context,
+    output: &mut dyn Output,
+    value: Value,
+    expr: &str,
+) -> RenderResult<()> {
+    ctx.emit(value, &expr, output)
+}
+
+pub fn variable(ctx: &Context, name: &str) -> RenderResult<Value> {
+    ctx.variable(name)
+}
+
+pub fn get_attr_or_undefined(ctx: &Context, value: Value, attr: &str) -> RenderResult<Value> {
+    ctx.attr_or_undefined(value, attr)
+}
+
+/// The attribute `attr` of `value`, the value of the expression `object`.
+pub fn get_attr(ctx: &Context, value: Value, object: &str, attr: &str) -> RenderResult<Value> {
+    ctx.attr(value, &object, Origin::of(object), attr)
+}
+
+/// The items which a loop over `value` visits.
+pub fn iterate(ctx: &Context, value: &Value) -> RenderResult<Vec<Value>> {
+    ctx.iterate(value)
+}
+
+pub fn check_output(ctx: &Context, output: &dyn Output) -> RenderResult<()> {
+    ctx.check_output(output)
+}
+
+pub fn spend_fuel(ctx: &Context) -> RenderResult<()> {
+    ctx.spend_fuel()
+}
+
+pub fn check_budget(ctx: &Context) -> RenderResult<()> {
+    ctx.check_budget()
+}
+
+pub fn scoped<T>(ctx: &mut Context, op: impl FnOnce(&mut Context) -> T) -> T {
+    ctx.scoped(op)
+}
+
+pub fn include(
+    files: Value,
+    ignore_missing: bool,
+    with_context: bool,
+    ctx: &mut Context,
+    output: &mut dyn Output,
+) -> RenderResult<()> {
+    include::include(files, ignore_missing, with_context, ctx, output)
+}
+
+pub fn module(name: &str, ctx: &Context, with_context: bool) -> RenderResult<Value> {
+    import::module(name, ctx, with_context)
+}
+
+pub fn export(module: &Value, name: &str, item: &str) -> RenderResult<Value> {
+    import::export(module, name, item)
+}
+
+/// The function which a macro called `name` is bound to. When it is called, `body` renders the
+/// macro in a new scope of the defining context, given the arguments bound to each of `params`
+/// (see `bind_args`).
+pub fn define_macro<F>(
+    ctx: &Context,
+    name: &'static str,
+    params: &'static [&'static str],
+    body: F,
+) -> Value
+where
+    F: Fn(&mut Context, Vec<Option<Value>>) -> RenderResult<Value> + 'static,
+{
+    let defined_in = ctx.downgrade();
+    let function = Function::new(move |args, kwargs| {
+        let mut ctx = defining_context(&defined_in)?;
+        let _depth = ctx.descend()?;
+        let values = bind_args(name, params, args, kwargs)?;
+        ctx.scoped(|ctx| body(ctx, values))
+    });
+    function.into()
+}
@@ -0,0 +1,183 @@
+//! The bytecode compiler.
+//!
+//! Rendering a template by walking its tree means chasing a pointer (and making a recursive call)
+//! for every node, every time it is rendered. Instead, a template can be compiled once into a
+//! flat list of instructions, which a small stack machine ([`vm`]) then executes.
+//!
+//! This is not (yet) noticeably faster: both spend most of their time on the values themselves
+//! (looking variables up through the scopes and cloning them, building constants from literals,
+//! applying operators and filters, and formatting the output), which the stack machine does in
+//! the same way, so in `benches/render.rs` the two are within the noise of each other. Making
+//! the machine pay off means making that work cheaper for it, e.g. by resolving variables to
+//! slots and building immutable constants once, when the template is compiled.
+//!
+//! Every node which can appear in a template implements [`Compile`]. Statements which are rarely
+//! hot (macros, imports and includes) are not lowered, and are rendered by walking their tree
+//! instead.
+
+mod vm;
+
+use crate::{
+    parse::{
+        expr::{
+            op::{BinOp, BinOpExpr, UnaryOp},
+            Expr,
+        },
+        literal::Literal,
+        stmt::Stmt,
+        Template,
+    },
+    render::{Context, Output, Render, RenderError, RenderResult},
+};
+
+/// Compiles `template`.
+pub fn compile<'i>(template: &'i Template<'i>) -> Program<'i> {
+    let mut compiler = Compiler::default();
+    template.compile(&mut compiler);
+    Program {
+        instructions: compiler.instructions,
+    }
+}
+
+/// A compiled template.
+#[derive(Debug, Clone)]
+pub struct Program<'i> {
+    instructions: Vec<Instruction<'i>>,
+}
+
+impl Program<'_> {
+    /// The number of instructions in the program.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+}
+
+impl Render for Program<'_> {
+    fn render(&self, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()> {
+        vm::execute(&self.instructions, ctx, output)
+    }
+}
+
+/// A single instruction.
+///
+/// Instructions operate on a stack of values. Jump targets are indices into the program.
+#[derive(Debug, Clone)]
+pub(crate) enum Instruction<'i> {
+    /// Writes text to the output.
+    EmitRaw(&'i str),
+    /// Pops a value, and writes it to the output.
+    Emit,
+    /// As `Emit`, for the value of an expression (which is written out instead if it is undefined
+    /// and undefined values are `Debug`).
+    EmitExpr(&'i Expr<'i>),
+    /// Pushes a constant. Each execution creates a fresh value, because lists and dicts are
+    /// mutable.
+    LoadConst(&'i Literal<'i>),
+    /// Pushes the value of a variable (or whatever the undefined policy makes of it).
+    LoadVar(&'i str),
+    /// Pushes the value of a variable, or `Undefined` (whatever the policy is).
+    LoadVarOrUndefined(&'i str),
+    /// Pops a value, and binds it to a name in the innermost scope.
+    StoreVar(&'i str),
+    /// Pops a value, and assigns it to an attribute of the namespace held by a variable.
+    StoreAttr(&'i str, &'i str),
+    /// Pops a value (of the left operand of an attribute lookup), and pushes the attribute.
+    GetAttr(&'i BinOpExpr<'i>, &'i str),
+    /// As `GetAttr`, but pushes `Undefined` whatever the policy is.
+    GetAttrOrUndefined(&'i str),
+    /// Discards the value on top of the stack.
+    Pop,
+    /// Pops two values, and pushes the result of applying an operator to them.
+    BinOp(BinOp),
+    /// Pops two values and compares them: if the comparison holds, pushes the second (to be
+    /// compared with the next operand of a chain), and otherwise pushes `false` and jumps.
+    CompareOrJump(BinOp, usize),
+    /// Pops a value, and pushes the result of applying an operator to it.
+    UnaryOp(UnaryOp),
+    /// Pops the keyword arguments (whose names are given), then the positional arguments, then
+    /// the value to call, and pushes the result.
+    Call(usize, Vec<&'i str>),
+    /// As `Call`, but calls a method of the value below the arguments.
+    CallMethod(&'i str, usize, Vec<&'i str>),
+    /// As `Call`, but applies a filter to the value below the arguments.
+    CallFilter(&'i str, usize, Vec<&'i str>),
+    /// As `CallFilter`, but applies a test (and pushes whether it held).
+    CallTest(&'i str, usize, Vec<&'i str>),
+    /// Pops a value, and pushes exactly this many items unpacked from it (in reverse, so that
+    /// storing them in order pops them in order).
+    Unpack(usize),
+    Jump(usize),
+    /// Pops a value, and jumps if it is falsy.
+    JumpIfFalse(usize),
+    /// Jumps (leaving the value on the stack) if it is falsy, and pops it otherwise.
+    JumpIfFalseOrPop(usize),
+    /// Jumps (leaving the value on the stack) if it is truthy, and pops it otherwise.
+    JumpIfTrueOrPop(usize),
+    /// Pops a value, and starts iterating over it.
+    IterStart,
+    /// Pushes the next item of the innermost iteration, or (once it is exhausted) ends the
+    /// iteration and jumps.
+    IterNext(usize),
+    PushScope,
+    PopScope,
+    /// Sends output into a new buffer, rather than to the output.
+    BeginCapture,
+    /// Ends the innermost capture, and pushes what it captured as a string.
+    EndCapture,
+    /// Renders a statement by walking its tree.
+    Render(&'i Stmt<'i>),
+    /// Spends a unit of the budget's fuel (before each expression and block).
+    SpendFuel,
+    /// Fails with an error (for constructs which only fail once they are rendered).
+    Fail(RenderError),
+}
+
+/// Collects the instructions of a program as the tree is compiled.
+#[derive(Debug, Default)]
+pub(crate) struct Compiler<'i> {
+    instructions: Vec<Instruction<'i>>,
+}
+
+impl<'i> Compiler<'i> {
+    /// Appends an instruction, returning its index.
+    pub(crate) fn emit(&mut self, instruction: Instruction<'i>) -> usize {
+        self.instructions.push(instruction);
+        self.instructions.len() - 1
+    }
+
+    /// The index which the next instruction will have.
+    pub(crate) fn next(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Points the jump at `index` to the next instruction (for forward jumps, whose target is not
+    /// known when they are emitted).
+    pub(crate) fn patch(&mut self, index: usize) {
+        let target = self.next();
+        match &mut self.instructions[index] {
+            Instruction::Jump(to)
+            | Instruction::JumpIfFalse(to)
+            | Instruction::JumpIfFalseOrPop(to)
+            | Instruction::JumpIfTrueOrPop(to)
+            | Instruction::CompareOrJump(_, to)
+            | Instruction::IterNext(to) => *to = target,
+            other => unreachable!("{:?} is not a jump", other),
+        }
+    }
+}
+
+pub(crate) trait Compile<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>);
+}
+
+impl<'i, T: Compile<'i>> Compile<'i> for [T] {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        for item in self {
+            item.compile(compiler);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! The stack machine which executes compiled templates.
+
+use crate::{
+    parse::expr::op::{apply, apply_unary, call_method},
+    render::{Context, Kwargs, Output, Render, RenderResult, Value},
+};
+
+use super::Instruction;
+
+pub(crate) fn execute(
+    instructions: &[Instruction],
+    ctx: &mut Context,
+    output: &mut dyn Output,
+) -> RenderResult<()> {
+    // scopes are pushed and popped by separate instructions, so if rendering fails part of the way
+    // through, any which are still open have to be popped here
+    let mut open_scopes = 0;
+    let res = run(instructions, ctx, output, &mut open_scopes);
+    for _ in 0..open_scopes {
+        ctx.pop_scope();
+    }
+    res
+}
+
+fn run(
+    instructions: &[Instruction],
+    ctx: &mut Context,
+    output: &mut dyn Output,
+    open_scopes: &mut usize,
+) -> RenderResult<()> {
+    let mut stack: Vec<Value> = vec![];
+    let mut iterators: Vec<std::vec::IntoIter<Value>> = vec![];
+    // the buffers of unfinished captures; output goes to the innermost one
+    let mut captures: Vec<String> = vec![];
+
+    let mut pc = 0;
+
+    while let Some(instruction) = instructions.get(pc) {
+        pc += 1;
+
+        // the innermost capture, if there is one, and the output otherwise
+        macro_rules! out {
+            () => {
+                match captures.last_mut() {
+                    Some(capture) => capture as &mut dyn Output,
+                    None => &mut *output,
+                }
+            };
+        }
+
+        match instruction {
+            Instruction::EmitRaw(raw) => {
+                out!().write(raw)?;
+                ctx.check_output(out!())?;
+            }
+            Instruction::Emit => {
+                write!(out!(), "{}", pop(&mut stack))?;
+                ctx.check_output(out!())?;
+            }
+            Instruction::EmitExpr(expr) => {
+                ctx.emit(pop(&mut stack), expr, out!())?;
+                ctx.check_output(out!())?;
+            }
+            Instruction::LoadConst(literal) => stack.push((*literal).into()),
+            Instruction::LoadVar(name) => stack.push(ctx.variable(name)?),
+            Instruction::LoadVarOrUndefined(name) => {
+                stack.push(ctx.get(name).unwrap_or(Value::Undefined))
+            }
+            Instruction::StoreVar(name) => ctx.insert(*name, pop(&mut stack)),
+            Instruction::StoreAttr(object, attr) => {
+                let value = pop(&mut stack);
+                ctx.get(object)
+                    .unwrap_or(Value::Undefined)
+                    .set_attr(attr, value)?
+            }
+            Instruction::GetAttr(expr, attr) => {
+                let value = pop(&mut stack);
+                stack.push(expr.get_attr(value, attr, ctx)?);
+            }
+            Instruction::GetAttrOrUndefined(attr) => {
+                let value = pop(&mut stack);
+                stack.push(ctx.attr_or_undefined(value, attr)?);
+            }
+            Instruction::Pop => {
+                pop(&mut stack);
+            }
+            Instruction::BinOp(op) => {
+                let rhs = pop(&mut stack);
+                let lhs = pop(&mut stack);
+                stack.push(ctx.apply(*op, lhs, rhs)?);
+            }
+            Instruction::CompareOrJump(op, to) => {
+                let rhs = pop(&mut stack);
+                let lhs = pop(&mut stack);
+                if apply(*op, lhs, rhs.clone())?.is_truthy() {
+                    stack.push(rhs);
+                } else {
+                    stack.push(Value::Bool(false));
+                    pc = *to;
+                }
+            }
+            Instruction::UnaryOp(op) => {
+                let value = pop(&mut stack);
+                stack.push(apply_unary(*op, value)?);
+            }
+            Instruction::Call(n_args, kwarg_names) => {
+                let (args, kwargs) = pop_args(&mut stack, *n_args, kwarg_names);
+                let function = pop(&mut stack);
+                stack.push(function.call(args, kwargs)?);
+            }
+            Instruction::CallMethod(name, n_args, kwarg_names) => {
+                let (args, kwargs) = pop_args(&mut stack, *n_args, kwarg_names);
+                let value = pop(&mut stack);
+                ctx.check_method(name)?;
+                stack.push(call_method(&value, name, args, kwargs)?);
+            }
+            Instruction::CallFilter(name, n_args, kwarg_names) => {
+                let (args, kwargs) = pop_args(&mut stack, *n_args, kwarg_names);
+                let value = pop(&mut stack);
+                stack.push(ctx.apply_filter(name, value, args, kwargs)?);
+            }
+            Instruction::CallTest(name, n_args, kwarg_names) => {
+                let (args, kwargs) = pop_args(&mut stack, *n_args, kwarg_names);
+                let value = pop(&mut stack);
+                stack.push(Value::Bool(ctx.apply_test(name, value, args, kwargs)?));
+            }
+            Instruction::Unpack(n) => {
+                let items = pop(&mut stack).unpack(*n)?;
+                stack.extend(items.into_iter().rev());
+            }
+            Instruction::Jump(to) => pc = *to,
+            Instruction::JumpIfFalse(to) => {
+                if !pop(&mut stack).is_truthy() {
+                    pc = *to;
+                }
+            }
+            Instruction::JumpIfFalseOrPop(to) => {
+                if stack.last().is_some_and(Value::is_truthy) {
+                    stack.pop();
+                } else {
+                    pc = *to;
+                }
+            }
+            Instruction::JumpIfTrueOrPop(to) => {
+                if stack.last().is_some_and(Value::is_truthy) {
+                    pc = *to;
+                } else {
+                    stack.pop();
+                }
+            }
+            Instruction::IterStart => {
+                let items = ctx.iterate(&pop(&mut stack))?;
+                iterators.push(items.into_iter());
+            }
+            Instruction::IterNext(to) => {
+                let iterator = iterators
+                    .last_mut()
+                    .expect("`IterNext` is only emitted after `IterStart`");
+                match iterator.next() {
+                    Some(item) => {
+                        ctx.check_budget()?;
+                        stack.push(item)
+                    }
+                    None => {
+                        iterators.pop();
+                        pc = *to;
+                    }
+                }
+            }
+            Instruction::PushScope => {
+                ctx.push_scope();
+                *open_scopes += 1;
+            }
+            Instruction::PopScope => {
+                ctx.pop_scope();
+                *open_scopes -= 1;
+            }
+            Instruction::BeginCapture => captures.push(String::new()),
+            Instruction::EndCapture => {
+                let captured = captures
+                    .pop()
+                    .expect("`EndCapture` is only emitted after `BeginCapture`");
+                stack.push(Value::String(captured));
+            }
+            Instruction::Render(stmt) => stmt.render(ctx, out!())?,
+            Instruction::SpendFuel => ctx.spend_fuel()?,
+            Instruction::Fail(error) => return Err(error.clone()),
+        }
+    }
+
+    Ok(())
+}
+
+fn pop(stack: &mut Vec<Value>) -> Value {
+    stack
+        .pop()
+        .expect("the compiler never emits an instruction which underflows the stack")
+}
+
+/// Pops the arguments of a call, which were pushed positional arguments first.
+fn pop_args(stack: &mut Vec<Value>, n_args: usize, kwarg_names: &[&str]) -> (Vec<Value>, Kwargs) {
+    let kwargs = stack.split_off(stack.len() - kwarg_names.len());
+    let kwargs = kwarg_names
+        .iter()
+        .map(|name| name.to_string())
+        .zip(kwargs)
+        .collect();
+    let args = stack.split_off(stack.len() - n_args);
+    (args, kwargs)
+}
@@ -0,0 +1,342 @@
+//! Typed views over the nodes of the tree.
+//!
+//! Each view wraps a [`SyntaxNode`] of one kind, and finds the parts of it which it is asked for.
+//! Parts which are missing (because the source has errors in it) are `None`.
+
+use crate::parse::{expr::op::Op, Parse};
+
+use super::{SyntaxKind, SyntaxNode, SyntaxToken};
+
+/// A view of a node of a particular kind.
+pub trait AstNode: Sized {
+    /// The view of `node`, if it is of the right kind.
+    fn cast(node: SyntaxNode) -> Option<Self>;
+
+    fn syntax(&self) -> &SyntaxNode;
+}
+
+macro_rules! ast_nodes {
+    ($($(#[$doc:meta])* $name:ident,)*) => {
+        $(
+            $(#[$doc])*
+            #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+            pub struct $name {
+                pub(crate) syntax: SyntaxNode,
+            }
+
+            impl AstNode for $name {
+                fn cast(node: SyntaxNode) -> Option<Self> {
+                    (node.kind() == SyntaxKind::$name).then(|| Self { syntax: node })
+                }
+
+                fn syntax(&self) -> &SyntaxNode {
+                    &self.syntax
+                }
+            }
+        )*
+    };
+}
+
+ast_nodes! {
+    /// The whole template.
+    Root,
+    /// `{# ... #}`
+    Comment,
+    /// `{{ ... }}`
+    Output,
+    /// `{% ... %}`
+    Tag,
+    /// A block tag, its body, and the tags which divide and end it.
+    Block,
+    Body,
+    Name,
+    Literal,
+    List,
+    Dict,
+    DictEntry,
+    Tuple,
+    Paren,
+    BinExpr,
+    UnaryExpr,
+    Call,
+    ArgList,
+    Kwarg,
+}
+
+/// The things which a template (or the body of a block) is made of.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Item {
+    Text(SyntaxToken),
+    Comment(Comment),
+    Output(Output),
+    Tag(Tag),
+    Block(Block),
+}
+
+/// An expression.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Expr {
+    Name(Name),
+    Literal(Literal),
+    List(List),
+    Dict(Dict),
+    Tuple(Tuple),
+    Paren(Paren),
+    BinExpr(BinExpr),
+    UnaryExpr(UnaryExpr),
+    Call(Call),
+}
+
+impl Expr {
+    pub fn cast(node: SyntaxNode) -> Option<Self> {
+        Some(match node.kind() {
+            SyntaxKind::Name => Expr::Name(Name { syntax: node }),
+            SyntaxKind::Literal => Expr::Literal(Literal { syntax: node }),
+            SyntaxKind::List => Expr::List(List { syntax: node }),
+            SyntaxKind::Dict => Expr::Dict(Dict { syntax: node }),
+            SyntaxKind::Tuple => Expr::Tuple(Tuple { syntax: node }),
+            SyntaxKind::Paren => Expr::Paren(Paren { syntax: node }),
+            SyntaxKind::BinExpr => Expr::BinExpr(BinExpr { syntax: node }),
+            SyntaxKind::UnaryExpr => Expr::UnaryExpr(UnaryExpr { syntax: node }),
+            SyntaxKind::Call => Expr::Call(Call { syntax: node }),
+            _ => return None,
+        })
+    }
+
+    pub fn syntax(&self) -> &SyntaxNode {
+        match self {
+            Expr::Name(e) => e.syntax(),
+            Expr::Literal(e) => e.syntax(),
+            Expr::List(e) => e.syntax(),
+            Expr::Dict(e) => e.syntax(),
+            Expr::Tuple(e) => e.syntax(),
+            Expr::Paren(e) => e.syntax(),
+            Expr::BinExpr(e) => e.syntax(),
+            Expr::UnaryExpr(e) => e.syntax(),
+            Expr::Call(e) => e.syntax(),
+        }
+    }
+}
+
+/// The children of `node` which are `N`s.
+fn children<N: AstNode>(node: &SyntaxNode) -> impl Iterator<Item = N> {
+    node.children().filter_map(N::cast)
+}
+
+/// The children of `node` which are expressions.
+fn exprs(node: &SyntaxNode) -> impl Iterator<Item = Expr> {
+    node.children().filter_map(Expr::cast)
+}
+
+/// The first token in `node` (but not in its children) of the given kind.
+fn token(node: &SyntaxNode, kind: SyntaxKind) -> Option<SyntaxToken> {
+    node.children_with_tokens()
+        .filter_map(|element| element.into_token())
+        .find(|token| token.kind() == kind)
+}
+
+/// The items which the children of `node` are.
+fn items(node: &SyntaxNode) -> impl Iterator<Item = Item> {
+    node.children_with_tokens()
+        .filter_map(|element| match element {
+            rowan::NodeOrToken::Token(token) => {
+                (token.kind() == SyntaxKind::Text).then(|| Item::Text(token))
+            }
+            rowan::NodeOrToken::Node(node) => match node.kind() {
+                SyntaxKind::Comment => Some(Item::Comment(Comment { syntax: node })),
+                SyntaxKind::Output => Some(Item::Output(Output { syntax: node })),
+                SyntaxKind::Tag => Some(Item::Tag(Tag { syntax: node })),
+                SyntaxKind::Block => Some(Item::Block(Block { syntax: node })),
+                _ => None,
+            },
+        })
+}
+
+impl Root {
+    pub fn items(&self) -> impl Iterator<Item = Item> {
+        items(&self.syntax)
+    }
+}
+
+impl Body {
+    pub fn items(&self) -> impl Iterator<Item = Item> {
+        items(&self.syntax)
+    }
+}
+
+impl Comment {
+    /// The text between `{#` and `#}`.
+    pub fn text(&self) -> Option<SyntaxToken> {
+        token(&self.syntax, SyntaxKind::CommentText)
+    }
+}
+
+impl Output {
+    pub fn expr(&self) -> Option<Expr> {
+        exprs(&self.syntax).next()
+    }
+}
+
+impl Tag {
+    /// The word which says what the tag is (e.g. `for` in `{% for x in y %}`).
+    pub fn keyword(&self) -> Option<SyntaxToken> {
+        token(&self.syntax, SyntaxKind::Keyword)
+    }
+
+    /// The expressions in the tag, in order (including the names which it binds, e.g. `x` and
+    /// `y` in `{% for x, y in z %}`).
+    pub fn exprs(&self) -> impl Iterator<Item = Expr> {
+        exprs(&self.syntax)
+    }
+
+    /// The `name=value` pairs in the tag (in `{% with %}`).
+    pub fn kwargs(&self) -> impl Iterator<Item = Kwarg> {
+        children(&self.syntax)
+    }
+
+    /// The parameters of a `{% macro %}`.
+    pub fn params(&self) -> Option<ArgList> {
+        children(&self.syntax).next()
+    }
+}
+
+impl Block {
+    /// The tags of the block: the one which starts it, the ones which divide it, and the one
+    /// which ends it (if it is ended).
+    pub fn tags(&self) -> impl Iterator<Item = Tag> {
+        children(&self.syntax)
+    }
+
+    /// The parts of the block between its tags (one after each tag but the last).
+    pub fn bodies(&self) -> impl Iterator<Item = Body> {
+        children(&self.syntax)
+    }
+
+    /// The tag which starts the block.
+    pub fn opening(&self) -> Option<Tag> {
+        self.tags().next()
+    }
+
+    /// The tag which ends the block, if there is one.
+    pub fn closing(&self) -> Option<Tag> {
+        self.tags().last().filter(|tag| {
+            tag.keyword()
+                .is_some_and(|keyword| keyword.text().starts_with("end"))
+        })
+    }
+}
+
+impl Name {
+    pub fn ident(&self) -> Option<SyntaxToken> {
+        token(&self.syntax, SyntaxKind::Ident)
+    }
+
+    pub fn text(&self) -> String {
+        self.syntax.text().to_string()
+    }
+}
+
+impl Literal {
+    pub fn token(&self) -> Option<SyntaxToken> {
+        self.syntax.first_token()
+    }
+}
+
+impl List {
+    pub fn items(&self) -> impl Iterator<Item = Expr> {
+        exprs(&self.syntax)
+    }
+}
+
+impl Tuple {
+    pub fn items(&self) -> impl Iterator<Item = Expr> {
+        exprs(&self.syntax)
+    }
+}
+
+impl Dict {
+    pub fn entries(&self) -> impl Iterator<Item = DictEntry> {
+        children(&self.syntax)
+    }
+}
+
+impl DictEntry {
+    pub fn key(&self) -> Option<Expr> {
+        exprs(&self.syntax).next()
+    }
+
+    pub fn value(&self) -> Option<Expr> {
+        exprs(&self.syntax).nth(1)
+    }
+}
+
+impl Paren {
+    pub fn expr(&self) -> Option<Expr> {
+        exprs(&self.syntax).next()
+    }
+}
+
+impl BinExpr {
+    pub fn lhs(&self) -> Option<Expr> {
+        exprs(&self.syntax).next()
+    }
+
+    pub fn rhs(&self) -> Option<Expr> {
+        exprs(&self.syntax).nth(1)
+    }
+
+    pub fn operator_token(&self) -> Option<SyntaxToken> {
+        token(&self.syntax, SyntaxKind::Operator)
+    }
+
+    pub fn operator(&self) -> Option<crate::parse::ast::BinOp> {
+        match Op::parse(self.operator_token()?.text()) {
+            Ok((Op::BinOp(operator), "")) => Some(operator),
+            _ => None,
+        }
+    }
+}
+
+impl UnaryExpr {
+    pub fn operator_token(&self) -> Option<SyntaxToken> {
+        token(&self.syntax, SyntaxKind::Operator)
+    }
+
+    pub fn operand(&self) -> Option<Expr> {
+        exprs(&self.syntax).next()
+    }
+}
+
+impl Call {
+    /// The name of the function which is called.
+    pub fn name(&self) -> Option<Name> {
+        children(&self.syntax).next()
+    }
+
+    pub fn args(&self) -> Option<ArgList> {
+        children(&self.syntax).next()
+    }
+}
+
+impl ArgList {
+    /// The arguments which are not named.
+    pub fn args(&self) -> impl Iterator<Item = Expr> {
+        exprs(&self.syntax)
+    }
+
+    /// The named arguments.
+    pub fn kwargs(&self) -> impl Iterator<Item = Kwarg> {
+        children(&self.syntax)
+    }
+}
+
+impl Kwarg {
+    pub fn name(&self) -> Option<Name> {
+        children(&self.syntax).next()
+    }
+
+    pub fn value(&self) -> Option<Expr> {
+        exprs(&self.syntax).nth(1)
+    }
+}
@@ -0,0 +1,172 @@
+//! Splits the source of a template into tokens, without losing any of it.
+
+use crate::parse::{literal::NumberParser, Parse};
+
+use super::SyntaxKind;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Token<'s> {
+    pub(crate) kind: SyntaxKind,
+    pub(crate) text: &'s str,
+}
+
+/// Where the lexer is.
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    Text,
+    Comment,
+    /// Inside `{{ }}` or `{% %}`, until `close`.
+    Code {
+        close: &'static str,
+    },
+}
+
+/// Operators which are made of symbols, longest first (so that e.g. `**` is not lexed as two
+/// `*`s). The ones which are words are lexed as identifiers, and told apart by the parser.
+const OPERATORS: &[&str] = &[
+    "**", "//", "==", "!=", ">=", "<=", "+", "-", "*", "/", "%", "<", ">", "|", "~", ".",
+];
+
+const PUNCTUATION: &[(&str, SyntaxKind)] = &[
+    ("(", SyntaxKind::LParen),
+    (")", SyntaxKind::RParen),
+    ("[", SyntaxKind::LBracket),
+    ("]", SyntaxKind::RBracket),
+    ("{", SyntaxKind::LBrace),
+    ("}", SyntaxKind::RBrace),
+    (",", SyntaxKind::Comma),
+    (":", SyntaxKind::Colon),
+    ("=", SyntaxKind::Eq),
+];
+
+struct Lexer<'s> {
+    rest: &'s str,
+    tokens: Vec<Token<'s>>,
+}
+
+impl Lexer<'_> {
+    /// Makes a token of the given kind out of the next `len` bytes.
+    fn push(&mut self, kind: SyntaxKind, len: usize) {
+        let (text, rest) = self.rest.split_at(len);
+        self.tokens.push(Token { kind, text });
+        self.rest = rest;
+    }
+}
+
+/// Lexes `source`. Joining the text of the tokens gives back `source`.
+pub(crate) fn lex(source: &str) -> Vec<Token<'_>> {
+    let mut lexer = Lexer {
+        rest: source,
+        tokens: vec![],
+    };
+    let mut mode = Mode::Text;
+    // how many `{`s of dict literals are open, so that `{{ {'a': {}}}` ends after the last `}}`
+    let mut braces = 0;
+
+    while !lexer.rest.is_empty() {
+        let rest = lexer.rest;
+        match mode {
+            Mode::Text => {
+                let start = ["{{", "{%", "{#"]
+                    .iter()
+                    .filter_map(|open| rest.find(open))
+                    .min();
+                match start {
+                    Some(0) => {
+                        let (kind, next) = match &rest[..2] {
+                            "{{" => (SyntaxKind::OutputOpen, Mode::Code { close: "}}" }),
+                            "{%" => (SyntaxKind::TagOpen, Mode::Code { close: "%}" }),
+                            _ => (SyntaxKind::CommentOpen, Mode::Comment),
+                        };
+                        lexer.push(kind, 2);
+                        mode = next;
+                        braces = 0;
+                    }
+                    Some(start) => lexer.push(SyntaxKind::Text, start),
+                    None => lexer.push(SyntaxKind::Text, rest.len()),
+                }
+            }
+            Mode::Comment => match rest.find("#}") {
+                Some(0) => {
+                    lexer.push(SyntaxKind::CommentClose, 2);
+                    mode = Mode::Text;
+                }
+                Some(end) => lexer.push(SyntaxKind::CommentText, end),
+                None => lexer.push(SyntaxKind::CommentText, rest.len()),
+            },
+            Mode::Code { close } => {
+                if rest.starts_with(close) && (close == "%}" || braces == 0) {
+                    let kind = if close == "}}" {
+                        SyntaxKind::OutputClose
+                    } else {
+                        SyntaxKind::TagClose
+                    };
+                    lexer.push(kind, 2);
+                    mode = Mode::Text;
+                } else if ["{{", "{%", "{#"].iter().any(|open| rest.starts_with(open)) {
+                    // the tag was never closed; the parser reports it
+                    mode = Mode::Text;
+                } else {
+                    let (kind, len) = code_token(rest);
+                    match kind {
+                        SyntaxKind::LBrace => braces += 1,
+                        SyntaxKind::RBrace if braces > 0 => braces -= 1,
+                        _ => {}
+                    }
+                    lexer.push(kind, len);
+                }
+            }
+        }
+    }
+
+    lexer.tokens
+}
+
+/// The kind and length of the token at the start of `rest`, which is inside a tag.
+fn code_token(rest: &str) -> (SyntaxKind, usize) {
+    let first = rest.chars().next().unwrap();
+
+    if first.is_whitespace() {
+        let len = rest
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(rest.len());
+        return (SyntaxKind::Whitespace, len);
+    }
+
+    if first.is_ascii_alphabetic() || first == '_' {
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        return (SyntaxKind::Ident, len);
+    }
+
+    if first.is_ascii_digit() {
+        if let Ok((number, after)) = NumberParser::parse(rest) {
+            let kind = if number.is_float() {
+                SyntaxKind::Float
+            } else {
+                SyntaxKind::Integer
+            };
+            return (kind, rest.len() - after.len());
+        }
+    }
+
+    if first == '"' || first == '\'' {
+        // as in `Literal::parse`, a string ends at the next quote of either kind
+        return match rest[1..].find(['"', '\'']) {
+            Some(end) => (SyntaxKind::String, end + 2),
+            None => (SyntaxKind::Error, rest.len()),
+        };
+    }
+
+    if let Some(operator) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+        // `==` was checked for before `=` could be taken for punctuation
+        return (SyntaxKind::Operator, operator.len());
+    }
+
+    if let Some((text, kind)) = PUNCTUATION.iter().find(|(text, _)| rest.starts_with(text)) {
+        return (*kind, text.len());
+    }
+
+    (SyntaxKind::Error, first.len_utf8())
+}
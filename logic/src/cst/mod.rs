@@ -0,0 +1,189 @@
+//! A lossless concrete syntax tree.
+//!
+//! The [`crate::parse`] module throws away everything which does not change what a template
+//! means (the spacing inside tags, for instance). The tree built here keeps every byte of the
+//! source instead – printing it gives back exactly the text it was parsed from – which makes it
+//! what tools which edit templates (rather than render them) should work with.
+//!
+//! ```
+//! use ophelia_logic::cst;
+//!
+//! let source = "{%for x in  items%}{{ x|upper }}{% endfor %}{# done #}";
+//! let tree = cst::parse(source);
+//! assert_eq!(tree.syntax().to_string(), source);
+//! assert!(tree.errors().is_empty());
+//! ```
+//!
+//! The tree is a [`rowan`] tree: [`SyntaxNode`]s hold the tokens (and other nodes) they are made
+//! of, and [`ast`] has typed views over the nodes. Parsing never fails; source which is not a
+//! valid template still becomes a tree, and the problems with it are reported in
+//! [`SyntaxTree::errors`]. The tree is more lenient than [`crate::parse`] is, so a template which
+//! has no syntax errors here may still be rejected there.
+
+pub mod ast;
+mod lexer;
+mod parser;
+
+pub use rowan::{GreenNode, GreenToken, NodeOrToken, TextRange, TextSize, WalkEvent};
+
+macro_rules! syntax_kinds {
+    ($($(#[$doc:meta])* $kind:ident,)*) => {
+        /// The kinds of tokens and nodes in the tree.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        #[repr(u16)]
+        #[non_exhaustive]
+        pub enum SyntaxKind {
+            $($(#[$doc])* $kind,)*
+        }
+
+        impl SyntaxKind {
+            const ALL: &'static [SyntaxKind] = &[$(SyntaxKind::$kind,)*];
+        }
+    };
+}
+
+syntax_kinds! {
+    // tokens
+    /// Text outside of any tag, which is rendered as it is.
+    Text,
+    Whitespace,
+    /// `{#`
+    CommentOpen,
+    CommentText,
+    /// `#}`
+    CommentClose,
+    /// `{{`
+    OutputOpen,
+    /// `}}`
+    OutputClose,
+    /// `{%`
+    TagOpen,
+    /// `%}`
+    TagClose,
+    Ident,
+    /// A word which is part of the syntax of a tag (`for`, `in`, `endfor`, `ignore missing`...).
+    Keyword,
+    /// An operator, including the ones which are words (`and`, `not`...).
+    Operator,
+    Integer,
+    Float,
+    String,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    Comma,
+    Colon,
+    /// `=` (which is not an operator)
+    Eq,
+    /// A character which cannot start any token.
+    Error,
+
+    // nodes
+    Root,
+    /// `{# ... #}`
+    Comment,
+    /// `{{ ... }}`
+    Output,
+    /// `{% ... %}`
+    Tag,
+    /// A tag which starts a block (e.g. `{% for %}`), followed by the body of the block and any
+    /// tags which divide it (`{% else %}`), and then by the tag which ends it.
+    Block,
+    /// The contents of a block between two of its tags.
+    Body,
+    Name,
+    Literal,
+    List,
+    Dict,
+    DictEntry,
+    Tuple,
+    /// `(expr)`
+    Paren,
+    BinExpr,
+    UnaryExpr,
+    Call,
+    ArgList,
+    /// `name=value`, in a call, a macro's parameters or a `{% with %}` tag.
+    Kwarg,
+    /// A part of a tag which could not be parsed.
+    ErrorNode,
+}
+
+impl From<SyntaxKind> for rowan::SyntaxKind {
+    fn from(kind: SyntaxKind) -> Self {
+        Self(kind as u16)
+    }
+}
+
+/// The language which [`rowan`] trees are parameterised over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Ophelia {}
+
+impl rowan::Language for Ophelia {
+    type Kind = SyntaxKind;
+
+    fn kind_from_raw(raw: rowan::SyntaxKind) -> SyntaxKind {
+        SyntaxKind::ALL[raw.0 as usize]
+    }
+
+    fn kind_to_raw(kind: SyntaxKind) -> rowan::SyntaxKind {
+        kind.into()
+    }
+}
+
+pub type SyntaxNode = rowan::SyntaxNode<Ophelia>;
+pub type SyntaxToken = rowan::SyntaxToken<Ophelia>;
+pub type SyntaxElement = rowan::SyntaxElement<Ophelia>;
+
+/// Something which is wrong with the source of a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    message: String,
+    range: TextRange,
+}
+
+impl SyntaxError {
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Where in the source the problem is.
+    pub fn range(&self) -> TextRange {
+        self.range
+    }
+}
+
+/// The tree which a template was parsed into, and any errors which were found while parsing it.
+#[derive(Debug, Clone)]
+pub struct SyntaxTree {
+    green: GreenNode,
+    errors: Vec<SyntaxError>,
+}
+
+impl SyntaxTree {
+    /// The root of the tree.
+    pub fn syntax(&self) -> SyntaxNode {
+        SyntaxNode::new_root(self.green.clone())
+    }
+
+    /// A typed view of the root of the tree.
+    pub fn root(&self) -> ast::Root {
+        ast::Root {
+            syntax: self.syntax(),
+        }
+    }
+
+    pub fn errors(&self) -> &[SyntaxError] {
+        &self.errors
+    }
+}
+
+/// Parses `source` into a lossless tree.
+pub fn parse(source: &str) -> SyntaxTree {
+    let tokens = lexer::lex(source);
+    let (green, errors) = parser::Parser::new(&tokens).parse();
+    SyntaxTree { green, errors }
+}
@@ -0,0 +1,627 @@
+//! Builds the tree out of the tokens. Every token ends up in the tree (in the order it was lexed);
+//! tokens which do not fit are wrapped in [`SyntaxKind::ErrorNode`]s, and reported.
+
+use rowan::{GreenNode, GreenNodeBuilder, TextRange, TextSize};
+
+use crate::parse::{
+    expr::op::{BinOp, Op, UnaryOp},
+    Parse,
+};
+
+use super::{lexer::Token, SyntaxError, SyntaxKind};
+
+/// The tags which start a block, the tags which may divide it, and the tag which ends it. `set`
+/// only starts a block when it has no `=`.
+const BLOCKS: &[(&str, &[&str], &str)] = &[
+    ("for", &["else"], "endfor"),
+    ("if", &["elif", "else"], "endif"),
+    ("macro", &[], "endmacro"),
+    ("filter", &[], "endfilter"),
+    ("set", &[], "endset"),
+    ("with", &[], "endwith"),
+];
+
+/// The binding power which the targets of `{% set %}` and the name of `{% filter %}` are parsed
+/// with, so that they stop at the first `|` (the one which starts the filters).
+const ABOVE_PIPE: u8 = 20;
+
+pub(crate) struct Parser<'t, 's> {
+    tokens: &'t [Token<'s>],
+    /// Where each token starts.
+    offsets: Vec<TextSize>,
+    position: usize,
+    builder: GreenNodeBuilder<'static>,
+    errors: Vec<SyntaxError>,
+}
+
+impl<'t, 's> Parser<'t, 's> {
+    pub(crate) fn new(tokens: &'t [Token<'s>]) -> Self {
+        let mut offsets = vec![TextSize::from(0)];
+        for token in tokens {
+            offsets.push(offsets[offsets.len() - 1] + TextSize::of(token.text));
+        }
+
+        Self {
+            tokens,
+            offsets,
+            position: 0,
+            builder: GreenNodeBuilder::new(),
+            errors: vec![],
+        }
+    }
+
+    pub(crate) fn parse(mut self) -> (GreenNode, Vec<SyntaxError>) {
+        self.builder.start_node(SyntaxKind::Root.into());
+        self.items(&[]);
+        self.builder.finish_node();
+        (self.builder.finish(), self.errors)
+    }
+
+    /// Parses text, comments, outputs and tags until a tag whose keyword is in `stop`.
+    fn items(&mut self, stop: &[&str]) {
+        while let Some(kind) = self.current() {
+            match kind {
+                SyntaxKind::CommentOpen => self.comment(),
+                SyntaxKind::OutputOpen => self.output(),
+                SyntaxKind::TagOpen => {
+                    let keyword = self.tag_keyword();
+                    match keyword {
+                        Some(keyword) if stop.contains(&keyword) => return,
+                        Some(keyword) if self.starts_block(keyword) => self.block(keyword, stop),
+                        Some(keyword)
+                            if keyword.starts_with("end")
+                                || ["elif", "else"].contains(&keyword) =>
+                        {
+                            self.error(format!("`{}` does not end or divide any block", keyword));
+                            self.tag(Some(keyword));
+                        }
+                        keyword => self.tag(keyword),
+                    }
+                }
+                // the lexer only writes other tokens out between the delimiters
+                _ => self.bump(),
+            }
+        }
+    }
+
+    fn comment(&mut self) {
+        self.builder.start_node(SyntaxKind::Comment.into());
+        self.bump();
+        if self.current() == Some(SyntaxKind::CommentText) {
+            self.bump();
+        }
+        self.expect(SyntaxKind::CommentClose, "`#}`");
+        self.builder.finish_node();
+    }
+
+    fn output(&mut self) {
+        self.builder.start_node(SyntaxKind::Output.into());
+        self.bump();
+        self.expr(0);
+        self.close(SyntaxKind::OutputClose, "`}}`");
+        self.builder.finish_node();
+    }
+
+    /// Parses a block which starts with a tag with the given `keyword`, and every tag which
+    /// divides or ends it. The blocks around it end at the tags in `outer`.
+    fn block(&mut self, keyword: &str, outer: &[&str]) {
+        let (_, dividers, end) = BLOCKS
+            .iter()
+            .find(|(start, _, _)| *start == keyword)
+            .copied()
+            .unwrap();
+        let stop = dividers
+            .iter()
+            .chain(std::iter::once(&end))
+            .chain(outer)
+            .copied()
+            .collect::<Vec<_>>();
+
+        self.builder.start_node(SyntaxKind::Block.into());
+        self.tag(Some(keyword));
+        loop {
+            self.builder.start_node(SyntaxKind::Body.into());
+            self.items(&stop);
+            self.builder.finish_node();
+
+            match self.tag_keyword() {
+                Some(keyword) if dividers.contains(&keyword) => self.tag(Some(keyword)),
+                Some(keyword) if keyword == end => {
+                    self.tag(Some(keyword));
+                    break;
+                }
+                _ => {
+                    self.error(format!("expected `{{% {} %}}`", end));
+                    break;
+                }
+            }
+        }
+        self.builder.finish_node();
+    }
+
+    /// Parses a tag (whose keyword has already been peeked at).
+    fn tag(&mut self, keyword: Option<&str>) {
+        self.builder.start_node(SyntaxKind::Tag.into());
+        self.bump();
+        self.skip_whitespace();
+
+        match keyword {
+            Some(keyword) => {
+                self.bump_as(SyntaxKind::Keyword);
+                self.tag_contents(keyword);
+            }
+            None => self.error("expected the name of a tag".to_string()),
+        }
+
+        self.close(SyntaxKind::TagClose, "`%}`");
+        self.builder.finish_node();
+    }
+
+    fn tag_contents(&mut self, keyword: &str) {
+        match keyword {
+            "for" => {
+                self.separated(|parser| parser.name());
+                self.keyword("in");
+                self.expr(0);
+            }
+            // the parser in `crate::parse` does not support `extends` yet, but editors can still
+            // follow it to the template which it names
+            "if" | "elif" | "do" | "extends" => self.expr(0),
+            "set" => {
+                self.separated(|parser| parser.expr(ABOVE_PIPE));
+                if self.at(SyntaxKind::Eq) {
+                    self.bump_next();
+                    self.expr(0);
+                } else {
+                    self.filters();
+                }
+            }
+            "filter" => {
+                self.expr(ABOVE_PIPE);
+                self.filters();
+            }
+            "macro" => {
+                self.name();
+                if self.at(SyntaxKind::LParen) {
+                    self.args();
+                } else {
+                    self.error("expected `(`".to_string());
+                }
+            }
+            "with" => self.separated(|parser| parser.kwarg()),
+            "include" => {
+                self.expr(0);
+                if self.at_word("ignore") {
+                    self.keyword("ignore");
+                    self.keyword("missing");
+                }
+                self.context();
+            }
+            "import" => {
+                self.expr(0);
+                self.keyword("as");
+                self.name();
+                self.context();
+            }
+            "from" => {
+                self.expr(0);
+                self.keyword("import");
+                self.separated(|parser| {
+                    parser.name();
+                    if parser.at_word("as") {
+                        parser.keyword("as");
+                        parser.name();
+                    }
+                });
+                self.context();
+            }
+            // `else`, the tags which end blocks, and tags which do not exist (which the parser in
+            // `crate::parse` rejects)
+            _ => {}
+        }
+    }
+
+    /// `with context` or `without context`, if they are there.
+    fn context(&mut self) {
+        if self.at_word("with") || self.at_word("without") {
+            self.bump_next_as(SyntaxKind::Keyword);
+            self.keyword("context");
+        }
+    }
+
+    /// Any number of `| filter`s.
+    fn filters(&mut self) {
+        while self.at_operator("|") {
+            self.bump_next_as(SyntaxKind::Operator);
+            self.expr(ABOVE_PIPE);
+        }
+    }
+
+    /// Parses what `item` does, as many times as there are commas between them.
+    fn separated(&mut self, mut item: impl FnMut(&mut Self)) {
+        loop {
+            item(self);
+            if self.at(SyntaxKind::Comma) {
+                self.bump_next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Parses an expression, whose operators all bind at least as tightly as `min_bp`.
+    fn expr(&mut self, min_bp: u8) {
+        self.skip_whitespace();
+        let start = self.builder.checkpoint();
+
+        let prefix = self
+            .current_token()
+            .filter(|token| matches!(token.kind, SyntaxKind::Operator | SyntaxKind::Ident))
+            .and_then(|token| match token.text {
+                "not" => Some(UnaryOp::Not),
+                "-" => Some(UnaryOp::Neg),
+                "+" => Some(UnaryOp::Pos),
+                _ => None,
+            });
+
+        if let Some(operator) = prefix {
+            let (_, r_bp) = Op::UnaryOp(operator).binding_power().unwrap();
+            self.builder.start_node(SyntaxKind::UnaryExpr.into());
+            self.bump_as(SyntaxKind::Operator);
+            self.expr(r_bp);
+            self.builder.finish_node();
+        } else {
+            self.primary();
+        }
+
+        while let Some(operator) = self.peek_bin_op() {
+            let (l_bp, r_bp) = Op::BinOp(operator).binding_power().unwrap();
+            if l_bp < min_bp {
+                break;
+            }
+            self.builder
+                .start_node_at(start, SyntaxKind::BinExpr.into());
+            self.bump_next_as(SyntaxKind::Operator);
+            self.expr(r_bp);
+            self.builder.finish_node();
+        }
+    }
+
+    /// The binary operator after any whitespace, if there is one.
+    fn peek_bin_op(&self) -> Option<BinOp> {
+        let token = self.peek()?;
+        match token.kind {
+            SyntaxKind::Operator | SyntaxKind::Ident => match Op::parse(token.text) {
+                Ok((Op::BinOp(operator), "")) => Some(operator),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Parses an operand (anything but an operator and its operands).
+    fn primary(&mut self) {
+        let start = self.builder.checkpoint();
+        match self.current() {
+            Some(SyntaxKind::Ident) if matches!(self.text(), "true" | "false") => {
+                self.wrap(SyntaxKind::Literal)
+            }
+            Some(SyntaxKind::Ident) => {
+                self.name();
+                if self.at(SyntaxKind::LParen) {
+                    self.builder.start_node_at(start, SyntaxKind::Call.into());
+                    self.args();
+                    self.builder.finish_node();
+                }
+            }
+            Some(SyntaxKind::Integer | SyntaxKind::Float | SyntaxKind::String) => {
+                self.wrap(SyntaxKind::Literal)
+            }
+            Some(SyntaxKind::LBracket) => {
+                self.builder.start_node(SyntaxKind::List.into());
+                self.bump();
+                self.items_until(SyntaxKind::RBracket, |parser| parser.expr(0));
+                self.builder.finish_node();
+            }
+            Some(SyntaxKind::LBrace) => {
+                self.builder.start_node(SyntaxKind::Dict.into());
+                self.bump();
+                self.items_until(SyntaxKind::RBrace, |parser| {
+                    parser.skip_whitespace();
+                    parser.builder.start_node(SyntaxKind::DictEntry.into());
+                    parser.expr(0);
+                    parser.expect_next(SyntaxKind::Colon, "`:`");
+                    parser.expr(0);
+                    parser.builder.finish_node();
+                });
+                self.builder.finish_node();
+            }
+            Some(SyntaxKind::LParen) => {
+                self.bump();
+                let mut items = 0;
+                let mut commas = 0;
+                while !self.at(SyntaxKind::RParen) && self.starts_expr() {
+                    self.expr(0);
+                    items += 1;
+                    if self.at(SyntaxKind::Comma) {
+                        self.bump_next();
+                        commas += 1;
+                    } else {
+                        break;
+                    }
+                }
+                // as in `Literal::parse`, `(,)` is the empty tuple
+                if items == 0 && self.at(SyntaxKind::Comma) {
+                    self.bump_next();
+                    commas += 1;
+                }
+                let kind = if items == 1 && commas == 0 {
+                    SyntaxKind::Paren
+                } else {
+                    SyntaxKind::Tuple
+                };
+                self.builder.start_node_at(start, kind.into());
+                self.expect_next(SyntaxKind::RParen, "`)`");
+                self.builder.finish_node();
+            }
+            _ => {
+                self.error("expected an expression".to_string());
+                if self.starts_expr() {
+                    self.wrap(SyntaxKind::ErrorNode);
+                }
+            }
+        }
+    }
+
+    /// Parses `(args, key=value)`.
+    fn args(&mut self) {
+        self.skip_whitespace();
+        self.builder.start_node(SyntaxKind::ArgList.into());
+        self.bump();
+        self.items_until(SyntaxKind::RParen, |parser| {
+            let is_kwarg = parser.peek().map(|token| token.kind) == Some(SyntaxKind::Ident)
+                && parser.peek_nth(1).map(|token| token.kind) == Some(SyntaxKind::Eq);
+            if is_kwarg {
+                parser.kwarg();
+            } else {
+                parser.expr(0);
+            }
+        });
+        self.builder.finish_node();
+    }
+
+    /// Parses items separated by commas (which may be followed by one more comma), and then
+    /// `close`.
+    fn items_until(&mut self, close: SyntaxKind, mut item: impl FnMut(&mut Self)) {
+        while !self.at(close) && self.starts_expr() {
+            item(self);
+            if self.at(close) {
+                break;
+            }
+            if !self.at(SyntaxKind::Comma) {
+                break;
+            }
+            self.bump_next();
+        }
+        self.expect_next(close, "a closing bracket");
+    }
+
+    /// `name=value`
+    fn kwarg(&mut self) {
+        self.skip_whitespace();
+        self.builder.start_node(SyntaxKind::Kwarg.into());
+        self.name();
+        if self.at(SyntaxKind::Eq) {
+            self.bump_next();
+            self.expr(0);
+        }
+        self.builder.finish_node();
+    }
+
+    fn name(&mut self) {
+        self.skip_whitespace();
+        if self.current() == Some(SyntaxKind::Ident) {
+            self.wrap(SyntaxKind::Name);
+        } else {
+            self.error("expected a name".to_string());
+        }
+    }
+
+    /// Parses the word `word`, which is part of the syntax of a tag.
+    fn keyword(&mut self, word: &str) {
+        if self.at_word(word) {
+            self.bump_next_as(SyntaxKind::Keyword);
+        } else {
+            self.error(format!("expected `{}`", word));
+        }
+    }
+
+    /// Reports anything which is left in a tag (or an output), and parses the `close` which ends
+    /// it.
+    fn close(&mut self, close: SyntaxKind, what: &str) {
+        self.skip_whitespace();
+        if self
+            .current()
+            .is_some_and(|kind| kind != close && kind.is_code())
+        {
+            self.error(format!("expected {}", what));
+            self.builder.start_node(SyntaxKind::ErrorNode.into());
+            while self
+                .current()
+                .is_some_and(|kind| kind != close && kind.is_code())
+            {
+                self.bump();
+            }
+            self.builder.finish_node();
+        }
+        self.expect(close, what);
+    }
+
+    /// Whether the token after any whitespace could start an expression.
+    fn starts_expr(&self) -> bool {
+        self.peek().is_some_and(|token| {
+            token.kind.is_code()
+                && !matches!(
+                    token.kind,
+                    SyntaxKind::RParen
+                        | SyntaxKind::RBracket
+                        | SyntaxKind::RBrace
+                        | SyntaxKind::Comma
+                        | SyntaxKind::Colon
+                        | SyntaxKind::Eq
+                        | SyntaxKind::OutputClose
+                        | SyntaxKind::TagClose
+                )
+        })
+    }
+
+    /// The keyword of the tag which starts at the current token.
+    fn tag_keyword(&self) -> Option<&'s str> {
+        if self.current() != Some(SyntaxKind::TagOpen) {
+            return None;
+        }
+        self.tokens[self.position + 1..]
+            .iter()
+            .find(|token| token.kind != SyntaxKind::Whitespace)
+            .filter(|token| token.kind == SyntaxKind::Ident)
+            .map(|token| token.text)
+    }
+
+    /// Whether the tag at the current token (with the given keyword) starts a block.
+    fn starts_block(&self, keyword: &str) -> bool {
+        if keyword == "set" {
+            // `{% set x = 1 %}` does not, `{% set x %}...{% endset %}` does
+            return !self.tokens[self.position + 1..]
+                .iter()
+                .take_while(|token| token.kind != SyntaxKind::TagClose && token.kind.is_code())
+                .any(|token| token.kind == SyntaxKind::Eq);
+        }
+        BLOCKS.iter().any(|(start, _, _)| *start == keyword)
+    }
+
+    fn current_token(&self) -> Option<Token<'s>> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn current(&self) -> Option<SyntaxKind> {
+        self.current_token().map(|token| token.kind)
+    }
+
+    fn text(&self) -> &'s str {
+        self.tokens[self.position].text
+    }
+
+    /// The `n`th token (from 0) after the current one which is not whitespace.
+    fn peek_nth(&self, n: usize) -> Option<Token<'s>> {
+        self.tokens[self.position..]
+            .iter()
+            .filter(|token| token.kind != SyntaxKind::Whitespace)
+            .nth(n)
+            .copied()
+    }
+
+    fn peek(&self) -> Option<Token<'s>> {
+        self.peek_nth(0)
+    }
+
+    /// Whether the next token which is not whitespace is of the given kind.
+    fn at(&self, kind: SyntaxKind) -> bool {
+        self.peek().is_some_and(|token| token.kind == kind)
+    }
+
+    fn at_word(&self, word: &str) -> bool {
+        self.peek()
+            .is_some_and(|token| token.kind == SyntaxKind::Ident && token.text == word)
+    }
+
+    fn at_operator(&self, operator: &str) -> bool {
+        self.peek()
+            .is_some_and(|token| token.kind == SyntaxKind::Operator && token.text == operator)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.current() == Some(SyntaxKind::Whitespace) {
+            self.bump();
+        }
+    }
+
+    /// Adds the current token to the tree.
+    fn bump(&mut self) {
+        let kind = self.current().unwrap();
+        self.bump_as(kind);
+    }
+
+    /// Adds the current token to the tree, as a token of the given kind.
+    fn bump_as(&mut self, kind: SyntaxKind) {
+        let token = self.tokens[self.position];
+        self.builder.token(kind.into(), token.text);
+        self.position += 1;
+    }
+
+    /// Adds the next token which is not whitespace (and the whitespace before it) to the tree.
+    fn bump_next(&mut self) {
+        self.skip_whitespace();
+        self.bump();
+    }
+
+    fn bump_next_as(&mut self, kind: SyntaxKind) {
+        self.skip_whitespace();
+        self.bump_as(kind);
+    }
+
+    /// Adds the current token to the tree, in a node of its own.
+    fn wrap(&mut self, kind: SyntaxKind) {
+        self.builder.start_node(kind.into());
+        self.bump();
+        self.builder.finish_node();
+    }
+
+    /// Parses a token of the given kind, or reports that it is missing.
+    fn expect(&mut self, kind: SyntaxKind, what: &str) {
+        if self.current() == Some(kind) {
+            self.bump();
+        } else {
+            self.error(format!("expected {}", what));
+        }
+    }
+
+    fn expect_next(&mut self, kind: SyntaxKind, what: &str) {
+        if self.at(kind) {
+            self.bump_next();
+        } else {
+            self.error(format!("expected {}", what));
+        }
+    }
+
+    /// Reports an error at the next token which is not whitespace.
+    fn error(&mut self, message: String) {
+        let index = self.tokens[self.position..]
+            .iter()
+            .position(|token| token.kind != SyntaxKind::Whitespace)
+            .map_or(self.tokens.len(), |index| self.position + index);
+        let start = self.offsets[index];
+        let len = self
+            .tokens
+            .get(index)
+            .map_or(0.into(), |token| TextSize::of(token.text));
+        self.errors.push(SyntaxError {
+            message,
+            range: TextRange::at(start, len),
+        });
+    }
+}
+
+impl SyntaxKind {
+    /// Whether tokens of this kind are only found inside `{{ }}` and `{% %}`.
+    fn is_code(self) -> bool {
+        !matches!(
+            self,
+            SyntaxKind::Text
+                | SyntaxKind::CommentOpen
+                | SyntaxKind::CommentText
+                | SyntaxKind::CommentClose
+                | SyntaxKind::OutputOpen
+                | SyntaxKind::TagOpen
+        )
+    }
+}
@@ -0,0 +1,148 @@
+//! A formatter which prints parsed templates in a canonical layout.
+//!
+//! Unlike `Display` (which only promises to print something which parses back into the same
+//! template), the formatter also tidies the template up:
+//!
+//! - there is exactly one space inside every delimiter (`{{ x }}`, `{% if x %}`, `{# note #}`)
+//! - every string literal is written with the same quotes
+//! - optionally, block tags which start a line are indented by how deeply they are nested
+//!
+//! Formatting a formatted template gives back the same text.
+//!
+//! ```
+//! use ophelia_logic::{format::Formatter, parse::{Parse, Template}};
+//!
+//! let (template, _) = Template::parse("{%if x%}\n{%set y='a'%}\n{%endif%}").unwrap();
+//! assert_eq!(
+//!     Formatter::new().indent(2).format(&template),
+//!     "{% if x %}\n  {% set y = 'a' %}\n{% endif %}",
+//! );
+//! ```
+
+use crate::parse::{block::Block, expr::Expr, Template};
+
+/// The quotes which string literals are written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quote {
+    /// `'string'`
+    Single,
+    /// `"string"`
+    Double,
+}
+
+/// How templates are formatted.
+#[derive(Debug, Clone, Copy)]
+pub struct Formatter {
+    indent: Option<usize>,
+    quote: Quote,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Formatter {
+    /// A formatter which leaves the whitespace around tags as it is, and writes strings with
+    /// single quotes.
+    pub fn new() -> Self {
+        Self {
+            indent: None,
+            quote: Quote::Single,
+        }
+    }
+
+    /// Indents block tags which start a line by `width` spaces for every block they are nested
+    /// in (the tags which end or divide a block line up with the tag which started it).
+    ///
+    /// This changes the whitespace which the template renders.
+    pub fn indent(self, width: usize) -> Self {
+        Self {
+            indent: Some(width),
+            ..self
+        }
+    }
+
+    pub fn quote(self, quote: Quote) -> Self {
+        Self { quote, ..self }
+    }
+
+    /// Formats `template`.
+    pub fn format(&self, template: &Template) -> String {
+        let mut printer = Printer {
+            formatter: self,
+            output: String::new(),
+            depth: 0,
+        };
+        for block in template.blocks() {
+            block.format(&mut printer);
+        }
+        printer.output
+    }
+}
+
+/// Formats `template` with the default [`Formatter`].
+pub fn format(template: &Template) -> String {
+    Formatter::new().format(template)
+}
+
+/// Writes out the nodes of a template, keeping track of how deeply they are nested.
+#[derive(Debug)]
+pub(crate) struct Printer<'f> {
+    formatter: &'f Formatter,
+    output: String,
+    depth: usize,
+}
+
+impl Printer<'_> {
+    pub(crate) fn raw(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    /// Writes `{% contents %}`, indenting it if it starts a line.
+    pub(crate) fn tag(&mut self, contents: &str) {
+        if let Some(width) = self.formatter.indent {
+            let line_start = self.output.rfind('\n').map_or(0, |index| index + 1);
+            if self.output[line_start..]
+                .chars()
+                .all(|c| c == ' ' || c == '\t')
+            {
+                self.output.truncate(line_start);
+                self.output.push_str(&" ".repeat(width * self.depth));
+            }
+        }
+        self.output.push_str("{% ");
+        self.output.push_str(contents);
+        self.output.push_str(" %}");
+    }
+
+    /// Writes the body of a block, one level deeper than the tag which started it.
+    pub(crate) fn body(&mut self, body: &[Block]) {
+        self.depth += 1;
+        for block in body {
+            block.format(self);
+        }
+        self.depth -= 1;
+    }
+
+    /// Formats an expression.
+    pub(crate) fn expr(&self, expr: &Expr) -> String {
+        self.quoted(expr.to_string())
+    }
+
+    /// Rewrites the strings in `text` (which was written by `Display`) with the configured quotes.
+    pub(crate) fn quoted(&self, text: String) -> String {
+        match self.formatter.quote {
+            Quote::Double => text,
+            // string literals cannot contain quotes of either kind (see `Literal::parse`), so
+            // every quote which `Display` writes delimits a string
+            Quote::Single => text.replace('"', "'"),
+        }
+    }
+}
+
+/// Nodes which can be formatted.
+pub(crate) trait Format {
+    fn format(&self, printer: &mut Printer);
+}
@@ -1,8 +1,16 @@
-#![feature(box_syntax)]
 #![deny(
     missing_debug_implementations,
     missing_copy_implementations,
     unused_must_use
 )]
 
+pub mod codegen;
+pub mod compile;
+pub mod cst;
+pub mod format;
+pub mod lint;
+pub mod meta;
+pub mod optimise;
 pub mod parse;
+pub mod render;
+pub mod visit;
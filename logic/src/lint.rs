@@ -0,0 +1,650 @@
+//! A linter, which finds mistakes (and likely mistakes) in parsed templates.
+//!
+//! Each kind of mistake is a [`Rule`], which has an id (used to refer to it in comments and on the
+//! command line) and a [`Severity`]:
+//!
+//! | id | severity | reports |
+//! |----|----------|---------|
+//! | `unused-variable` | warning | a variable which is `set`, but never read |
+//! | `unused-argument` | warning | a macro parameter which the macro's body never reads |
+//! | `shadowed-loop-variable` | warning | a loop variable with the same name as one of an enclosing loop |
+//! | `dynamic-include` | warning | an `include` of a computed name, without `ignore missing` |
+//! | `safe-on-user-input` | warning | `| safe` applied to a variable which the template did not set |
+//! | `deprecated` | warning | the deprecated names of tests (`equalto`, `greaterthan` and `lessthan`) |
+//! | `unreachable-branch` | warning | an `elif` or `else` after a condition which is always true |
+//! | `unknown-filter` | error | a filter which is neither builtin nor added to the linter |
+//! | `unknown-test` | error | a test which is neither builtin nor added to the linter |
+//!
+//! Names which start with `_` are never reported as unused. A variable is only unused if it is not
+//! read anywhere in the template, since templates which import it can read it too.
+//!
+//! Rules can be turned off in a part of a template with a comment:
+//!
+//! ```text
+//! {# ophelia: allow(unused-variable, unknown-filter) #}
+//! ```
+//!
+//! which applies from where it is to the end of the body it is in (so at the top of a template it
+//! applies to the whole template, and inside a `for` to the rest of the loop).
+//!
+//! ```
+//! use ophelia_logic::{lint::{Linter, Rule}, parse::{Parse, Template}};
+//!
+//! let source = "{% set title = 'a' %}{{ body | markdown }}";
+//! let (template, _) = Template::parse(source).unwrap();
+//! let lints = Linter::new().filter("markdown").lint(source, &template);
+//! assert_eq!(lints.len(), 1);
+//! assert_eq!(lints[0].rule, Rule::UnusedVariable);
+//! assert_eq!(lints[0].span, Some(7..12));
+//! ```
+
+use std::{collections::HashSet, fmt::Display, ops::Range};
+
+use crate::{
+    parse::ast::{
+        BinOp, BinOpExpr, Block, Expr, Filter, ForStmt, Ident, If, Import, Include, Items, Literal,
+        Macro, Set, SetData, SetTarget, Template, With,
+    },
+    render::{Value, BUILTIN_FILTERS, BUILTIN_TESTS},
+    visit::{
+        walk_bin_op, walk_block, walk_expr, walk_for, walk_if, walk_import, walk_literal,
+        walk_macro, walk_set, walk_set_data, walk_with, Visit,
+    },
+};
+
+/// A kind of mistake which the linter finds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Rule {
+    UnusedVariable,
+    UnusedArgument,
+    ShadowedLoopVariable,
+    DynamicInclude,
+    SafeOnUserInput,
+    Deprecated,
+    UnreachableBranch,
+    UnknownFilter,
+    UnknownTest,
+}
+
+impl Rule {
+    pub const ALL: &'static [Rule] = &[
+        Rule::UnusedVariable,
+        Rule::UnusedArgument,
+        Rule::ShadowedLoopVariable,
+        Rule::DynamicInclude,
+        Rule::SafeOnUserInput,
+        Rule::Deprecated,
+        Rule::UnreachableBranch,
+        Rule::UnknownFilter,
+        Rule::UnknownTest,
+    ];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::UnusedArgument => "unused-argument",
+            Rule::ShadowedLoopVariable => "shadowed-loop-variable",
+            Rule::DynamicInclude => "dynamic-include",
+            Rule::SafeOnUserInput => "safe-on-user-input",
+            Rule::Deprecated => "deprecated",
+            Rule::UnreachableBranch => "unreachable-branch",
+            Rule::UnknownFilter => "unknown-filter",
+            Rule::UnknownTest => "unknown-test",
+        }
+    }
+
+    /// The rule whose id is `id`, if there is one.
+    pub fn from_id(id: &str) -> Option<Rule> {
+        Rule::ALL.iter().copied().find(|rule| rule.id() == id)
+    }
+
+    /// The severity of the rule's lints, unless the linter was told otherwise. Mistakes which make
+    /// rendering fail are errors, and the rest are warnings.
+    pub fn default_severity(self) -> Severity {
+        match self {
+            Rule::UnknownFilter | Rule::UnknownTest => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// A mistake which the linter found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+    /// Where the mistake is in the source, in bytes (if the linter could tell).
+    pub span: Option<Range<usize>>,
+}
+
+/// The tests which have been renamed, and their new names.
+const DEPRECATED_TESTS: &[(&str, &str)] =
+    &[("equalto", "eq"), ("greaterthan", "gt"), ("lessthan", "lt")];
+
+/// Which rules are checked (and how severe they are), and which filters and tests exist.
+#[derive(Debug, Clone, Default)]
+pub struct Linter {
+    /// The rules whose severity was changed, and their new severity (or `None` if they are not
+    /// checked at all).
+    severities: Vec<(Rule, Option<Severity>)>,
+    filters: Vec<String>,
+    tests: Vec<String>,
+}
+
+impl Linter {
+    /// A linter which checks every rule, and knows about the builtin filters and tests.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn severity(mut self, rule: Rule, severity: Severity) -> Self {
+        self.severities.push((rule, Some(severity)));
+        self
+    }
+
+    /// Stops checking `rule`.
+    pub fn allow(mut self, rule: Rule) -> Self {
+        self.severities.push((rule, None));
+        self
+    }
+
+    /// Adds a filter, which templates are rendered with (see `Context::add_filter`).
+    pub fn filter(mut self, name: impl Into<String>) -> Self {
+        self.filters.push(name.into());
+        self
+    }
+
+    /// Adds a test, which templates are rendered with (see `Context::add_test`).
+    pub fn test(mut self, name: impl Into<String>) -> Self {
+        self.tests.push(name.into());
+        self
+    }
+
+    /// Lints `template`, which was parsed from `source` (which is used to tell where each mistake
+    /// is). Lints are ordered by where they are, and those whose place is not known come first.
+    pub fn lint<'ast>(&self, source: &'ast str, template: &'ast Template<'ast>) -> Vec<Lint> {
+        let mut reads = Reads::default();
+        reads.visit_template(template);
+        let mut bindings = Bindings::default();
+        bindings.visit_template(template);
+
+        let mut linter = Linting {
+            linter: self,
+            source,
+            lints: vec![],
+            allowed: vec![],
+            loops: vec![],
+            unused: vec![],
+            safe: vec![],
+        };
+        linter.visit_template(template);
+
+        let mut lints = linter.lints;
+        lints.extend(
+            linter
+                .unused
+                .into_iter()
+                .filter(|(name, _)| !reads.names.contains(name))
+                .map(|(_, lint)| lint),
+        );
+        lints.extend(
+            linter
+                .safe
+                .into_iter()
+                .filter(|(names, _)| names.iter().any(|name| !bindings.names.contains(name)))
+                .map(|(_, lint)| lint),
+        );
+        lints.sort_by_key(|lint| lint.span.as_ref().map(|span| span.start));
+        lints
+    }
+
+    fn severity_of(&self, rule: Rule) -> Option<Severity> {
+        self.severities
+            .iter()
+            .rev()
+            .find(|(changed, _)| *changed == rule)
+            .map_or(Some(rule.default_severity()), |(_, severity)| *severity)
+    }
+}
+
+/// Walks a template, reporting the mistakes in it.
+struct Linting<'l, 'ast> {
+    linter: &'l Linter,
+    source: &'ast str,
+    lints: Vec<Lint>,
+    /// The rules which comments allow, in each of the bodies which are being visited.
+    allowed: Vec<Vec<Rule>>,
+    /// The variables of each of the loops which are being visited.
+    loops: Vec<Vec<&'ast str>>,
+    /// Variables which were set, which are unused if they are never read.
+    unused: Vec<(&'ast str, Lint)>,
+    /// The variables read by expressions which `safe` was applied to, which are user input if
+    /// any of them is never bound by the template.
+    safe: Vec<(Vec<&'ast str>, Lint)>,
+}
+
+impl<'ast> Linting<'_, 'ast> {
+    /// The lint for a mistake at `span` (or `None` if `rule` is not checked here).
+    fn lint(
+        &self,
+        rule: Rule,
+        span: Option<Range<usize>>,
+        message: impl Into<String>,
+    ) -> Option<Lint> {
+        if self
+            .allowed
+            .iter()
+            .flatten()
+            .any(|allowed| *allowed == rule)
+        {
+            return None;
+        }
+        Some(Lint {
+            rule,
+            severity: self.linter.severity_of(rule)?,
+            message: message.into(),
+            span,
+        })
+    }
+
+    fn report(&mut self, rule: Rule, span: Option<Range<usize>>, message: impl Into<String>) {
+        if let Some(lint) = self.lint(rule, span, message) {
+            self.lints.push(lint);
+        }
+    }
+
+    fn span(&self, text: &str) -> Option<Range<usize>> {
+        span(self.source, text)
+    }
+
+    /// Where the first thing which `visit` visits (of those whose place is known) is.
+    fn locate(&self, visit: impl FnOnce(&mut Locate<'_>)) -> Option<Range<usize>> {
+        let mut locate = Locate {
+            source: self.source,
+            span: None,
+        };
+        visit(&mut locate);
+        locate.span
+    }
+
+    fn check_filter(&mut self, filter: &'ast Expr<'ast>) {
+        if let Expr::Ident(name) | Expr::FunctionCall(name, ..) = filter {
+            let known = BUILTIN_FILTERS.iter().any(|b| b.name == name.name())
+                || self.linter.filters.iter().any(|f| f == name.name());
+            if !known {
+                self.report(
+                    Rule::UnknownFilter,
+                    self.span(name.name()),
+                    format!("there is no filter called `{}`", name),
+                );
+            }
+        }
+    }
+
+    fn check_test(&mut self, test: &'ast Expr<'ast>) {
+        // (`true` and `false` are literals, and always tests)
+        if let Expr::Ident(name) | Expr::FunctionCall(name, ..) = test {
+            let known = BUILTIN_TESTS.iter().any(|b| b.name == name.name())
+                || self.linter.tests.iter().any(|t| t == name.name());
+            if !known {
+                self.report(
+                    Rule::UnknownTest,
+                    self.span(name.name()),
+                    format!("there is no test called `{}`", name),
+                );
+            }
+            if let Some((_, new)) = DEPRECATED_TESTS.iter().find(|(old, _)| *old == name.name()) {
+                self.report(
+                    Rule::Deprecated,
+                    self.span(name.name()),
+                    format!("the `{}` test is deprecated; use `{}` instead", name, new),
+                );
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Linting<'_, 'ast> {
+    fn visit_body(&mut self, body: &'ast [Block<'ast>]) {
+        self.allowed.push(vec![]);
+        for block in body {
+            if let Block::Comment(comment) = block {
+                let allowed = self.allowed.last_mut().unwrap();
+                allowed.extend(allowed_by(comment));
+            }
+            self.visit_block(block);
+        }
+        self.allowed.pop();
+    }
+
+    fn visit_for(&mut self, stmt: &'ast ForStmt<'ast>) {
+        for ident in &stmt.idents_of_iter {
+            if self
+                .loops
+                .iter()
+                .flatten()
+                .any(|name| *name == ident.name())
+            {
+                self.report(
+                    Rule::ShadowedLoopVariable,
+                    self.span(ident.name()),
+                    format!("`{}` hides the variable of an enclosing loop", ident),
+                );
+            }
+        }
+        self.loops
+            .push(stmt.idents_of_iter.iter().map(Ident::name).collect());
+        walk_for(self, stmt);
+        self.loops.pop();
+    }
+
+    fn visit_if(&mut self, r#if: &'ast If<'ast>) {
+        let mut always = always_true(&r#if.if_branch.condition);
+        for branch in &r#if.elif_branches {
+            if always {
+                let span = self.locate(|locate| {
+                    locate.visit_expr(&branch.condition);
+                    locate.visit_body(&branch.body);
+                });
+                self.report(
+                    Rule::UnreachableBranch,
+                    span,
+                    "this `elif` is never reached, because an earlier condition is always true",
+                );
+            }
+            always = always || always_true(&branch.condition);
+        }
+        if let (true, Some(r#else)) = (always, &r#if.else_branch) {
+            let span = self.locate(|locate| locate.visit_body(&r#else.body));
+            self.report(
+                Rule::UnreachableBranch,
+                span,
+                "this `else` is never reached, because an earlier condition is always true",
+            );
+        }
+        walk_if(self, r#if);
+    }
+
+    fn visit_macro(&mut self, r#macro: &'ast Macro<'ast>) {
+        let mut reads = Reads::default();
+        reads.visit_body(&r#macro.body);
+        let params = r#macro
+            .args
+            .iter()
+            .chain(r#macro.kwargs.iter().map(|(name, _)| name));
+        for param in params {
+            if !param.name().starts_with('_') && !reads.names.contains(param.name()) {
+                self.report(
+                    Rule::UnusedArgument,
+                    self.span(param.name()),
+                    format!(
+                        "the `{}` macro never uses its `{}` parameter",
+                        r#macro.name, param
+                    ),
+                );
+            }
+        }
+        walk_macro(self, r#macro);
+    }
+
+    fn visit_filter(&mut self, filter: &'ast Filter<'ast>) {
+        if !BUILTIN_FILTERS.iter().any(|b| b.name == filter.name.name())
+            && !self.linter.filters.iter().any(|f| f == filter.name.name())
+        {
+            self.report(
+                Rule::UnknownFilter,
+                self.span(filter.name.name()),
+                format!("there is no filter called `{}`", filter.name),
+            );
+        }
+        self.visit_body(&filter.body);
+    }
+
+    fn visit_set(&mut self, set: &'ast Set<'ast>) {
+        for target in &set.targets {
+            if let SetTarget::Ident(name) = target {
+                if name.name().starts_with('_') {
+                    continue;
+                }
+                let message = format!("`{}` is set, but never used", name);
+                if let Some(lint) = self.lint(Rule::UnusedVariable, self.span(name.name()), message)
+                {
+                    self.unused.push((name.name(), lint));
+                }
+            }
+        }
+        walk_set(self, set);
+    }
+
+    fn visit_set_data(&mut self, data: &'ast SetData<'ast>) {
+        if let SetData::Block(_, filters) = data {
+            for filter in filters {
+                self.check_filter(filter);
+            }
+        }
+        walk_set_data(self, data);
+    }
+
+    fn visit_include(&mut self, include: &'ast Include<'ast>) {
+        let constant = match &include.files {
+            Expr::Literal(Literal::String(_)) => true,
+            Expr::Literal(Literal::List(names) | Literal::Tuple(names)) => {
+                names.iter().all(|name| matches!(name, Literal::String(_)))
+            }
+            _ => false,
+        };
+        if !constant && !include.ignore_missing {
+            let span = self.locate(|locate| locate.visit_expr(&include.files));
+            self.report(
+                Rule::DynamicInclude,
+                span,
+                "the name of the included template is computed, so it may not exist \
+                 (add `ignore missing` if that is expected)",
+            );
+        }
+        self.visit_expr(&include.files);
+    }
+
+    fn visit_bin_op(&mut self, expr: &'ast BinOpExpr<'ast>) {
+        match expr.operator {
+            BinOp::Pipe => {
+                self.check_filter(&expr.arg2);
+                if let Expr::Ident(name) | Expr::FunctionCall(name, ..) = &expr.arg2 {
+                    if name.name() == "safe" {
+                        let mut reads = Reads::default();
+                        reads.visit_expr(&expr.arg1);
+                        let message = format!(
+                            "`{}` may come from a user, so marking it as safe may let them \
+                             inject markup",
+                            expr.arg1
+                        );
+                        let span = self.span(name.name());
+                        if let Some(lint) = self.lint(Rule::SafeOnUserInput, span, message) {
+                            self.safe.push((reads.names.into_iter().collect(), lint));
+                        }
+                    }
+                }
+            }
+            BinOp::Is => self.check_test(&expr.arg2),
+            _ => {}
+        }
+        walk_bin_op(self, expr);
+    }
+}
+
+/// The rules which a comment allows, if it is a `ophelia: allow(...)` comment.
+fn allowed_by(comment: &str) -> Vec<Rule> {
+    let rules = comment
+        .trim()
+        .strip_prefix("ophelia:")
+        .map(str::trim_start)
+        .and_then(|comment| comment.strip_prefix("allow("))
+        .and_then(|comment| comment.strip_suffix(')'));
+    rules
+        .into_iter()
+        .flat_map(|rules| rules.split(','))
+        .filter_map(|id| Rule::from_id(id.trim()))
+        .collect()
+}
+
+/// Whether `condition` is a constant which is truthy.
+fn always_true(condition: &Expr) -> bool {
+    match condition.clone().optimise() {
+        Expr::Literal(literal) => Value::from(&literal).is_truthy(),
+        _ => false,
+    }
+}
+
+/// Where `text` is in `source`, if it is a part of it (the nodes which a parsed template is made
+/// of borrow from its source, unless they had to be changed).
+fn span(source: &str, text: &str) -> Option<Range<usize>> {
+    let start = (text.as_ptr() as usize).checked_sub(source.as_ptr() as usize)?;
+    let end = start + text.len();
+    (end <= source.len()).then_some(start..end)
+}
+
+/// Finds the first name, string or text whose place in the source is known.
+struct Locate<'s> {
+    source: &'s str,
+    span: Option<Range<usize>>,
+}
+
+impl<'ast> Visit<'ast> for Locate<'_> {
+    fn visit_block(&mut self, block: &'ast Block<'ast>) {
+        match block {
+            Block::RawText(text) | Block::Comment(text) if self.span.is_none() => {
+                self.span = span(self.source, text);
+            }
+            block => walk_block(self, block),
+        }
+    }
+
+    fn visit_literal(&mut self, literal: &'ast Literal<'ast>) {
+        match literal {
+            Literal::String(string) if self.span.is_none() => {
+                self.span = span(self.source, string);
+            }
+            literal => walk_literal(self, literal),
+        }
+    }
+
+    fn visit_ident(&mut self, ident: &'ast Ident<'ast>) {
+        if self.span.is_none() {
+            self.span = span(self.source, ident.name());
+        }
+    }
+}
+
+/// Collects the names of the variables which are read (but not those of filters, tests or
+/// attributes).
+#[derive(Default)]
+struct Reads<'ast> {
+    names: HashSet<&'ast str>,
+}
+
+impl<'ast> Visit<'ast> for Reads<'ast> {
+    fn visit_expr(&mut self, expr: &'ast Expr<'ast>) {
+        match expr {
+            Expr::Ident(name) => {
+                self.names.insert(name.name());
+            }
+            Expr::FunctionCall(name, ..) => {
+                self.names.insert(name.name());
+                walk_expr(self, expr);
+            }
+            _ => walk_expr(self, expr),
+        }
+    }
+
+    fn visit_bin_op(&mut self, expr: &'ast BinOpExpr<'ast>) {
+        match (expr.operator, &expr.arg2) {
+            // only the arguments of filters, tests and methods are read
+            (BinOp::Pipe | BinOp::Is | BinOp::Dot, Expr::FunctionCall(_, args, kwargs)) => {
+                self.visit_expr(&expr.arg1);
+                for arg in args.iter().chain(kwargs.iter().map(|(_, arg)| arg)) {
+                    self.visit_expr(arg);
+                }
+            }
+            (BinOp::Pipe | BinOp::Is | BinOp::Dot, _) => self.visit_expr(&expr.arg1),
+            _ => walk_bin_op(self, expr),
+        }
+    }
+
+    fn visit_set_target(&mut self, target: &'ast SetTarget<'ast>) {
+        // `{% set ns.attr = ... %}` reads `ns`
+        if let SetTarget::Attr(name, _) = target {
+            self.names.insert(name.name());
+        }
+    }
+}
+
+/// Collects the names which are bound anywhere in a template.
+#[derive(Default)]
+struct Bindings<'ast> {
+    names: HashSet<&'ast str>,
+}
+
+impl<'ast> Visit<'ast> for Bindings<'ast> {
+    fn visit_for(&mut self, stmt: &'ast ForStmt<'ast>) {
+        self.names
+            .extend(stmt.idents_of_iter.iter().map(Ident::name));
+        walk_for(self, stmt);
+    }
+
+    fn visit_macro(&mut self, r#macro: &'ast Macro<'ast>) {
+        self.names.insert(r#macro.name.name());
+        self.names.extend(r#macro.args.iter().map(Ident::name));
+        self.names
+            .extend(r#macro.kwargs.iter().map(|(name, _)| name.name()));
+        walk_macro(self, r#macro);
+    }
+
+    fn visit_set_target(&mut self, target: &'ast SetTarget<'ast>) {
+        if let SetTarget::Ident(name) = target {
+            self.names.insert(name.name());
+        }
+    }
+
+    fn visit_import(&mut self, import: &'ast Import<'ast>) {
+        match &import.items {
+            Items::All(alias) => {
+                self.names.insert(alias.name());
+            }
+            Items::List(items) => {
+                for (item, alias) in items {
+                    self.names.insert(alias.as_ref().unwrap_or(item).name());
+                }
+            }
+        }
+        walk_import(self, import);
+    }
+
+    fn visit_with(&mut self, with: &'ast With<'ast>) {
+        self.names
+            .extend(with.bindings.iter().map(|(name, _)| name.name()));
+        walk_with(self, with);
+    }
+}
@@ -0,0 +1,234 @@
+//! Questions about parsed templates which can be answered without rendering them (like Jinja's
+//! `jinja2.meta`): which variables a template expects to be given, and which other templates it
+//! needs.
+//!
+//! ```
+//! use ophelia_logic::{meta, parse::{Parse, Template}};
+//!
+//! let (template, _) = Template::parse(
+//!     "{% import 'forms.html' as forms %}{% for item in items %}{{ forms.input(item, size) }}\
+//!      {% endfor %}{% include 'footer.html' %}",
+//! )
+//! .unwrap();
+//! assert_eq!(
+//!     meta::undeclared_variables(&template).into_iter().collect::<Vec<_>>(),
+//!     ["items", "size"]
+//! );
+//! assert_eq!(
+//!     meta::referenced_templates(&template),
+//!     [Some("forms.html"), Some("footer.html")]
+//! );
+//! ```
+//!
+//! (Templates cannot `extends` others yet, so imports and includes are the only references.)
+
+use std::collections::{BTreeSet, HashSet};
+
+use crate::{
+    parse::ast::{
+        BinOp, BinOpExpr, Expr, ForStmt, Import, Include, Items, Literal, Macro, Set, SetTarget,
+        Template, With,
+    },
+    render::builtins,
+    visit::{walk_bin_op, walk_expr, walk_template, Visit},
+};
+
+/// The variables which `template` reads before (or without) binding them itself, so which have to
+/// be in the context it is rendered with (unless it is fine for them to be undefined).
+///
+/// Variables are bound by `set`, `for`, macros (and their parameters), `with` and imports, in the
+/// same scopes as when rendering. Macros' bodies see everything which the template binds around
+/// them, since they can be called after it has been bound. Builtin globals (such as `namespace`)
+/// are not included.
+pub fn undeclared_variables<'ast>(template: &'ast Template<'ast>) -> BTreeSet<&'ast str> {
+    let mut undeclared = Undeclared {
+        globals: builtins::globals()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect(),
+        scopes: vec![],
+        found: BTreeSet::new(),
+    };
+    undeclared.visit_template(template);
+    undeclared.found
+}
+
+/// The names of the templates which `template` imports or includes, in the order in which they
+/// first appear. A name which is computed while rendering is `None` (which appears once, however
+/// many there are).
+pub fn referenced_templates<'ast>(template: &'ast Template<'ast>) -> Vec<Option<&'ast str>> {
+    let mut references = References { names: vec![] };
+    references.visit_template(template);
+    references.names
+}
+
+/// The variables which are bound in a scope, and the macros defined in it (whose bodies are
+/// visited when the scope ends, once everything they can see has been bound).
+struct Scope<'ast> {
+    names: HashSet<&'ast str>,
+    macros: Vec<&'ast Macro<'ast>>,
+}
+
+struct Undeclared<'ast> {
+    globals: HashSet<&'static str>,
+    scopes: Vec<Scope<'ast>>,
+    found: BTreeSet<&'ast str>,
+}
+
+impl<'ast> Undeclared<'ast> {
+    fn read(&mut self, name: &'ast str) {
+        let bound = self.globals.contains(name)
+            || self.scopes.iter().any(|scope| scope.names.contains(name));
+        if !bound {
+            self.found.insert(name);
+        }
+    }
+
+    fn bind(&mut self, name: &'ast str) {
+        self.scopes
+            .last_mut()
+            .expect("templates are visited in a scope")
+            .names
+            .insert(name);
+    }
+
+    /// Starts a new scope, in which `names` are bound.
+    fn push(&mut self, names: impl IntoIterator<Item = &'ast str>) {
+        self.scopes.push(Scope {
+            names: names.into_iter().collect(),
+            macros: vec![],
+        });
+    }
+
+    /// Ends the innermost scope, after visiting the bodies of the macros defined in it.
+    fn pop(&mut self) {
+        let macros = std::mem::take(&mut self.scopes.last_mut().unwrap().macros);
+        for r#macro in macros {
+            self.push(r#macro.args.iter().map(|arg| arg.name()));
+            // defaults can refer to the parameters before them
+            for (name, default) in &r#macro.kwargs {
+                self.visit_expr(default);
+                self.bind(name.name());
+            }
+            self.visit_body(&r#macro.body);
+            self.pop();
+        }
+        self.scopes.pop();
+    }
+}
+
+impl<'ast> Visit<'ast> for Undeclared<'ast> {
+    fn visit_template(&mut self, template: &'ast Template<'ast>) {
+        self.push(None);
+        walk_template(self, template);
+        self.pop();
+    }
+
+    fn visit_for(&mut self, stmt: &'ast ForStmt<'ast>) {
+        self.visit_expr(&stmt.in_expr);
+        self.push(stmt.idents_of_iter.iter().map(|ident| ident.name()));
+        self.visit_body(&stmt.body);
+        self.pop();
+    }
+
+    fn visit_macro(&mut self, r#macro: &'ast Macro<'ast>) {
+        self.bind(r#macro.name.name());
+        self.scopes.last_mut().unwrap().macros.push(r#macro);
+    }
+
+    fn visit_set(&mut self, set: &'ast Set<'ast>) {
+        // the value is evaluated before anything is bound
+        self.visit_set_data(&set.data);
+        for target in &set.targets {
+            match target {
+                SetTarget::Ident(name) => self.bind(name.name()),
+                SetTarget::Attr(namespace, _) => self.read(namespace.name()),
+            }
+        }
+    }
+
+    fn visit_import(&mut self, import: &'ast Import<'ast>) {
+        self.visit_expr(&import.file);
+        match &import.items {
+            Items::All(alias) => self.bind(alias.name()),
+            Items::List(items) => {
+                for (item, alias) in items {
+                    self.bind(alias.as_ref().unwrap_or(item).name());
+                }
+            }
+        }
+    }
+
+    fn visit_with(&mut self, with: &'ast With<'ast>) {
+        for (_, expr) in &with.bindings {
+            self.visit_expr(expr);
+        }
+        self.push(with.bindings.iter().map(|(name, _)| name.name()));
+        self.visit_body(&with.body);
+        self.pop();
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr<'ast>) {
+        match expr {
+            Expr::Ident(name) => self.read(name.name()),
+            Expr::FunctionCall(name, ..) => {
+                self.read(name.name());
+                walk_expr(self, expr);
+            }
+            _ => walk_expr(self, expr),
+        }
+    }
+
+    fn visit_bin_op(&mut self, expr: &'ast BinOpExpr<'ast>) {
+        match (expr.operator, &expr.arg2) {
+            // the names of filters, tests, attributes and methods are not variables (but their
+            // arguments are read)
+            (BinOp::Pipe | BinOp::Is | BinOp::Dot, Expr::FunctionCall(_, args, kwargs)) => {
+                self.visit_expr(&expr.arg1);
+                for arg in args.iter().chain(kwargs.iter().map(|(_, arg)| arg)) {
+                    self.visit_expr(arg);
+                }
+            }
+            (BinOp::Pipe | BinOp::Is | BinOp::Dot, _) => self.visit_expr(&expr.arg1),
+            _ => walk_bin_op(self, expr),
+        }
+    }
+}
+
+struct References<'ast> {
+    names: Vec<Option<&'ast str>>,
+}
+
+impl<'ast> References<'ast> {
+    fn add(&mut self, name: Option<&'ast str>) {
+        if !self.names.contains(&name) {
+            self.names.push(name);
+        }
+    }
+
+    /// Adds the templates which `files` names (a name, or a list of names to choose from).
+    fn add_all(&mut self, files: &'ast Expr<'ast>) {
+        match files {
+            Expr::Literal(Literal::List(names) | Literal::Tuple(names)) => {
+                for name in names {
+                    match name {
+                        Literal::String(name) => self.add(Some(name)),
+                        _ => self.add(None),
+                    }
+                }
+            }
+            Expr::Literal(Literal::String(name)) => self.add(Some(name)),
+            _ => self.add(None),
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for References<'ast> {
+    fn visit_include(&mut self, include: &'ast Include<'ast>) {
+        self.add_all(&include.files);
+    }
+
+    fn visit_import(&mut self, import: &'ast Import<'ast>) {
+        self.add_all(&import.file);
+    }
+}
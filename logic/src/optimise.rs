@@ -0,0 +1,42 @@
+//! An optimisation pass over parsed templates.
+//!
+//! The pass rewrites a template into one which renders exactly the same output (or fails with
+//! the same error), but does less work when it is rendered:
+//!
+//! - operators whose operands are all constants are evaluated ahead of time (`{{ 60 * 60 }}`
+//!   becomes `{{ 3600 }}`), unless evaluating them fails – in which case they are left for the
+//!   renderer to report
+//! - branches of an `if` whose condition is constant are either removed, or (when they would
+//!   always be taken) replace the `if` altogether
+//! - comments are removed, and the raw text on either side of them merged
+//!
+//! Because rendering does not depend on the layout of a template, the optimised template is
+//! usually not the one which a user would have written; it is only meant to be rendered (or
+//! compiled).
+
+use crate::parse::{block::Block, Template};
+
+/// Optimises `template`.
+pub fn optimise(template: Template<'_>) -> Template<'_> {
+    template.optimise()
+}
+
+/// Optimises the body of a template or a statement.
+pub(crate) fn optimise_body(body: Vec<Block<'_>>) -> Vec<Block<'_>> {
+    let mut optimised = Vec::with_capacity(body.len());
+    for block in body {
+        block.optimise_into(&mut optimised);
+    }
+
+    let mut merged: Vec<Block> = Vec::with_capacity(optimised.len());
+    for block in optimised {
+        match (merged.last_mut(), block) {
+            (Some(Block::RawText(text)), Block::RawText(next)) => {
+                text.to_mut().push_str(&next);
+            }
+            (_, Block::RawText(text)) if text.is_empty() => {}
+            (_, block) => merged.push(block),
+        }
+    }
+    merged
+}
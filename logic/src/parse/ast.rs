@@ -0,0 +1,30 @@
+//! The nodes which a parsed [`Template`] is made of.
+//!
+//! Every node borrows from the source it was parsed from (hence the `'i` lifetime). Nodes can be
+//! inspected through their accessors, traversed with [`crate::visit`], and built by hand with their
+//! `new` constructors (a hand-built template renders like a parsed one).
+//!
+//! This module is the stable path to the nodes. The enums here may gain variants as the template
+//! language grows, so they are `#[non_exhaustive]`.
+
+pub use super::{
+    block::Block,
+    expr::{
+        op::{BinOp, BinOpExpr, CompareExpr, UnaryOp, UnaryOpExpr},
+        Expr,
+    },
+    filter::Filter,
+    ident::Ident,
+    import::{Import, Items},
+    include::Include,
+    literal::Literal,
+    r#do::Do,
+    r#else::Else,
+    r#for::ForStmt,
+    r#if::{If, IfBranch},
+    r#macro::Macro,
+    set::{Set, SetData, SetTarget},
+    stmt::Stmt,
+    template::Template,
+    with::With,
+};
@@ -1,67 +1,72 @@
-use std::fmt::Display;
+use std::{borrow::Cow, fmt::Display};
 
-use crate::parse::{skip, up_to_optional, ParseError};
+use crate::{
+    compile::{Compile, Compiler, Instruction},
+    format::{Format, Printer},
+    parse::{peek_multiple_bool, skip, up_to_optional, ParseError, ParseResult},
+    render::{Context, Output, Render, RenderResult},
+};
 
-use super::{expr::Expr, ignore_whitespace, stmt::Stmt, Parse};
+use super::{expr::Expr, stmt::Stmt, Parse};
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 
 pub enum Block<'i> {
     /// Raw text, to be output as-is
-    RawText(&'i str),
+    RawText(Cow<'i, str>),
     /// An expression
     Expr(Expr<'i>),
     /// A statement
     Stmt(Stmt<'i>),
     /// A comment
-    Comment(&'i str),
+    Comment(Cow<'i, str>),
 }
 
 impl<'i> Parse<'i> for Block<'i> {
     fn parse_optional(input: &'i str) -> super::ParseResult<Option<Self>> {
-        ignore_whitespace(input, |input| {
-            if let Some(indicator) = input.get(0..2) {
-                match indicator {
-                    "{%" => Stmt::parse(input).map(|(a, b)| (Some(Self::Stmt(a)), b)),
-                    "{{" => Expr::parse(input).map(|(a, b)| (Some(Self::Expr(a)), b)),
-                    "{#" => skip(input, 2, |input| {
-                        let (comment, rest) = up_to_optional(input, &["#}"])?;
-
-                        let comment = match comment {
-                            Some(t) => t,
-                            None => return Ok((None, rest)),
-                        };
-
-                        if rest.len() < 2 {
-                            return Err(ParseError::UnexpectedEndOfInput);
-                        }
-
-                        if rest.get(0..2).unwrap() != "#}" {
-                            return Err(ParseError::UnexpectedToken(rest.get(0..2).unwrap()));
-                        }
-
-                        skip(rest, 2, |input| Ok((Some(Self::Comment(comment)), input)))
-                    }),
-                    _ => {
-                        let (raw_string, rest) = up_to_optional(input, &["{%", "{{", "{#"])?;
-
-                        let raw_string = match raw_string {
-                            Some(t) => t,
-                            None => return Ok((Some(Self::RawText(input)), "")),
-                        };
-
-                        Ok((Some(Self::RawText(raw_string)), rest))
+        if let Some(indicator) = input.get(0..2) {
+            match indicator {
+                "{%" => Stmt::parse(input).map(|(a, b)| (Some(Self::Stmt(a)), b)),
+                "{{" => Expr::parse(input).map(|(a, b)| (Some(Self::Expr(a)), b)),
+                "{#" => skip(input, 2, |input| {
+                    let (comment, rest) = up_to_optional(input, &["#}"])?;
+
+                    let comment = match comment {
+                        Some(t) => t,
+                        None => return Ok((None, rest)),
+                    };
+
+                    if rest.len() < 2 {
+                        return Err(ParseError::UnexpectedEndOfInput);
+                    }
+
+                    if rest.get(0..2).unwrap() != "#}" {
+                        return Err(ParseError::UnexpectedToken(rest.get(0..2).unwrap()));
                     }
-                }
-            } else {
-                let (raw_string, rest) = up_to_optional(input, &["{%", "{{", "{#"])?;
 
-                match raw_string {
-                    Some(s) => Ok((Some(Self::RawText(s)), rest)),
-                    None => Ok((Some(Self::RawText(input)), "")),
+                    // (a comment may end the template, so there need not be anything after it)
+                    Ok((Some(Self::Comment(comment.into())), rest.get(2..).unwrap()))
+                }),
+                _ => {
+                    let (raw_string, rest) = up_to_optional(input, &["{%", "{{", "{#"])?;
+
+                    let raw_string = match raw_string {
+                        Some(t) => t,
+                        None => return Ok((Some(Self::RawText(input.into())), "")),
+                    };
+
+                    Ok((Some(Self::RawText(raw_string.into())), rest))
                 }
             }
-        })
+        } else {
+            let (raw_string, rest) = up_to_optional(input, &["{%", "{{", "{#"])?;
+
+            match raw_string {
+                Some(s) => Ok((Some(Self::RawText(s.into())), rest)),
+                None => Ok((Some(Self::RawText(input.into())), "")),
+            }
+        }
     }
 
     fn parse(input: &'i str) -> super::ParseResult<Self> {
@@ -76,11 +81,38 @@ impl<'i> Parse<'i> for Block<'i> {
     }
 }
 
+/// Parses the body of a statement: every block up to (but not including) the first of the
+/// `{% <end>` tags which can close it.
+pub(crate) fn parse_body<'i>(mut input: &'i str, ends: &[&str]) -> ParseResult<'i, Vec<Block<'i>>> {
+    let mut body = vec![];
+
+    // leading whitespace belongs to the body, so the tag has to start right here
+    while !(input.starts_with("{%")
+        && ends
+            .iter()
+            .any(|end| peek_multiple_bool(&input[2..], &[end])))
+    {
+        if input.is_empty() {
+            return Err(ParseError::UnexpectedEndOfInput);
+        }
+
+        let (block, rest) = Block::parse(input)?;
+        body.push(block);
+        input = rest;
+    }
+
+    Ok((body, input))
+}
+
 impl<'i> Display for Block<'i> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Block::RawText(raw) => raw.fmt(f),
-            Block::Expr(e) => e.fmt(f),
+            Block::Expr(e) => {
+                f.write_str("{{ ")?;
+                e.fmt(f)?;
+                f.write_str(" }}")
+            }
             Block::Stmt(s) => s.fmt(f),
             Block::Comment(c) => {
                 f.write_str("{#")?;
@@ -90,3 +122,129 @@ impl<'i> Display for Block<'i> {
         }
     }
 }
+
+impl<'i> Block<'i> {
+    /// Optimises this block, appending the result (which may be any number of blocks) to `out`.
+    pub(crate) fn optimise_into(self, out: &mut Vec<Block<'i>>) {
+        match self {
+            Block::RawText(_) => out.push(self),
+            Block::Expr(e) => out.push(Block::Expr(e.optimise())),
+            Block::Stmt(s) => s.optimise_into(out),
+            Block::Comment(_) => {}
+        }
+    }
+}
+
+impl Block<'_> {
+    /// Copies everything which this block borrows from the source, so that it can outlive it.
+    pub fn into_owned(self) -> Block<'static> {
+        match self {
+            Block::RawText(raw) => Block::RawText(Cow::Owned(raw.into_owned())),
+            Block::Expr(e) => Block::Expr(e.into_owned()),
+            Block::Stmt(s) => Block::Stmt(s.into_owned()),
+            Block::Comment(c) => Block::Comment(Cow::Owned(c.into_owned())),
+        }
+    }
+}
+
+/// See `Block::into_owned`.
+pub(crate) fn into_owned_body(body: Vec<Block>) -> Vec<Block<'static>> {
+    body.into_iter().map(Block::into_owned).collect()
+}
+
+impl Render for Block<'_> {
+    fn render(&self, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()> {
+        match self {
+            Block::RawText(raw) => output.write(raw)?,
+            Block::Expr(e) => ctx.emit(e.evaluate(ctx)?, e, output)?,
+            Block::Stmt(s) => s.render(ctx, output)?,
+            Block::Comment(_) => {}
+        }
+        Ok(())
+    }
+}
+
+impl Render for [Block<'_>] {
+    fn render(&self, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()> {
+        for block in self {
+            ctx.spend_fuel()?;
+            block.render(ctx, output)?;
+            ctx.check_output(output)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'i> Compile<'i> for Block<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        compiler.emit(Instruction::SpendFuel);
+        match self {
+            Block::RawText(raw) => {
+                compiler.emit(Instruction::EmitRaw(raw.as_ref()));
+            }
+            Block::Expr(e) => {
+                e.compile(compiler);
+                compiler.emit(Instruction::EmitExpr(e));
+            }
+            Block::Stmt(s) => s.compile(compiler),
+            Block::Comment(_) => {}
+        }
+    }
+}
+
+impl Format for Block<'_> {
+    fn format(&self, printer: &mut Printer) {
+        match self {
+            Block::RawText(raw) => printer.raw(raw),
+            Block::Expr(e) => {
+                let e = printer.expr(e);
+                printer.raw(&format!("{{{{ {} }}}}", e));
+            }
+            Block::Stmt(s) => s.format(printer),
+            Block::Comment(c) if c.trim().is_empty() => printer.raw("{# #}"),
+            Block::Comment(c) => printer.raw(&format!("{{# {} #}}", c.trim())),
+        }
+    }
+}
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::codegen::Generate;
+
+    use super::Block;
+
+    impl Generate for Block<'_> {
+        fn generate(&self) -> TokenStream {
+            match self {
+                Block::RawText(raw) => {
+                    let raw = raw.as_ref();
+                    quote! { runtime::write(output, #raw)?; }
+                }
+                Block::Expr(e) => {
+                    let (value, text) = (e.generate(), e.to_string());
+                    quote! { runtime::emit_expr(ctx, output, #value, #text)?; }
+                }
+                Block::Stmt(s) => s.generate(),
+                Block::Comment(_) => TokenStream::new(),
+            }
+        }
+    }
+
+    impl Generate for [Block<'_>] {
+        fn generate(&self) -> TokenStream {
+            self.iter()
+                .map(|block| {
+                    let block = block.generate();
+                    quote! {
+                        runtime::spend_fuel(ctx)?;
+                        #block
+                        runtime::check_output(ctx, output)?;
+                    }
+                })
+                .collect()
+        }
+    }
+}
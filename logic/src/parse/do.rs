@@ -0,0 +1,105 @@
+//! Expression statements (the `do` extension)
+
+use std::fmt::Display;
+
+use crate::{
+    compile::{Compile, Compiler, Instruction},
+    format::{Format, Printer},
+    parse::{parse_multiple, parse_token},
+    render::{Context, Output, Render, RenderResult},
+};
+
+use super::{expr::Expr, Parse};
+
+/// `{% do items.append(x) %}`
+///
+/// Evaluates an expression for its side effects, without writing anything to the output.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Do<'i> {
+    pub(crate) expr: Expr<'i>,
+}
+
+impl<'i> Do<'i> {
+    pub fn new(expr: Expr<'i>) -> Self {
+        Self { expr }
+    }
+
+    pub fn expr(&self) -> &Expr<'i> {
+        &self.expr
+    }
+}
+
+impl<'i> Parse<'i> for Do<'i> {
+    fn parse(input: &'i str) -> super::ParseResult<Self> {
+        let (_, input) = parse_multiple(input, &["{%", "do"])?;
+
+        let (expr, input) = Expr::parse_bp(input, 0)?;
+
+        let (_, input) = parse_token(input, "%}")?;
+
+        Ok((Self { expr }, input))
+    }
+}
+
+impl Display for Do<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("{% do ")?;
+        self.expr.fmt(f)?;
+        f.write_str(" %}")
+    }
+}
+
+impl Render for Do<'_> {
+    fn render(&self, ctx: &mut Context, _output: &mut dyn Output) -> RenderResult<()> {
+        self.expr.evaluate(ctx)?;
+        Ok(())
+    }
+}
+
+impl<'i> Compile<'i> for Do<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        self.expr.compile(compiler);
+        compiler.emit(Instruction::Pop);
+    }
+}
+
+impl<'i> Do<'i> {
+    pub(crate) fn optimise(self) -> Self {
+        Self {
+            expr: self.expr.optimise(),
+        }
+    }
+}
+
+impl Do<'_> {
+    pub fn into_owned(self) -> Do<'static> {
+        Do {
+            expr: self.expr.into_owned(),
+        }
+    }
+}
+
+impl Format for Do<'_> {
+    fn format(&self, printer: &mut Printer) {
+        let expr = printer.expr(&self.expr);
+        printer.tag(&format!("do {}", expr));
+    }
+}
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::codegen::Generate;
+
+    use super::Do;
+
+    impl Generate for Do<'_> {
+        fn generate(&self) -> TokenStream {
+            let expr = self.expr.generate();
+            quote! { #expr; }
+        }
+    }
+}
@@ -1,45 +1,100 @@
 use std::fmt::Display;
 
-use crate::parse::parse_token;
+use crate::{
+    compile::{Compile, Compiler},
+    format::{Format, Printer},
+    parse::parse_multiple,
+    render::{Context, Output, Render, RenderResult},
+};
 
-use super::{block::Block, peek_token, Parse, ParseError, ParseResult};
+use super::{
+    block::{into_owned_body, parse_body, Block},
+    Parse, ParseResult,
+};
 
+/// `{% else %}...`, which ends an `if` (or a `for`).
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 
 pub struct Else<'i> {
-    block: Block<'i>,
+    pub(crate) body: Vec<Block<'i>>,
+}
+
+impl<'i> Else<'i> {
+    pub fn body(&self) -> &[Block<'i>] {
+        &self.body
+    }
 }
 
 impl<'i> Parse<'i> for Else<'i> {
     fn parse(input: &'i str) -> ParseResult<Self> {
-        let (_token, input) = parse_token(input, "else")?;
-
-        let (block, input) = Block::parse(input)?;
-
-        Ok((Self { block }, input))
-    }
-
-    fn parse_optional(input: &'i str) -> ParseResult<Option<Self>> {
-        if peek_token(input, "else").unwrap_or((false, "")).0 {
-            Self::parse(input).map(|(a, b)| (Some(a), b))
-        } else {
-            if input.is_empty() {
-                return Err(ParseError::UnexpectedEndOfInput);
-            }
-            if input.len() < "else".len() {
-                return Err(ParseError::UnexpectedToken(input.get(0..).unwrap()));
-            } else {
-                return Err(ParseError::UnexpectedToken(
-                    input.get(0..input.len()).unwrap(),
-                ));
-            }
-        }
+        let (_, input) = parse_multiple(input, &["{%", "else", "%}"])?;
+
+        // the statement which this belongs to checks that it is closed by the right tag
+        let (body, input) = parse_body(input, &["endif", "endfor"])?;
+
+        Ok((Self { body }, input))
     }
 }
 
 impl Display for Else<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str("{% else %}")?;
-        self.block.fmt(f)
+        for block in &self.body {
+            block.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Render for Else<'_> {
+    fn render(&self, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()> {
+        self.body.render(ctx, output)
+    }
+}
+
+impl<'i> Compile<'i> for Else<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        self.body.compile(compiler)
+    }
+}
+
+impl<'i> Else<'i> {
+    pub fn new(body: Vec<Block<'i>>) -> Self {
+        Self { body }
+    }
+
+    pub(crate) fn into_body(self) -> Vec<Block<'i>> {
+        self.body
+    }
+}
+
+impl Else<'_> {
+    pub fn into_owned(self) -> Else<'static> {
+        Else {
+            body: into_owned_body(self.body),
+        }
+    }
+}
+
+impl Format for Else<'_> {
+    fn format(&self, printer: &mut Printer) {
+        printer.tag("else");
+        printer.body(&self.body);
+    }
+}
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+
+    use crate::codegen::Generate;
+
+    use super::Else;
+
+    impl Generate for Else<'_> {
+        fn generate(&self) -> TokenStream {
+            self.body.generate()
+        }
     }
 }
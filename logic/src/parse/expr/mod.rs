@@ -1,92 +1,104 @@
-mod op;
+pub(crate) mod op;
 
 use std::fmt::{Display, Write};
 
-use crate::parse::{expr::op::Op, ignore_whitespace, parse_token, ParseError};
+use crate::{
+    compile::{Compile, Compiler, Instruction},
+    parse::{expr::op::Op, ignore_whitespace, parse_token, ParseError},
+    render::{Context, Kwargs, Origin, RenderError, RenderResult, Value},
+};
 
-use self::op::{BinOpExpr, UnaryOpExpr};
+use self::op::{BinOpExpr, CompareExpr, UnaryOpExpr};
 
-use super::{
-    ident::Ident, literal::Literal, parse_multiple, peek_multiple_bool, peek_token_bool, Parse,
-    ParseResult,
-};
+use super::{ident::Ident, literal::Literal, peek_token_bool, Parse, ParseResult};
 
 #[derive(Debug, Clone, PartialEq)]
-
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
 pub enum Expr<'i> {
     UnaryOp(Box<UnaryOpExpr<'i>>),
     BinOpExpr(Box<BinOpExpr<'i>>),
+    /// `a < b < c`
+    Compare(Box<CompareExpr<'i>>),
     Literal(Literal<'i>),
     Ident(Ident<'i>),
-    FunctionCall(Ident<'i>, Vec<Expr<'i>>),
-}
-
-#[derive(Debug, Clone)]
-enum ExprOpSum<'i> {
-    Expr(Expr<'i>),
-    Op(Op),
-}
-
-impl<'i> ExprOpSum<'i> {
-    fn try_into_expr(self) -> Result<Expr<'i>, Self> {
-        if let Self::Expr(v) = self {
-            Ok(v)
-        } else {
-            Err(self)
-        }
-    }
+    /// `name(args, key=kwarg)`
+    FunctionCall(Ident<'i>, Vec<Expr<'i>>, Vec<(Ident<'i>, Expr<'i>)>),
 }
 
 impl<'i> Parse<'i> for Expr<'i> {
     fn parse(input: &'i str) -> super::ParseResult<Self> {
-        if input.len() <= 2 {
-            return Err(ParseError::UnexpectedEndOfInput);
-        }
-        let (res, input) = Self::parse_bp(input.get(2..).unwrap(), 0)?;
-        if input.len() < 2 {
-            return Err(ParseError::UnexpectedEndOfInput);
-        }
-        if input.get(0..2).unwrap() != "}}" {
-            return Err(ParseError::UnexpectedToken(input.get(0..2).unwrap()));
-        }
-        Ok((res, input.get(2..).unwrap_or("")))
+        let (_, input) = parse_token(input, "{{")?;
+        let (res, input) = Self::parse_bp(input, 0)?;
+        let (_, input) = parse_token(input, "}}")?;
+        Ok((res, input))
     }
 }
 
 impl<'i> Expr<'i> {
-    fn parse_bp(input: &'i str, min_bp: u8) -> ParseResult<Self> {
+    /// Parses an expression which is not surrounded by `{{` and `}}` (e.g. one which appears
+    /// inside a statement).
+    pub(crate) fn parse_bp(input: &'i str, min_bp: u8) -> ParseResult<Self> {
         ignore_whitespace(input, |mut input| {
             let mut lhs = {
                 if let Ok((ident, rest)) = Ident::parse(input) {
                     input = rest;
 
                     if peek_token_bool(input, "(") {
+                        let (_, rest) = parse_token(input, "(")?;
+                        input = rest;
+
                         let mut args = vec![];
-                        loop {
-                            let (expr, rest) = Expr::parse(input)?;
-                            args.push(expr);
-                            input = rest;
+                        let mut kwargs = vec![];
+                        while !peek_token_bool(input, ")") {
+                            match Ident::parse(input) {
+                                Ok((name, rest))
+                                    if peek_token_bool(rest, "=")
+                                        && !peek_token_bool(rest, "==") =>
+                                {
+                                    let (_, rest) = parse_token(rest, "=")?;
+                                    let (expr, rest) = Expr::parse_bp(rest, 0)?;
+                                    kwargs.push((name, expr));
+                                    input = rest;
+                                }
+                                _ => {
+                                    let (expr, rest) = Expr::parse_bp(input, 0)?;
+                                    args.push(expr);
+                                    input = rest;
+                                }
+                            }
 
-                            if peek_token_bool(input, ")") {
-                                let (_, rest) = parse_token(input, "(")?;
-                                input = rest;
-                                break;
-                            } else if peek_multiple_bool(input, &[",", ")"]) {
-                                let (_, rest) = parse_multiple(input, &[",", ")"])?;
+                            if peek_token_bool(input, ",") {
+                                let (_, rest) = parse_token(input, ",")?;
                                 input = rest;
+                            } else {
                                 break;
                             }
                         }
-                        Some(ExprOpSum::Expr(Self::FunctionCall(ident, args)))
+
+                        let (_, rest) = parse_token(input, ")")?;
+                        input = rest;
+
+                        Self::FunctionCall(ident, args, kwargs)
                     } else {
-                        Some(ExprOpSum::Expr(Self::Ident(ident)))
+                        Self::Ident(ident)
                     }
                 } else if let Ok((operator, rest)) = Op::parse(input) {
+                    // a prefix operator applies to the expression which follows it
+                    let operator = operator
+                        .try_into_unary_op()
+                        .map_err(|_| ParseError::OperatorUsedInExpressionPosition)?;
+                    let (_, r_bp) = Op::UnaryOp(operator)
+                        .binding_power()
+                        .ok_or(ParseError::OperatorUsedInExpressionPosition)?;
+
+                    let (arg, rest) = Expr::parse_bp(rest, r_bp)?;
                     input = rest;
-                    Some(ExprOpSum::Op(operator))
+
+                    Expr::UnaryOp(Box::new(UnaryOpExpr::new(operator, arg)))
                 } else if let Ok((literal, rest)) = Literal::parse(input) {
                     input = rest;
-                    Some(ExprOpSum::Expr(Expr::Literal(literal)))
+                    Expr::Literal(literal)
                 } else if let Ok((_, rest)) = parse_token(input, "(") {
                     input = rest;
 
@@ -98,20 +110,32 @@ impl<'i> Expr<'i> {
 
                     input = rest;
 
-                    Some(ExprOpSum::Expr(lhs))
+                    lhs
                 } else {
                     return Err(ParseError::UnexpectedToken(input.get(0..).unwrap()));
                 }
             };
 
+            // whether `lhs` is a comparison which the next one chains onto (so not if it was
+            // bracketed)
+            let mut chaining = false;
+
             loop {
-                let (op, rest) = Op::parse(input)?;
+                // `%}` would otherwise be read as the modulo operator
+                if peek_token_bool(input, "%}") || peek_token_bool(input, "-%}") {
+                    break;
+                }
+
+                let (op, rest) = match Op::parse(input) {
+                    Ok(t) => t,
+                    Err(_) => break,
+                };
 
                 if !op.is_bin_op() {
                     return Err(ParseError::UnexpectedToken(input.get(0..=1).unwrap_or("")));
                 }
 
-                if let Some((l_bp, r_bp)) = op.binding_power(lhs.is_some()) {
+                if let Some((l_bp, r_bp)) = op.binding_power() {
                     if l_bp < min_bp {
                         break;
                     }
@@ -122,54 +146,416 @@ impl<'i> Expr<'i> {
 
                     input = rest;
 
-                    if let Some(old_lhs) = lhs {
-                        let old_lhs = match old_lhs.try_into_expr() {
-                            Ok(t) => t,
-                            Err(_) => return Err(ParseError::OperatorUsedInExpressionPosition),
-                        };
-
-                        lhs = Some(ExprOpSum::Expr(Expr::BinOpExpr(Box::new(BinOpExpr::new(
-                            op.try_into_bin_op().unwrap(),
-                            old_lhs,
-                            rhs,
-                        )))));
-
-                        continue;
+                    let op = op.try_into_bin_op().unwrap();
+                    lhs = if chaining && op.is_comparison() {
+                        CompareExpr::chain(lhs, op, rhs)
                     } else {
-                        lhs = Some(ExprOpSum::Expr(Expr::UnaryOp(
-                            box (UnaryOpExpr::new(op.try_into_unary_op().unwrap(), rhs)),
-                        )));
-                    }
+                        Expr::BinOpExpr(Box::new(BinOpExpr::new(op, lhs, rhs)))
+                    };
+                    chaining = op.is_comparison();
+
+                    continue;
                 }
 
                 break;
             }
 
-            lhs.ok_or(ParseError::UndiagnosedError)
-                .and_then(|expr| match expr.try_into_expr() {
-                    Ok(t) => Ok((t, input)),
-                    Err(_) => Err(ParseError::UndiagnosedError),
-                })
+            Ok((lhs, input))
         })
     }
 }
 
+impl Expr<'_> {
+    pub(crate) fn evaluate(&self, ctx: &Context) -> RenderResult<Value> {
+        ctx.spend_fuel()?;
+        match self {
+            Expr::UnaryOp(u) => u.evaluate(ctx),
+            Expr::BinOpExpr(b) => b.evaluate(ctx),
+            Expr::Compare(c) => c.evaluate(ctx),
+            Expr::Literal(l) => Ok(l.into()),
+            Expr::Ident(i) => ctx.variable(i.name()),
+            Expr::FunctionCall(name, args, kwargs) => {
+                let function = ctx.variable(name.name())?;
+                let (args, kwargs) = evaluate_args(args, kwargs, ctx)?;
+                function.call(args, kwargs)
+            }
+        }
+    }
+
+    /// Evaluates this expression where it may be undefined (before `is defined`, or `default`),
+    /// so that names and attributes which are undefined are never an error.
+    pub(crate) fn evaluate_or_undefined(&self, ctx: &Context) -> RenderResult<Value> {
+        match self {
+            Expr::Ident(i) => Ok(ctx.get(i.name()).unwrap_or(Value::Undefined)),
+            Expr::BinOpExpr(b) => match b.attr() {
+                Some(attr) => ctx.attr_or_undefined(b.arg1.evaluate_or_undefined(ctx)?, attr),
+                None => b.evaluate(ctx),
+            },
+            _ => self.evaluate(ctx),
+        }
+    }
+
+    /// Where this expression is in the source of its template, if it is a name, or an attribute
+    /// of one.
+    pub(crate) fn origin(&self) -> Option<Origin> {
+        match self {
+            Expr::Ident(name) | Expr::FunctionCall(name, ..) => Some(Origin::of(name.name())),
+            Expr::BinOpExpr(b) if b.operator == op::BinOp::Dot => {
+                Some(b.arg1.origin()?.to(b.arg2.origin()?))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'i> Expr<'i> {
+    /// Applies the filter which this expression names (e.g. `upper`, or `replace('a', 'b')`) to
+    /// `value`.
+    pub(crate) fn apply_filter(&self, value: Value, ctx: &Context) -> RenderResult<Value> {
+        match self {
+            Expr::Ident(name) => ctx.apply_filter(name.name(), value, vec![], vec![]),
+            Expr::FunctionCall(name, args, kwargs) => {
+                let (args, kwargs) = evaluate_args(args, kwargs, ctx)?;
+                ctx.apply_filter(name.name(), value, args, kwargs)
+            }
+            other => Err(RenderError::InvalidOperation(format!(
+                "`{}` is not a filter",
+                other
+            ))),
+        }
+    }
+
+    /// Applies the test which this expression names (e.g. `defined`, or `divisibleby(3)`) to
+    /// `value`.
+    pub(crate) fn apply_test(&self, value: Value, ctx: &Context) -> RenderResult<Value> {
+        let held = match self {
+            Expr::Ident(name) => ctx.apply_test(name.name(), value, vec![], vec![]),
+            Expr::FunctionCall(name, args, kwargs) => {
+                let (args, kwargs) = evaluate_args(args, kwargs, ctx)?;
+                ctx.apply_test(name.name(), value, args, kwargs)
+            }
+            // `true` and `false` are literals, rather than names
+            Expr::Literal(Literal::Bool(b)) => {
+                ctx.apply_test(&b.to_string(), value, vec![], vec![])
+            }
+            other => Err(RenderError::InvalidOperation(format!(
+                "`{}` is not a test",
+                other
+            ))),
+        };
+        held.map(Value::Bool)
+    }
+
+    /// Parses a filter (the part after a `|`), which is either a name or a call.
+    pub(crate) fn parse_filter(input: &'i str) -> ParseResult<'i, Self> {
+        // nothing binds more tightly than `u8::MAX`, so this stops before any operator
+        match Self::parse_bp(input, u8::MAX)? {
+            (filter @ (Expr::Ident(_) | Expr::FunctionCall(..)), rest) => Ok((filter, rest)),
+            _ => Err(ParseError::UnexpectedToken(input)),
+        }
+    }
+}
+
+impl<'i> Compile<'i> for Expr<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        compiler.emit(Instruction::SpendFuel);
+        match self {
+            Expr::UnaryOp(u) => u.compile(compiler),
+            Expr::BinOpExpr(b) => b.compile(compiler),
+            Expr::Compare(c) => c.compile(compiler),
+            Expr::Literal(l) => {
+                compiler.emit(Instruction::LoadConst(l));
+            }
+            Expr::Ident(i) => {
+                compiler.emit(Instruction::LoadVar(i.name()));
+            }
+            Expr::FunctionCall(name, args, kwargs) => {
+                compiler.emit(Instruction::LoadVar(name.name()));
+                let kwarg_names = compile_args(args, kwargs, compiler);
+                compiler.emit(Instruction::Call(args.len(), kwarg_names));
+            }
+        }
+    }
+}
+
+impl<'i> Expr<'i> {
+    /// Compiles this expression where it may be undefined (see `evaluate_or_undefined`).
+    pub(crate) fn compile_or_undefined(&'i self, compiler: &mut Compiler<'i>) {
+        match self {
+            Expr::Ident(i) => {
+                compiler.emit(Instruction::LoadVarOrUndefined(i.name()));
+            }
+            Expr::BinOpExpr(b) => match b.attr() {
+                Some(attr) => {
+                    b.arg1.compile_or_undefined(compiler);
+                    compiler.emit(Instruction::GetAttrOrUndefined(attr));
+                }
+                None => b.compile(compiler),
+            },
+            _ => self.compile(compiler),
+        }
+    }
+
+    /// Compiles this expression as a filter (see `apply_filter`), which is applied to the value on
+    /// top of the stack.
+    pub(crate) fn compile_filter(&'i self, compiler: &mut Compiler<'i>) {
+        match self {
+            Expr::Ident(name) => {
+                compiler.emit(Instruction::CallFilter(name.name(), 0, vec![]));
+            }
+            Expr::FunctionCall(name, args, kwargs) => {
+                let kwarg_names = compile_args(args, kwargs, compiler);
+                compiler.emit(Instruction::CallFilter(
+                    name.name(),
+                    args.len(),
+                    kwarg_names,
+                ));
+            }
+            other => {
+                compiler.emit(Instruction::Fail(RenderError::InvalidOperation(format!(
+                    "`{}` is not a filter",
+                    other
+                ))));
+            }
+        }
+    }
+
+    /// Compiles this expression as a test (see `apply_test`), which is applied to the value on
+    /// top of the stack.
+    pub(crate) fn compile_test(&'i self, compiler: &mut Compiler<'i>) {
+        match self {
+            Expr::Ident(name) => {
+                compiler.emit(Instruction::CallTest(name.name(), 0, vec![]));
+            }
+            Expr::FunctionCall(name, args, kwargs) => {
+                let kwarg_names = compile_args(args, kwargs, compiler);
+                compiler.emit(Instruction::CallTest(name.name(), args.len(), kwarg_names));
+            }
+            Expr::Literal(Literal::Bool(b)) => {
+                let name = if *b { "true" } else { "false" };
+                compiler.emit(Instruction::CallTest(name, 0, vec![]));
+            }
+            other => {
+                compiler.emit(Instruction::Fail(RenderError::InvalidOperation(format!(
+                    "`{}` is not a test",
+                    other
+                ))));
+            }
+        }
+    }
+}
+
+/// Pushes the arguments of a call, returning the names of the keyword arguments.
+pub(crate) fn compile_args<'i>(
+    args: &'i [Expr<'i>],
+    kwargs: &'i [(Ident<'i>, Expr<'i>)],
+    compiler: &mut Compiler<'i>,
+) -> Vec<&'i str> {
+    args.compile(compiler);
+    kwargs
+        .iter()
+        .map(|(name, arg)| {
+            arg.compile(compiler);
+            name.name()
+        })
+        .collect()
+}
+
+pub(crate) fn evaluate_args(
+    args: &[Expr],
+    kwargs: &[(Ident, Expr)],
+    ctx: &Context,
+) -> RenderResult<(Vec<Value>, Kwargs)> {
+    let args = args
+        .iter()
+        .map(|arg| arg.evaluate(ctx))
+        .collect::<RenderResult<_>>()?;
+    let kwargs = kwargs
+        .iter()
+        .map(|(name, arg)| Ok((name.name().to_string(), arg.evaluate(ctx)?)))
+        .collect::<RenderResult<_>>()?;
+    Ok((args, kwargs))
+}
+
+impl<'i> Expr<'i> {
+    /// Evaluates the parts of this expression which do not depend on the context (see
+    /// [`crate::optimise`]).
+    pub(crate) fn optimise(self) -> Self {
+        match self {
+            Expr::UnaryOp(u) => u.optimise(),
+            Expr::BinOpExpr(b) => b.optimise(),
+            Expr::Compare(c) => c.optimise(),
+            Expr::FunctionCall(name, args, kwargs) => Expr::FunctionCall(
+                name,
+                args.into_iter().map(Expr::optimise).collect(),
+                kwargs
+                    .into_iter()
+                    .map(|(name, arg)| (name, arg.optimise()))
+                    .collect(),
+            ),
+            Expr::Literal(_) | Expr::Ident(_) => self,
+        }
+    }
+}
+
+impl Expr<'_> {
+    pub fn into_owned(self) -> Expr<'static> {
+        match self {
+            Expr::UnaryOp(u) => Expr::UnaryOp(Box::new(u.into_owned())),
+            Expr::BinOpExpr(b) => Expr::BinOpExpr(Box::new(b.into_owned())),
+            Expr::Compare(c) => Expr::Compare(Box::new(c.into_owned())),
+            Expr::Literal(l) => Expr::Literal(l.into_owned()),
+            Expr::Ident(i) => Expr::Ident(i.into_owned()),
+            Expr::FunctionCall(name, args, kwargs) => Expr::FunctionCall(
+                name.into_owned(),
+                args.into_iter().map(Expr::into_owned).collect(),
+                into_owned_kwargs(kwargs),
+            ),
+        }
+    }
+}
+
+pub(crate) fn into_owned_kwargs(
+    kwargs: Vec<(Ident, Expr)>,
+) -> Vec<(Ident<'static>, Expr<'static>)> {
+    kwargs
+        .into_iter()
+        .map(|(name, expr)| (name.into_owned(), expr.into_owned()))
+        .collect()
+}
+
 impl<'i> Display for Expr<'i> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Expr::UnaryOp(u) => u.fmt(f),
             Expr::BinOpExpr(b) => b.fmt(f),
+            Expr::Compare(c) => c.fmt(f),
             Expr::Literal(l) => l.fmt(f),
             Expr::Ident(i) => i.fmt(f),
-            Expr::FunctionCall(name, args) => {
+            Expr::FunctionCall(name, args, kwargs) => {
                 name.fmt(f)?;
                 f.write_char('(')?;
-                for arg in args {
-                    arg.fmt(f)?;
-                    f.write_char(',')?;
-                }
+                let kwargs = kwargs.iter().map(|(name, arg)| format!("{}={}", name, arg));
+                let args = args.iter().map(ToString::to_string).chain(kwargs);
+                f.write_str(&args.collect::<Vec<_>>().join(", "))?;
                 f.write_char(')')
             }
         }
     }
 }
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::codegen::Generate;
+
+    use super::{Expr, Ident, Literal};
+
+    impl Generate for Expr<'_> {
+        fn generate(&self) -> TokenStream {
+            let value = match self {
+                Expr::UnaryOp(u) => u.generate(),
+                Expr::BinOpExpr(b) => b.generate(),
+                Expr::Compare(c) => c.generate(),
+                Expr::Literal(l) => l.generate(),
+                Expr::Ident(i) => {
+                    let name = i.name();
+                    quote! { runtime::variable(ctx, #name)? }
+                }
+                Expr::FunctionCall(name, args, kwargs) => {
+                    let name = name.name();
+                    let (args, kwargs) = generate_args(args, kwargs);
+                    quote! {{
+                        let function = runtime::variable(ctx, #name)?;
+                        function.call(#args, #kwargs)?
+                    }}
+                }
+            };
+            quote! {{
+                runtime::spend_fuel(ctx)?;
+                #value
+            }}
+        }
+    }
+
+    impl Expr<'_> {
+        /// Generates the code which evaluates this expression where it may be undefined (see
+        /// `evaluate_or_undefined`).
+        pub(crate) fn generate_or_undefined(&self) -> TokenStream {
+            match self {
+                Expr::Ident(i) => {
+                    let name = i.name();
+                    quote! { ctx.get(#name).unwrap_or(Value::Undefined) }
+                }
+                Expr::BinOpExpr(b) => match b.attr() {
+                    Some(attr) => {
+                        let lhs = b.arg1.generate_or_undefined();
+                        quote! { runtime::get_attr_or_undefined(ctx, #lhs, #attr)? }
+                    }
+                    None => b.generate(),
+                },
+                _ => self.generate(),
+            }
+        }
+
+        /// Generates the code which applies this filter (see `apply_filter`) to the variable
+        /// called `value`.
+        pub(crate) fn generate_filter(&self, value: TokenStream) -> TokenStream {
+            match self {
+                Expr::Ident(name) => {
+                    let name = name.name();
+                    quote! { runtime::filter(ctx, #name, #value, vec![], vec![])? }
+                }
+                Expr::FunctionCall(name, args, kwargs) => {
+                    let name = name.name();
+                    let (args, kwargs) = generate_args(args, kwargs);
+                    quote! { runtime::filter(ctx, #name, #value, #args, #kwargs)? }
+                }
+                other => fail(&format!("`{}` is not a filter", other)),
+            }
+        }
+
+        /// As `generate_filter`, but for a test.
+        pub(crate) fn generate_test(&self, value: TokenStream) -> TokenStream {
+            match self {
+                Expr::Ident(name) => {
+                    let name = name.name();
+                    quote! { runtime::test(ctx, #name, #value, vec![], vec![])? }
+                }
+                Expr::FunctionCall(name, args, kwargs) => {
+                    let name = name.name();
+                    let (args, kwargs) = generate_args(args, kwargs);
+                    quote! { runtime::test(ctx, #name, #value, #args, #kwargs)? }
+                }
+                Expr::Literal(Literal::Bool(b)) => {
+                    let name = b.to_string();
+                    quote! { runtime::test(ctx, #name, #value, vec![], vec![])? }
+                }
+                other => fail(&format!("`{}` is not a test", other)),
+            }
+        }
+    }
+
+    /// Generates the arguments of a call, as `Vec<Value>` and `Kwargs` (see `evaluate_args`).
+    pub(crate) fn generate_args(
+        args: &[Expr],
+        kwargs: &[(Ident, Expr)],
+    ) -> (TokenStream, TokenStream) {
+        let args = args.iter().map(Generate::generate);
+        let names = kwargs.iter().map(|(name, _)| name.name());
+        let values = kwargs.iter().map(|(_, arg)| arg.generate());
+        (
+            quote! { vec![#(#args),*] },
+            quote! { vec![#((String::from(#names), #values)),*] },
+        )
+    }
+
+    /// An expression which fails with `RenderError::InvalidOperation(message)`.
+    pub(crate) fn fail(message: &str) -> TokenStream {
+        quote! {{
+            return Err(RenderError::InvalidOperation(String::from(#message)))
+        }}
+    }
+}
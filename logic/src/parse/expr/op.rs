@@ -1,56 +1,154 @@
-use std::fmt::Display;
+use std::{cmp::Ordering, fmt::Display};
 
-use crate::parse::{parse_token, Parse};
+use crate::{
+    compile::{Compile, Compiler, Instruction},
+    parse::{parse_token, Parse},
+    render::{Context, Kwargs, Origin, RenderError, RenderResult, Value},
+};
 
-use super::Expr;
+use super::{compile_args, evaluate_args, Expr, Literal};
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 
 pub struct UnaryOpExpr<'i> {
-    operator: UnaryOp,
-    arg: Expr<'i>,
+    pub(crate) operator: UnaryOp,
+    pub(crate) arg: Expr<'i>,
 }
 
 impl<'i> UnaryOpExpr<'i> {
     pub fn new(operator: UnaryOp, arg: Expr<'i>) -> Self {
         Self { operator, arg }
     }
+
+    pub fn operator(&self) -> UnaryOp {
+        self.operator
+    }
+
+    pub fn arg(&self) -> &Expr<'i> {
+        &self.arg
+    }
+}
+
+impl UnaryOpExpr<'_> {
+    pub(crate) fn evaluate(&self, ctx: &Context) -> RenderResult<Value> {
+        apply_unary(self.operator, self.arg.evaluate(ctx)?)
+    }
+}
+
+impl<'i> UnaryOpExpr<'i> {
+    pub(crate) fn optimise(self) -> Expr<'i> {
+        let operator = self.operator;
+        let arg = self.arg.optimise();
+
+        let folded = constant_value(&arg)
+            .and_then(|value| apply_unary(operator, value).ok())
+            .as_ref()
+            .and_then(constant);
+
+        folded.unwrap_or_else(|| Expr::UnaryOp(Box::new(Self { operator, arg })))
+    }
+}
+
+impl UnaryOpExpr<'_> {
+    pub fn into_owned(self) -> UnaryOpExpr<'static> {
+        UnaryOpExpr {
+            operator: self.operator,
+            arg: self.arg.into_owned(),
+        }
+    }
+}
+
+impl<'i> Compile<'i> for UnaryOpExpr<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        self.arg.compile(compiler);
+        compiler.emit(Instruction::UnaryOp(self.operator));
+    }
 }
 
 impl Display for UnaryOpExpr<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.operator.fmt(f)?;
-        self.arg.fmt(f)
+        let (_, r_bp) = Op::UnaryOp(self.operator).binding_power().unwrap();
+        fmt_operand(f, &self.arg, precedence(&self.arg) < r_bp)
     }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
-
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
 pub enum UnaryOp {
     Not,
+    /// Negation (-)
+    Neg,
+    /// Unary plus (+), which only checks that its operand is a number
+    Pos,
 }
 
 impl Display for UnaryOp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             UnaryOp::Not => f.write_str("not "),
+            UnaryOp::Neg => f.write_str("-"),
+            UnaryOp::Pos => f.write_str("+"),
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 
 pub struct BinOpExpr<'i> {
-    operator: BinOp,
-    arg1: Expr<'i>,
-    arg2: Expr<'i>,
+    pub(crate) operator: BinOp,
+    pub(crate) arg1: Expr<'i>,
+    pub(crate) arg2: Expr<'i>,
 }
 
 impl Display for BinOpExpr<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.arg1.fmt(f)?;
-        self.operator.fmt(f)?;
-        self.arg2.fmt(f)
+        let (l_bp, r_bp) = Op::BinOp(self.operator).binding_power().unwrap();
+        let right_associative = l_bp > r_bp;
+
+        // operands are bracketed wherever they would otherwise be parsed differently
+        let lhs = precedence(&self.arg1);
+        fmt_operand(
+            f,
+            &self.arg1,
+            lhs < l_bp
+                // `(a < b) < c` would otherwise become the chain `a < b < c`
+                || (lhs == l_bp && (right_associative || self.operator.is_comparison())),
+        )?;
+
+        match self.operator {
+            BinOp::Dot => f.write_str(".")?,
+            op => write!(f, " {} ", op)?,
+        }
+
+        let rhs = precedence(&self.arg2);
+        fmt_operand(
+            f,
+            &self.arg2,
+            rhs < l_bp || (rhs == l_bp && !right_associative),
+        )
+    }
+}
+
+/// How tightly an expression holds together, in terms of the binding power of its operator on the
+/// side facing outwards.
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::BinOpExpr(b) => Op::BinOp(b.operator).binding_power().unwrap().0,
+        Expr::UnaryOp(u) => Op::UnaryOp(u.operator).binding_power().unwrap().1,
+        Expr::Compare(c) => Op::BinOp(c.rest[0].0).binding_power().unwrap().0,
+        _ => u8::MAX,
+    }
+}
+
+fn fmt_operand(f: &mut std::fmt::Formatter<'_>, expr: &Expr, bracketed: bool) -> std::fmt::Result {
+    if bracketed {
+        write!(f, "({})", expr)
+    } else {
+        expr.fmt(f)
     }
 }
 
@@ -62,10 +160,549 @@ impl<'i> BinOpExpr<'i> {
             arg2,
         }
     }
+
+    pub fn operator(&self) -> BinOp {
+        self.operator
+    }
+
+    pub fn lhs(&self) -> &Expr<'i> {
+        &self.arg1
+    }
+
+    pub fn rhs(&self) -> &Expr<'i> {
+        &self.arg2
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+impl BinOpExpr<'_> {
+    pub(crate) fn evaluate(&self, ctx: &Context) -> RenderResult<Value> {
+        match self.operator {
+            // `and` and `or` short-circuit, and (as in Python) return one of their operands
+            BinOp::And => {
+                let lhs = self.arg1.evaluate(ctx)?;
+                if lhs.is_truthy() {
+                    self.arg2.evaluate(ctx)
+                } else {
+                    Ok(lhs)
+                }
+            }
+            BinOp::Or => {
+                let lhs = self.arg1.evaluate(ctx)?;
+                if lhs.is_truthy() {
+                    Ok(lhs)
+                } else {
+                    self.arg2.evaluate(ctx)
+                }
+            }
+            BinOp::Dot => match &self.arg2 {
+                Expr::Ident(attr) => self.get_attr(self.arg1.evaluate(ctx)?, attr.name(), ctx),
+                Expr::FunctionCall(method, args, kwargs) => {
+                    let value = self.arg1.evaluate(ctx)?;
+                    let (args, kwargs) = evaluate_args(args, kwargs, ctx)?;
+                    ctx.check_method(method.name())?;
+                    call_method(&value, method.name(), args, kwargs)
+                }
+                _ => Err(RenderError::InvalidOperation(format!(
+                    "`{}` is not a valid attribute name",
+                    self.arg2
+                ))),
+            },
+            BinOp::Pipe => self.arg2.apply_filter(self.lhs_value(ctx)?, ctx),
+            BinOp::Is => self.arg2.apply_test(self.lhs_value(ctx)?, ctx),
+            op => ctx.apply(op, self.arg1.evaluate(ctx)?, self.arg2.evaluate(ctx)?),
+        }
+    }
+
+    /// The value of the left operand of a filter or test.
+    fn lhs_value(&self, ctx: &Context) -> RenderResult<Value> {
+        if self.tolerates_undefined() {
+            self.arg1.evaluate_or_undefined(ctx)
+        } else {
+            self.arg1.evaluate(ctx)
+        }
+    }
+
+    /// Looks up the attribute `attr` of `value`, the value of the left operand.
+    pub(crate) fn get_attr(&self, value: Value, attr: &str, ctx: &Context) -> RenderResult<Value> {
+        let origin = self.arg1.origin().unwrap_or_else(|| Origin::of(attr));
+        ctx.attr(value, &self.arg1, origin, attr)
+    }
+}
+
+impl<'i> BinOpExpr<'i> {
+    /// The name of the attribute which this looks up, if it is an attribute lookup (rather than
+    /// a method call, or another operator).
+    pub(crate) fn attr(&self) -> Option<&str> {
+        match (self.operator, &self.arg2) {
+            (BinOp::Dot, Expr::Ident(attr)) => Some(attr.name()),
+            _ => None,
+        }
+    }
 
+    /// Whether this tests whether the left operand is defined, or gives it a default, so that it
+    /// is not an error for it to be undefined.
+    pub(crate) fn tolerates_undefined(&self) -> bool {
+        let name = match &self.arg2 {
+            Expr::Ident(name) | Expr::FunctionCall(name, ..) => name.name(),
+            _ => return false,
+        };
+        match self.operator {
+            BinOp::Is => matches!(name, "defined" | "undefined"),
+            BinOp::Pipe => matches!(name, "default" | "d"),
+            _ => false,
+        }
+    }
+}
+
+impl<'i> BinOpExpr<'i> {
+    pub(crate) fn optimise(self) -> Expr<'i> {
+        let operator = self.operator;
+        let arg1 = self.arg1.optimise();
+        let arg2 = self.arg2.optimise();
+
+        let folded = match (operator, constant_value(&arg1), constant_value(&arg2)) {
+            // these return one of their operands, so only the left one has to be constant
+            (BinOp::And, Some(lhs), _) => return if lhs.is_truthy() { arg2 } else { arg1 },
+            (BinOp::Or, Some(lhs), _) => return if lhs.is_truthy() { arg1 } else { arg2 },
+            // the right hand side of these is a name, rather than a value
+            (BinOp::Dot | BinOp::Pipe | BinOp::Is, _, _) => None,
+            (op, Some(lhs), Some(rhs)) => apply(op, lhs, rhs).ok().as_ref().and_then(constant),
+            _ => None,
+        };
+
+        folded.unwrap_or_else(|| {
+            Expr::BinOpExpr(Box::new(Self {
+                operator,
+                arg1,
+                arg2,
+            }))
+        })
+    }
+}
+
+/// The value of an expression which [`constant`] could have produced.
+fn constant_value(expr: &Expr) -> Option<Value> {
+    match expr {
+        Expr::Literal(literal) => Some(literal.into()),
+        Expr::UnaryOp(negation) if negation.operator == UnaryOp::Neg => match &negation.arg {
+            Expr::Literal(literal) => apply_unary(UnaryOp::Neg, literal.into()).ok(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// An expression which evaluates to `value` without doing any work (other than negating a
+/// number), if there is one.
+fn constant(value: &Value) -> Option<Expr<'static>> {
+    let negated = match value {
+        Value::Integer(int) if *int < 0 => Value::Integer(int.checked_neg()?),
+        Value::Float(float) if float.is_sign_negative() => Value::Float(-float),
+        value => return Literal::from_value(value).map(Expr::Literal),
+    };
+    Literal::from_value(&negated).map(|literal| {
+        Expr::UnaryOp(Box::new(UnaryOpExpr::new(
+            UnaryOp::Neg,
+            Expr::Literal(literal),
+        )))
+    })
+}
+
+impl BinOpExpr<'_> {
+    pub fn into_owned(self) -> BinOpExpr<'static> {
+        BinOpExpr {
+            operator: self.operator,
+            arg1: self.arg1.into_owned(),
+            arg2: self.arg2.into_owned(),
+        }
+    }
+}
+
+impl<'i> Compile<'i> for BinOpExpr<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        match self.operator {
+            BinOp::And => {
+                self.arg1.compile(compiler);
+                let jump = compiler.emit(Instruction::JumpIfFalseOrPop(0));
+                self.arg2.compile(compiler);
+                compiler.patch(jump);
+            }
+            BinOp::Or => {
+                self.arg1.compile(compiler);
+                let jump = compiler.emit(Instruction::JumpIfTrueOrPop(0));
+                self.arg2.compile(compiler);
+                compiler.patch(jump);
+            }
+            BinOp::Dot => match &self.arg2 {
+                Expr::Ident(attr) => {
+                    self.arg1.compile(compiler);
+                    compiler.emit(Instruction::GetAttr(self, attr.name()));
+                }
+                Expr::FunctionCall(method, args, kwargs) => {
+                    self.arg1.compile(compiler);
+                    let kwarg_names = compile_args(args, kwargs, compiler);
+                    compiler.emit(Instruction::CallMethod(
+                        method.name(),
+                        args.len(),
+                        kwarg_names,
+                    ));
+                }
+                _ => {
+                    compiler.emit(Instruction::Fail(RenderError::InvalidOperation(format!(
+                        "`{}` is not a valid attribute name",
+                        self.arg2
+                    ))));
+                }
+            },
+            BinOp::Pipe => {
+                self.compile_lhs(compiler);
+                self.arg2.compile_filter(compiler);
+            }
+            BinOp::Is => {
+                self.compile_lhs(compiler);
+                self.arg2.compile_test(compiler);
+            }
+            op => {
+                self.arg1.compile(compiler);
+                self.arg2.compile(compiler);
+                compiler.emit(Instruction::BinOp(op));
+            }
+        }
+    }
+}
+
+impl<'i> BinOpExpr<'i> {
+    /// Compiles the left operand of a filter or test (see `lhs_value`).
+    fn compile_lhs(&'i self, compiler: &mut Compiler<'i>) {
+        if self.tolerates_undefined() {
+            self.arg1.compile_or_undefined(compiler);
+        } else {
+            self.arg1.compile(compiler);
+        }
+    }
+}
+
+/// A chain of two or more comparisons (e.g. `a < b < c`), which holds if each of them holds. As
+/// in Jinja (and Python), the operands in the middle are only evaluated once, and evaluation stops
+/// at the first comparison which does not hold.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CompareExpr<'i> {
+    pub(crate) first: Expr<'i>,
+    pub(crate) rest: Vec<(BinOp, Expr<'i>)>,
+}
+
+impl<'i> CompareExpr<'i> {
+    /// `first`, compared with each operand in `rest` in turn.
+    pub fn new(first: Expr<'i>, rest: Vec<(BinOp, Expr<'i>)>) -> Self {
+        Self { first, rest }
+    }
+
+    /// The operand before the first operator.
+    pub fn first(&self) -> &Expr<'i> {
+        &self.first
+    }
+
+    /// Each operator, with the operand after it.
+    pub fn rest(&self) -> &[(BinOp, Expr<'i>)] {
+        &self.rest
+    }
+}
+
+impl<'i> CompareExpr<'i> {
+    /// Extends `lhs`, a comparison which was just parsed (e.g. `a < b` in `a < b < c`), into a
+    /// chain.
+    pub(crate) fn chain(lhs: Expr<'i>, operator: BinOp, rhs: Expr<'i>) -> Expr<'i> {
+        match lhs {
+            Expr::Compare(mut compare) => {
+                compare.rest.push((operator, rhs));
+                Expr::Compare(compare)
+            }
+            Expr::BinOpExpr(b) if b.operator.is_comparison() => {
+                let BinOpExpr {
+                    operator: first_operator,
+                    arg1,
+                    arg2,
+                } = *b;
+                Expr::Compare(Box::new(Self {
+                    first: arg1,
+                    rest: vec![(first_operator, arg2), (operator, rhs)],
+                }))
+            }
+            lhs => Expr::BinOpExpr(Box::new(BinOpExpr::new(operator, lhs, rhs))),
+        }
+    }
+}
+
+impl CompareExpr<'_> {
+    pub(crate) fn evaluate(&self, ctx: &Context) -> RenderResult<Value> {
+        let mut lhs = self.first.evaluate(ctx)?;
+        for (operator, arg) in &self.rest {
+            let rhs = arg.evaluate(ctx)?;
+            if !apply(*operator, lhs, rhs.clone())?.is_truthy() {
+                return Ok(Value::Bool(false));
+            }
+            lhs = rhs;
+        }
+        Ok(Value::Bool(true))
+    }
+}
+
+impl<'i> CompareExpr<'i> {
+    pub(crate) fn optimise(self) -> Expr<'i> {
+        let first = self.first.optimise();
+        let rest = self
+            .rest
+            .into_iter()
+            .map(|(operator, arg)| (operator, arg.optimise()))
+            .collect::<Vec<_>>();
+
+        let constants = std::iter::once(&first)
+            .chain(rest.iter().map(|(_, arg)| arg))
+            .map(constant_value)
+            .collect::<Option<Vec<_>>>();
+        let folded = constants.and_then(|values| {
+            let holds = values
+                .windows(2)
+                .zip(&rest)
+                .map(|(pair, (operator, _))| apply(*operator, pair[0].clone(), pair[1].clone()))
+                .collect::<RenderResult<Vec<_>>>()
+                .ok()?
+                .iter()
+                .all(Value::is_truthy);
+            Some(Expr::Literal(Literal::Bool(holds)))
+        });
+
+        folded.unwrap_or_else(|| Expr::Compare(Box::new(Self { first, rest })))
+    }
+}
+
+impl CompareExpr<'_> {
+    pub fn into_owned(self) -> CompareExpr<'static> {
+        CompareExpr {
+            first: self.first.into_owned(),
+            rest: self
+                .rest
+                .into_iter()
+                .map(|(operator, arg)| (operator, arg.into_owned()))
+                .collect(),
+        }
+    }
+}
+
+impl<'i> Compile<'i> for CompareExpr<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        self.first.compile(compiler);
+
+        let (last, init) = self.rest.split_last().expect("a chain has two comparisons");
+        let jumps = init
+            .iter()
+            .map(|(operator, arg)| {
+                arg.compile(compiler);
+                compiler.emit(Instruction::CompareOrJump(*operator, 0))
+            })
+            .collect::<Vec<_>>();
+
+        let (operator, arg) = last;
+        arg.compile(compiler);
+        compiler.emit(Instruction::BinOp(*operator));
+
+        for jump in jumps {
+            compiler.patch(jump);
+        }
+    }
+}
+
+impl Display for CompareExpr<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (l_bp, _) = Op::BinOp(self.rest[0].0).binding_power().unwrap();
+
+        // an operand which is itself a comparison would otherwise become part of the chain
+        fmt_operand(f, &self.first, precedence(&self.first) <= l_bp)?;
+        for (operator, arg) in &self.rest {
+            write!(f, " {} ", operator)?;
+            fmt_operand(f, arg, precedence(arg) <= l_bp)?;
+        }
+        Ok(())
+    }
+}
+
+/// Calls `value.name(args)`, which is either a function stored in an attribute (e.g. a macro
+/// which was imported as part of a module), or one of the value's methods.
+pub(crate) fn call_method(
+    value: &Value,
+    name: &str,
+    args: Vec<Value>,
+    kwargs: Kwargs,
+) -> RenderResult<Value> {
+    if let function @ Value::Function(_) = value.get_attr(name) {
+        return function.call(args, kwargs);
+    }
+    if !kwargs.is_empty() {
+        return Err(RenderError::InvalidOperation(format!(
+            "`{}` does not take keyword arguments",
+            name
+        )));
+    }
+    value.call_method(name, args)
+}
+
+pub(crate) fn apply_unary(op: UnaryOp, value: Value) -> RenderResult<Value> {
+    match (op, &value) {
+        (UnaryOp::Not, _) => Ok(Value::Bool(!value.is_truthy())),
+        (UnaryOp::Neg, Value::Integer(int)) => int
+            .checked_neg()
+            .map(Value::Integer)
+            .ok_or_else(|| RenderError::InvalidOperation("integer overflow".to_string())),
+        (UnaryOp::Neg, Value::Float(float)) => Ok(Value::Float(-float)),
+        (UnaryOp::Pos, Value::Integer(_) | Value::Float(_)) => Ok(value),
+        _ => Err(RenderError::InvalidOperation(format!(
+            "unsupported operand type for unary `{}`: {:?}",
+            op.to_string().trim_end(),
+            value
+        ))),
+    }
+}
+
+/// Applies an operator whose operands are always both evaluated.
+pub(crate) fn apply(op: BinOp, lhs: Value, rhs: Value) -> RenderResult<Value> {
+    let invalid = || {
+        Err(RenderError::InvalidOperation(format!(
+            "unsupported operand types for `{}`: {:?} and {:?}",
+            op, lhs, rhs
+        )))
+    };
+
+    match (op, &lhs, &rhs) {
+        (BinOp::Tilde, _, _) => Ok(Value::String(format!("{}{}", lhs, rhs))),
+        (BinOp::Eq, _, _) => Ok(Value::Bool(compare(&lhs, &rhs) == Some(Ordering::Equal))),
+        (BinOp::NotEq, _, _) => Ok(Value::Bool(compare(&lhs, &rhs) != Some(Ordering::Equal))),
+        (BinOp::Gt | BinOp::Lt | BinOp::GtEq | BinOp::LtEq, _, _) => {
+            let ordering = match compare(&lhs, &rhs) {
+                Some(ordering) => ordering,
+                None => return invalid(),
+            };
+            Ok(Value::Bool(match op {
+                BinOp::Gt => ordering == Ordering::Greater,
+                BinOp::Lt => ordering == Ordering::Less,
+                BinOp::GtEq => ordering != Ordering::Less,
+                _ => ordering != Ordering::Greater,
+            }))
+        }
+        (BinOp::In, needle, Value::List(list)) => {
+            Ok(Value::Bool(list.borrow().iter().any(|item| {
+                compare(needle, item) == Some(Ordering::Equal)
+            })))
+        }
+        (BinOp::In, needle, Value::Dict(dict)) => {
+            Ok(Value::Bool(dict.borrow().iter().any(|(key, _)| {
+                compare(needle, key) == Some(Ordering::Equal)
+            })))
+        }
+        (BinOp::In, Value::String(needle), Value::String(haystack)) => {
+            Ok(Value::Bool(haystack.contains(needle.as_str())))
+        }
+        (BinOp::Add, Value::String(a), Value::String(b)) => {
+            Ok(Value::String(format!("{}{}", a, b)))
+        }
+        (BinOp::Add, Value::List(a), Value::List(b)) => Ok(Value::list(
+            a.borrow()
+                .iter()
+                .chain(b.borrow().iter())
+                .cloned()
+                .collect(),
+        )),
+        (BinOp::Mul, Value::String(string), Value::Integer(n))
+        | (BinOp::Mul, Value::Integer(n), Value::String(string)) => {
+            Ok(Value::String(string.repeat((*n).max(0) as usize)))
+        }
+        (_, Value::Integer(a), Value::Integer(b)) => apply_int(op, *a, *b),
+        (_, Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => {
+            apply_float(op, as_float(&lhs).unwrap(), as_float(&rhs).unwrap())
+        }
+        _ => invalid(),
+    }
+}
+
+fn apply_int(op: BinOp, a: i32, b: i32) -> RenderResult<Value> {
+    if b == 0 && matches!(op, BinOp::Div | BinOp::IntDiv | BinOp::Mod) {
+        return Err(RenderError::InvalidOperation(
+            "division by zero".to_string(),
+        ));
+    }
+
+    let res = match op {
+        BinOp::Add => a.checked_add(b),
+        BinOp::Sub => a.checked_sub(b),
+        BinOp::Mul => a.checked_mul(b),
+        BinOp::Div => return Ok(Value::Float(a as f32 / b as f32)),
+        // Python rounds towards negative infinity, and the remainder takes the sign of the divisor
+        BinOp::IntDiv => a.checked_div(b).map(|q| {
+            if a % b != 0 && (a < 0) != (b < 0) {
+                q - 1
+            } else {
+                q
+            }
+        }),
+        BinOp::Mod => a.checked_rem(b).map(|r| {
+            if r != 0 && (r < 0) != (b < 0) {
+                r + b
+            } else {
+                r
+            }
+        }),
+        BinOp::Exp if b < 0 => return Ok(Value::Float((a as f32).powi(b))),
+        BinOp::Exp => a.checked_pow(b as u32),
+        _ => unreachable!("`{}` is not an arithmetic operator", op),
+    };
+
+    res.map(Value::Integer)
+        .ok_or_else(|| RenderError::InvalidOperation("integer overflow".to_string()))
+}
+
+fn apply_float(op: BinOp, a: f32, b: f32) -> RenderResult<Value> {
+    if b == 0.0 && matches!(op, BinOp::Div | BinOp::IntDiv | BinOp::Mod) {
+        return Err(RenderError::InvalidOperation(
+            "division by zero".to_string(),
+        ));
+    }
+
+    Ok(Value::Float(match op {
+        BinOp::Add => a + b,
+        BinOp::Sub => a - b,
+        BinOp::Mul => a * b,
+        BinOp::Div => a / b,
+        BinOp::IntDiv => (a / b).floor(),
+        BinOp::Mod => a - b * (a / b).floor(),
+        BinOp::Exp => a.powf(b),
+        _ => unreachable!("`{}` is not an arithmetic operator", op),
+    }))
+}
+
+fn as_float(value: &Value) -> Option<f32> {
+    match value {
+        Value::Integer(int) => Some(*int as f32),
+        Value::Float(float) => Some(*float),
+        _ => None,
+    }
+}
+
+/// Compares two values; returns `None` if they cannot be ordered relative to one another.
+fn compare(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+        (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => {
+            as_float(lhs)?.partial_cmp(&as_float(rhs)?)
+        }
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (a, b) if a == b => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
 pub enum BinOp {
     /// Addition (+)
     Add,
@@ -109,6 +746,22 @@ pub enum BinOp {
     Dot,
 }
 
+impl BinOp {
+    /// Whether this operator can be chained with others like it (e.g. `a < b < c`).
+    pub(crate) fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinOp::Eq
+                | BinOp::NotEq
+                | BinOp::Gt
+                | BinOp::Lt
+                | BinOp::GtEq
+                | BinOp::LtEq
+                | BinOp::In
+        )
+    }
+}
+
 impl Display for BinOp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
@@ -147,28 +800,31 @@ impl Op {
     ///
     /// See <https://matklad.github.io/2020/04/13/simple-but-powerful-pratt-parsing.html> for more
     /// details.
-    pub(crate) fn binding_power(&self, prefix: bool) -> Option<(u8, u8)> {
+    pub(crate) fn binding_power(&self) -> Option<(u8, u8)> {
+        // from loosest to tightest, following Jinja
         Some(match *self {
-            Op::BinOp(BinOp::Add | BinOp::Sub) if prefix => (99, 9),
-            Op::BinOp(BinOp::Add | BinOp::Sub) => (5, 6),
+            Op::BinOp(BinOp::Or) => (1, 2),
+            Op::BinOp(BinOp::And) => (3, 4),
+            Op::UnaryOp(UnaryOp::Not) => (5, 5),
             Op::BinOp(
-                BinOp::Mul | BinOp::Div | BinOp::IntDiv | BinOp::Mod | BinOp::Pipe | BinOp::Tilde,
-            ) => (7, 8),
-            Op::BinOp(
-                BinOp::And
-                | BinOp::Eq
-                | BinOp::Or
-                | BinOp::GtEq
-                | BinOp::Lt
+                BinOp::Eq
+                | BinOp::NotEq
                 | BinOp::Gt
+                | BinOp::Lt
+                | BinOp::GtEq
                 | BinOp::LtEq
-                | BinOp::Is
-                | BinOp::NotEq
-                | BinOp::In,
-            ) => (9, 10),
-            Op::BinOp(BinOp::Exp) => (11, 12),
-            Op::BinOp(BinOp::Dot) => (14, 13),
-            Op::UnaryOp(UnaryOp::Not) => (11, 100),
+                | BinOp::In
+                | BinOp::Is,
+            ) => (7, 8),
+            Op::BinOp(BinOp::Tilde) => (9, 10),
+            Op::BinOp(BinOp::Add | BinOp::Sub) => (11, 12),
+            Op::BinOp(BinOp::Mul | BinOp::Div | BinOp::IntDiv | BinOp::Mod) => (13, 14),
+            // `-2 ** 2` is `-(2 ** 2)`, as in Python
+            Op::UnaryOp(UnaryOp::Neg | UnaryOp::Pos) => (99, 15),
+            // right-associative, as in Python
+            Op::BinOp(BinOp::Exp) => (17, 16),
+            Op::BinOp(BinOp::Pipe) => (19, 20),
+            Op::BinOp(BinOp::Dot) => (21, 22),
         })
     }
 
@@ -186,10 +842,12 @@ impl Op {
     }
 
     pub(crate) fn try_into_unary_op(self) -> Result<UnaryOp, Self> {
-        if let Self::UnaryOp(v) = self {
-            Ok(v)
-        } else {
-            Err(self)
+        match self {
+            Self::UnaryOp(v) => Ok(v),
+            // these are also binary operators, depending on where they appear
+            Self::BinOp(BinOp::Add) => Ok(UnaryOp::Pos),
+            Self::BinOp(BinOp::Sub) => Ok(UnaryOp::Neg),
+            _ => Err(self),
         }
     }
 }
@@ -201,7 +859,11 @@ macro_rules! parse_operators {
                 if $crate::parse::utils::peek_token_bool($input, $op) {
                     let (_, rest) = parse_token($input, $op)?;
 
-                    return Ok(($item, rest));
+                    // a word operator must not be the start of a longer name (e.g. `in` in
+                    // `import`)
+                    if !(is_word($op) && rest.starts_with(is_word_char)) {
+                        return Ok(($item, rest));
+                    }
                 }
             )*
             Err($crate::parse::ParseError::UnexpectedToken(
@@ -213,6 +875,14 @@ macro_rules! parse_operators {
     };
 }
 
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_word(op: &str) -> bool {
+    op.chars().all(is_word_char)
+}
+
 impl<'i> Parse<'i> for Op {
     fn parse(input: &'i str) -> crate::parse::ParseResult<Self> {
         // **important**: if you update this, make sure to update `BinOp` and `UnaryOp`'s `Parse`
@@ -225,14 +895,14 @@ impl<'i> Parse<'i> for Op {
                 "//" => Op::BinOp(BinOp::IntDiv),
                 "/" => Op::BinOp(BinOp::Div),
                 "%" => Op::BinOp(BinOp::Mod),
-                "*" => Op::BinOp(BinOp::Mul),
                 "**" => Op::BinOp(BinOp::Exp),
+                "*" => Op::BinOp(BinOp::Mul),
                 "==" => Op::BinOp(BinOp::Eq),
                 "!=" => Op::BinOp(BinOp::NotEq),
                 ">=" => Op::BinOp(BinOp::GtEq),
                 "<=" => Op::BinOp(BinOp::LtEq),
-                "<" => Op::BinOp(BinOp::Gt),
-                ">" => Op::BinOp(BinOp::Lt),
+                "<" => Op::BinOp(BinOp::Lt),
+                ">" => Op::BinOp(BinOp::Gt),
                 "and" => Op::BinOp(BinOp::And),
                 "or" => Op::BinOp(BinOp::Or),
                 "in" => Op::BinOp(BinOp::In),
@@ -245,3 +915,122 @@ impl<'i> Parse<'i> for Op {
         )
     }
 }
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::{Ident, Span, TokenStream};
+    use quote::{quote, ToTokens};
+
+    use crate::{
+        codegen::Generate,
+        parse::expr::generate::{fail, generate_args},
+    };
+
+    use super::{BinOp, BinOpExpr, CompareExpr, Expr, UnaryOp, UnaryOpExpr};
+
+    impl Generate for UnaryOpExpr<'_> {
+        fn generate(&self) -> TokenStream {
+            let (operator, arg) = (self.operator, self.arg.generate());
+            quote! { runtime::apply_unary(#operator, #arg)? }
+        }
+    }
+
+    impl Generate for BinOpExpr<'_> {
+        fn generate(&self) -> TokenStream {
+            let lhs = if self.tolerates_undefined() {
+                self.arg1.generate_or_undefined()
+            } else {
+                self.arg1.generate()
+            };
+            match self.operator {
+                BinOp::And => {
+                    let rhs = self.arg2.generate();
+                    quote! {{
+                        let lhs = #lhs;
+                        if lhs.is_truthy() { #rhs } else { lhs }
+                    }}
+                }
+                BinOp::Or => {
+                    let rhs = self.arg2.generate();
+                    quote! {{
+                        let lhs = #lhs;
+                        if lhs.is_truthy() { lhs } else { #rhs }
+                    }}
+                }
+                BinOp::Dot => match &self.arg2 {
+                    Expr::Ident(attr) => {
+                        let (object, attr) = (self.arg1.to_string(), attr.name());
+                        quote! { runtime::get_attr(ctx, #lhs, #object, #attr)? }
+                    }
+                    Expr::FunctionCall(method, args, kwargs) => {
+                        let method = method.name();
+                        let (args, kwargs) = generate_args(args, kwargs);
+                        quote! {{
+                            let value = #lhs;
+                            runtime::call_method(ctx, &value, #method, #args, #kwargs)?
+                        }}
+                    }
+                    _ => fail(&format!("`{}` is not a valid attribute name", self.arg2)),
+                },
+                BinOp::Pipe => {
+                    let filter = self.arg2.generate_filter(quote!(value));
+                    quote! {{
+                        let value = #lhs;
+                        #filter
+                    }}
+                }
+                BinOp::Is => {
+                    let test = self.arg2.generate_test(quote!(value));
+                    quote! {{
+                        let value = #lhs;
+                        #test
+                    }}
+                }
+                operator => {
+                    let rhs = self.arg2.generate();
+                    quote! { runtime::apply(ctx, #operator, #lhs, #rhs)? }
+                }
+            }
+        }
+    }
+
+    impl Generate for CompareExpr<'_> {
+        fn generate(&self) -> TokenStream {
+            // each comparison only runs if the ones before it held (see `evaluate`), so the chain
+            // is generated from the last comparison outwards
+            let mut chain = quote!(Value::Bool(true));
+            for (operator, arg) in self.rest.iter().rev() {
+                let arg = arg.generate();
+                chain = quote! {{
+                    let rhs = #arg;
+                    if runtime::apply(ctx, #operator, lhs, rhs.clone())?.is_truthy() {
+                        let lhs = rhs;
+                        #chain
+                    } else {
+                        Value::Bool(false)
+                    }
+                }};
+            }
+
+            let first = self.first.generate();
+            quote! {{
+                let lhs = #first;
+                #chain
+            }}
+        }
+    }
+
+    impl ToTokens for UnaryOp {
+        fn to_tokens(&self, tokens: &mut TokenStream) {
+            let variant = Ident::new(&format!("{:?}", self), Span::call_site());
+            tokens.extend(quote!(runtime::UnaryOp::#variant));
+        }
+    }
+
+    impl ToTokens for BinOp {
+        fn to_tokens(&self, tokens: &mut TokenStream) {
+            let variant = Ident::new(&format!("{:?}", self), Span::call_site());
+            tokens.extend(quote!(runtime::BinOp::#variant));
+        }
+    }
+}
@@ -1,11 +1,38 @@
 use std::fmt::Display;
 
-use super::{block::Block, ident::Ident, parse_token, Parse};
+use crate::{
+    compile::{Compile, Compiler, Instruction},
+    format::{Format, Printer},
+    optimise::optimise_body,
+    render::{Context, Output, Render, RenderResult, Value},
+};
+
+use super::{
+    block::{into_owned_body, parse_body, Block},
+    ident::Ident,
+    parse_token, Parse,
+};
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Filter<'i> {
-    name: Ident<'i>,
-    block: Box<Block<'i>>,
+    pub(crate) name: Ident<'i>,
+    pub(crate) body: Vec<Block<'i>>,
+}
+
+impl<'i> Filter<'i> {
+    pub fn new(name: Ident<'i>, body: Vec<Block<'i>>) -> Self {
+        Self { name, body }
+    }
+
+    /// The name of the filter which is applied to the body.
+    pub fn name(&self) -> &Ident<'i> {
+        &self.name
+    }
+
+    pub fn body(&self) -> &[Block<'i>] {
+        &self.body
+    }
 }
 
 impl<'i> Parse<'i> for Filter<'i> {
@@ -17,14 +44,13 @@ impl<'i> Parse<'i> for Filter<'i> {
 
         let (_, input) = parse_token(input, "%}")?;
 
-        let (block, input) = Block::parse(input)?;
-        let block = box (block);
+        let (body, input) = parse_body(input, &["endfilter"])?;
 
         let (_, input) = parse_token(input, "{%")?;
         let (_, input) = parse_token(input, "endfilter")?;
         let (_, input) = parse_token(input, "%}")?;
 
-        Ok((Self { name, block }, input))
+        Ok((Self { name, body }, input))
     }
 }
 
@@ -32,10 +58,86 @@ impl Display for Filter<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str("{% filter ")?;
         self.name.fmt(f)?;
-        f.write_str("%}")?;
+        f.write_str(" %}")?;
 
-        self.block.fmt(f)?;
+        for block in &self.body {
+            block.fmt(f)?;
+        }
 
         f.write_str("{% endfilter %}")
     }
 }
+
+impl Render for Filter<'_> {
+    fn render(&self, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()> {
+        let mut captured = String::new();
+        self.body.render(ctx, &mut captured)?;
+
+        let filtered =
+            ctx.apply_filter(self.name.name(), Value::String(captured), vec![], vec![])?;
+        write!(output, "{}", filtered)
+    }
+}
+
+impl<'i> Compile<'i> for Filter<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        compiler.emit(Instruction::BeginCapture);
+        self.body.compile(compiler);
+        compiler.emit(Instruction::EndCapture);
+        compiler.emit(Instruction::CallFilter(self.name.name(), 0, vec![]));
+        compiler.emit(Instruction::Emit);
+    }
+}
+
+impl<'i> Filter<'i> {
+    pub(crate) fn optimise(self) -> Self {
+        Self {
+            body: optimise_body(self.body),
+            ..self
+        }
+    }
+}
+
+impl Filter<'_> {
+    pub fn into_owned(self) -> Filter<'static> {
+        Filter {
+            name: self.name.into_owned(),
+            body: into_owned_body(self.body),
+        }
+    }
+}
+
+impl Format for Filter<'_> {
+    fn format(&self, printer: &mut Printer) {
+        printer.tag(&format!("filter {}", self.name));
+        printer.body(&self.body);
+        printer.tag("endfilter");
+    }
+}
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::codegen::Generate;
+
+    use super::Filter;
+
+    impl Generate for Filter<'_> {
+        fn generate(&self) -> TokenStream {
+            let name = self.name.name();
+            let body = self.body.generate();
+
+            quote! {{
+                let mut captured = String::new();
+                {
+                    let output = &mut captured;
+                    #body
+                }
+                let filtered = runtime::filter(ctx, #name, Value::String(captured), vec![], vec![])?;
+                runtime::emit(output, filtered)?;
+            }}
+        }
+    }
+}
@@ -1,71 +1,75 @@
 use std::fmt::Display;
 
-use crate::parse::{ignore_whitespace, up_to};
-
-use super::{block::Block, expr::Expr, ident::Ident, parse_token, Parse};
+use crate::{
+    compile::{Compile, Compiler, Instruction},
+    format::{Format, Printer},
+    optimise::optimise_body,
+    parse::{bracketed::parse_delimited, parse_multiple},
+    render::{Context, Output, Render, RenderResult},
+};
+
+use super::{
+    block::{into_owned_body, parse_body, Block},
+    expr::Expr,
+    ident::Ident,
+    parse_token, Parse,
+};
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 
 pub struct ForStmt<'i> {
-    idents_of_iter: Vec<Ident<'i>>,
-    in_expr: Expr<'i>,
-    block: Block<'i>,
+    pub(crate) idents_of_iter: Vec<Ident<'i>>,
+    pub(crate) in_expr: Expr<'i>,
+    pub(crate) body: Vec<Block<'i>>,
 }
 
-impl<'i> Parse<'i> for ForStmt<'i> {
-    fn parse(input: &'i str) -> super::ParseResult<Self> {
-        let (_, input) = ignore_whitespace(input, |input| parse_token(input, "{%"))?;
-
-        let (_, mut input) = parse_token(input, "for")?;
-
-        let mut idents_of_iter = vec![];
-
-        loop {
-            let (_, leftover) = up_to(input, &[",", "in"])?;
-
-            let (ident, rest) = Ident::parse(input)?;
-
-            input = rest;
-
-            idents_of_iter.push(ident);
+impl<'i> ForStmt<'i> {
+    /// `{% for <idents> in <iterable> %}<body>{% endfor %}`
+    pub fn new(idents: Vec<Ident<'i>>, iterable: Expr<'i>, body: Vec<Block<'i>>) -> Self {
+        Self {
+            idents_of_iter: idents,
+            in_expr: iterable,
+            body,
+        }
+    }
 
-            let input_is_empty =
-                ignore_whitespace(leftover, |input| Ok((input.is_empty(), input)))?.0;
+    /// The names which each item is bound to (more than one if items are unpacked).
+    pub fn idents(&self) -> &[Ident<'i>] {
+        &self.idents_of_iter
+    }
 
-            if input_is_empty {
-                break;
-            }
+    /// The expression which is iterated over.
+    pub fn iterable(&self) -> &Expr<'i> {
+        &self.in_expr
+    }
 
-            let next_token_is_in = ignore_whitespace(leftover, |whitespace_vanquished| {
-                let cond = input.get(0..=1) == Some("in");
+    pub fn body(&self) -> &[Block<'i>] {
+        &self.body
+    }
+}
 
-                if cond {
-                    input = whitespace_vanquished;
-                }
+impl<'i> Parse<'i> for ForStmt<'i> {
+    fn parse(input: &'i str) -> super::ParseResult<Self> {
+        let (_, input) = parse_multiple(input, &["{%", "for"])?;
 
-                Ok((cond, whitespace_vanquished))
-            })?
-            .0;
+        let (idents_of_iter, input) = parse_delimited::<Ident>(input, ",")?;
 
-            if next_token_is_in {
-                break;
-            }
-        }
+        let (_, input) = parse_token(input, "in")?;
 
-        let (in_expr, input) = Expr::parse(input)?;
+        let (in_expr, input) = Expr::parse_bp(input, 0)?;
 
         let (_, input) = parse_token(input, "%}")?;
 
-        let (block, input) = Block::parse(input)?;
+        let (body, input) = parse_body(input, &["endfor"])?;
 
-        // todo: make parsing more forgiving
-        let (_, input) = parse_token(input, "{% endfor %}")?;
+        let (_, input) = parse_multiple(input, &["{%", "endfor", "%}"])?;
 
         Ok((
             Self {
                 idents_of_iter,
                 in_expr,
-                block,
+                body,
             },
             input,
         ))
@@ -75,14 +79,141 @@ impl<'i> Parse<'i> for ForStmt<'i> {
 impl Display for ForStmt<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str("{% for ")?;
-        for ident in &self.idents_of_iter {
+        for (index, ident) in self.idents_of_iter.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
             ident.fmt(f)?;
-            f.write_str(",")?;
         }
         f.write_str(" in ")?;
         self.in_expr.fmt(f)?;
-        f.write_str("%}")?;
-        self.block.fmt(f)?;
+        f.write_str(" %}")?;
+        for block in &self.body {
+            block.fmt(f)?;
+        }
         f.write_str("{% endfor %}")
     }
 }
+
+impl Render for ForStmt<'_> {
+    fn render(&self, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()> {
+        for item in ctx.iterate(&self.in_expr.evaluate(ctx)?)? {
+            ctx.check_budget()?;
+            // every iteration gets a fresh scope, so that nothing assigned inside the loop is
+            // visible in later iterations or after the loop
+            ctx.scoped(|ctx| {
+                if let [ident] = self.idents_of_iter.as_slice() {
+                    ctx.insert(ident.name(), item);
+                } else {
+                    let items = item.unpack(self.idents_of_iter.len())?;
+                    for (ident, item) in self.idents_of_iter.iter().zip(items) {
+                        ctx.insert(ident.name(), item);
+                    }
+                }
+                self.body.render(ctx, output)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl<'i> Compile<'i> for ForStmt<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        self.in_expr.compile(compiler);
+        compiler.emit(Instruction::IterStart);
+
+        let next = compiler.emit(Instruction::IterNext(0));
+        compiler.emit(Instruction::PushScope);
+        if let [ident] = self.idents_of_iter.as_slice() {
+            compiler.emit(Instruction::StoreVar(ident.name()));
+        } else {
+            compiler.emit(Instruction::Unpack(self.idents_of_iter.len()));
+            for ident in &self.idents_of_iter {
+                compiler.emit(Instruction::StoreVar(ident.name()));
+            }
+        }
+        self.body.compile(compiler);
+        compiler.emit(Instruction::PopScope);
+        compiler.emit(Instruction::Jump(next));
+
+        compiler.patch(next);
+    }
+}
+
+impl<'i> ForStmt<'i> {
+    pub(crate) fn optimise(self) -> Self {
+        Self {
+            in_expr: self.in_expr.optimise(),
+            body: optimise_body(self.body),
+            ..self
+        }
+    }
+}
+
+impl ForStmt<'_> {
+    pub fn into_owned(self) -> ForStmt<'static> {
+        ForStmt {
+            idents_of_iter: self
+                .idents_of_iter
+                .into_iter()
+                .map(Ident::into_owned)
+                .collect(),
+            in_expr: self.in_expr.into_owned(),
+            body: into_owned_body(self.body),
+        }
+    }
+}
+
+/// Writes everything but the `{% endfor %}`, which comes after the `{% else %}` branch.
+impl Format for ForStmt<'_> {
+    fn format(&self, printer: &mut Printer) {
+        let idents = self
+            .idents_of_iter
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let iterable = printer.expr(&self.in_expr);
+        printer.tag(&format!("for {} in {}", idents.join(", "), iterable));
+        printer.body(&self.body);
+    }
+}
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::codegen::Generate;
+
+    use super::{ForStmt, Ident};
+
+    impl Generate for ForStmt<'_> {
+        fn generate(&self) -> TokenStream {
+            let items = self.in_expr.generate();
+            let bind = if let [ident] = self.idents_of_iter.as_slice() {
+                let name = ident.name();
+                quote! { ctx.insert(#name, item); }
+            } else {
+                let names = self.idents_of_iter.iter().map(Ident::name);
+                let n = self.idents_of_iter.len();
+                quote! {
+                    for (name, item) in [#(#names),*].iter().zip(item.unpack(#n)?) {
+                        ctx.insert(*name, item);
+                    }
+                }
+            };
+            let body = self.body.generate();
+
+            quote! {
+                for item in runtime::iterate(ctx, &#items)? {
+                    runtime::check_budget(ctx)?;
+                    runtime::scoped(ctx, |ctx| -> RenderResult<()> {
+                        #bind
+                        #body
+                        Ok(())
+                    })?;
+                }
+            }
+        }
+    }
+}
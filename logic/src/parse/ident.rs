@@ -1,12 +1,17 @@
-use std::fmt::Display;
+use std::{borrow::Cow, fmt::Display};
 
-use crate::parse::{ignore_whitespace, up_to, ParseError};
+use crate::parse::{ignore_whitespace, ParseError};
 
-use super::{Parse, ParseResult};
+use super::Parse;
+
+/// Words which the expression parser treats specially, and which therefore cannot be used as
+/// identifiers.
+const KEYWORDS: &[&str] = &["and", "or", "not", "in", "is", "true", "false"];
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Ident<'i> {
-    name: &'i str,
+    pub(crate) name: Cow<'i, str>,
 }
 
 impl<'i> Parse<'i> for Ident<'i> {
@@ -19,28 +24,29 @@ impl<'i> Parse<'i> for Ident<'i> {
                 .next()
                 .ok_or(ParseError::UnexpectedEndOfInput)?;
 
-            if !next.is_ascii_alphabetic() {
+            if !(next.is_ascii_alphabetic() || next == '_') {
                 return Err(ParseError::UnexpectedToken(input.get(0..0).unwrap()));
             }
 
             index += 1;
 
-            loop {
-                let next = input
-                    .chars()
-                    .next()
-                    .ok_or(ParseError::UnexpectedEndOfInput)?;
-
-                if next.is_alphanumeric() {
+            for next in input.get(index..).unwrap().chars() {
+                if next.is_ascii_alphanumeric() || next == '_' {
                     index += 1;
                 } else {
                     break;
                 }
             }
 
+            let name = input.get(0..index).unwrap();
+
+            if KEYWORDS.contains(&name) {
+                return Err(ParseError::UnexpectedToken(name));
+            }
+
             Ok((
                 Self {
-                    name: input.get(0..index).unwrap(),
+                    name: Cow::Borrowed(name),
                 },
                 input.get(index..).unwrap_or(""),
             ))
@@ -48,45 +54,27 @@ impl<'i> Parse<'i> for Ident<'i> {
     }
 }
 
-impl Display for Ident<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.name.fmt(f)
+impl<'i> Ident<'i> {
+    /// An identifier called `name`, which is not checked to be a valid one.
+    pub fn new(name: impl Into<Cow<'i, str>>) -> Self {
+        Self { name: name.into() }
     }
 }
 
-pub fn ident_list(mut input: &str) -> ParseResult<Vec<Ident<'_>>> {
-    let mut idents_of_iter = vec![];
-
-    loop {
-        let (_, leftover) = up_to(input, &[",", "in"])?;
-
-        let (ident, rest) = Ident::parse(input)?;
-
-        input = rest;
-
-        idents_of_iter.push(ident);
-
-        let input_is_empty = ignore_whitespace(leftover, |input| Ok((input.is_empty(), input)))?.0;
-
-        if input_is_empty {
-            break;
-        }
-
-        let next_token_is_in = ignore_whitespace(leftover, |whitespace_vanquished| {
-            let cond = input.get(0..=1) == Some("in");
-
-            if cond {
-                input = whitespace_vanquished;
-            }
-
-            Ok((cond, whitespace_vanquished))
-        })?
-        .0;
+impl Ident<'_> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 
-        if next_token_is_in {
-            break;
+    pub fn into_owned(self) -> Ident<'static> {
+        Ident {
+            name: Cow::Owned(self.name.into_owned()),
         }
     }
+}
 
-    Ok((idents_of_iter, input))
+impl Display for Ident<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.name.fmt(f)
+    }
 }
@@ -1,15 +1,54 @@
 use std::fmt::Display;
 
-use crate::parse::{ignore_whitespace, Parse, ParseError};
-
-use super::{block::Block, expr::Expr, r#else::Else, ParseResult};
-
+use crate::{
+    compile::{Compile, Compiler, Instruction},
+    format::{Format, Printer},
+    optimise::optimise_body,
+    parse::{parse_multiple, parse_token, peek_multiple_bool, stmt::Stmt, Parse},
+    render::{Context, Output, Render, RenderResult, Value},
+};
+
+use super::{
+    block::{into_owned_body, parse_body, Block},
+    expr::Expr,
+    r#else::Else,
+    ParseResult,
+};
+
+/// `{% if a %}...{% elif b %}...{% else %}...{% endif %}`
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 
 pub struct If<'i> {
-    if_branch: IfBranch<'i>,
-    elif_branches: Vec<IfBranch<'i>>,
-    else_branch: Option<Else<'i>>,
+    pub(crate) if_branch: IfBranch<'i>,
+    pub(crate) elif_branches: Vec<IfBranch<'i>>,
+    pub(crate) else_branch: Option<Else<'i>>,
+}
+
+impl<'i> If<'i> {
+    pub fn new(
+        if_branch: IfBranch<'i>,
+        elif_branches: Vec<IfBranch<'i>>,
+        else_branch: Option<Else<'i>>,
+    ) -> Self {
+        Self {
+            if_branch,
+            elif_branches,
+            else_branch,
+        }
+    }
+
+    pub fn if_branch(&self) -> &IfBranch<'i> {
+        &self.if_branch
+    }
+
+    pub fn elif_branches(&self) -> &[IfBranch<'i>] {
+        &self.elif_branches
+    }
+
+    pub fn else_branch(&self) -> Option<&Else<'i>> {
+        self.else_branch.as_ref()
+    }
 }
 
 impl<'i> Parse<'i> for If<'i> {
@@ -30,14 +69,16 @@ impl<'i> Parse<'i> for If<'i> {
             elif_branches
         };
 
-        let else_branch = {
-            let (else_branch, leftover) = Else::parse_optional(input)?;
-
+        let else_branch = if peek_multiple_bool(input, &["{%", "else"]) {
+            let (else_branch, leftover) = Else::parse(input)?;
             input = leftover;
-
-            else_branch
+            Some(else_branch)
+        } else {
+            None
         };
 
+        let (_, input) = parse_multiple(input, &["{%", "endif", "%}"])?;
+
         Ok((
             Self {
                 if_branch,
@@ -74,22 +115,144 @@ impl Display for If<'_> {
     }
 }
 
+impl If<'_> {
+    fn branches(&self) -> impl Iterator<Item = &IfBranch<'_>> {
+        std::iter::once(&self.if_branch).chain(&self.elif_branches)
+    }
+}
+
+impl Render for If<'_> {
+    fn render(&self, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()> {
+        // as in Jinja, an `if` does not introduce a scope
+        for branch in self.branches() {
+            if branch.condition.evaluate(ctx)?.is_truthy() {
+                return branch.body.render(ctx, output);
+            }
+        }
+
+        match &self.else_branch {
+            Some(else_branch) => else_branch.render(ctx, output),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'i> Compile<'i> for If<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        let mut to_end = vec![];
+
+        for branch in self.branches() {
+            branch.condition.compile(compiler);
+            let to_next = compiler.emit(Instruction::JumpIfFalse(0));
+            branch.body.compile(compiler);
+            to_end.push(compiler.emit(Instruction::Jump(0)));
+            compiler.patch(to_next);
+        }
+
+        if let Some(else_branch) = &self.else_branch {
+            else_branch.compile(compiler);
+        }
+
+        for jump in to_end {
+            compiler.patch(jump);
+        }
+    }
+}
+
+impl<'i> If<'i> {
+    /// Removes the branches which can never be taken. A branch which is always taken becomes the
+    /// `else` (and if it is the first branch left, the `if` is replaced by its body).
+    ///
+    /// See `Block::optimise_into`.
+    pub(crate) fn optimise_into(self, out: &mut Vec<Block<'i>>) {
+        let If {
+            if_branch,
+            elif_branches,
+            else_branch,
+        } = self;
+
+        let mut branches = vec![];
+        let mut else_body = None;
+
+        for branch in std::iter::once(if_branch).chain(elif_branches) {
+            let condition = branch.condition.optimise();
+            match &condition {
+                Expr::Literal(literal) if Value::from(literal).is_truthy() => {
+                    else_body = Some(branch.body);
+                    break;
+                }
+                Expr::Literal(_) => {}
+                _ => branches.push(IfBranch {
+                    condition,
+                    body: optimise_body(branch.body),
+                }),
+            }
+        }
+
+        let else_body = else_body
+            .or_else(|| else_branch.map(Else::into_body))
+            .map(optimise_body);
+
+        let mut branches = branches.into_iter();
+        match branches.next() {
+            Some(if_branch) => out.push(Block::Stmt(Stmt::If(Box::new(If {
+                if_branch,
+                elif_branches: branches.collect(),
+                else_branch: else_body.map(Else::new),
+            })))),
+            None => out.extend(else_body.unwrap_or_default()),
+        }
+    }
+}
+
+impl If<'_> {
+    pub fn into_owned(self) -> If<'static> {
+        If {
+            if_branch: self.if_branch.into_owned(),
+            elif_branches: self
+                .elif_branches
+                .into_iter()
+                .map(IfBranch::into_owned)
+                .collect(),
+            else_branch: self.else_branch.map(Else::into_owned),
+        }
+    }
+}
+
+impl IfBranch<'_> {
+    pub fn into_owned(self) -> IfBranch<'static> {
+        IfBranch {
+            condition: self.condition.into_owned(),
+            body: into_owned_body(self.body),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 
 pub struct IfBranch<'i> {
-    condition: Expr<'i>,
-    block: Block<'i>,
+    pub(crate) condition: Expr<'i>,
+    pub(crate) body: Vec<Block<'i>>,
 }
 
 impl<'i> IfBranch<'i> {
-    pub(crate) fn peek_input_is_elif(input: &'i str) -> bool {
-        Self::peek_input_is(input, "elif")
+    pub fn new(condition: Expr<'i>, body: Vec<Block<'i>>) -> Self {
+        Self { condition, body }
+    }
+
+    pub fn condition(&self) -> &Expr<'i> {
+        &self.condition
+    }
+
+    pub fn body(&self) -> &[Block<'i>] {
+        &self.body
     }
+}
 
-    pub(crate) fn peek_input_is(input: &'i str, token: &'static str) -> bool {
-        ignore_whitespace(input, |input| Ok((input == token, input)))
-            .unwrap()
-            .0
+impl<'i> IfBranch<'i> {
+    pub(crate) fn peek_input_is_elif(input: &'i str) -> bool {
+        peek_multiple_bool(input, &["{%", "elif"])
     }
 
     pub(crate) fn parse_as_if(input: &'i str) -> ParseResult<'i, Self> {
@@ -101,21 +264,15 @@ impl<'i> IfBranch<'i> {
     }
 
     pub(crate) fn base_parse(input: &'i str, token: &'static str) -> ParseResult<'i, Self> {
-        ignore_whitespace(input, |input| {
-            let r#if = input.get(0..=1).ok_or(ParseError::UnexpectedEndOfInput)?;
-
-            if r#if != token {
-                return Err(ParseError::UnexpectedToken(input.get(0..=1).unwrap()));
-            }
+        let (_, input) = parse_multiple(input, &["{%", token])?;
 
-            let input = input.get(0..=1).unwrap();
+        let (condition, input) = Expr::parse_bp(input, 0)?;
 
-            let (condition, advanced) = Expr::parse(input.get(0..=1).unwrap())?;
+        let (_, input) = parse_token(input, "%}")?;
 
-            let (block, advanced) = Block::parse(advanced)?;
+        let (body, input) = parse_body(input, &["elif", "else", "endif"])?;
 
-            Ok((Self { condition, block }, advanced))
-        })
+        Ok((Self { condition, body }, input))
     }
 }
 
@@ -133,6 +290,51 @@ impl Display for FmtIfBranch<'_> {
         }
         self.branch.condition.fmt(f)?;
         f.write_str(" %}")?;
-        self.branch.block.fmt(f)
+        for block in &self.branch.body {
+            block.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Format for If<'_> {
+    fn format(&self, printer: &mut Printer) {
+        let condition = printer.expr(&self.if_branch.condition);
+        printer.tag(&format!("if {}", condition));
+        printer.body(&self.if_branch.body);
+
+        for branch in &self.elif_branches {
+            let condition = printer.expr(&branch.condition);
+            printer.tag(&format!("elif {}", condition));
+            printer.body(&branch.body);
+        }
+
+        if let Some(else_branch) = &self.else_branch {
+            else_branch.format(printer);
+        }
+
+        printer.tag("endif");
+    }
+}
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::codegen::Generate;
+
+    use super::If;
+
+    impl Generate for If<'_> {
+        fn generate(&self) -> TokenStream {
+            let conditions = self.branches().map(|branch| branch.condition.generate());
+            let bodies = self.branches().map(|branch| branch.body.generate());
+            let else_body = self.else_branch.as_ref().map(Generate::generate);
+
+            quote! {
+                #(if (#conditions).is_truthy() { #bodies } else)* { #else_body }
+            }
+        }
     }
 }
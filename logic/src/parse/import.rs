@@ -1,27 +1,61 @@
-use std::fmt::{Display, Formatter, Write};
+use std::{
+    fmt::{Display, Formatter},
+    rc::Rc,
+};
 
-use crate::parse::{bracketed::parse_delimited, parse_multiple, parse_token, peek_token_bool};
+use crate::{
+    format::{Format, Printer},
+    parse::{parse_multiple, parse_token, peek_multiple_bool, peek_token_bool},
+    render::{Context, Function, Output, Render, RenderError, RenderResult, Value},
+};
 
-use super::{expr::Expr, ident::Ident, Parse, ParseError, ParseResult};
+use super::{expr::Expr, ident::Ident, template::Template, Parse, ParseError, ParseResult};
 
+/// `{% import 'forms.html' as forms %}` or
+/// `{% from 'forms.html' import input as field, textarea %}`
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Import<'i> {
-    file: Expr<'i>,
-    r#as: Option<Vec<Ident<'i>>>,
-    items: Items<'i>,
-    with_context: bool,
+    pub(crate) file: Expr<'i>,
+    pub(crate) items: Items<'i>,
+    pub(crate) with_context: bool,
+}
+
+impl<'i> Import<'i> {
+    pub fn new(file: Expr<'i>, items: Items<'i>, with_context: bool) -> Self {
+        Self {
+            file,
+            items,
+            with_context,
+        }
+    }
+
+    /// The name of the template to import.
+    pub fn file(&self) -> &Expr<'i> {
+        &self.file
+    }
+
+    pub fn items(&self) -> &Items<'i> {
+        &self.items
+    }
+
+    pub fn with_context(&self) -> bool {
+        self.with_context
+    }
 }
 
 impl<'i> Parse<'i> for Import<'i> {
     fn parse(input: &'i str) -> super::ParseResult<Self> {
         let (_, input) = parse_token(input, "{%")?;
-        if peek_token_bool(input, "from") {
-            Self::parse_from(input)
+        let (import, input) = if peek_token_bool(input, "from") {
+            Self::parse_from(input)?
         } else if peek_token_bool(input, "import") {
-            Self::parse_vanilla(input)
+            Self::parse_vanilla(input)?
         } else {
-            Err(ParseError::UnexpectedToken(input))
-        }
+            return Err(ParseError::UnexpectedToken(input));
+        };
+        let (_, input) = parse_token(input, "%}")?;
+        Ok((import, input))
     }
 }
 
@@ -29,32 +63,40 @@ impl<'i> Import<'i> {
     fn parse_from(input: &'i str) -> ParseResult<Self> {
         let (_, input) = parse_token(input, "from")?;
 
-        let (file, _) = Expr::parse(input)?;
+        let (file, input) = Expr::parse_bp(input, 0)?;
 
-        let (_, input) = parse_token(input, "import")?;
+        let (_, mut input) = parse_token(input, "import")?;
 
-        let (items, input) = parse_delimited::<Ident>(input, ",")?;
+        let mut items = vec![];
 
-        if items.is_empty() {
-            // todo: return a proper error message
-            return Err(ParseError::UnexpectedEndOfInput);
-        }
+        loop {
+            let (name, rest) = Ident::parse(input)?;
+            input = rest;
 
-        let (r#as, input) = if peek_token_bool(input, "as") {
-            let (_, input) = parse_token(input, "as")?;
+            let alias = if peek_token_bool(input, "as") {
+                let (_, rest) = parse_token(input, "as")?;
+                let (alias, rest) = Ident::parse(rest)?;
+                input = rest;
+                Some(alias)
+            } else {
+                None
+            };
 
-            let (r#as, input) = parse_delimited::<Ident>(input, ",")?;
-            (Some(r#as), input)
-        } else {
-            (None, input)
-        };
+            items.push((name, alias));
 
-        let (with_context, input) = Self::with_context(input)?;
+            if peek_token_bool(input, ",") {
+                let (_, rest) = parse_token(input, ",")?;
+                input = rest;
+            } else {
+                break;
+            }
+        }
+
+        let (with_context, input) = Self::parse_context(input)?;
 
         Ok((
             Self {
                 file,
-                r#as,
                 items: Items::List(items),
                 with_context,
             },
@@ -65,97 +107,228 @@ impl<'i> Import<'i> {
     fn parse_vanilla(input: &'i str) -> ParseResult<Self> {
         let (_, input) = parse_token(input, "import")?;
 
-        let (file, input) = Expr::parse(input)?;
+        let (file, input) = Expr::parse_bp(input, 0)?;
 
         let (_, input) = parse_token(input, "as")?;
 
-        let (r#as, input) = parse_delimited::<Ident>(input, ",")?;
-
-        if r#as.is_empty() {
-            return Err(ParseError::UnexpectedEndOfInput);
-        }
+        let (name, input) = Ident::parse(input)?;
 
-        let (with_context, input) = Self::with_context(input)?;
+        let (with_context, input) = Self::parse_context(input)?;
 
         Ok((
             Self {
                 file,
-                r#as: Some(r#as),
-                items: Items::All,
+                items: Items::All(name),
                 with_context,
             },
             input,
         ))
     }
 
-    fn with_context(input: &'i str) -> ParseResult<bool> {
-        Ok(if peek_token_bool(input, "without") {
+    /// As in Jinja, imported templates do not see the importing template's variables unless
+    /// `with context` is given.
+    fn parse_context(input: &'i str) -> ParseResult<bool> {
+        Ok(if peek_multiple_bool(input, &["without", "context"]) {
             let (_, input) = parse_multiple(input, &["without", "context"])?;
             (false, input)
-        } else if peek_token_bool(input, "with") {
+        } else if peek_multiple_bool(input, &["with", "context"]) {
             let (_, input) = parse_multiple(input, &["with", "context"])?;
             (true, input)
         } else {
-            (true, input)
+            (false, input)
         })
     }
 
-    fn write_as(&self, f: &mut Formatter) -> std::fmt::Result {
-        if let Some(ref r#as) = self.r#as {
-            f.write_str(" as ")?;
-            for (i, ident) in r#as.iter().enumerate() {
-                ident.fmt(f)?;
-                if i != 0 && i != (r#as.len() - 1) {
-                    f.write_char(',')?;
-                }
-            }
-        }
-        Ok(())
-    }
-
     fn write_context(&self, f: &mut Formatter) -> std::fmt::Result {
-        if !self.with_context {
-            f.write_str(" without context ")
+        if self.with_context {
+            f.write_str(" with context")
         } else {
             Ok(())
         }
     }
 }
 
+impl Import<'_> {
+    pub fn into_owned(self) -> Import<'static> {
+        Import {
+            file: self.file.into_owned(),
+            items: match self.items {
+                Items::All(name) => Items::All(name.into_owned()),
+                Items::List(items) => Items::List(
+                    items
+                        .into_iter()
+                        .map(|(name, alias)| (name.into_owned(), alias.map(Ident::into_owned)))
+                        .collect(),
+                ),
+            },
+            with_context: self.with_context,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Items<'i> {
-    All,
-    List(Vec<Ident<'i>>),
+    /// The whole module, bound to a name.
+    All(Ident<'i>),
+    /// Some of the module's exports, each optionally renamed (`name as alias`).
+    List(Vec<(Ident<'i>, Option<Ident<'i>>)>),
 }
 
 impl<'i> Display for Import<'i> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.items {
-            Items::All => {
+            Items::All(name) => {
                 f.write_str("{% import ")?;
                 self.file.fmt(f)?;
-                self.write_as(f)?;
-                self.write_context(f)?;
-                f.write_str("%}")
+                f.write_str(" as ")?;
+                name.fmt(f)?;
             }
             Items::List(items) => {
                 f.write_str("{% from ")?;
-
                 self.file.fmt(f)?;
-
                 f.write_str(" import ")?;
 
-                for (i, item) in items.iter().enumerate() {
-                    item.fmt(f)?;
-                    if i != 0 && i != (items.len() - 1) {
-                        f.write_char(',')?;
+                for (i, (name, alias)) in items.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    name.fmt(f)?;
+                    if let Some(alias) = alias {
+                        f.write_str(" as ")?;
+                        alias.fmt(f)?;
                     }
                 }
+            }
+        }
+        self.write_context(f)?;
+        f.write_str(" %}")
+    }
+}
 
-                self.write_as(f)?;
-                self.write_context(f)?;
-                f.write_str("%}")
+impl Render for Import<'_> {
+    fn render(&self, ctx: &mut Context, _: &mut dyn Output) -> RenderResult<()> {
+        let name = self.file.evaluate(ctx)?.to_string();
+        let module = module(&name, ctx, self.with_context)?;
+
+        match &self.items {
+            Items::All(alias) => ctx.insert(alias.name(), module),
+            Items::List(items) => {
+                for (item, alias) in items {
+                    let value = export(&module, &name, item.name())?;
+                    ctx.insert(alias.as_ref().unwrap_or(item).name(), value);
+                }
             }
         }
+
+        Ok(())
+    }
+}
+
+/// The item called `item` of the module made from the template called `name`.
+pub(crate) fn export(module: &Value, name: &str, item: &str) -> RenderResult<Value> {
+    match module.get_attr(item) {
+        Value::Undefined => Err(RenderError::InvalidOperation(format!(
+            "the template `{}` does not export `{}`",
+            name, item
+        ))),
+        value => Ok(value),
+    }
+}
+
+/// Renders the template called `name`, and collects its macros and top-level variables into a
+/// module (a dict of names to values). Names which start with an underscore are private, and so
+/// are left out.
+pub(crate) fn module(name: &str, ctx: &Context, with_context: bool) -> RenderResult<Value> {
+    let source = ctx.load(name)?;
+    let template = Template::parse_loaded(name, &source)?;
+
+    let _depth = ctx.descend()?;
+    let mut module_ctx = if with_context {
+        ctx.clone()
+    } else {
+        ctx.without_variables()
+    };
+
+    module_ctx.scoped(|module_ctx| {
+        // only the template's side effects matter; its output is thrown away
+        template.render(module_ctx, &mut String::new())?;
+
+        // macros only hold weak references to the scopes they were defined in, so the exported
+        // ones keep the module's scopes alive for them
+        let scopes = Rc::new(module_ctx.own_scopes(ctx));
+
+        let mut exports = module_ctx
+            .locals()
+            .into_iter()
+            .filter(|(key, _)| !key.starts_with('_'))
+            .collect::<Vec<_>>();
+        // so that the module is displayed the same way every time
+        exports.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Ok(Value::dict(
+            exports
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        Value::Function(function) => {
+                            let scopes = scopes.clone();
+                            Value::Function(Function::new(move |args, kwargs| {
+                                let _ = &scopes;
+                                function.call(args, kwargs)
+                            }))
+                        }
+                        value => value,
+                    };
+                    (Value::from(key.as_str()), value)
+                })
+                .collect(),
+        ))
+    })
+}
+
+impl Format for Import<'_> {
+    fn format(&self, printer: &mut Printer) {
+        let contents = printer.quoted(self.to_string());
+        // `Display` writes the whole tag
+        printer.tag(&contents["{% ".len()..contents.len() - " %}".len()]);
+    }
+}
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::codegen::Generate;
+
+    use super::{Import, Items};
+
+    impl Generate for Import<'_> {
+        fn generate(&self) -> TokenStream {
+            let file = self.file.generate();
+            let with_context = self.with_context;
+            let bind = match &self.items {
+                Items::All(alias) => {
+                    let alias = alias.name();
+                    quote! { ctx.insert(#alias, module); }
+                }
+                Items::List(items) => {
+                    let names = items.iter().map(|(item, _)| item.name());
+                    let aliases = items
+                        .iter()
+                        .map(|(item, alias)| alias.as_ref().unwrap_or(item).name());
+                    quote! {
+                        #(ctx.insert(#aliases, runtime::export(&module, &name, #names)?);)*
+                    }
+                }
+            };
+
+            quote! {{
+                let name = (#file).to_string();
+                let module = runtime::module(&name, ctx, #with_context)?;
+                #bind
+            }}
+        }
     }
 }
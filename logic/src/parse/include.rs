@@ -1,25 +1,56 @@
 use std::fmt::Display;
 
-use crate::parse::parse_multiple;
+use crate::{
+    format::{Format, Printer},
+    parse::{parse_multiple, parse_token, peek_multiple_bool},
+    render::{Context, Output, Render, RenderError, RenderResult, Value},
+};
 
-use super::{expr::Expr, peek_token_bool, Parse};
+use super::{expr::Expr, template::Template, Parse};
 
+/// `{% include 'header.html' %}`, or `{% include ['a.html', 'b.html'] ignore missing %}`
+///
+/// When given a list, the first template which exists is rendered.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Include<'i> {
-    files: Expr<'i>,
+    pub(crate) files: Expr<'i>,
     // `false` by default
-    ignore_missing: bool,
+    pub(crate) ignore_missing: bool,
     // `true` by default
-    with_context: bool,
+    pub(crate) with_context: bool,
+}
+
+impl<'i> Include<'i> {
+    pub fn new(files: Expr<'i>, ignore_missing: bool, with_context: bool) -> Self {
+        Self {
+            files,
+            ignore_missing,
+            with_context,
+        }
+    }
+
+    /// The name of the template to include, or a list of names.
+    pub fn files(&self) -> &Expr<'i> {
+        &self.files
+    }
+
+    pub fn ignore_missing(&self) -> bool {
+        self.ignore_missing
+    }
+
+    pub fn with_context(&self) -> bool {
+        self.with_context
+    }
 }
 
 impl<'i> Parse<'i> for Include<'i> {
     fn parse(input: &'i str) -> super::ParseResult<Self> {
         let (_, input) = parse_multiple(input, &["{%", "include"])?;
 
-        let (files, mut input) = Expr::parse(input)?;
+        let (files, mut input) = Expr::parse_bp(input, 0)?;
 
-        let ignore_missing = if peek_token_bool(input, "ignore") {
+        let ignore_missing = if peek_multiple_bool(input, &["ignore", "missing"]) {
             let (_, rest) = parse_multiple(input, &["ignore", "missing"])?;
             input = rest;
             true
@@ -27,18 +58,20 @@ impl<'i> Parse<'i> for Include<'i> {
             false
         };
 
-        let with_context = if peek_token_bool(input, "without") {
-            let (_, rest) = parse_multiple(input, &["with", "context"])?;
-            input = rest;
-            true
-        } else if peek_token_bool(input, "with") {
+        let with_context = if peek_multiple_bool(input, &["without", "context"]) {
             let (_, rest) = parse_multiple(input, &["without", "context"])?;
             input = rest;
             false
+        } else if peek_multiple_bool(input, &["with", "context"]) {
+            let (_, rest) = parse_multiple(input, &["with", "context"])?;
+            input = rest;
+            true
         } else {
             true
         };
 
+        let (_, input) = parse_token(input, "%}")?;
+
         Ok((
             Self {
                 files,
@@ -52,15 +85,93 @@ impl<'i> Parse<'i> for Include<'i> {
 
 impl Display for Include<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("{% include")?;
+        f.write_str("{% include ")?;
         self.files.fmt(f)?;
         if self.ignore_missing {
-            f.write_str("ignore missing")?;
+            f.write_str(" ignore missing")?;
+        }
+        if !self.with_context {
+            f.write_str(" without context")?;
         }
-        if self.with_context {
-            f.write_str("with context")?;
+        f.write_str(" %}")
+    }
+}
+
+impl Render for Include<'_> {
+    fn render(&self, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()> {
+        let files = self.files.evaluate(ctx)?;
+        include(files, self.ignore_missing, self.with_context, ctx, output)
+    }
+}
+
+/// Renders the first of `files` (a name, or a list of names) which the loader can find.
+pub(crate) fn include(
+    files: Value,
+    ignore_missing: bool,
+    with_context: bool,
+    ctx: &mut Context,
+    output: &mut dyn Output,
+) -> RenderResult<()> {
+    let names: Vec<String> = match files {
+        files @ Value::List(_) => files.iter()?.iter().map(ToString::to_string).collect(),
+        file => vec![file.to_string()],
+    };
+
+    let (name, source) = match names
+        .iter()
+        .find_map(|name| Some((name, ctx.load(name).ok()?)))
+    {
+        Some(found) => found,
+        None if ignore_missing => return Ok(()),
+        None => return Err(RenderError::TemplateNotFound(names.join(", "))),
+    };
+
+    let template = Template::parse_loaded(name, &source)?;
+
+    let _depth = ctx.descend()?;
+    if with_context {
+        // the included template sees our variables, but its own assignments stay inside it
+        ctx.scoped(|ctx| template.render(ctx, output))
+    } else {
+        template.render(&mut ctx.without_variables(), output)
+    }
+}
+
+impl Include<'_> {
+    pub fn into_owned(self) -> Include<'static> {
+        Include {
+            files: self.files.into_owned(),
+            ..self
+        }
+    }
+}
+
+impl Format for Include<'_> {
+    fn format(&self, printer: &mut Printer) {
+        let contents = printer.quoted(self.to_string());
+        // `Display` writes the whole tag
+        printer.tag(&contents["{% ".len()..contents.len() - " %}".len()]);
+    }
+}
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::codegen::Generate;
+
+    use super::Include;
+
+    impl Generate for Include<'_> {
+        fn generate(&self) -> TokenStream {
+            let files = self.files.generate();
+            let (ignore_missing, with_context) = (self.ignore_missing, self.with_context);
+
+            quote! {{
+                let files = #files;
+                runtime::include(files, #ignore_missing, #with_context, ctx, output)?;
+            }}
         }
-        f.write_str("%}")?;
-        todo!()
     }
 }
@@ -1,7 +1,10 @@
-use std::{borrow::BorrowMut, fmt::Display};
+use std::{borrow::Cow, fmt::Display};
 
-use crate::parse::{
-    ignore_whitespace, next, parse_multiple, parse_token, peek_token_bool, up_to, ParseError,
+use crate::{
+    parse::{
+        ignore_whitespace, next, parse_multiple, parse_token, peek_token_bool, up_to, ParseError,
+    },
+    render::Value,
 };
 
 use super::{peek_multiple_bool, Parse, ParseResult};
@@ -10,9 +13,12 @@ use super::{peek_multiple_bool, Parse, ParseResult};
 ///
 /// See https://jinja.palletsprojects.com/en/3.0.x/templates/#literals for more details.
 #[derive(Debug, Clone, PartialEq)]
-
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
 pub enum Literal<'i> {
-    String(&'i str),
+    /// Folding constants can create strings which do not appear in the source, so this is not
+    /// always borrowed.
+    String(Cow<'i, str>),
     Integer(i32),
     Float(f32),
     List(Vec<Literal<'i>>),
@@ -51,7 +57,10 @@ impl<'i> Parse<'i> for Literal<'i> {
             if input.starts_with('\"') || input.starts_with('\'') {
                 let (string, rest) = up_to(input.get(1..).unwrap(), &["\"", "'"])?;
 
-                return Ok((Self::String(string), rest.get(1..).unwrap_or("")));
+                return Ok((
+                    Self::String(Cow::Borrowed(string)),
+                    rest.get(1..).unwrap_or(""),
+                ));
             };
 
             if input.chars().next().unwrap().is_digit(10) {
@@ -73,28 +82,27 @@ impl<'i> Parse<'i> for Literal<'i> {
 }
 
 fn parse_list<'i>(input: &'i str) -> ParseResult<Vec<Literal<'i>>> {
-    ignore_whitespace(input, |input| {
-        let mut list = vec![];
-
-        let (_, mut input) = parse_token(input, "[")?;
+    let (_, mut input) = parse_token(input, "[")?;
 
-        loop {
-            let (literal, rest) = up_to(input, &[",", "]"])?;
-
-            input = rest;
+    let mut list = vec![];
 
-            let (literal, _) = Literal::parse(literal)?;
+    while !peek_token_bool(input, "]") {
+        let (literal, rest) = Literal::parse(input)?;
+        input = rest;
 
-            list.push(literal);
+        list.push(literal);
 
-            if peek_token_bool(input, "]") {
-                input = input.get(1..).unwrap_or("");
-                break;
-            }
+        if peek_token_bool(input, ",") {
+            let (_, rest) = parse_token(input, ",")?;
+            input = rest;
+        } else {
+            break;
         }
+    }
+
+    let (_, input) = parse_token(input, "]")?;
 
-        Ok((list, input))
-    })
+    Ok((list, input))
 }
 
 fn parse_dict<'i>(input: &'i str) -> ParseResult<Vec<(Literal<'i>, Literal<'i>)>> {
@@ -102,36 +110,26 @@ fn parse_dict<'i>(input: &'i str) -> ParseResult<Vec<(Literal<'i>, Literal<'i>)>
 
     let mut dict = vec![];
 
-    if peek_multiple_bool(input, &[",", "}"]) {
-        let (_, rest) = parse_multiple(input, &[",", ")"])?;
-        input = rest;
-        return Ok((dict, input));
-    }
-    if peek_token_bool(input, ")") {
-        let (_, rest) = parse_token(input, ")")?;
-        input = rest;
-        return Ok((dict, input));
-    }
-
-    loop {
+    while !peek_token_bool(input, "}") {
         let (key, rest) = Literal::parse(input)?;
 
         let (_, rest) = parse_token(rest, ":")?;
 
-        input = rest;
-
-        let (value, rest) = Literal::parse(input)?;
-
+        let (value, rest) = Literal::parse(rest)?;
         input = rest;
 
         dict.push((key, value));
 
-        let should_exit = peek_token_bool(input, "}") || peek_multiple_bool(input, &[",", "}"]);
-        if should_exit {
+        if peek_token_bool(input, ",") {
+            let (_, rest) = parse_token(input, ",")?;
+            input = rest;
+        } else {
             break;
         }
     }
 
+    let (_, input) = parse_token(input, "}")?;
+
     Ok((dict, input))
 }
 
@@ -140,36 +138,28 @@ fn parse_tuple<'i>(input: &'i str) -> ParseResult<Vec<Literal<'i>>> {
 
     let mut tuple = vec![];
 
+    // as in Python, `(,)` is the empty tuple too
     if peek_multiple_bool(input, &[",", ")"]) {
         let (_, rest) = parse_multiple(input, &[",", ")"])?;
-        input = rest;
-        return Ok((tuple, input));
-    }
-    if peek_token_bool(input, ")") {
-        let (_, rest) = parse_token(input, ")")?;
-        input = rest;
-        return Ok((tuple, input));
+        return Ok((tuple, rest));
     }
 
-    loop {
+    while !peek_token_bool(input, ")") {
         let (item, rest) = Literal::parse(input)?;
-
         input = rest;
 
         tuple.push(item);
 
-        if peek_multiple_bool(input, &[",", ")"]) {
-            let (_, rest) = parse_multiple(input, &[",", ")"])?;
-            input = rest;
-            break;
-        }
-        if peek_token_bool(input, ")") {
-            let (_, rest) = parse_token(input, ")")?;
+        if peek_token_bool(input, ",") {
+            let (_, rest) = parse_token(input, ",")?;
             input = rest;
+        } else {
             break;
         }
     }
 
+    let (_, input) = parse_token(input, ")")?;
+
     Ok((tuple, input))
 }
 
@@ -193,8 +183,8 @@ impl<'i> NumberParser<'i> {
     }
 
     /// If it's not a float, it's *probably* an integer (we'll see if this is true when testing.)
-    fn is_float(&self) -> bool {
-        self.float_part.is_some()
+    pub(crate) fn is_float(&self) -> bool {
+        self.float_part.is_some() || self.exponent_part.is_some()
     }
 
     fn into_float(self) -> Option<f32> {
@@ -230,66 +220,45 @@ impl<'i> Parse<'i> for NumberParser<'i> {
 
         let mut index = 0;
 
-        'parse_loop: loop {
-            match myself.state {
-                NumberParserState::IntPart => {
-                    if peek_token_bool(input, ".") {
-                        myself.state = NumberParserState::FloatPart;
-                        continue 'parse_loop;
-                    }
-                    if peek_token_bool(input, "e") {
-                        myself.state = NumberParserState::ExponentPart;
-                        continue 'parse_loop;
-                    }
-
-                    let c = next(input)?;
-                    if !c.is_digit(10) {
-                        break;
-                    }
-
-                    if let Some((_, stop)) = myself.int_part.borrow_mut() {
-                        *stop += 1;
-                    } else {
-                        *myself.int_part.borrow_mut() = Some((index, index));
-                    }
+        loop {
+            let rest = input.get(index..).unwrap_or("");
+            let digit_follows = |offset: usize| {
+                rest.get(offset..)
+                    .is_some_and(|rest| next(rest).is_ok_and(|c| c.is_ascii_digit()))
+            };
 
+            match myself.state {
+                NumberParserState::IntPart | NumberParserState::FloatPart
+                    if rest.starts_with('e') && digit_follows(1) =>
+                {
+                    myself.state = NumberParserState::ExponentPart;
                     index += 1;
+                    continue;
                 }
-                NumberParserState::FloatPart => {
-                    if peek_token_bool(input, ".") {
-                        myself.state = NumberParserState::FloatPart;
-                        continue 'parse_loop;
-                    }
-
-                    let c = next(input)?;
-                    if !c.is_digit(10) {
-                        break;
-                    }
-
-                    if let Some((_, stop)) = myself.int_part.borrow_mut() {
-                        *stop += 1;
-                    } else {
-                        *myself.int_part.borrow_mut() = Some((index, index));
-                    }
-
+                NumberParserState::IntPart if rest.starts_with('.') && digit_follows(1) => {
+                    myself.state = NumberParserState::FloatPart;
                     index += 1;
+                    continue;
                 }
-                NumberParserState::ExponentPart => {
-                    let c = next(input)?;
+                _ => {}
+            }
 
-                    if !c.is_digit(10) {
-                        break;
-                    }
+            if !digit_follows(0) {
+                break;
+            }
 
-                    if let Some((_, stop)) = myself.int_part.borrow_mut() {
-                        *stop += 1;
-                    } else {
-                        *myself.int_part.borrow_mut() = Some((index, index));
-                    }
+            let part = match myself.state {
+                NumberParserState::IntPart => &mut myself.int_part,
+                NumberParserState::FloatPart => &mut myself.float_part,
+                NumberParserState::ExponentPart => &mut myself.exponent_part,
+            };
 
-                    index += 1;
-                }
+            match part {
+                Some((_, stop)) => *stop = index + 1,
+                None => *part = Some((index, index + 1)),
             }
+
+            index += 1;
         }
 
         if index == 0 {
@@ -319,30 +288,31 @@ impl Display for Literal<'_> {
                 f.write_str("\"")
             }
             Literal::Integer(int) => int.fmt(f),
-            Literal::Float(float) => float.fmt(f),
+            // `Debug` always writes a decimal point (or an exponent), which keeps it a float
+            Literal::Float(float) => write!(f, "{:?}", float),
             Literal::List(l) => {
                 f.write_str("[")?;
-                for literal in l {
-                    literal.fmt(f)?;
-                    f.write_str(",")?;
-                }
+                write_separated(f, l)?;
                 f.write_str("]")
             }
             Literal::Tuple(t) => {
                 f.write_str("(")?;
-                for literal in t {
-                    literal.fmt(f)?;
+                write_separated(f, t)?;
+                // `(1)` is not a tuple
+                if t.len() == 1 {
                     f.write_str(",")?;
                 }
                 f.write_str(")")
             }
             Literal::Dict(d) => {
                 f.write_str("{")?;
-                for (key, value) in d {
+                for (index, (key, value)) in d.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
                     key.fmt(f)?;
-                    f.write_str(":")?;
+                    f.write_str(": ")?;
                     value.fmt(f)?;
-                    f.write_str(",")?;
                 }
                 f.write_str("}")
             }
@@ -350,3 +320,129 @@ impl Display for Literal<'_> {
         }
     }
 }
+
+fn write_separated(f: &mut std::fmt::Formatter<'_>, literals: &[Literal]) -> std::fmt::Result {
+    for (index, literal) in literals.iter().enumerate() {
+        if index > 0 {
+            f.write_str(", ")?;
+        }
+        literal.fmt(f)?;
+    }
+    Ok(())
+}
+
+impl Literal<'_> {
+    /// Converts a value back into a literal which evaluates to it, if there is one.
+    ///
+    /// Not every value can be written as a literal: there are no literals for functions or
+    /// `None`, negative numbers are written with an operator, and strings cannot contain quotes.
+    pub(crate) fn from_value(value: &Value) -> Option<Literal<'static>> {
+        Some(match value {
+            Value::Bool(b) => Literal::Bool(*b),
+            Value::Integer(int) if *int >= 0 => Literal::Integer(*int),
+            Value::Float(float) if float.is_finite() && float.is_sign_positive() => {
+                Literal::Float(*float)
+            }
+            Value::String(string) if !string.contains(&['"', '\''][..]) => {
+                Literal::String(Cow::Owned(string.clone()))
+            }
+            Value::List(items) => Literal::List(
+                items
+                    .borrow()
+                    .iter()
+                    .map(Literal::from_value)
+                    .collect::<Option<_>>()?,
+            ),
+            Value::Dict(pairs) => Literal::Dict(
+                pairs
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| {
+                        Some((Literal::from_value(key)?, Literal::from_value(value)?))
+                    })
+                    .collect::<Option<_>>()?,
+            ),
+            _ => return None,
+        })
+    }
+}
+
+impl Literal<'_> {
+    pub fn into_owned(self) -> Literal<'static> {
+        match self {
+            Literal::String(string) => Literal::String(Cow::Owned(string.into_owned())),
+            Literal::Integer(int) => Literal::Integer(int),
+            Literal::Float(float) => Literal::Float(float),
+            Literal::List(items) => {
+                Literal::List(items.into_iter().map(Literal::into_owned).collect())
+            }
+            Literal::Tuple(items) => {
+                Literal::Tuple(items.into_iter().map(Literal::into_owned).collect())
+            }
+            Literal::Dict(pairs) => Literal::Dict(
+                pairs
+                    .into_iter()
+                    .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                    .collect(),
+            ),
+            Literal::Bool(b) => Literal::Bool(b),
+        }
+    }
+}
+
+impl From<&Literal<'_>> for Value {
+    fn from(literal: &Literal<'_>) -> Self {
+        match literal {
+            Literal::String(string) => Value::String(string.to_string()),
+            Literal::Integer(int) => Value::Integer(*int),
+            Literal::Float(float) => Value::Float(*float),
+            Literal::List(items) | Literal::Tuple(items) => {
+                Value::list(items.iter().map(Value::from).collect())
+            }
+            Literal::Dict(pairs) => Value::dict(
+                pairs
+                    .iter()
+                    .map(|(key, value)| (key.into(), value.into()))
+                    .collect(),
+            ),
+            Literal::Bool(b) => Value::Bool(*b),
+        }
+    }
+}
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::codegen::Generate;
+
+    use super::Literal;
+
+    impl Generate for Literal<'_> {
+        fn generate(&self) -> TokenStream {
+            match self {
+                Literal::String(string) => {
+                    let string = string.as_ref();
+                    quote! { Value::String(String::from(#string)) }
+                }
+                Literal::Integer(int) => quote! { Value::Integer(#int) },
+                Literal::Float(float) => {
+                    // a float literal can be infinite, which Rust has no literal for
+                    let bits = float.to_bits();
+                    quote! { Value::Float(f32::from_bits(#bits)) }
+                }
+                Literal::List(items) | Literal::Tuple(items) => {
+                    let items = items.iter().map(Generate::generate);
+                    quote! { Value::list(vec![#(#items),*]) }
+                }
+                Literal::Dict(pairs) => {
+                    let keys = pairs.iter().map(|(key, _)| key.generate());
+                    let values = pairs.iter().map(|(_, value)| value.generate());
+                    quote! { Value::dict(vec![#((#keys, #values)),*]) }
+                }
+                Literal::Bool(b) => quote! { Value::Bool(#b) },
+            }
+        }
+    }
+}
@@ -1,171 +1,346 @@
-use std::fmt::{Display, Write};
+use std::fmt::Display;
+
+use crate::{
+    format::{Format, Printer},
+    optimise::optimise_body,
+    parse::{parse_multiple, peek_token_bool},
+    render::{
+        Context, Function, Kwargs, Output, Render, RenderError, RenderResult, Value, WeakContext,
+    },
+};
+
+use super::{
+    block::{into_owned_body, parse_body, Block},
+    expr::{into_owned_kwargs, Expr},
+    ident::Ident,
+    parse_token, Parse, ParseResult,
+};
+
+/// `{% macro name(a, b, c=1) %}...{% endmacro %}`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Macro<'i> {
+    pub(crate) name: Ident<'i>,
+    pub(crate) args: Vec<Ident<'i>>,
+    pub(crate) kwargs: Vec<(Ident<'i>, Expr<'i>)>,
+    pub(crate) body: Vec<Block<'i>>,
+}
 
-use crate::parse::{peek_multiple, peek_token};
+impl<'i> Macro<'i> {
+    /// A macro taking the positional parameters `args`, followed by the parameters in `kwargs`
+    /// (each with its default value).
+    pub fn new(
+        name: Ident<'i>,
+        args: Vec<Ident<'i>>,
+        kwargs: Vec<(Ident<'i>, Expr<'i>)>,
+        body: Vec<Block<'i>>,
+    ) -> Self {
+        Self {
+            name,
+            args,
+            kwargs,
+            body,
+        }
+    }
 
-use super::{block::Block, expr::Expr, ident::Ident, parse_token, Parse, ParseResult};
+    pub fn name(&self) -> &Ident<'i> {
+        &self.name
+    }
 
-#[derive(Clone, PartialEq, Debug)]
+    /// The parameters which do not have defaults.
+    pub fn args(&self) -> &[Ident<'i>] {
+        &self.args
+    }
 
-pub struct Macro<'i> {
-    name: Ident<'i>,
-    args: Vec<Ident<'i>>,
-    kwargs: Vec<(Ident<'i>, Expr<'i>)>,
-    ast: Box<Block<'i>>,
+    /// The parameters which have defaults, with their defaults.
+    pub fn kwargs(&self) -> &[(Ident<'i>, Expr<'i>)] {
+        &self.kwargs
+    }
+
+    pub fn body(&self) -> &[Block<'i>] {
+        &self.body
+    }
 }
 
 impl<'i> Parse<'i> for Macro<'i> {
     fn parse(input: &'i str) -> super::ParseResult<Self> {
-        // todo: make parsing more forgiving
-        let (_, input) = parse_token(input, "{% macro")?;
+        let (_, input) = parse_multiple(input, &["{%", "macro"])?;
 
         let (name, input) = Ident::parse(input)?;
 
-        let (args, input) = ArgParser::parse(input)?;
+        let ((args, kwargs), input) = parse_params(input)?;
 
-        let (args, kwargs) = args.take();
-
-        let (_, input) = parse_token(input, "-%}")?;
-
-        let (ast, input) = Block::parse(input)?;
-        let ast = box (ast);
+        let (_, input) = parse_token(input, "%}")?;
 
-        let (_, input) = parse_token(input, "{%-")?;
+        let (body, input) = parse_body(input, &["endmacro"])?;
 
-        let (_, input) = parse_token(input, "endmacro")?;
-
-        let (_, input) = parse_token(input, "%}")?;
+        let (_, input) = parse_multiple(input, &["{%", "endmacro", "%}"])?;
 
         Ok((
             Self {
                 name,
                 args,
                 kwargs,
-                ast,
+                body,
             },
             input,
         ))
     }
 }
 
-impl Display for Macro<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("{% macro ")?;
+type Params<'i> = (Vec<Ident<'i>>, Vec<(Ident<'i>, Expr<'i>)>);
 
-        self.name.fmt(f)?;
+/// Parses `(a, b, c=1)`. Arguments with defaults must come after those without.
+fn parse_params(input: &str) -> ParseResult<Params> {
+    let (_, mut input) = parse_token(input, "(")?;
+
+    let mut args = vec![];
+    let mut kwargs = vec![];
+
+    while !peek_token_bool(input, ")") {
+        let start = input;
+        let (ident, rest) = Ident::parse(input)?;
+        input = rest;
+
+        if peek_token_bool(input, "=") {
+            let (_, rest) = parse_token(input, "=")?;
+            let (default, rest) = Expr::parse_bp(rest, 0)?;
+            input = rest;
+
+            kwargs.push((ident, default));
+        } else if kwargs.is_empty() {
+            args.push(ident);
+        } else {
+            let ident = &start[..start.len() - input.len()];
+            return Err(super::ParseError::UnexpectedToken(ident.trim_start()));
+        }
 
-        f.write_str("{%- endmacro %}")
+        if peek_token_bool(input, ",") {
+            let (_, rest) = parse_token(input, ",")?;
+            input = rest;
+        } else {
+            break;
+        }
     }
-}
 
-struct ArgFmt<'i> {
-    args: Vec<Ident<'i>>,
-    kwargs: Vec<(Ident<'i>, Expr<'i>)>,
+    let (_, input) = parse_token(input, ")")?;
+
+    Ok(((args, kwargs), input))
 }
 
-impl Display for ArgFmt<'_> {
+impl Display for Macro<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for arg in &self.args {
-            arg.fmt(f)?;
-            f.write_char(',')?;
-        }
-        for (ident, expr) in &self.kwargs {
-            ident.fmt(f)?;
-            f.write_char('=')?;
-            expr.fmt(f)?;
-            f.write_char(',')?;
+        f.write_str("{% macro ")?;
+        self.name.fmt(f)?;
+        f.write_str("(")?;
+
+        let params = self
+            .args
+            .iter()
+            .map(|arg| arg.to_string())
+            .chain(
+                self.kwargs
+                    .iter()
+                    .map(|(ident, default)| format!("{}={}", ident, default)),
+            )
+            .collect::<Vec<_>>();
+        f.write_str(&params.join(", "))?;
+
+        f.write_str(") %}")?;
+        for block in &self.body {
+            block.fmt(f)?;
         }
-        Ok(())
+        f.write_str("{% endmacro %}")
     }
 }
 
-struct ArgParser<'i> {
-    state: ArgParserState,
-    pub(crate) args: Vec<Ident<'i>>,
-    pub(crate) kwargs: Vec<(Ident<'i>, Expr<'i>)>,
+impl Render for Macro<'_> {
+    /// Defining a macro binds its name to a function; nothing is written to the output.
+    fn render(&self, ctx: &mut Context, _: &mut dyn Output) -> RenderResult<()> {
+        // the function outlives the template, so it needs its own copy of the macro
+        let r#macro = self.clone().into_owned();
+        // free names are looked up when the macro is called, in the scopes where it was defined
+        let defined_in = ctx.downgrade();
+        let function = Function::new(move |args, kwargs| {
+            r#macro.call(defining_context(&defined_in)?, args, kwargs)
+        });
+        ctx.insert(self.name.name(), function.into());
+        Ok(())
+    }
 }
 
-impl<'i> ArgParser<'i> {
-    fn new() -> Self {
+impl<'i> Macro<'i> {
+    pub(crate) fn optimise(self) -> Self {
         Self {
-            state: ArgParserState::Args,
-            args: Default::default(),
-            kwargs: Default::default(),
+            kwargs: self
+                .kwargs
+                .into_iter()
+                .map(|(ident, default)| (ident, default.optimise()))
+                .collect(),
+            body: optimise_body(self.body),
+            ..self
         }
     }
+}
 
-    fn take(self) -> (Vec<Ident<'i>>, Vec<(Ident<'i>, Expr<'i>)>) {
-        (self.args, self.kwargs)
+impl Macro<'_> {
+    pub fn into_owned(self) -> Macro<'static> {
+        Macro {
+            name: self.name.into_owned(),
+            args: self.args.into_iter().map(Ident::into_owned).collect(),
+            kwargs: into_owned_kwargs(self.kwargs),
+            body: into_owned_body(self.body),
+        }
     }
-}
 
-impl<'i> Parse<'i> for ArgParser<'i> {
-    fn parse(input: &'i str) -> super::ParseResult<Self> {
-        let mut myself = ArgParser::new();
-
-        let (_, mut input) = parse_token(input, "(")?;
-
-        'parse_loop: loop {
-            match myself.state {
-                ArgParserState::Args => {
-                    let (ident, rest) = Ident::parse(input)?;
-
-                    input = rest;
-
-                    if peek_token(input, "=").unwrap_or((false, "")).0 {
-                        let (expr, rest) = Expr::parse(input)?;
-                        input = rest;
-
-                        myself.kwargs.push((ident, expr));
-
-                        myself.state = ArgParserState::VarArgs;
-                        continue 'parse_loop;
-                    }
-
-                    myself.args.push(ident);
-
-                    let (should_exit, _) =
-                        match (peek_token(input, ")"), peek_multiple(input, &[",", ")"])) {
-                            (_, Ok(m)) => m,
-                            (Ok(m), Err(_)) => m,
-                            (Err(_), Err(e)) => return Err(e),
-                        };
-
-                    if should_exit {
-                        break;
-                    }
-                }
-                ArgParserState::VarArgs => {
-                    let (arg, rest) = parse_kwarg(input)?;
-                    myself.kwargs.push(arg);
-                    input = rest;
-
-                    let (should_exit, _) =
-                        match (peek_token(input, ")"), peek_multiple(input, &[",", ")"])) {
-                            (_, Ok(m)) => m,
-                            (Ok(m), Err(_)) => m,
-                            (Err(_), Err(e)) => return Err(e),
-                        };
-
-                    if should_exit {
-                        break;
-                    }
-                }
+    fn call(&self, mut ctx: Context, args: Vec<Value>, kwargs: Kwargs) -> RenderResult<Value> {
+        let _depth = ctx.descend()?;
+        let params = self
+            .args
+            .iter()
+            .chain(self.kwargs.iter().map(|(ident, _)| ident))
+            .map(Ident::name)
+            .collect::<Vec<_>>();
+        let values = bind_args(self.name.name(), &params, args, kwargs)?;
+
+        ctx.scoped(|ctx| {
+            let defaults = self
+                .args
+                .iter()
+                .map(|_| None)
+                .chain(self.kwargs.iter().map(|(_, default)| Some(default)));
+
+            for ((name, value), default) in params.iter().zip(values).zip(defaults) {
+                let value = match (value, default) {
+                    (Some(value), _) => value,
+                    // defaults can refer to the parameters before them
+                    (None, Some(default)) => default.evaluate(ctx)?,
+                    (None, None) => Value::Undefined,
+                };
+                ctx.insert(*name, value);
             }
-        }
 
-        Ok((myself, input))
+            let mut output = String::new();
+            self.body.render(ctx, &mut output)?;
+            Ok(Value::String(output))
+        })
     }
 }
 
-enum ArgParserState {
-    Args,
-    VarArgs,
+/// The context of the template which defined a macro, which the macro's body is rendered in.
+pub(crate) fn defining_context(defined_in: &WeakContext) -> RenderResult<Context> {
+    defined_in.upgrade().ok_or_else(|| {
+        RenderError::InvalidOperation(
+            "the template which defined this macro is no longer being rendered".into(),
+        )
+    })
 }
 
-fn parse_kwarg<'i>(input: &'i str) -> ParseResult<(Ident<'i>, Expr<'i>)> {
-    let (ident, input) = Ident::parse(input)?;
+/// Matches the arguments of a call to the macro called `name` with its parameters. Each parameter
+/// gets the argument in its position, or else the keyword argument with its name, or else `None`
+/// (for the caller to fill in with its default).
+pub(crate) fn bind_args(
+    name: &str,
+    params: &[&str],
+    args: Vec<Value>,
+    mut kwargs: Kwargs,
+) -> RenderResult<Vec<Option<Value>>> {
+    if args.len() > params.len() {
+        return Err(RenderError::InvalidOperation(format!(
+            "macro `{}` takes at most {} argument(s)",
+            name,
+            params.len()
+        )));
+    }
 
-    let (expr, input) = Expr::parse(input)?;
+    let mut args = args.into_iter();
+    let values = params
+        .iter()
+        .map(|param| {
+            args.next().or_else(|| {
+                let index = kwargs.iter().position(|(key, _)| key == param)?;
+                Some(kwargs.remove(index).1)
+            })
+        })
+        .collect();
+
+    match kwargs.first() {
+        Some((key, _)) => Err(RenderError::InvalidOperation(format!(
+            "macro `{}` has no argument `{}`",
+            name, key
+        ))),
+        None => Ok(values),
+    }
+}
+
+impl Format for Macro<'_> {
+    fn format(&self, printer: &mut Printer) {
+        let params = self
+            .args
+            .iter()
+            .map(ToString::to_string)
+            .chain(
+                self.kwargs
+                    .iter()
+                    .map(|(ident, default)| format!("{}={}", ident, printer.expr(default))),
+            )
+            .collect::<Vec<_>>();
+        printer.tag(&format!("macro {}({})", self.name, params.join(", ")));
+        printer.body(&self.body);
+        printer.tag("endmacro");
+    }
+}
 
-    Ok(((ident, expr), input))
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::codegen::Generate;
+
+    use super::{Ident, Macro};
+
+    impl Generate for Macro<'_> {
+        fn generate(&self) -> TokenStream {
+            let name = self.name.name();
+            let params = self
+                .args
+                .iter()
+                .chain(self.kwargs.iter().map(|(ident, _)| ident))
+                .map(Ident::name)
+                .collect::<Vec<_>>();
+            let defaults = self
+                .args
+                .iter()
+                .map(|_| quote!(Value::Undefined))
+                .chain(self.kwargs.iter().map(|(_, default)| default.generate()));
+            let body = self.body.generate();
+
+            // see `call`
+            quote! {{
+                let function = runtime::define_macro(
+                    ctx,
+                    #name,
+                    &[#(#params),*],
+                    |ctx: &mut Context, values: Vec<Option<Value>>| -> RenderResult<Value> {
+                        let mut values = values.into_iter();
+                        #(
+                            let value = match values.next().flatten() {
+                                Some(value) => value,
+                                None => #defaults,
+                            };
+                            ctx.insert(#params, value);
+                        )*
+                        let mut captured = String::new();
+                        {
+                            let output = &mut captured;
+                            #body
+                        }
+                        Ok(Value::String(captured))
+                    },
+                );
+                ctx.insert(#name, function);
+            }}
+        }
+    }
 }
@@ -9,22 +9,25 @@
 //! todo: investigate using SIMD for faster parsing
 //! todo: better error messages
 
-mod block;
+pub mod ast;
+pub(crate) mod block;
 mod call;
+mod r#do;
 mod r#else;
-mod expr;
+pub(crate) mod expr;
 mod filter;
 mod r#for;
 mod ident;
 mod r#if;
-mod import;
-mod include;
-mod literal;
-mod r#macro;
+pub(crate) mod import;
+pub(crate) mod include;
+pub(crate) mod literal;
+pub(crate) mod r#macro;
 mod set;
-mod stmt;
+pub(crate) mod stmt;
 mod template;
 mod utils;
+mod with;
 
 pub(crate) use utils::*;
 
@@ -2,14 +2,41 @@
 
 use std::fmt::{Display, Write};
 
-use crate::parse::{ident::ident_list, parse_token};
+use crate::{
+    compile::{Compile, Compiler, Instruction},
+    format::{Format, Printer},
+    optimise::optimise_body,
+    parse::{bracketed::parse_delimited, parse_multiple, parse_token},
+    render::{Context, Output, Render, RenderResult, Value},
+};
 
-use super::{block::Block, expr::Expr, ident::Ident, peek_token_bool, Parse};
+use super::{
+    block::{into_owned_body, parse_body, Block},
+    expr::Expr,
+    ident::Ident,
+    peek_token_bool, Parse,
+};
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Set<'i> {
-    idents: Vec<Ident<'i>>,
-    data: SetData<'i>,
+    pub(crate) targets: Vec<SetTarget<'i>>,
+    pub(crate) data: SetData<'i>,
+}
+
+impl<'i> Set<'i> {
+    pub fn new(targets: Vec<SetTarget<'i>>, data: SetData<'i>) -> Self {
+        Self { targets, data }
+    }
+
+    /// What is assigned to (more than one target if the value is unpacked).
+    pub fn targets(&self) -> &[SetTarget<'i>] {
+        &self.targets
+    }
+
+    pub fn data(&self) -> &SetData<'i> {
+        &self.data
+    }
 }
 
 impl<'i> Parse<'i> for Set<'i> {
@@ -17,31 +44,42 @@ impl<'i> Parse<'i> for Set<'i> {
         let (_, input) = parse_token(input, "{%")?;
         let (_, input) = parse_token(input, "set")?;
 
-        let (idents, input) = ident_list(input)?;
+        let (targets, mut input) = parse_delimited::<SetTarget>(input, ",")?;
+
+        if peek_token_bool(input, "%}") || peek_token_bool(input, "|") {
+            let mut filters = vec![];
+            while peek_token_bool(input, "|") {
+                let (_, rest) = parse_token(input, "|")?;
+                let (filter, rest) = Expr::parse_filter(rest)?;
+                input = rest;
+
+                filters.push(filter);
+            }
 
-        if peek_token_bool(input, "%}") {
             let (_, input) = parse_token(input, "%}")?;
 
-            let (ast, input) = Block::parse(input)?;
+            let (body, input) = parse_body(input, &["endset"])?;
 
-            let (_, input) = parse_token(input, "{% endset %}")?;
+            let (_, input) = parse_multiple(input, &["{%", "endset", "%}"])?;
 
             return Ok((
                 Self {
-                    idents,
-                    data: SetData::Block(box (ast)),
+                    targets,
+                    data: SetData::Block(body, filters),
                 },
                 input,
             ));
         }
 
-        let (expr, input) = Expr::parse(input)?;
+        let (_, input) = parse_token(input, "=")?;
+
+        let (expr, input) = Expr::parse_bp(input, 0)?;
 
         let (_, input) = parse_token(input, "%}")?;
 
         Ok((
             Self {
-                idents,
+                targets,
                 data: SetData::Expr(expr),
             },
             input,
@@ -52,35 +90,303 @@ impl<'i> Parse<'i> for Set<'i> {
 impl Display for Set<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str("{% set ")?;
-        for (index, ident) in self.idents.iter().enumerate() {
-            ident.fmt(f)?;
-            if index > 0 && index != self.idents.len() - 1 {
-                f.write_char(',')?;
+        for (index, target) in self.targets.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
             }
+            target.fmt(f)?;
         }
         f.write_char(' ')?;
         self.data.fmt(f)
     }
 }
 
+impl Render for Set<'_> {
+    fn render(&self, ctx: &mut Context, _output: &mut dyn Output) -> RenderResult<()> {
+        let value = match &self.data {
+            SetData::Expr(expr) => expr.evaluate(ctx)?,
+            SetData::Block(body, filters) => {
+                let mut captured = String::new();
+                body.render(ctx, &mut captured)?;
+                filters
+                    .iter()
+                    .try_fold(Value::String(captured), |value, filter| {
+                        filter.apply_filter(value, ctx)
+                    })?
+            }
+        };
+
+        if let [target] = self.targets.as_slice() {
+            return target.assign(ctx, value);
+        }
+
+        for (target, value) in self.targets.iter().zip(value.unpack(self.targets.len())?) {
+            target.assign(ctx, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'i> Compile<'i> for Set<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        match &self.data {
+            SetData::Expr(expr) => expr.compile(compiler),
+            SetData::Block(body, filters) => {
+                compiler.emit(Instruction::BeginCapture);
+                body.compile(compiler);
+                compiler.emit(Instruction::EndCapture);
+                for filter in filters {
+                    filter.compile_filter(compiler);
+                }
+            }
+        }
+
+        if let [target] = self.targets.as_slice() {
+            return target.compile_assign(compiler);
+        }
+
+        compiler.emit(Instruction::Unpack(self.targets.len()));
+        for target in &self.targets {
+            target.compile_assign(compiler);
+        }
+    }
+}
+
+impl<'i> Set<'i> {
+    pub(crate) fn optimise(self) -> Self {
+        let data = match self.data {
+            SetData::Expr(expr) => SetData::Expr(expr.optimise()),
+            SetData::Block(body, filters) => SetData::Block(
+                optimise_body(body),
+                filters.into_iter().map(Expr::optimise).collect(),
+            ),
+        };
+        Self { data, ..self }
+    }
+}
+
+impl Set<'_> {
+    pub fn into_owned(self) -> Set<'static> {
+        Set {
+            targets: self
+                .targets
+                .into_iter()
+                .map(|target| match target {
+                    SetTarget::Ident(ident) => SetTarget::Ident(ident.into_owned()),
+                    SetTarget::Attr(ident, attr) => {
+                        SetTarget::Attr(ident.into_owned(), attr.into_owned())
+                    }
+                })
+                .collect(),
+            data: match self.data {
+                SetData::Expr(expr) => SetData::Expr(expr.into_owned()),
+                SetData::Block(body, filters) => SetData::Block(
+                    into_owned_body(body),
+                    filters.into_iter().map(Expr::into_owned).collect(),
+                ),
+            },
+        }
+    }
+}
+
+/// Something which can be assigned to.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum SetTarget<'i> {
+    /// `{% set name = ... %}`
+    Ident(Ident<'i>),
+    /// `{% set ns.attr = ... %}` (only valid when `ns` is a namespace)
+    Attr(Ident<'i>, Ident<'i>),
+}
+
+impl<'i> Parse<'i> for SetTarget<'i> {
+    fn parse(input: &'i str) -> super::ParseResult<Self> {
+        let (ident, input) = Ident::parse(input)?;
+
+        if peek_token_bool(input, ".") {
+            let (_, input) = parse_token(input, ".")?;
+            let (attr, input) = Ident::parse(input)?;
+
+            return Ok((Self::Attr(ident, attr), input));
+        }
+
+        Ok((Self::Ident(ident), input))
+    }
+}
+
+impl SetTarget<'_> {
+    /// Binds the value in the innermost scope (which means that assignments made inside a loop
+    /// or a `with` block are not visible outside of it – namespaces are the way out).
+    fn assign(&self, ctx: &mut Context, value: Value) -> RenderResult<()> {
+        match self {
+            SetTarget::Ident(ident) => {
+                ctx.insert(ident.name(), value);
+                Ok(())
+            }
+            SetTarget::Attr(ident, attr) => ctx
+                .get(ident.name())
+                .unwrap_or(Value::Undefined)
+                .set_attr(attr.name(), value),
+        }
+    }
+}
+
+impl<'i> SetTarget<'i> {
+    /// Compiles an assignment of the value on top of the stack (see `assign`).
+    fn compile_assign(&'i self, compiler: &mut Compiler<'i>) {
+        match self {
+            SetTarget::Ident(ident) => compiler.emit(Instruction::StoreVar(ident.name())),
+            SetTarget::Attr(ident, attr) => {
+                compiler.emit(Instruction::StoreAttr(ident.name(), attr.name()))
+            }
+        };
+    }
+}
+
+impl Display for SetTarget<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SetTarget::Ident(ident) => ident.fmt(f),
+            SetTarget::Attr(ident, attr) => {
+                ident.fmt(f)?;
+                f.write_char('.')?;
+                attr.fmt(f)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum SetData<'i> {
+    /// `{% set x = expr %}`
     Expr(Expr<'i>),
-    Block(Box<Block<'i>>),
+    /// `{% set x | filter %}block{% endset %}` – the rendered block, passed through the filters
+    /// from left to right.
+    Block(Vec<Block<'i>>, Vec<Expr<'i>>),
 }
 
 impl Display for SetData<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             SetData::Expr(expr) => {
+                f.write_str("= ")?;
                 expr.fmt(f)?;
-                f.write_str("%}")
+                f.write_str(" %}")
             }
-            SetData::Block(ast) => {
+            SetData::Block(body, filters) => {
+                for filter in filters {
+                    f.write_str("| ")?;
+                    filter.fmt(f)?;
+                    f.write_char(' ')?;
+                }
                 f.write_str("%}")?;
-                ast.fmt(f)?;
+                for block in body {
+                    block.fmt(f)?;
+                }
                 f.write_str("{% endset %}")
             }
         }
     }
 }
+
+impl Format for Set<'_> {
+    fn format(&self, printer: &mut Printer) {
+        let targets = self
+            .targets
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        match &self.data {
+            SetData::Expr(expr) => {
+                let expr = printer.expr(expr);
+                printer.tag(&format!("set {} = {}", targets, expr));
+            }
+            SetData::Block(body, filters) => {
+                let filters = filters
+                    .iter()
+                    .map(|filter| format!(" | {}", printer.expr(filter)))
+                    .collect::<String>();
+                printer.tag(&format!("set {}{}", targets, filters));
+                printer.body(body);
+                printer.tag("endset");
+            }
+        }
+    }
+}
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::codegen::Generate;
+
+    use super::{Set, SetData, SetTarget};
+
+    impl Generate for Set<'_> {
+        fn generate(&self) -> TokenStream {
+            let value = match &self.data {
+                SetData::Expr(expr) => expr.generate(),
+                SetData::Block(body, filters) => {
+                    let body = body.generate();
+                    let filters = filters
+                        .iter()
+                        .map(|filter| filter.generate_filter(quote!(value)));
+                    quote! {{
+                        let mut captured = String::new();
+                        {
+                            let output = &mut captured;
+                            #body
+                        }
+                        let value = Value::String(captured);
+                        #(let value = #filters;)*
+                        value
+                    }}
+                }
+            };
+
+            let assign = if let [target] = self.targets.as_slice() {
+                target.generate_assign()
+            } else {
+                let n = self.targets.len();
+                let assigns = self.targets.iter().map(SetTarget::generate_assign);
+                quote! {
+                    // `unpack` returns exactly `n` values
+                    let mut values = value.unpack(#n)?.into_iter();
+                    #(
+                        let value = values.next().unwrap_or(Value::Undefined);
+                        #assigns
+                    )*
+                }
+            };
+
+            quote! {{
+                let value = #value;
+                #assign
+            }}
+        }
+    }
+
+    impl SetTarget<'_> {
+        /// Generates the code which assigns `value` (see `assign`).
+        fn generate_assign(&self) -> TokenStream {
+            match self {
+                SetTarget::Ident(ident) => {
+                    let name = ident.name();
+                    quote! { ctx.insert(#name, value); }
+                }
+                SetTarget::Attr(ident, attr) => {
+                    let (name, attr) = (ident.name(), attr.name());
+                    quote! {
+                        ctx.get(#name)
+                            .unwrap_or(Value::Undefined)
+                            .set_attr(#attr, value)?;
+                    }
+                }
+            }
+        }
+    }
+}
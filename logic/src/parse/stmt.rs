@@ -1,14 +1,20 @@
 use std::fmt::Display;
 
-use crate::parse::{ignore_whitespace, peek_multiple_bool, r#macro::Macro};
+use crate::{
+    compile::{Compile, Compiler, Instruction},
+    format::{Format, Printer},
+    parse::{ignore_whitespace, peek_multiple_bool, r#macro::Macro},
+    render::{Context, Output, Render, RenderError, RenderResult},
+};
 
 use super::{
-    filter::Filter, import::Import, include::Include, r#else::Else, r#for::ForStmt, r#if::If,
-    set::Set, Parse, ParseError,
+    block::Block, filter::Filter, import::Import, include::Include, r#do::Do, r#else::Else,
+    r#for::ForStmt, r#if::If, set::Set, with::With, Parse, ParseError,
 };
 
 #[derive(Debug, Clone, PartialEq)]
-
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
 pub enum Stmt<'i> {
     For(Box<ForStmt<'i>>, Option<Box<Else<'i>>>),
     If(Box<If<'i>>),
@@ -17,6 +23,8 @@ pub enum Stmt<'i> {
     Set(Set<'i>),
     Include(Include<'i>),
     Import(Import<'i>),
+    With(With<'i>),
+    Do(Do<'i>),
 }
 
 impl<'i> Parse<'i> for Stmt<'i> {
@@ -52,6 +60,14 @@ impl<'i> Parse<'i> for Stmt<'i> {
                 let (import, leftover) = Import::parse(input)?;
 
                 return Ok((Self::Import(import), leftover));
+            } else if peek_multiple_bool(input, &["{%", "with"]) {
+                let (with, leftover) = With::parse(input)?;
+
+                return Ok((Self::With(with), leftover));
+            } else if peek_multiple_bool(input, &["{%", "do"]) {
+                let (stmt, leftover) = Do::parse(input)?;
+
+                return Ok((Self::Do(stmt), leftover));
             } else {
                 return Err(ParseError::UnexpectedToken(input.get(0..).unwrap()));
             }
@@ -75,6 +91,132 @@ impl Display for Stmt<'_> {
             Stmt::Set(set) => set.fmt(f),
             Stmt::Include(i) => i.fmt(f),
             Stmt::Import(i) => i.fmt(f),
+            Stmt::With(w) => w.fmt(f),
+            Stmt::Do(d) => d.fmt(f),
+        }
+    }
+}
+
+impl Render for Stmt<'_> {
+    fn render(&self, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()> {
+        match self {
+            Stmt::For(stmt, None) => stmt.render(ctx, output),
+            Stmt::For(_, Some(_)) => Err(RenderError::Unsupported("for ... else")),
+            Stmt::If(i) => i.render(ctx, output),
+            Stmt::Macro(m) => m.render(ctx, output),
+            Stmt::Filter(filter) => filter.render(ctx, output),
+            Stmt::Set(set) => set.render(ctx, output),
+            Stmt::Include(i) => i.render(ctx, output),
+            Stmt::Import(i) => i.render(ctx, output),
+            Stmt::With(w) => w.render(ctx, output),
+            Stmt::Do(d) => d.render(ctx, output),
+        }
+    }
+}
+
+impl<'i> Compile<'i> for Stmt<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        match self {
+            Stmt::For(stmt, None) => stmt.compile(compiler),
+            Stmt::For(_, Some(_)) => {
+                compiler.emit(Instruction::Fail(RenderError::Unsupported("for ... else")));
+            }
+            Stmt::If(i) => i.compile(compiler),
+            Stmt::Filter(filter) => filter.compile(compiler),
+            Stmt::Set(set) => set.compile(compiler),
+            Stmt::With(w) => w.compile(compiler),
+            Stmt::Do(d) => d.compile(compiler),
+            // these are rarely hot, so they are not worth lowering
+            Stmt::Macro(_) | Stmt::Include(_) | Stmt::Import(_) => {
+                compiler.emit(Instruction::Render(self));
+            }
+        }
+    }
+}
+
+impl<'i> Stmt<'i> {
+    /// See `Block::optimise_into`.
+    pub(crate) fn optimise_into(self, out: &mut Vec<Block<'i>>) {
+        let stmt = match self {
+            Stmt::For(stmt, None) => Stmt::For(Box::new(stmt.optimise()), None),
+            Stmt::If(i) => return i.optimise_into(out),
+            Stmt::Filter(filter) => Stmt::Filter(filter.optimise()),
+            Stmt::Set(set) => Stmt::Set(set.optimise()),
+            Stmt::With(w) => Stmt::With(w.optimise()),
+            Stmt::Do(d) => Stmt::Do(d.optimise()),
+            Stmt::Macro(m) => Stmt::Macro(m.optimise()),
+            Stmt::For(_, Some(_)) | Stmt::Include(_) | Stmt::Import(_) => self,
+        };
+        out.push(Block::Stmt(stmt));
+    }
+}
+
+impl Stmt<'_> {
+    pub fn into_owned(self) -> Stmt<'static> {
+        match self {
+            Stmt::For(stmt, r#else) => Stmt::For(
+                Box::new(stmt.into_owned()),
+                r#else.map(|r#else| Box::new(r#else.into_owned())),
+            ),
+            Stmt::If(i) => Stmt::If(Box::new(i.into_owned())),
+            Stmt::Macro(m) => Stmt::Macro(m.into_owned()),
+            Stmt::Filter(filter) => Stmt::Filter(filter.into_owned()),
+            Stmt::Set(set) => Stmt::Set(set.into_owned()),
+            Stmt::Include(i) => Stmt::Include(i.into_owned()),
+            Stmt::Import(i) => Stmt::Import(i.into_owned()),
+            Stmt::With(w) => Stmt::With(w.into_owned()),
+            Stmt::Do(d) => Stmt::Do(d.into_owned()),
+        }
+    }
+}
+
+impl Format for Stmt<'_> {
+    fn format(&self, printer: &mut Printer) {
+        match self {
+            Stmt::For(stmt, else_branch) => {
+                stmt.format(printer);
+                if let Some(else_branch) = else_branch {
+                    else_branch.format(printer);
+                }
+                printer.tag("endfor");
+            }
+            Stmt::If(i) => i.format(printer),
+            Stmt::Macro(m) => m.format(printer),
+            Stmt::Filter(filter) => filter.format(printer),
+            Stmt::Set(set) => set.format(printer),
+            Stmt::Include(i) => i.format(printer),
+            Stmt::Import(i) => i.format(printer),
+            Stmt::With(w) => w.format(printer),
+            Stmt::Do(d) => d.format(printer),
+        }
+    }
+}
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::codegen::Generate;
+
+    use super::Stmt;
+
+    impl Generate for Stmt<'_> {
+        fn generate(&self) -> TokenStream {
+            match self {
+                Stmt::For(stmt, None) => stmt.generate(),
+                Stmt::For(_, Some(_)) => {
+                    quote! { return Err(RenderError::Unsupported("for ... else")); }
+                }
+                Stmt::If(i) => i.generate(),
+                Stmt::Macro(m) => m.generate(),
+                Stmt::Filter(filter) => filter.generate(),
+                Stmt::Set(set) => set.generate(),
+                Stmt::Include(i) => i.generate(),
+                Stmt::Import(i) => i.generate(),
+                Stmt::With(w) => w.generate(),
+                Stmt::Do(d) => d.generate(),
+            }
         }
     }
 }
@@ -1,11 +1,45 @@
-use std::{fmt::Display, path::PathBuf};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
 
-use super::{block::Block, Parse, ParseResult};
+use crate::{
+    compile::{Compile, Compiler},
+    optimise::optimise_body,
+    render::{Context, Output, Render, RenderError, RenderResult},
+};
+
+use super::{
+    block::{into_owned_body, Block},
+    Parse, ParseResult,
+};
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Template<'i> {
-    path: Option<PathBuf>,
-    expressions: Vec<Block<'i>>,
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) expressions: Vec<Block<'i>>,
+}
+
+impl<'i> Template<'i> {
+    /// A template made of `blocks`, which was not loaded from anywhere.
+    pub fn new(blocks: Vec<Block<'i>>) -> Self {
+        Self {
+            path: None,
+            expressions: blocks,
+        }
+    }
+
+    /// The blocks which make up the template.
+    pub fn blocks(&self) -> &[Block<'i>] {
+        &self.expressions
+    }
+
+    /// The name which the template was loaded under, if it was loaded (by an import or an
+    /// include).
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
 }
 
 impl<'i> Parse<'i> for Template<'i> {
@@ -54,6 +88,37 @@ impl<'i> Parse<'i> for Template<'i> {
     }
 }
 
+impl<'i> Template<'i> {
+    /// Parses a template which was fetched through the loader while rendering (by an import or an
+    /// include).
+    pub(crate) fn parse_loaded(name: &str, source: &'i str) -> RenderResult<Self> {
+        let (template, _) = Self::parse(source).map_err(|error| RenderError::InvalidTemplate {
+            name: name.to_string(),
+            error: error.to_string(),
+        })?;
+        Ok(Self {
+            path: Some(name.into()),
+            ..template
+        })
+    }
+
+    /// Copies everything which the template borrows from its source, so that it can outlive it
+    /// (e.g. to be kept in a cache, or sent to another thread).
+    pub fn into_owned(self) -> Template<'static> {
+        Template {
+            path: self.path,
+            expressions: into_owned_body(self.expressions),
+        }
+    }
+
+    pub(crate) fn optimise(self) -> Self {
+        Self {
+            expressions: optimise_body(self.expressions),
+            ..self
+        }
+    }
+}
+
 impl Display for Template<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for ast in &self.expressions {
@@ -62,3 +127,30 @@ impl Display for Template<'_> {
         Ok(())
     }
 }
+
+impl Render for Template<'_> {
+    fn render(&self, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()> {
+        self.expressions.render(ctx, output)
+    }
+}
+
+impl<'i> Compile<'i> for Template<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        self.expressions.compile(compiler)
+    }
+}
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+
+    use crate::codegen::Generate;
+
+    use super::Template;
+
+    impl Generate for Template<'_> {
+        fn generate(&self) -> TokenStream {
+            self.expressions.generate()
+        }
+    }
+}
@@ -20,6 +20,38 @@ pub enum ParseError<'i> {
     OperatorUsedInExpressionPosition,
 }
 
+impl ParseError<'_> {
+    /// Where in `source` (the text which failed to parse) the error is, in bytes. Errors which
+    /// were not found at a particular token are put at the start.
+    pub fn offset(&self, source: &str) -> usize {
+        match self {
+            // the token is a slice of the source
+            ParseError::UnexpectedToken(token) => (token.as_ptr() as usize)
+                .saturating_sub(source.as_ptr() as usize)
+                .min(source.len()),
+            ParseError::UnexpectedEndOfInput => source.len(),
+            _ => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnexpectedToken(token) => {
+                write!(f, "unexpected `{}`", token.lines().next().unwrap_or(""))
+            }
+            ParseError::UnexpectedEndOfInput => f.write_str("unexpected end of input"),
+            ParseError::UndiagnosedError => f.write_str("the template could not be parsed"),
+            ParseError::OperatorUsedInExpressionPosition => {
+                f.write_str("an operator was used where an expression was expected")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError<'_> {}
+
 pub(crate) fn ignore_whitespace<'i, T, F>(input: &'i str, func: F) -> ParseResult<T>
 where
     F: FnOnce(&'i str) -> ParseResult<T>,
@@ -111,30 +143,19 @@ pub(crate) fn peek_token_bool<'i>(input: &'i str, selector: &str) -> bool {
 pub(crate) fn peek_multiple<'i>(input: &'i str, selectors: &[&str]) -> ParseResult<'i, bool> {
     assert!(!selectors.is_empty());
 
-    let mut cursor = input.get(0..).ok_or(ParseError::UnexpectedEndOfInput)?;
-    let mut index = 0;
-    let mut error = None;
-
-    let predicate = selectors.iter().all(|selector| {
-        if cursor.starts_with(selector) {
-            index += selector.len();
-            cursor = match input.get(index..) {
-                Some(t) => t,
-                None => {
-                    error = Some(ParseError::UnexpectedEndOfInput);
-                    return false;
-                }
-            };
-            true
-        } else {
-            false
+    let mut cursor = input;
+
+    for selector in selectors {
+        cursor = cursor.trim_start_matches(' ');
+
+        if !cursor.starts_with(selector) {
+            return Ok((false, input));
         }
-    });
 
-    match (predicate, error) {
-        (predicate, None) => Ok((predicate, input)),
-        (_, Some(e)) => Err(e),
+        cursor = cursor.get(selector.len()..).unwrap();
     }
+
+    Ok((true, input))
 }
 
 pub(crate) fn peek_multiple_bool<'i>(input: &'i str, selectors: &[&str]) -> bool {
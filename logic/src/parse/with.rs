@@ -0,0 +1,195 @@
+//! Scoped variable blocks
+
+use std::fmt::Display;
+
+use crate::{
+    compile::{Compile, Compiler, Instruction},
+    format::{Format, Printer},
+    optimise::optimise_body,
+    parse::{parse_multiple, parse_token, peek_token_bool},
+    render::{Context, Output, Render, RenderResult},
+};
+
+use super::{
+    block::{into_owned_body, parse_body, Block},
+    expr::{into_owned_kwargs, Expr},
+    ident::Ident,
+    Parse,
+};
+
+/// `{% with a = 1, b = foo %}...{% endwith %}`
+///
+/// The bindings are only visible inside the block.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct With<'i> {
+    pub(crate) bindings: Vec<(Ident<'i>, Expr<'i>)>,
+    pub(crate) body: Vec<Block<'i>>,
+}
+
+impl<'i> With<'i> {
+    pub fn new(bindings: Vec<(Ident<'i>, Expr<'i>)>, body: Vec<Block<'i>>) -> Self {
+        Self { bindings, body }
+    }
+
+    pub fn bindings(&self) -> &[(Ident<'i>, Expr<'i>)] {
+        &self.bindings
+    }
+
+    pub fn body(&self) -> &[Block<'i>] {
+        &self.body
+    }
+}
+
+impl<'i> Parse<'i> for With<'i> {
+    fn parse(input: &'i str) -> super::ParseResult<Self> {
+        let (_, mut input) = parse_multiple(input, &["{%", "with"])?;
+
+        let mut bindings = vec![];
+
+        while !peek_token_bool(input, "%}") {
+            let (ident, rest) = Ident::parse(input)?;
+            let (_, rest) = parse_token(rest, "=")?;
+            let (expr, rest) = Expr::parse_bp(rest, 0)?;
+            input = rest;
+
+            bindings.push((ident, expr));
+
+            if peek_token_bool(input, ",") {
+                let (_, rest) = parse_token(input, ",")?;
+                input = rest;
+            } else {
+                break;
+            }
+        }
+
+        let (_, input) = parse_token(input, "%}")?;
+
+        let (body, input) = parse_body(input, &["endwith"])?;
+
+        let (_, input) = parse_multiple(input, &["{%", "endwith", "%}"])?;
+
+        Ok((Self { bindings, body }, input))
+    }
+}
+
+impl Display for With<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("{% with")?;
+        for (index, (ident, expr)) in self.bindings.iter().enumerate() {
+            if index > 0 {
+                f.write_str(",")?;
+            }
+            f.write_str(" ")?;
+            ident.fmt(f)?;
+            f.write_str(" = ")?;
+            expr.fmt(f)?;
+        }
+        f.write_str(" %}")?;
+        for block in &self.body {
+            block.fmt(f)?;
+        }
+        f.write_str("{% endwith %}")
+    }
+}
+
+impl Render for With<'_> {
+    fn render(&self, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()> {
+        // as in Jinja, every value is evaluated in the enclosing scope, so `{% with a = 1, b = a %}`
+        // binds `b` to the outer `a`
+        let values = self
+            .bindings
+            .iter()
+            .map(|(ident, expr)| Ok((ident.name(), expr.evaluate(ctx)?)))
+            .collect::<RenderResult<Vec<_>>>()?;
+
+        ctx.scoped(|ctx| {
+            for (name, value) in values {
+                ctx.insert(name, value);
+            }
+            self.body.render(ctx, output)
+        })
+    }
+}
+
+impl<'i> Compile<'i> for With<'i> {
+    fn compile(&'i self, compiler: &mut Compiler<'i>) {
+        for (_, expr) in &self.bindings {
+            expr.compile(compiler);
+        }
+
+        compiler.emit(Instruction::PushScope);
+        // the values were pushed in order, so they are popped in reverse
+        for (ident, _) in self.bindings.iter().rev() {
+            compiler.emit(Instruction::StoreVar(ident.name()));
+        }
+        self.body.compile(compiler);
+        compiler.emit(Instruction::PopScope);
+    }
+}
+
+impl<'i> With<'i> {
+    pub(crate) fn optimise(self) -> Self {
+        Self {
+            bindings: self
+                .bindings
+                .into_iter()
+                .map(|(ident, expr)| (ident, expr.optimise()))
+                .collect(),
+            body: optimise_body(self.body),
+        }
+    }
+}
+
+impl With<'_> {
+    pub fn into_owned(self) -> With<'static> {
+        With {
+            bindings: into_owned_kwargs(self.bindings),
+            body: into_owned_body(self.body),
+        }
+    }
+}
+
+impl Format for With<'_> {
+    fn format(&self, printer: &mut Printer) {
+        let bindings = self
+            .bindings
+            .iter()
+            .map(|(ident, expr)| format!("{} = {}", ident, printer.expr(expr)))
+            .collect::<Vec<_>>();
+        printer.tag(&format!("with {}", bindings.join(", ")));
+        printer.body(&self.body);
+        printer.tag("endwith");
+    }
+}
+
+#[cfg(feature = "codegen")]
+mod generate {
+    use proc_macro2::TokenStream;
+    use quote::quote;
+
+    use crate::codegen::Generate;
+
+    use super::With;
+
+    impl Generate for With<'_> {
+        fn generate(&self) -> TokenStream {
+            let n = self.bindings.len();
+            let names = self.bindings.iter().map(|(ident, _)| ident.name());
+            let values = self.bindings.iter().map(|(_, expr)| expr.generate());
+            let body = self.body.generate();
+
+            quote! {{
+                // every value is evaluated in the enclosing scope (see `render`)
+                let values: [(&str, Value); #n] = [#((#names, #values)),*];
+                runtime::scoped(ctx, |ctx| -> RenderResult<()> {
+                    for (name, value) in values {
+                        ctx.insert(name, value);
+                    }
+                    #body
+                    Ok(())
+                })?;
+            }}
+        }
+    }
+}
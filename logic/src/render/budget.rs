@@ -0,0 +1,177 @@
+//! How much work rendering can do before it is stopped, so that a template which would take too
+//! long (e.g. `{% for i in range(10**9) %}`) cannot hold up the thread which renders it.
+
+use std::{
+    cell::Cell,
+    error::Error,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use super::{Context, RenderError, RenderResult};
+
+/// What rendering with a context can spend (see [`Context::set_budget`]). Running out of any of it
+/// is a [`BudgetError`].
+///
+/// - Fuel is spent one unit at a time, on every expression which is evaluated and every block
+///   which is rendered, and on every number which `range()` produces.
+/// - The deadline, and whether the render was cancelled, are checked at each iteration of a loop,
+///   each time a macro is called or a template is imported or included, and while `range()`
+///   builds a long list.
+///
+/// ```
+/// use std::time::Duration;
+/// use ophelia_logic::render::{Budget, CancellationToken, Context};
+///
+/// let token = CancellationToken::new();
+/// let mut ctx = Context::new();
+/// ctx.set_budget(
+///     Budget::new()
+///         .fuel(1_000_000)
+///         .timeout(Duration::from_secs(1))
+///         .cancel_with(token.clone()),
+/// );
+/// // e.g. from another thread
+/// token.cancel();
+/// ```
+///
+/// The budget is spent by everything which is rendered with the context (and its clones), so a
+/// context which is reused should be given a new one for each render.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    fuel: Option<u64>,
+    spent: Cell<u64>,
+    deadline: Option<Instant>,
+    token: Option<CancellationToken>,
+}
+
+impl Budget {
+    /// A budget which never runs out.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many units of fuel can be spent.
+    pub fn fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// When rendering has to stop.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stops rendering once `timeout` has passed from now.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.deadline(Instant::now() + timeout)
+    }
+
+    /// Stops rendering once `token` is cancelled.
+    pub fn cancel_with(mut self, token: CancellationToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// How much fuel has been spent so far.
+    pub fn spent(&self) -> u64 {
+        self.spent.get()
+    }
+
+    /// Spends `amount` units of fuel.
+    pub(crate) fn spend(&self, amount: u64) -> RenderResult<()> {
+        let spent = self.spent.get().saturating_add(amount);
+        match self.fuel {
+            Some(fuel) if spent > fuel => Err(BudgetError::OutOfFuel(fuel).into()),
+            _ => {
+                self.spent.set(spent);
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks that the deadline has not passed, and that the render was not cancelled.
+    pub(crate) fn check(&self) -> RenderResult<()> {
+        if matches!(&self.token, Some(token) if token.is_cancelled()) {
+            return Err(BudgetError::Cancelled.into());
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(BudgetError::TimedOut.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Cancels the renders whose [`Budget`] was given a clone of it. It can be sent to (and cancelled
+/// from) another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Why rendering was stopped before it finished.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BudgetError {
+    /// More than this much fuel would have been spent.
+    OutOfFuel(u64),
+    /// The deadline passed.
+    TimedOut,
+    /// The render was cancelled through its [`CancellationToken`].
+    Cancelled,
+}
+
+impl Display for BudgetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BudgetError::OutOfFuel(fuel) => {
+                write!(f, "rendering ran out of fuel after {} units", fuel)
+            }
+            BudgetError::TimedOut => write!(f, "rendering did not finish before its deadline"),
+            BudgetError::Cancelled => write!(f, "rendering was cancelled"),
+        }
+    }
+}
+
+impl Error for BudgetError {}
+
+impl From<BudgetError> for RenderError {
+    fn from(error: BudgetError) -> Self {
+        RenderError::Budget(error)
+    }
+}
+
+impl Context {
+    /// Spends a unit of fuel (for an expression or a block).
+    pub(crate) fn spend_fuel(&self) -> RenderResult<()> {
+        match self.budget() {
+            Some(budget) => budget.spend(1),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that rendering can go on (at each iteration of a loop, and so on).
+    pub(crate) fn check_budget(&self) -> RenderResult<()> {
+        match self.budget() {
+            Some(budget) => budget.check(),
+            None => Ok(()),
+        }
+    }
+}
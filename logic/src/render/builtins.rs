@@ -0,0 +1,107 @@
+//! Globals which are available to every template.
+
+use std::rc::Rc;
+
+use super::{Budget, Function, Kwargs, RenderError, RenderResult, SecurityError, Value};
+
+pub(crate) fn globals() -> Vec<(&'static str, Value)> {
+    vec![
+        ("namespace", Function::new(namespace).into()),
+        ("range", range(None, None).into()),
+    ]
+}
+
+/// `range(stop)` or `range(start, stop, step=1)` – a list of numbers, as in Python. Lists which
+/// would be longer than `limit` are a [`SecurityError`], and each number spends a unit of fuel
+/// from `budget` (whose deadline and cancellation are also checked while long lists are built).
+pub(crate) fn range(limit: Option<usize>, budget: Option<Rc<Budget>>) -> Function {
+    Function::new(move |args, kwargs| {
+        if !kwargs.is_empty() {
+            return Err(RenderError::InvalidOperation(
+                "range() does not take keyword arguments".to_string(),
+            ));
+        }
+        let numbers = args
+            .iter()
+            .map(|arg| match arg {
+                Value::Integer(n) => Ok(*n as i64),
+                other => Err(RenderError::InvalidOperation(format!(
+                    "range() takes integers, not {:?}",
+                    other
+                ))),
+            })
+            .collect::<RenderResult<Vec<_>>>()?;
+        let (start, stop, step) = match numbers.as_slice() {
+            [stop] => (0, *stop, 1),
+            [start, stop] => (*start, *stop, 1),
+            [start, stop, step] => (*start, *stop, *step),
+            _ => {
+                return Err(RenderError::InvalidOperation(
+                    "range() takes one to three arguments".to_string(),
+                ))
+            }
+        };
+        if step == 0 {
+            return Err(RenderError::InvalidOperation(
+                "the step of range() cannot be zero".to_string(),
+            ));
+        }
+
+        let len = if step > 0 {
+            (stop - start + step - 1) / step
+        } else {
+            (start - stop - step - 1) / -step
+        }
+        .max(0) as usize;
+        if let Some(limit) = limit.filter(|limit| len > *limit) {
+            return Err(SecurityError::RangeTooLarge(limit).into());
+        }
+        if let Some(budget) = &budget {
+            budget.spend(len as u64)?;
+        }
+
+        // the list grows as it is built (rather than being allocated all at once), and the
+        // deadline and cancellation are checked as it goes, so that a budget without fuel can
+        // still stop a huge range
+        let mut numbers = Vec::with_capacity(len.min(CHECK_RANGE_EVERY));
+        for i in 0..len as i64 {
+            if i > 0 && (i as usize).is_multiple_of(CHECK_RANGE_EVERY) {
+                if let Some(budget) = &budget {
+                    budget.check()?;
+                }
+            }
+            numbers.push(Value::Integer((start + i * step) as i32));
+        }
+        Ok(Value::list(numbers))
+    })
+}
+
+/// How many numbers `range()` produces between checks of the budget's deadline and cancellation.
+const CHECK_RANGE_EVERY: usize = 1 << 16;
+
+/// `namespace(attrs, **kwargs)` – creates a namespace, optionally initialised from a dict and/or
+/// from keyword arguments.
+fn namespace(args: Vec<Value>, kwargs: Kwargs) -> RenderResult<Value> {
+    let mut attrs = match args.as_slice() {
+        [] => vec![],
+        [Value::Dict(dict)] => dict
+            .borrow()
+            .iter()
+            .map(|(key, value)| match key {
+                Value::String(key) => Ok((key.clone(), value.clone())),
+                other => Err(RenderError::InvalidOperation(format!(
+                    "namespace attribute names must be strings, not {:?}",
+                    other
+                ))),
+            })
+            .collect::<RenderResult<_>>()?,
+        _ => {
+            return Err(RenderError::InvalidOperation(
+                "namespace() takes at most one positional argument (a dict)".to_string(),
+            ))
+        }
+    };
+    attrs.extend(kwargs);
+
+    Ok(Value::namespace(attrs))
+}
@@ -0,0 +1,304 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::{Rc, Weak},
+};
+
+use super::{
+    builtins, filters, tests, Budget, Function, Kwargs, Loader, RenderError, RenderResult, Sandbox,
+    Undefined, Value,
+};
+
+type Scope = Rc<RefCell<HashMap<String, Value>>>;
+
+/// Filters or tests which were added to a context, by name.
+type Registry = Rc<HashMap<String, Function>>;
+
+/// The variables which are visible to a template while it is being rendered.
+///
+/// Variables live in a stack of scopes; lookups start at the innermost scope and work outwards,
+/// and a scope's variables disappear once the block which created it has been rendered. The
+/// outermost scope holds the builtin globals (e.g. `namespace`).
+///
+/// The context also carries the [`Loader`] which `{% import %}` and `{% include %}` use to find other templates,
+/// any filters and tests which were added to the builtin ones, how undefined variables behave, the
+/// [`Sandbox`] (if any) which limits what templates can do, and the [`Budget`] (if any) which
+/// limits how long they can take.
+///
+/// Scopes are shared: a clone of a context sees (and makes) the same changes to the scopes which
+/// it was cloned with, but scopes pushed onto one are not visible from the other.
+#[derive(Debug, Clone)]
+pub struct Context {
+    scopes: Vec<Scope>,
+    loader: Option<Rc<dyn Loader>>,
+    filters: Registry,
+    tests: Registry,
+    undefined: Undefined,
+    sandbox: Option<Rc<Sandbox>>,
+    budget: Option<Rc<Budget>>,
+    /// How deep macro calls and templates are nested, which is shared by the contexts they are
+    /// rendered in.
+    depth: Rc<Cell<usize>>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        let globals = builtins::globals()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+
+        Self {
+            scopes: vec![Rc::new(RefCell::new(globals)), Scope::default()],
+            loader: None,
+            filters: Registry::default(),
+            tests: Registry::default(),
+            undefined: Undefined::default(),
+            sandbox: None,
+            budget: None,
+            depth: Rc::default(),
+        }
+    }
+
+    pub fn set_loader(&mut self, loader: impl Loader + 'static) {
+        self.loader = Some(Rc::new(loader));
+    }
+
+    /// Sets how undefined variables (and attributes) behave, which is [`Undefined::Chainable`] by
+    /// default.
+    pub fn set_undefined(&mut self, undefined: Undefined) {
+        self.undefined = undefined;
+    }
+
+    pub fn undefined(&self) -> Undefined {
+        self.undefined
+    }
+
+    /// Limits what templates rendered with this context can do (see [`Sandbox`]).
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.sandbox = Some(Rc::new(sandbox));
+        self.rebind_range();
+    }
+
+    pub fn sandbox(&self) -> Option<&Sandbox> {
+        self.sandbox.as_deref()
+    }
+
+    /// Limits how long templates rendered with this context can take (see [`Budget`]).
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.budget = Some(Rc::new(budget));
+        self.rebind_range();
+    }
+
+    /// The budget which this context is rendering with, which says how much of it was spent.
+    pub fn budget(&self) -> Option<&Budget> {
+        self.budget.as_deref()
+    }
+
+    /// Binds `range()` to one which knows the sandbox's limit and spends the budget.
+    fn rebind_range(&mut self) {
+        // `range()` is a global, so the globals are replaced by ones with the new `range()`
+        // (rather than changed, since clones of this context share them)
+        let mut globals = self.scopes[0].borrow().clone();
+        let limit = self.sandbox().map(Sandbox::range_limit);
+        globals.insert(
+            "range".to_string(),
+            builtins::range(limit, self.budget.clone()).into(),
+        );
+        self.scopes[0] = Rc::new(RefCell::new(globals));
+    }
+
+    pub(crate) fn depth(&self) -> Rc<Cell<usize>> {
+        self.depth.clone()
+    }
+
+    /// Adds a filter, which is called with the value being filtered followed by the arguments
+    /// which were passed to the filter. It takes precedence over any builtin filter with the same
+    /// name.
+    pub fn add_filter(&mut self, name: impl Into<String>, filter: Function) {
+        Rc::make_mut(&mut self.filters).insert(name.into(), filter);
+    }
+
+    /// Adds a test (which can be used as in `{% if x is name %}`), which is called with the value
+    /// being tested followed by the arguments which were passed to the test. It takes precedence
+    /// over any builtin test with the same name.
+    pub fn add_test(&mut self, name: impl Into<String>, test: Function) {
+        Rc::make_mut(&mut self.tests).insert(name.into(), test);
+    }
+
+    /// A context which shares this one's loader, filters, tests, undefined policy, sandbox and
+    /// budget (and its globals), but none of its variables.
+    pub(crate) fn without_variables(&self) -> Self {
+        Self {
+            scopes: vec![self.scopes[0].clone(), Scope::default()],
+            loader: self.loader.clone(),
+            filters: self.filters.clone(),
+            tests: self.tests.clone(),
+            undefined: self.undefined,
+            sandbox: self.sandbox.clone(),
+            budget: self.budget.clone(),
+            depth: self.depth.clone(),
+        }
+    }
+
+    /// Applies the filter called `name` to `value` (which, in a sandbox, cannot make a string which
+    /// is longer than the output can be).
+    pub(crate) fn apply_filter(
+        &self,
+        name: &str,
+        value: Value,
+        mut args: Vec<Value>,
+        kwargs: Kwargs,
+    ) -> RenderResult<Value> {
+        let filtered = match self.filters.get(name) {
+            Some(filter) => {
+                args.insert(0, value);
+                filter.call(args, kwargs)?
+            }
+            None => filters::apply(
+                name,
+                value,
+                args,
+                kwargs,
+                self.sandbox().map(Sandbox::output_limit),
+            )?,
+        };
+        self.check_size(&filtered)?;
+        Ok(filtered)
+    }
+
+    /// Applies the test called `name` to `value`.
+    pub(crate) fn apply_test(
+        &self,
+        name: &str,
+        value: Value,
+        mut args: Vec<Value>,
+        kwargs: Kwargs,
+    ) -> RenderResult<bool> {
+        match self.tests.get(name) {
+            Some(test) => {
+                args.insert(0, value);
+                Ok(test.call(args, kwargs)?.is_truthy())
+            }
+            None => tests::apply(name, value, args, kwargs),
+        }
+    }
+
+    /// Fetches the source of the template called `name` through the loader.
+    pub(crate) fn load(&self, name: &str) -> RenderResult<String> {
+        self.loader
+            .as_ref()
+            .and_then(|loader| loader.load(name))
+            .ok_or_else(|| RenderError::TemplateNotFound(name.to_string()))
+    }
+
+    /// Binds `name` in the innermost scope.
+    pub fn insert(&mut self, name: impl Into<String>, value: Value) {
+        self.innermost().borrow_mut().insert(name.into(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.borrow().get(name).cloned())
+    }
+
+    /// The variables bound in the innermost scope.
+    pub(crate) fn locals(&self) -> Vec<(String, Value)> {
+        self.innermost()
+            .borrow()
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    fn innermost(&self) -> &Scope {
+        self.scopes
+            .last()
+            .expect("the global scope is never popped")
+    }
+
+    /// Runs `op` inside a new scope, which is discarded afterwards.
+    pub(crate) fn scoped<T, F: FnOnce(&mut Self) -> T>(&mut self, op: F) -> T {
+        self.push_scope();
+        let res = (op)(self);
+        self.pop_scope();
+        res
+    }
+
+    pub(crate) fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    pub(crate) fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// The scopes which this context has, and `other` does not (e.g. those pushed onto a clone of
+    /// `other`).
+    pub(crate) fn own_scopes(&self, other: &Context) -> Vec<Scope> {
+        self.scopes
+            .iter()
+            .filter(|scope| !other.scopes.iter().any(|other| Rc::ptr_eq(scope, other)))
+            .cloned()
+            .collect()
+    }
+
+    /// A reference to this context which does not keep its scopes alive.
+    ///
+    /// Macros hold one of these, so that they see the variables of the template which defined
+    /// them as they are when the macro is called. A strong reference would create a cycle,
+    /// because the macro is itself stored in one of those scopes.
+    pub(crate) fn downgrade(&self) -> WeakContext {
+        WeakContext {
+            scopes: self.scopes.iter().map(Rc::downgrade).collect(),
+            loader: self.loader.clone(),
+            filters: self.filters.clone(),
+            tests: self.tests.clone(),
+            undefined: self.undefined,
+            sandbox: self.sandbox.clone(),
+            budget: self.budget.clone(),
+            depth: self.depth.clone(),
+        }
+    }
+}
+
+/// See [`Context::downgrade`].
+#[derive(Debug, Clone)]
+pub(crate) struct WeakContext {
+    scopes: Vec<Weak<RefCell<HashMap<String, Value>>>>,
+    loader: Option<Rc<dyn Loader>>,
+    filters: Registry,
+    tests: Registry,
+    undefined: Undefined,
+    sandbox: Option<Rc<Sandbox>>,
+    budget: Option<Rc<Budget>>,
+    depth: Rc<Cell<usize>>,
+}
+
+impl WeakContext {
+    /// The context which this refers to, unless some of its scopes have since been dropped.
+    pub(crate) fn upgrade(&self) -> Option<Context> {
+        Some(Context {
+            scopes: self
+                .scopes
+                .iter()
+                .map(Weak::upgrade)
+                .collect::<Option<_>>()?,
+            loader: self.loader.clone(),
+            filters: self.filters.clone(),
+            tests: self.tests.clone(),
+            undefined: self.undefined,
+            sandbox: self.sandbox.clone(),
+            budget: self.budget.clone(),
+            depth: self.depth.clone(),
+        })
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
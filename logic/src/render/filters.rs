@@ -0,0 +1,270 @@
+//! The builtin filters.
+//!
+//! See <https://jinja.palletsprojects.com/en/3.0.x/templates/#list-of-builtin-filters> for what
+//! each one does in Jinja.
+
+use super::{Builtin, Kwargs, RenderError, RenderResult, SecurityError, Value};
+
+/// The builtin filters, for tools which explain them (such as editors).
+pub const BUILTINS: &[Builtin] = &[
+    Builtin::new("abs", "abs()", "The absolute value of a number."),
+    Builtin::new(
+        "capitalize",
+        "capitalize()",
+        "The string with its first character upper case, and the rest lower case.",
+    ),
+    Builtin::new("count", "count()", "Another name for `length`."),
+    Builtin::new(
+        "d",
+        "d(default_value='', boolean=false)",
+        "Another name for `default`.",
+    ),
+    Builtin::new(
+        "default",
+        "default(default_value='', boolean=false)",
+        "`default_value` if the value is undefined (or, if `boolean` is true, if it is falsy), \
+         and the value otherwise.",
+    ),
+    Builtin::new("first", "first()", "The first item of a sequence."),
+    Builtin::new(
+        "float",
+        "float(default=0.0)",
+        "The value converted to a float, or `default` if it cannot be.",
+    ),
+    Builtin::new(
+        "int",
+        "int(default=0)",
+        "The value converted to an integer, or `default` if it cannot be.",
+    ),
+    Builtin::new(
+        "join",
+        "join(d='')",
+        "The items of a sequence converted to strings, with `d` between each of them.",
+    ),
+    Builtin::new("last", "last()", "The last item of a sequence."),
+    Builtin::new(
+        "length",
+        "length()",
+        "The number of items in a sequence (or characters in a string).",
+    ),
+    Builtin::new("list", "list()", "The items of a sequence, as a list."),
+    Builtin::new("lower", "lower()", "The value converted to lower case."),
+    Builtin::new(
+        "replace",
+        "replace(old, new)",
+        "The string with every occurrence of `old` replaced by `new`.",
+    ),
+    Builtin::new(
+        "reverse",
+        "reverse()",
+        "The items of a sequence (or characters of a string) in reverse order.",
+    ),
+    Builtin::new(
+        "safe",
+        "safe()",
+        "The value, unchanged (nothing is escaped, so there is nothing to mark as safe).",
+    ),
+    Builtin::new("string", "string()", "The value converted to a string."),
+    Builtin::new(
+        "title",
+        "title()",
+        "The string with the first character of each word upper case.",
+    ),
+    Builtin::new(
+        "trim",
+        "trim()",
+        "The string without the whitespace at its start and end.",
+    ),
+    Builtin::new("upper", "upper()", "The value converted to upper case."),
+];
+
+/// Applies the filter called `name` to `value`. Filters which could build a string much longer than
+/// what they were given fail instead if it would be longer than `limit` (in bytes).
+pub(crate) fn apply(
+    name: &str,
+    value: Value,
+    args: Vec<Value>,
+    kwargs: Kwargs,
+    limit: Option<usize>,
+) -> RenderResult<Value> {
+    let mut args = Args::new("filter", name, args, kwargs);
+
+    let res = match name {
+        "upper" => Value::String(value.to_string().to_uppercase()),
+        "lower" => Value::String(value.to_string().to_lowercase()),
+        "capitalize" => Value::String(capitalize(&value.to_string())),
+        "title" => Value::String(
+            value
+                .to_string()
+                .split(' ')
+                .map(capitalize)
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        "trim" => Value::String(value.to_string().trim().to_string()),
+        // (for templates which were written for an environment which escapes its output)
+        "safe" => value,
+        "string" => Value::String(value.to_string()),
+        "length" | "count" => match &value {
+            Value::String(string) => Value::Integer(string.chars().count() as i32),
+            other => Value::Integer(other.iter()?.len() as i32),
+        },
+        "first" => value.iter()?.into_iter().next().unwrap_or(Value::Undefined),
+        "last" => value.iter()?.into_iter().last().unwrap_or(Value::Undefined),
+        "reverse" => match &value {
+            Value::String(string) => Value::String(string.chars().rev().collect()),
+            other => Value::list(other.iter()?.into_iter().rev().collect()),
+        },
+        "list" => Value::list(value.iter()?),
+        "join" => {
+            let separator = args.optional("d", Value::from("")).to_string();
+            let items = value
+                .iter()?
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            let separators = separator
+                .len()
+                .saturating_mul(items.len().saturating_sub(1));
+            check_length(
+                items
+                    .iter()
+                    .fold(separators, |length, item| length.saturating_add(item.len())),
+                limit,
+            )?;
+            Value::String(items.join(&separator))
+        }
+        "replace" => {
+            let old = args.required("old")?.to_string();
+            let new = args.required("new")?.to_string();
+            let string = value.to_string();
+            if new.len() > old.len() {
+                // (an empty `old` matches between every character, and at both ends)
+                let matches = match old.as_str() {
+                    "" => string.chars().count() + 1,
+                    old => string.matches(old).count(),
+                };
+                check_length(
+                    (new.len() - old.len())
+                        .saturating_mul(matches)
+                        .saturating_add(string.len()),
+                    limit,
+                )?;
+            }
+            Value::String(string.replace(&old, &new))
+        }
+        "default" | "d" => {
+            let default = args.optional("default_value", Value::from(""));
+            let boolean = args.optional("boolean", Value::Bool(false)).is_truthy();
+            match value {
+                Value::Undefined => default,
+                value if boolean && !value.is_truthy() => default,
+                value => value,
+            }
+        }
+        "int" => {
+            let default = args.optional("default", Value::Integer(0));
+            match value {
+                Value::Integer(int) => Value::Integer(int),
+                Value::Float(float) => Value::Integer(float as i32),
+                Value::String(string) => string.trim().parse().map_or(default, Value::Integer),
+                _ => default,
+            }
+        }
+        "float" => {
+            let default = args.optional("default", Value::Float(0.0));
+            match value {
+                Value::Integer(int) => Value::Float(int as f32),
+                Value::Float(float) => Value::Float(float),
+                Value::String(string) => string.trim().parse().map_or(default, Value::Float),
+                _ => default,
+            }
+        }
+        "abs" => match value {
+            Value::Integer(int) => Value::Integer(int.abs()),
+            Value::Float(float) => Value::Float(float.abs()),
+            other => {
+                return Err(RenderError::InvalidOperation(format!(
+                    "bad operand type for abs(): {:?}",
+                    other
+                )))
+            }
+        },
+        _ => return Err(RenderError::UnknownFilter(name.to_string())),
+    };
+
+    args.finish()?;
+
+    Ok(res)
+}
+
+/// Fails if a string of `length` bytes would be longer than `limit`, before it is built.
+fn check_length(length: usize, limit: Option<usize>) -> RenderResult<()> {
+    match limit {
+        Some(limit) if length > limit => Err(SecurityError::OutputTooLarge(limit).into()),
+        _ => Ok(()),
+    }
+}
+
+fn capitalize(string: &str) -> String {
+    let mut chars = string.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+/// The arguments passed to a filter (or a test), which are consumed in the order of its Python
+/// signature.
+pub(crate) struct Args<'a> {
+    /// `"filter"` or `"test"`, for error messages.
+    kind: &'static str,
+    name: &'a str,
+    args: std::vec::IntoIter<Value>,
+    kwargs: Kwargs,
+}
+
+impl<'a> Args<'a> {
+    pub(crate) fn new(kind: &'static str, name: &'a str, args: Vec<Value>, kwargs: Kwargs) -> Self {
+        Self {
+            kind,
+            name,
+            args: args.into_iter(),
+            kwargs,
+        }
+    }
+
+    pub(crate) fn optional(&mut self, name: &str, default: Value) -> Value {
+        self.take(name).unwrap_or(default)
+    }
+
+    pub(crate) fn required(&mut self, name: &str) -> RenderResult<Value> {
+        self.take(name).ok_or_else(|| {
+            RenderError::InvalidOperation(format!(
+                "the `{}` {} is missing the argument `{}`",
+                self.name, self.kind, name
+            ))
+        })
+    }
+
+    fn take(&mut self, name: &str) -> Option<Value> {
+        self.args.next().or_else(|| {
+            let index = self.kwargs.iter().position(|(key, _)| key == name)?;
+            Some(self.kwargs.remove(index).1)
+        })
+    }
+
+    /// Fails if any arguments were passed which the filter does not accept.
+    pub(crate) fn finish(mut self) -> RenderResult<()> {
+        if self.args.next().is_some() || !self.kwargs.is_empty() {
+            return Err(RenderError::InvalidOperation(format!(
+                "too many arguments passed to the `{}` {}",
+                self.name, self.kind
+            )));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use std::{collections::HashMap, fmt::Debug};
+
+/// Finds the source of the templates which `{% import %}` and `{% include %}` refer to.
+pub trait Loader: Debug {
+    /// Returns the source of the template called `name`, or `None` if there is no such template.
+    fn load(&self, name: &str) -> Option<String>;
+}
+
+/// Loads templates from an in-memory map of names to sources.
+#[derive(Debug, Clone, Default)]
+pub struct DictLoader {
+    templates: HashMap<String, String>,
+}
+
+impl DictLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, source: impl Into<String>) {
+        self.templates.insert(name.into(), source.into());
+    }
+}
+
+impl Loader for DictLoader {
+    fn load(&self, name: &str) -> Option<String> {
+        self.templates.get(name).cloned()
+    }
+}
+
+#[cfg(feature = "fs")]
+pub use file_system::FileSystemLoader;
+
+#[cfg(feature = "fs")]
+mod file_system {
+    use std::{
+        fs,
+        path::{Component, Path, PathBuf},
+    };
+
+    use super::Loader;
+
+    /// Loads templates from files inside a directory (template names are paths relative to it).
+    #[derive(Debug, Clone)]
+    pub struct FileSystemLoader {
+        root: PathBuf,
+    }
+
+    impl FileSystemLoader {
+        pub fn new(root: impl Into<PathBuf>) -> Self {
+            Self { root: root.into() }
+        }
+    }
+
+    impl Loader for FileSystemLoader {
+        fn load(&self, name: &str) -> Option<String> {
+            // template names may not escape the root directory, so only plain relative paths are
+            // allowed (`root.join` would replace the root with an absolute path, for example)
+            let path = Path::new(name);
+            if !path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+            {
+                return None;
+            }
+            fs::read_to_string(self.root.join(path)).ok()
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! The renderer.
+//!
+//! Rendering walks the tree produced by the parser, writing output (to an [`Output`]) as it goes.
+//! Every node which can appear in a template implements [`Render`].
+
+use std::{error::Error, fmt::Display, io, ops::Range};
+
+mod budget;
+pub(crate) mod builtins;
+mod context;
+pub(crate) mod filters;
+mod loader;
+mod output;
+mod sandbox;
+pub(crate) mod tests;
+mod undefined;
+mod value;
+
+pub use budget::{Budget, BudgetError, CancellationToken};
+pub use context::Context;
+pub(crate) use context::WeakContext;
+pub use filters::BUILTINS as BUILTIN_FILTERS;
+#[cfg(feature = "fs")]
+pub use loader::FileSystemLoader;
+pub use loader::{DictLoader, Loader};
+pub use output::{FmtOutput, IoOutput, Output, DEFAULT_CAPACITY};
+pub use sandbox::{Sandbox, SecurityError};
+pub use tests::BUILTINS as BUILTIN_TESTS;
+pub use undefined::{Origin, Undefined};
+pub use value::{Function, Kwargs, Value};
+
+pub trait Render {
+    /// Renders this into `output`. To handle the output a chunk at a time while it is rendered
+    /// (as Jinja's `generate()` does), render into an [`IoOutput`] or a [`FmtOutput`].
+    fn render(&self, ctx: &mut Context, output: &mut dyn Output) -> RenderResult<()>;
+}
+
+/// A builtin filter or test, as it is documented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Builtin {
+    pub name: &'static str,
+    /// How it is called (e.g. `replace(old, new)`).
+    pub signature: &'static str,
+    pub description: &'static str,
+}
+
+impl Builtin {
+    pub(crate) const fn new(
+        name: &'static str,
+        signature: &'static str,
+        description: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            signature,
+            description,
+        }
+    }
+}
+
+pub type RenderResult<T> = Result<T, RenderError>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderError {
+    /// An operation was applied to values which do not support it (e.g. `{{ [] - 1 }}`).
+    InvalidOperation(String),
+    /// A filter was used which does not exist.
+    UnknownFilter(String),
+    /// A test (the name after `is`) was used which does not exist.
+    UnknownTest(String),
+    /// A template which was imported or included could not be found by the loader (when
+    /// including a list of templates, this names all of them).
+    TemplateNotFound(String),
+    /// A template which was imported or included could not be parsed.
+    InvalidTemplate { name: String, error: String },
+    /// The template contains a construct which the renderer does not support yet.
+    Unsupported(&'static str),
+    /// An expression (`expr`) was used which is undefined, and the context's [`Undefined`] policy
+    /// does not allow that.
+    Undefined { expr: String, origin: Origin },
+    /// The template tried to do something which the context's [`Sandbox`] does not allow.
+    Security(SecurityError),
+    /// Rendering was stopped because the context's [`Budget`] ran out.
+    Budget(BudgetError),
+    /// The output could not be written to.
+    Io {
+        kind: io::ErrorKind,
+        message: String,
+    },
+}
+
+impl RenderError {
+    /// Where in `source` (the text of the template which failed to render) the error is, in
+    /// bytes, if that is known.
+    pub fn span(&self, source: &str) -> Option<Range<usize>> {
+        match self {
+            RenderError::Undefined { origin, .. } => origin.span(source),
+            _ => None,
+        }
+    }
+}
+
+impl Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderError::InvalidOperation(message) => f.write_str(message),
+            RenderError::UnknownFilter(name) => write!(f, "there is no filter called `{}`", name),
+            RenderError::UnknownTest(name) => write!(f, "there is no test called `{}`", name),
+            RenderError::TemplateNotFound(name) => {
+                write!(f, "could not find the template `{}`", name)
+            }
+            RenderError::InvalidTemplate { name, error } => {
+                write!(f, "the template `{}` could not be parsed: {}", name, error)
+            }
+            RenderError::Unsupported(what) => write!(f, "not supported yet: {}", what),
+            RenderError::Undefined { expr, .. } => write!(f, "`{}` is undefined", expr),
+            RenderError::Security(error) => error.fmt(f),
+            RenderError::Budget(error) => error.fmt(f),
+            RenderError::Io { message, .. } => {
+                write!(f, "the output could not be written: {}", message)
+            }
+        }
+    }
+}
+
+impl Error for RenderError {}
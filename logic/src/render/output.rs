@@ -0,0 +1,213 @@
+//! Where rendered text goes.
+//!
+//! Templates render into an [`Output`], which is usually a `String`. To avoid holding all of a
+//! large template's output in memory, it can be written out as it is rendered instead, a chunk at
+//! a time, to anything which implements [`std::io::Write`] (with [`IoOutput`]) or
+//! [`std::fmt::Write`] (with [`FmtOutput`]).
+
+use std::{fmt, io};
+
+use super::{RenderError, RenderResult};
+
+/// Something which templates can be rendered into.
+pub trait Output {
+    /// Appends `text`.
+    fn write(&mut self, text: &str) -> RenderResult<()>;
+
+    /// How many bytes have been written, including any which were already passed on.
+    fn written(&self) -> usize;
+
+    /// Appends formatted text, so that `write!` can be used.
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> RenderResult<()> {
+        match args.as_str() {
+            Some(text) => self.write(text),
+            None => self.write(&args.to_string()),
+        }
+    }
+}
+
+impl Output for String {
+    fn write(&mut self, text: &str) -> RenderResult<()> {
+        self.push_str(text);
+        Ok(())
+    }
+
+    fn written(&self) -> usize {
+        self.len()
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> RenderResult<()> {
+        fmt::Write::write_fmt(self, args).map_err(fmt_error)
+    }
+}
+
+/// How many bytes [`IoOutput`] and [`FmtOutput`] buffer by default.
+pub const DEFAULT_CAPACITY: usize = 8 * 1024;
+
+/// Text which has been written, but not passed on yet.
+#[derive(Debug)]
+struct Buffer {
+    text: String,
+    capacity: usize,
+    written: usize,
+}
+
+impl Buffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            text: String::with_capacity(capacity),
+            capacity,
+            written: 0,
+        }
+    }
+
+    /// Appends `text`, passing what has been buffered on to `sink` once there is enough of it.
+    fn write(
+        &mut self,
+        text: &str,
+        sink: impl FnOnce(&str) -> RenderResult<()>,
+    ) -> RenderResult<()> {
+        self.written += text.len();
+        self.text.push_str(text);
+        if self.text.len() >= self.capacity {
+            self.flush(sink)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, sink: impl FnOnce(&str) -> RenderResult<()>) -> RenderResult<()> {
+        if !self.text.is_empty() {
+            sink(&self.text)?;
+            self.text.clear();
+        }
+        Ok(())
+    }
+}
+
+/// Writes the output to an [`io::Write`] (e.g. a file or a socket), in chunks of (at least) its
+/// capacity.
+///
+/// Whatever is still buffered has to be written out at the end with [`IoOutput::flush`] (or
+/// [`IoOutput::into_inner`]).
+///
+/// ```
+/// use ophelia_logic::{
+///     parse::{Parse, Template},
+///     render::{Context, IoOutput, Render},
+/// };
+///
+/// let (template, _) = Template::parse("{% for i in range(3) %}{{ i }}{% endfor %}").unwrap();
+/// let mut output = IoOutput::new(Vec::new());
+/// template.render(&mut Context::new(), &mut output).unwrap();
+/// assert_eq!(output.into_inner().unwrap(), b"012");
+/// ```
+#[derive(Debug)]
+pub struct IoOutput<W: io::Write> {
+    writer: W,
+    buffer: Buffer,
+}
+
+impl<W: io::Write> IoOutput<W> {
+    /// Writes to `writer`, buffering [`DEFAULT_CAPACITY`] bytes.
+    pub fn new(writer: W) -> Self {
+        Self::with_capacity(writer, DEFAULT_CAPACITY)
+    }
+
+    /// Writes to `writer`, buffering `capacity` bytes (or nothing, if it is 0).
+    pub fn with_capacity(writer: W, capacity: usize) -> Self {
+        Self {
+            writer,
+            buffer: Buffer::new(capacity),
+        }
+    }
+
+    /// Writes out whatever is buffered, and flushes the writer.
+    pub fn flush(&mut self) -> RenderResult<()> {
+        let writer = &mut self.writer;
+        self.buffer
+            .flush(|text| Ok(writer.write_all(text.as_bytes())?))?;
+        Ok(self.writer.flush()?)
+    }
+
+    /// Flushes, and returns the writer.
+    pub fn into_inner(mut self) -> RenderResult<W> {
+        self.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: io::Write> Output for IoOutput<W> {
+    fn write(&mut self, text: &str) -> RenderResult<()> {
+        let writer = &mut self.writer;
+        self.buffer
+            .write(text, |text| Ok(writer.write_all(text.as_bytes())?))
+    }
+
+    fn written(&self) -> usize {
+        self.buffer.written
+    }
+}
+
+/// Writes the output to a [`fmt::Write`] (e.g. a [`fmt::Formatter`]), in chunks of (at least) its
+/// capacity.
+///
+/// Whatever is still buffered has to be written out at the end with [`FmtOutput::flush`] (or
+/// [`FmtOutput::into_inner`]).
+#[derive(Debug)]
+pub struct FmtOutput<W: fmt::Write> {
+    writer: W,
+    buffer: Buffer,
+}
+
+impl<W: fmt::Write> FmtOutput<W> {
+    /// Writes to `writer`, buffering [`DEFAULT_CAPACITY`] bytes.
+    pub fn new(writer: W) -> Self {
+        Self::with_capacity(writer, DEFAULT_CAPACITY)
+    }
+
+    /// Writes to `writer`, buffering `capacity` bytes (or nothing, if it is 0).
+    pub fn with_capacity(writer: W, capacity: usize) -> Self {
+        Self {
+            writer,
+            buffer: Buffer::new(capacity),
+        }
+    }
+
+    /// Writes out whatever is buffered.
+    pub fn flush(&mut self) -> RenderResult<()> {
+        let writer = &mut self.writer;
+        self.buffer
+            .flush(|text| writer.write_str(text).map_err(fmt_error))
+    }
+
+    /// Flushes, and returns the writer.
+    pub fn into_inner(mut self) -> RenderResult<W> {
+        self.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: fmt::Write> Output for FmtOutput<W> {
+    fn write(&mut self, text: &str) -> RenderResult<()> {
+        let writer = &mut self.writer;
+        self.buffer
+            .write(text, |text| writer.write_str(text).map_err(fmt_error))
+    }
+
+    fn written(&self) -> usize {
+        self.buffer.written
+    }
+}
+
+fn fmt_error(_: fmt::Error) -> RenderError {
+    io::Error::other("the output could not be formatted").into()
+}
+
+impl From<io::Error> for RenderError {
+    fn from(error: io::Error) -> Self {
+        RenderError::Io {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
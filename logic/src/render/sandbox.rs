@@ -0,0 +1,258 @@
+//! Limits on what templates can do, so that templates which are not trusted (e.g. ones which users
+//! upload) can be rendered safely.
+
+use std::{cell::Cell, collections::HashSet, error::Error, fmt::Display, rc::Rc};
+
+use crate::parse::expr::op::{apply, BinOp};
+
+use super::{Context, Output, RenderError, RenderResult, Value};
+
+/// How deep macro calls, imports and includes can nest when there is no sandbox, which stops a
+/// template which includes itself from overflowing the stack.
+pub(crate) const MAX_DEPTH: usize = 64;
+
+/// What a sandboxed context allows templates to do (see [`Context::set_sandbox`]). Anything else
+/// is a [`SecurityError`].
+///
+/// Attributes (`value.name`) and methods (`value.name()`) can only be used if they were allowed
+/// by name, except for the attributes of namespaces, which templates create for themselves. The
+/// other limits have defaults, which can be changed:
+///
+/// ```
+/// use ophelia_logic::render::{Context, Sandbox};
+///
+/// let mut ctx = Context::new();
+/// ctx.set_sandbox(
+///     Sandbox::new()
+///         .allow_attribute("name")
+///         .allow_method("append")
+///         .max_output(1 << 16),
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sandbox {
+    attributes: HashSet<String>,
+    methods: HashSet<String>,
+    max_output: usize,
+    max_iterations: usize,
+    max_depth: usize,
+    max_range: usize,
+}
+
+impl Sandbox {
+    /// A sandbox which allows no attributes or methods, and 10 MiB of output, 100,000 iterations
+    /// of each loop, macros and templates nested 32 deep, and ranges of 100,000 numbers.
+    pub fn new() -> Self {
+        Self {
+            attributes: HashSet::new(),
+            methods: HashSet::new(),
+            max_output: 10 << 20,
+            max_iterations: 100_000,
+            max_depth: 32,
+            max_range: 100_000,
+        }
+    }
+
+    /// Allows the attribute called `name` (of any value) to be looked up.
+    pub fn allow_attribute(mut self, name: impl Into<String>) -> Self {
+        self.attributes.insert(name.into());
+        self
+    }
+
+    /// Allows the method called `name` (of any value) to be called.
+    pub fn allow_method(mut self, name: impl Into<String>) -> Self {
+        self.methods.insert(name.into());
+        self
+    }
+
+    /// How long (in bytes) the output can be. No string which a template builds (e.g. with `*`
+    /// or `~`, or a filter) can be longer than this either.
+    pub fn max_output(mut self, bytes: usize) -> Self {
+        self.max_output = bytes;
+        self
+    }
+
+    /// How many times each loop can run.
+    pub fn max_iterations(mut self, iterations: usize) -> Self {
+        self.max_iterations = iterations;
+        self
+    }
+
+    /// How deep calls to macros, and imports and includes of templates, can nest.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// How many numbers `range()` can produce.
+    pub fn max_range(mut self, numbers: usize) -> Self {
+        self.max_range = numbers;
+        self
+    }
+
+    pub(crate) fn range_limit(&self) -> usize {
+        self.max_range
+    }
+
+    pub(crate) fn output_limit(&self) -> usize {
+        self.max_output
+    }
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Something which a template tried to do that its context's [`Sandbox`] does not allow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SecurityError {
+    /// An attribute was looked up which was not allowed.
+    Attribute(String),
+    /// A method was called which was not allowed.
+    Method(String),
+    /// The output (or a string) grew longer than this many bytes.
+    OutputTooLarge(usize),
+    /// A loop would have run more than this many times.
+    TooManyIterations(usize),
+    /// Macro calls, imports and includes nested deeper than this (which is limited even without
+    /// a sandbox).
+    TooDeep(usize),
+    /// `range()` would have produced more than this many numbers.
+    RangeTooLarge(usize),
+}
+
+impl Display for SecurityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecurityError::Attribute(name) => {
+                write!(f, "the attribute `{}` is not allowed in the sandbox", name)
+            }
+            SecurityError::Method(name) => {
+                write!(f, "the method `{}` is not allowed in the sandbox", name)
+            }
+            SecurityError::OutputTooLarge(limit) => {
+                write!(f, "the output is longer than the limit of {} bytes", limit)
+            }
+            SecurityError::TooManyIterations(limit) => {
+                write!(f, "a loop ran more than the limit of {} times", limit)
+            }
+            SecurityError::TooDeep(limit) => write!(
+                f,
+                "macros and templates are nested deeper than the limit of {}",
+                limit
+            ),
+            SecurityError::RangeTooLarge(limit) => {
+                write!(f, "a range is larger than the limit of {} numbers", limit)
+            }
+        }
+    }
+}
+
+impl Error for SecurityError {}
+
+impl From<SecurityError> for RenderError {
+    fn from(error: SecurityError) -> Self {
+        RenderError::Security(error)
+    }
+}
+
+/// How deep macros and templates are nested at the moment (see [`Context::descend`]). It goes back
+/// up when this is dropped.
+pub(crate) struct Depth(Rc<Cell<usize>>);
+
+impl Drop for Depth {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
+    }
+}
+
+impl Context {
+    /// Checks that the attribute `attr` of `value` can be looked up.
+    pub(crate) fn check_attr(&self, value: &Value, attr: &str) -> RenderResult<()> {
+        match self.sandbox() {
+            Some(sandbox)
+                if !sandbox.attributes.contains(attr) && !matches!(value, Value::Namespace(_)) =>
+            {
+                Err(SecurityError::Attribute(attr.to_string()).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that the method called `name` can be called.
+    pub(crate) fn check_method(&self, name: &str) -> RenderResult<()> {
+        match self.sandbox() {
+            Some(sandbox) if !sandbox.methods.contains(name) => {
+                Err(SecurityError::Method(name.to_string()).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that `value` is not a string which is longer than the output can be.
+    pub(crate) fn check_size(&self, value: &Value) -> RenderResult<()> {
+        match (self.sandbox(), value) {
+            (Some(sandbox), Value::String(string)) if string.len() > sandbox.max_output => {
+                Err(SecurityError::OutputTooLarge(sandbox.max_output).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that `output` is not longer than it is allowed to be.
+    pub(crate) fn check_output(&self, output: &dyn Output) -> RenderResult<()> {
+        match self.sandbox() {
+            Some(sandbox) if output.written() > sandbox.max_output => {
+                Err(SecurityError::OutputTooLarge(sandbox.max_output).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies a binary operator, without building a string which is longer than the output can
+    /// be.
+    pub(crate) fn apply(&self, op: BinOp, lhs: Value, rhs: Value) -> RenderResult<Value> {
+        if let (Some(sandbox), BinOp::Mul) = (self.sandbox(), op) {
+            if let (Value::String(string), Value::Integer(n))
+            | (Value::Integer(n), Value::String(string)) = (&lhs, &rhs)
+            {
+                let length = string.len().saturating_mul((*n).max(0) as usize);
+                if length > sandbox.max_output {
+                    return Err(SecurityError::OutputTooLarge(sandbox.max_output).into());
+                }
+            }
+        }
+        let value = apply(op, lhs, rhs)?;
+        self.check_size(&value)?;
+        Ok(value)
+    }
+
+    /// The items which a loop over `value` visits, if there are not too many of them.
+    pub(crate) fn iterate(&self, value: &Value) -> RenderResult<Vec<Value>> {
+        let items = value.iter()?;
+        match self.sandbox() {
+            Some(sandbox) if items.len() > sandbox.max_iterations => {
+                Err(SecurityError::TooManyIterations(sandbox.max_iterations).into())
+            }
+            _ => Ok(items),
+        }
+    }
+
+    /// Goes one level deeper into macros and templates (if the budget has not run out), which
+    /// lasts until the result is dropped.
+    pub(crate) fn descend(&self) -> RenderResult<Depth> {
+        self.check_budget()?;
+        let limit = self
+            .sandbox()
+            .map_or(MAX_DEPTH, |sandbox| sandbox.max_depth);
+        let depth = self.depth();
+        if depth.get() >= limit {
+            return Err(SecurityError::TooDeep(limit).into());
+        }
+        depth.set(depth.get() + 1);
+        Ok(Depth(depth))
+    }
+}
@@ -0,0 +1,151 @@
+//! The builtin tests (the names which can follow `is`, e.g. `{% if x is defined %}`).
+//!
+//! See <https://jinja.palletsprojects.com/en/3.0.x/templates/#list-of-builtin-tests> for what
+//! each one does in Jinja.
+
+use crate::parse::expr::op::{apply as apply_op, BinOp};
+
+use super::{filters::Args, Builtin, Kwargs, RenderError, RenderResult, Value};
+
+/// The builtin tests, for tools which explain them (such as editors).
+pub const BUILTINS: &[Builtin] = &[
+    Builtin::new(
+        "boolean",
+        "boolean()",
+        "Whether the value is `true` or `false`.",
+    ),
+    Builtin::new("callable", "callable()", "Whether the value can be called."),
+    Builtin::new("defined", "defined()", "Whether the value is defined."),
+    Builtin::new(
+        "divisibleby",
+        "divisibleby(num)",
+        "Whether the value (an integer) is divisible by `num`.",
+    ),
+    Builtin::new("eq", "eq(other)", "Whether the value is equal to `other`."),
+    Builtin::new("equalto", "equalto(other)", "A deprecated name for `eq`."),
+    Builtin::new("even", "even()", "Whether the value (an integer) is even."),
+    Builtin::new("false", "false()", "Whether the value is `false`."),
+    Builtin::new("float", "float()", "Whether the value is a float."),
+    Builtin::new("ge", "ge(other)", "Whether the value is at least `other`."),
+    Builtin::new(
+        "greaterthan",
+        "greaterthan(other)",
+        "A deprecated name for `gt`.",
+    ),
+    Builtin::new(
+        "gt",
+        "gt(other)",
+        "Whether the value is greater than `other`.",
+    ),
+    Builtin::new("integer", "integer()", "Whether the value is an integer."),
+    Builtin::new(
+        "iterable",
+        "iterable()",
+        "Whether the value can be looped over.",
+    ),
+    Builtin::new("le", "le(other)", "Whether the value is at most `other`."),
+    Builtin::new("lessthan", "lessthan(other)", "A deprecated name for `lt`."),
+    Builtin::new(
+        "lower",
+        "lower()",
+        "Whether the value is a string which is all lower case.",
+    ),
+    Builtin::new("lt", "lt(other)", "Whether the value is less than `other`."),
+    Builtin::new("mapping", "mapping()", "Whether the value is a dict."),
+    Builtin::new(
+        "ne",
+        "ne(other)",
+        "Whether the value is not equal to `other`.",
+    ),
+    Builtin::new("none", "none()", "Whether the value is `none`."),
+    Builtin::new(
+        "number",
+        "number()",
+        "Whether the value is an integer or a float.",
+    ),
+    Builtin::new("odd", "odd()", "Whether the value (an integer) is odd."),
+    Builtin::new(
+        "sequence",
+        "sequence()",
+        "Whether the value is a string, a list or a dict.",
+    ),
+    Builtin::new("string", "string()", "Whether the value is a string."),
+    Builtin::new("true", "true()", "Whether the value is `true`."),
+    Builtin::new(
+        "undefined",
+        "undefined()",
+        "Whether the value is undefined.",
+    ),
+    Builtin::new(
+        "upper",
+        "upper()",
+        "Whether the value is a string which is all upper case.",
+    ),
+];
+
+/// Applies the test called `name` to `value`.
+pub(crate) fn apply(
+    name: &str,
+    value: Value,
+    args: Vec<Value>,
+    kwargs: Kwargs,
+) -> RenderResult<bool> {
+    let mut args = Args::new("test", name, args, kwargs);
+
+    let compare = |op, args: &mut Args| -> RenderResult<bool> {
+        let other = args.required("other")?;
+        Ok(apply_op(op, value.clone(), other)?.is_truthy())
+    };
+
+    let res = match name {
+        "defined" => !matches!(value, Value::Undefined),
+        "undefined" => matches!(value, Value::Undefined),
+        "none" => matches!(value, Value::None),
+        "boolean" => matches!(value, Value::Bool(_)),
+        "true" => matches!(value, Value::Bool(true)),
+        "false" => matches!(value, Value::Bool(false)),
+        "integer" => matches!(value, Value::Integer(_)),
+        "float" => matches!(value, Value::Float(_)),
+        "number" => matches!(value, Value::Integer(_) | Value::Float(_)),
+        "string" => matches!(value, Value::String(_)),
+        "mapping" => matches!(value, Value::Dict(_)),
+        "sequence" => matches!(value, Value::String(_) | Value::List(_) | Value::Dict(_)),
+        "iterable" => value.iter().is_ok(),
+        "callable" => matches!(value, Value::Function(_)),
+        "lower" => matches!(&value, Value::String(string) if string.to_lowercase() == *string),
+        "upper" => matches!(&value, Value::String(string) if string.to_uppercase() == *string),
+        "even" | "odd" => match value {
+            Value::Integer(int) => (int % 2 == 0) == (name == "even"),
+            other => {
+                return Err(RenderError::InvalidOperation(format!(
+                    "the `{}` test only applies to integers, not {:?}",
+                    name, other
+                )))
+            }
+        },
+        "divisibleby" => match (&value, args.required("num")?) {
+            (Value::Integer(int), Value::Integer(num)) if num != 0 => int % num == 0,
+            (_, num) => {
+                return Err(RenderError::InvalidOperation(format!(
+                    "cannot test whether {:?} is divisible by {:?}",
+                    value, num
+                )))
+            }
+        },
+        "eq" | "equalto" | "==" => compare(BinOp::Eq, &mut args)?,
+        "ne" | "!=" => compare(BinOp::NotEq, &mut args)?,
+        "lt" | "lessthan" | "<" => compare(BinOp::Lt, &mut args)?,
+        "le" | "<=" => compare(BinOp::LtEq, &mut args)?,
+        "gt" | "greaterthan" | ">" => compare(BinOp::Gt, &mut args)?,
+        "ge" | ">=" => compare(BinOp::GtEq, &mut args)?,
+        "in" => {
+            let seq = args.required("seq")?;
+            apply_op(BinOp::In, value, seq)?.is_truthy()
+        }
+        _ => return Err(RenderError::UnknownTest(name.to_string())),
+    };
+
+    args.finish()?;
+
+    Ok(res)
+}
@@ -0,0 +1,131 @@
+//! What happens when a template uses a variable (or attribute) which is not defined.
+
+use std::{fmt::Display, ops::Range};
+
+use super::{Context, Output, RenderError, RenderResult, Value};
+
+/// How undefined values behave, which is chosen for each context (see [`Context::set_undefined`]).
+/// These are Jinja's `Undefined` classes.
+///
+/// Only variables which are not in the context, and attributes which values do not have, are
+/// undefined; a variable which is bound to `Value::Undefined` (e.g. a macro argument which was not
+/// given) is not an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Undefined {
+    /// Undefined values render as nothing, are falsy and iterate as empty, but looking up an
+    /// attribute of one is an error (`Undefined`, which is Jinja's default).
+    Lenient,
+    /// As `Lenient`, but attributes of undefined values are undefined too, so that `{{ a.b.c }}`
+    /// renders as nothing when `a` is undefined (`ChainableUndefined`). This is the default, since
+    /// it is how undefined values behaved before there was a choice.
+    #[default]
+    Chainable,
+    /// As `Chainable`, but an expression in `{{ … }}` which is undefined is written out as it
+    /// was, e.g. `{{ user.name }}`, so that it can be seen in the output (`DebugUndefined`).
+    Debug,
+    /// Using an undefined value is an error ([`RenderError::Undefined`]), unless it is only
+    /// tested with `is defined` (or `is undefined`), or given a `default` (`StrictUndefined`).
+    Strict,
+}
+
+/// Where an expression which was undefined is in the source of its template (see
+/// [`RenderError::span`]).
+///
+/// Expressions do not know where they were parsed from, but the names in them borrow their text
+/// from the source, so this is where that text is in memory. It only means anything while the
+/// source is still alive, and names in templates which were made owned are not anywhere in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Origin {
+    start: usize,
+    end: usize,
+}
+
+impl Origin {
+    /// Where `text` is.
+    pub(crate) fn of(text: &str) -> Self {
+        let start = text.as_ptr() as usize;
+        Self {
+            start,
+            end: start + text.len(),
+        }
+    }
+
+    /// From the start of this to the end of `other`.
+    pub(crate) fn to(self, other: Origin) -> Self {
+        Self {
+            start: self.start,
+            end: other.end,
+        }
+    }
+
+    /// Where this is in `source`, in bytes, if it is there at all.
+    pub fn span(&self, source: &str) -> Option<Range<usize>> {
+        let start = self.start.checked_sub(source.as_ptr() as usize)?;
+        let end = self.end.checked_sub(source.as_ptr() as usize)?;
+        (start <= end && end <= source.len()).then_some(start..end)
+    }
+}
+
+impl Context {
+    /// The value of the variable `name` (a slice of the source of the template, if it was parsed
+    /// from one), or what the policy makes of it if there is none.
+    pub(crate) fn variable(&self, name: &str) -> RenderResult<Value> {
+        match self.get(name) {
+            Some(value) => Ok(value),
+            None if self.undefined() == Undefined::Strict => Err(RenderError::Undefined {
+                expr: name.to_string(),
+                origin: Origin::of(name),
+            }),
+            None => Ok(Value::Undefined),
+        }
+    }
+
+    /// The attribute `attr` of `value`, or what the policy makes of it if `value` is undefined or
+    /// does not have it. `object` is the expression which `value` came from (e.g. `user` in
+    /// `user.name`), which is found at `origin`.
+    pub(crate) fn attr(
+        &self,
+        value: Value,
+        object: &dyn Display,
+        origin: Origin,
+        attr: &str,
+    ) -> RenderResult<Value> {
+        self.check_attr(&value, attr)?;
+        match (value, self.undefined()) {
+            (Value::Undefined, Undefined::Chainable | Undefined::Debug) => Ok(Value::Undefined),
+            (Value::Undefined, _) => Err(RenderError::Undefined {
+                expr: object.to_string(),
+                origin,
+            }),
+            (value, Undefined::Strict) => match value.get_attr(attr) {
+                Value::Undefined => Err(RenderError::Undefined {
+                    expr: format!("{}.{}", object, attr),
+                    origin: origin.to(Origin::of(attr)),
+                }),
+                value => Ok(value),
+            },
+            (value, _) => Ok(value.get_attr(attr)),
+        }
+    }
+
+    /// The attribute `attr` of `value`, or `Undefined` (whatever the policy is).
+    pub(crate) fn attr_or_undefined(&self, value: Value, attr: &str) -> RenderResult<Value> {
+        self.check_attr(&value, attr)?;
+        Ok(value.get_attr(attr))
+    }
+
+    /// Writes `value` (the value of `expr`) to `output`.
+    pub(crate) fn emit(
+        &self,
+        value: Value,
+        expr: &dyn Display,
+        output: &mut dyn Output,
+    ) -> RenderResult<()> {
+        match value {
+            Value::Undefined if self.undefined() == Undefined::Debug => {
+                write!(output, "{{{{ {} }}}}", expr)
+            }
+            value => write!(output, "{}", value),
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use std::{
+    cell::RefCell,
+    fmt::{Display, Write},
+    rc::Rc,
+};
+
+use super::{RenderError, RenderResult};
+
+/// A value which a template can manipulate.
+///
+/// The variants follow Python's types, because Jinja templates are written with Python semantics
+/// in mind. In particular, lists and dicts are shared by reference, so changes made through one
+/// variable (e.g. by `{% do items.append(1) %}`) are visible through every other.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A variable which was never defined. Renders as an empty string.
+    Undefined,
+    None,
+    Bool(bool),
+    Integer(i32),
+    Float(f32),
+    String(String),
+    List(Rc<RefCell<Vec<Value>>>),
+    Dict(Rc<RefCell<Vec<(Value, Value)>>>),
+    Function(Function),
+    /// A mutable object (created by calling `namespace()`), which allows templates to carry
+    /// values out of a scope.
+    Namespace(Rc<RefCell<Vec<(String, Value)>>>),
+}
+
+impl Value {
+    pub fn list(items: Vec<Value>) -> Self {
+        Self::List(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn dict(pairs: Vec<(Value, Value)>) -> Self {
+        Self::Dict(Rc::new(RefCell::new(pairs)))
+    }
+
+    pub fn namespace(attrs: Vec<(String, Value)>) -> Self {
+        Self::Namespace(Rc::new(RefCell::new(attrs)))
+    }
+
+    /// Python's notion of truthiness.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Undefined | Value::None => false,
+            Value::Bool(b) => *b,
+            Value::Integer(int) => *int != 0,
+            Value::Float(float) => *float != 0.0,
+            Value::String(string) => !string.is_empty(),
+            Value::List(list) => !list.borrow().is_empty(),
+            Value::Dict(dict) => !dict.borrow().is_empty(),
+            Value::Function(_) | Value::Namespace(_) => true,
+        }
+    }
+
+    /// Looks up an attribute (`value.name`) of this value.
+    pub fn get_attr(&self, name: &str) -> Value {
+        match self {
+            Value::Dict(dict) => dict
+                .borrow()
+                .iter()
+                .find(|(key, _)| matches!(key, Value::String(key) if key == name))
+                .map(|(_, value)| value.clone())
+                .unwrap_or(Value::Undefined),
+            Value::Namespace(attrs) => attrs
+                .borrow()
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+                .unwrap_or(Value::Undefined),
+            _ => Value::Undefined,
+        }
+    }
+
+    /// Assigns to an attribute (`{% set value.name = ... %}`) of this value, which must be a
+    /// namespace.
+    pub fn set_attr(&self, name: &str, value: Value) -> RenderResult<()> {
+        match self {
+            Value::Namespace(attrs) => {
+                let mut attrs = attrs.borrow_mut();
+                match attrs.iter_mut().find(|(key, _)| key == name) {
+                    Some((_, existing)) => *existing = value,
+                    None => attrs.push((name.to_string(), value)),
+                }
+                Ok(())
+            }
+            other => Err(RenderError::InvalidOperation(format!(
+                "cannot assign attribute on non-namespace object {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// Splits the value into exactly `n` items (`{% set a, b = [1, 2] %}`).
+    pub fn unpack(&self, n: usize) -> RenderResult<Vec<Value>> {
+        let items = self.iter()?;
+        if items.len() != n {
+            return Err(RenderError::InvalidOperation(format!(
+                "expected {} values to unpack, found {}",
+                n,
+                items.len()
+            )));
+        }
+        Ok(items)
+    }
+
+    /// The items which a `for` loop over this value visits (for dicts, these are the keys).
+    pub fn iter(&self) -> RenderResult<Vec<Value>> {
+        match self {
+            Value::Undefined => Ok(vec![]),
+            Value::String(string) => Ok(string.chars().map(|c| Value::String(c.into())).collect()),
+            Value::List(list) => Ok(list.borrow().clone()),
+            Value::Dict(dict) => Ok(dict.borrow().iter().map(|(key, _)| key.clone()).collect()),
+            other => Err(not_iterable(other)),
+        }
+    }
+
+    /// Calls a method (`value.name(args)`) of this value.
+    ///
+    /// Only the methods of Python's `list` and `dict` which are commonly used from templates are
+    /// supported.
+    pub fn call_method(&self, name: &str, mut args: Vec<Value>) -> RenderResult<Value> {
+        match (self, name, args.len()) {
+            (Value::List(list), "append", 1) => {
+                list.borrow_mut().push(args.remove(0));
+                Ok(Value::None)
+            }
+            (Value::List(list), "extend", 1) => {
+                let other = match args.remove(0) {
+                    Value::List(other) => other.borrow().clone(),
+                    other => return Err(not_iterable(&other)),
+                };
+                list.borrow_mut().extend(other);
+                Ok(Value::None)
+            }
+            (Value::List(list), "pop", 0) => list
+                .borrow_mut()
+                .pop()
+                .ok_or_else(|| RenderError::InvalidOperation("pop from empty list".to_string())),
+            (Value::Dict(dict), "update", 1) => {
+                let other = match args.remove(0) {
+                    Value::Dict(other) => other.borrow().clone(),
+                    other => return Err(not_iterable(&other)),
+                };
+                let mut dict = dict.borrow_mut();
+                for (key, value) in other {
+                    match dict.iter_mut().find(|(existing, _)| *existing == key) {
+                        Some((_, existing)) => *existing = value,
+                        None => dict.push((key, value)),
+                    }
+                }
+                Ok(Value::None)
+            }
+            _ => Err(RenderError::InvalidOperation(format!(
+                "{:?} has no method `{}` taking {} argument(s)",
+                self,
+                name,
+                args.len()
+            ))),
+        }
+    }
+
+    /// Calls this value (`value(args, name=kwarg)`).
+    pub fn call(&self, args: Vec<Value>, kwargs: Kwargs) -> RenderResult<Value> {
+        match self {
+            Value::Function(function) => function.call(args, kwargs),
+            other => Err(RenderError::InvalidOperation(format!(
+                "{:?} is not callable",
+                other
+            ))),
+        }
+    }
+
+    /// Writes the value as Python's `repr` would (used for items inside lists and dicts).
+    fn fmt_repr(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::String(string) => {
+                f.write_char('\'')?;
+                f.write_str(string)?;
+                f.write_char('\'')
+            }
+            other => other.fmt(f),
+        }
+    }
+}
+
+fn not_iterable(value: &Value) -> RenderError {
+    RenderError::InvalidOperation(format!("{:?} is not iterable", value))
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Undefined => Ok(()),
+            Value::None => f.write_str("None"),
+            Value::Bool(b) => f.write_str(if *b { "True" } else { "False" }),
+            Value::Integer(int) => int.fmt(f),
+            Value::Float(float) if float.is_finite() && float.fract() == 0.0 => {
+                write!(f, "{:.1}", float)
+            }
+            Value::Float(float) => float.fmt(f),
+            Value::String(string) => f.write_str(string),
+            Value::List(list) => {
+                f.write_char('[')?;
+                for (index, item) in list.borrow().iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    item.fmt_repr(f)?;
+                }
+                f.write_char(']')
+            }
+            Value::Dict(dict) => {
+                f.write_char('{')?;
+                for (index, (key, value)) in dict.borrow().iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    key.fmt_repr(f)?;
+                    f.write_str(": ")?;
+                    value.fmt_repr(f)?;
+                }
+                f.write_char('}')
+            }
+            Value::Function(_) => f.write_str("<function>"),
+            Value::Namespace(attrs) => {
+                f.write_str("<Namespace {")?;
+                for (index, (key, value)) in attrs.borrow().iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "'{}': ", key)?;
+                    value.fmt_repr(f)?;
+                }
+                f.write_str("}>")
+            }
+        }
+    }
+}
+
+/// Keyword arguments, in the order in which they were passed.
+pub type Kwargs = Vec<(String, Value)>;
+
+type FunctionImpl = dyn Fn(Vec<Value>, Kwargs) -> RenderResult<Value>;
+
+/// A function which templates can call. It receives its positional arguments, followed by its
+/// keyword arguments.
+#[derive(Clone)]
+pub struct Function(Rc<FunctionImpl>);
+
+impl Function {
+    pub fn new<F>(function: F) -> Self
+    where
+        F: Fn(Vec<Value>, Kwargs) -> RenderResult<Value> + 'static,
+    {
+        Self(Rc::new(function))
+    }
+
+    pub fn call(&self, args: Vec<Value>, kwargs: Kwargs) -> RenderResult<Value> {
+        (self.0)(args, kwargs)
+    }
+}
+
+impl std::fmt::Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Function")
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(int: i32) -> Self {
+        Self::Integer(int)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(float: f32) -> Self {
+        Self::Float(float)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(string: &str) -> Self {
+        Self::String(string.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(string: String) -> Self {
+        Self::String(string)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(list: Vec<T>) -> Self {
+        Self::list(list.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<Function> for Value {
+    fn from(function: Function) -> Self {
+        Self::Function(function)
+    }
+}
+
+/// Values can be read from any format which serde supports (e.g. to fill a context from a JSON
+/// file). Integers which do not fit in an `i32` become floats, and `null` becomes `None`.
+#[cfg(feature = "serde")]
+mod deserialize {
+    use std::{convert::TryFrom, fmt};
+
+    use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+
+    use super::Value;
+
+    impl<'de> Deserialize<'de> for Value {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(ValueVisitor)
+        }
+    }
+
+    struct ValueVisitor;
+
+    impl<'de> Visitor<'de> for ValueVisitor {
+        type Value = Value;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a value which a template can use")
+        }
+
+        fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+            Ok(Value::Bool(b))
+        }
+
+        fn visit_i64<E>(self, int: i64) -> Result<Value, E> {
+            Ok(i32::try_from(int).map_or(Value::Float(int as f32), Value::Integer))
+        }
+
+        fn visit_u64<E>(self, int: u64) -> Result<Value, E> {
+            Ok(i32::try_from(int).map_or(Value::Float(int as f32), Value::Integer))
+        }
+
+        fn visit_f64<E>(self, float: f64) -> Result<Value, E> {
+            Ok(Value::Float(float as f32))
+        }
+
+        fn visit_str<E>(self, string: &str) -> Result<Value, E> {
+            Ok(Value::from(string))
+        }
+
+        fn visit_string<E>(self, string: String) -> Result<Value, E> {
+            Ok(Value::String(string))
+        }
+
+        fn visit_unit<E>(self) -> Result<Value, E> {
+            Ok(Value::None)
+        }
+
+        fn visit_none<E>(self) -> Result<Value, E> {
+            Ok(Value::None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+            Value::deserialize(deserializer)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+            let mut items = vec![];
+            while let Some(item) = seq.next_element()? {
+                items.push(item);
+            }
+            Ok(Value::list(items))
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+            let mut pairs = vec![];
+            while let Some(pair) = map.next_entry()? {
+                pairs.push(pair);
+            }
+            Ok(Value::dict(pairs))
+        }
+    }
+}
@@ -0,0 +1,623 @@
+//! Traversing parsed templates.
+//!
+//! [`Visit`] walks a template by reference, and [`VisitMut`] by mutable reference. Both have a
+//! method for every kind of node, whose default implementation visits the node's children by
+//! calling the `walk_*` function of the same name. Overriding a method replaces this, so an
+//! implementation which still wants the children to be visited has to call the `walk_*` function
+//! itself.
+//!
+//! Nodes are visited in the order in which they appear in the source.
+
+use crate::parse::ast::{
+    BinOpExpr, Block, CompareExpr, Do, Else, Expr, Filter, ForStmt, Ident, If, IfBranch, Import,
+    Include, Items, Literal, Macro, Set, SetData, SetTarget, Stmt, Template, UnaryOpExpr, With,
+};
+
+/// Visits the nodes of a template by reference.
+///
+/// Nodes are borrowed for `'ast`, so a visitor can keep references to them.
+pub trait Visit<'ast> {
+    fn visit_template(&mut self, template: &'ast Template<'ast>) {
+        walk_template(self, template)
+    }
+
+    /// Visits a body (of a template or a statement) as a whole. By default, visits each block.
+    fn visit_body(&mut self, body: &'ast [Block<'ast>]) {
+        walk_body(self, body)
+    }
+
+    fn visit_block(&mut self, block: &'ast Block<'ast>) {
+        walk_block(self, block)
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt<'ast>) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_for(&mut self, stmt: &'ast ForStmt<'ast>) {
+        walk_for(self, stmt)
+    }
+
+    fn visit_else(&mut self, r#else: &'ast Else<'ast>) {
+        walk_else(self, r#else)
+    }
+
+    fn visit_if(&mut self, r#if: &'ast If<'ast>) {
+        walk_if(self, r#if)
+    }
+
+    fn visit_if_branch(&mut self, branch: &'ast IfBranch<'ast>) {
+        walk_if_branch(self, branch)
+    }
+
+    fn visit_macro(&mut self, r#macro: &'ast Macro<'ast>) {
+        walk_macro(self, r#macro)
+    }
+
+    fn visit_filter(&mut self, filter: &'ast Filter<'ast>) {
+        walk_filter(self, filter)
+    }
+
+    fn visit_set(&mut self, set: &'ast Set<'ast>) {
+        walk_set(self, set)
+    }
+
+    fn visit_set_target(&mut self, target: &'ast SetTarget<'ast>) {
+        walk_set_target(self, target)
+    }
+
+    fn visit_set_data(&mut self, data: &'ast SetData<'ast>) {
+        walk_set_data(self, data)
+    }
+
+    fn visit_include(&mut self, include: &'ast Include<'ast>) {
+        walk_include(self, include)
+    }
+
+    fn visit_import(&mut self, import: &'ast Import<'ast>) {
+        walk_import(self, import)
+    }
+
+    fn visit_with(&mut self, with: &'ast With<'ast>) {
+        walk_with(self, with)
+    }
+
+    fn visit_do(&mut self, r#do: &'ast Do<'ast>) {
+        walk_do(self, r#do)
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr<'ast>) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_unary_op(&mut self, expr: &'ast UnaryOpExpr<'ast>) {
+        walk_unary_op(self, expr)
+    }
+
+    fn visit_bin_op(&mut self, expr: &'ast BinOpExpr<'ast>) {
+        walk_bin_op(self, expr)
+    }
+
+    fn visit_compare(&mut self, expr: &'ast CompareExpr<'ast>) {
+        walk_compare(self, expr)
+    }
+
+    fn visit_literal(&mut self, literal: &'ast Literal<'ast>) {
+        walk_literal(self, literal)
+    }
+
+    /// Visits every identifier: names which are read or bound, but also the names of filters,
+    /// attributes, macro parameters and keyword arguments.
+    fn visit_ident(&mut self, _ident: &'ast Ident<'ast>) {}
+}
+
+pub fn walk_template<'ast, V: Visit<'ast> + ?Sized>(
+    visitor: &mut V,
+    template: &'ast Template<'ast>,
+) {
+    visitor.visit_body(&template.expressions);
+}
+
+pub fn walk_body<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, body: &'ast [Block<'ast>]) {
+    for block in body {
+        visitor.visit_block(block);
+    }
+}
+
+pub fn walk_block<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, block: &'ast Block<'ast>) {
+    match block {
+        Block::Expr(e) => visitor.visit_expr(e),
+        Block::Stmt(s) => visitor.visit_stmt(s),
+        Block::RawText(_) | Block::Comment(_) => {}
+    }
+}
+
+pub fn walk_stmt<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, stmt: &'ast Stmt<'ast>) {
+    match stmt {
+        Stmt::For(stmt, r#else) => {
+            visitor.visit_for(stmt);
+            if let Some(r#else) = r#else {
+                visitor.visit_else(r#else);
+            }
+        }
+        Stmt::If(i) => visitor.visit_if(i),
+        Stmt::Macro(m) => visitor.visit_macro(m),
+        Stmt::Filter(filter) => visitor.visit_filter(filter),
+        Stmt::Set(set) => visitor.visit_set(set),
+        Stmt::Include(i) => visitor.visit_include(i),
+        Stmt::Import(i) => visitor.visit_import(i),
+        Stmt::With(w) => visitor.visit_with(w),
+        Stmt::Do(d) => visitor.visit_do(d),
+    }
+}
+
+pub fn walk_for<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, stmt: &'ast ForStmt<'ast>) {
+    for ident in &stmt.idents_of_iter {
+        visitor.visit_ident(ident);
+    }
+    visitor.visit_expr(&stmt.in_expr);
+    visitor.visit_body(&stmt.body);
+}
+
+pub fn walk_else<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, r#else: &'ast Else<'ast>) {
+    visitor.visit_body(&r#else.body);
+}
+
+pub fn walk_if<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, r#if: &'ast If<'ast>) {
+    visitor.visit_if_branch(&r#if.if_branch);
+    for branch in &r#if.elif_branches {
+        visitor.visit_if_branch(branch);
+    }
+    if let Some(r#else) = &r#if.else_branch {
+        visitor.visit_else(r#else);
+    }
+}
+
+pub fn walk_if_branch<'ast, V: Visit<'ast> + ?Sized>(
+    visitor: &mut V,
+    branch: &'ast IfBranch<'ast>,
+) {
+    visitor.visit_expr(&branch.condition);
+    visitor.visit_body(&branch.body);
+}
+
+pub fn walk_macro<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, r#macro: &'ast Macro<'ast>) {
+    visitor.visit_ident(&r#macro.name);
+    for arg in &r#macro.args {
+        visitor.visit_ident(arg);
+    }
+    walk_kwargs(visitor, &r#macro.kwargs);
+    visitor.visit_body(&r#macro.body);
+}
+
+fn walk_kwargs<'ast, V: Visit<'ast> + ?Sized>(
+    visitor: &mut V,
+    kwargs: &'ast [(Ident<'ast>, Expr<'ast>)],
+) {
+    for (ident, expr) in kwargs {
+        visitor.visit_ident(ident);
+        visitor.visit_expr(expr);
+    }
+}
+
+pub fn walk_filter<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, filter: &'ast Filter<'ast>) {
+    visitor.visit_ident(&filter.name);
+    visitor.visit_body(&filter.body);
+}
+
+pub fn walk_set<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, set: &'ast Set<'ast>) {
+    for target in &set.targets {
+        visitor.visit_set_target(target);
+    }
+    visitor.visit_set_data(&set.data);
+}
+
+pub fn walk_set_target<'ast, V: Visit<'ast> + ?Sized>(
+    visitor: &mut V,
+    target: &'ast SetTarget<'ast>,
+) {
+    match target {
+        SetTarget::Ident(ident) => visitor.visit_ident(ident),
+        SetTarget::Attr(ident, attr) => {
+            visitor.visit_ident(ident);
+            visitor.visit_ident(attr);
+        }
+    }
+}
+
+pub fn walk_set_data<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, data: &'ast SetData<'ast>) {
+    match data {
+        SetData::Expr(expr) => visitor.visit_expr(expr),
+        SetData::Block(body, filters) => {
+            visitor.visit_body(body);
+            for filter in filters {
+                visitor.visit_expr(filter);
+            }
+        }
+    }
+}
+
+pub fn walk_include<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, include: &'ast Include<'ast>) {
+    visitor.visit_expr(&include.files);
+}
+
+pub fn walk_import<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, import: &'ast Import<'ast>) {
+    visitor.visit_expr(&import.file);
+    match &import.items {
+        Items::All(alias) => visitor.visit_ident(alias),
+        Items::List(items) => {
+            for (item, alias) in items {
+                visitor.visit_ident(item);
+                if let Some(alias) = alias {
+                    visitor.visit_ident(alias);
+                }
+            }
+        }
+    }
+}
+
+pub fn walk_with<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, with: &'ast With<'ast>) {
+    walk_kwargs(visitor, &with.bindings);
+    visitor.visit_body(&with.body);
+}
+
+pub fn walk_do<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, r#do: &'ast Do<'ast>) {
+    visitor.visit_expr(&r#do.expr);
+}
+
+pub fn walk_expr<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, expr: &'ast Expr<'ast>) {
+    match expr {
+        Expr::UnaryOp(u) => visitor.visit_unary_op(u),
+        Expr::BinOpExpr(b) => visitor.visit_bin_op(b),
+        Expr::Compare(c) => visitor.visit_compare(c),
+        Expr::Literal(l) => visitor.visit_literal(l),
+        Expr::Ident(i) => visitor.visit_ident(i),
+        Expr::FunctionCall(name, args, kwargs) => {
+            visitor.visit_ident(name);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+            walk_kwargs(visitor, kwargs);
+        }
+    }
+}
+
+pub fn walk_unary_op<'ast, V: Visit<'ast> + ?Sized>(
+    visitor: &mut V,
+    expr: &'ast UnaryOpExpr<'ast>,
+) {
+    visitor.visit_expr(&expr.arg);
+}
+
+pub fn walk_bin_op<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, expr: &'ast BinOpExpr<'ast>) {
+    visitor.visit_expr(&expr.arg1);
+    visitor.visit_expr(&expr.arg2);
+}
+
+pub fn walk_compare<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, expr: &'ast CompareExpr<'ast>) {
+    visitor.visit_expr(&expr.first);
+    for (_, arg) in &expr.rest {
+        visitor.visit_expr(arg);
+    }
+}
+
+pub fn walk_literal<'ast, V: Visit<'ast> + ?Sized>(visitor: &mut V, literal: &'ast Literal<'ast>) {
+    match literal {
+        Literal::List(items) | Literal::Tuple(items) => {
+            for item in items {
+                visitor.visit_literal(item);
+            }
+        }
+        Literal::Dict(pairs) => {
+            for (key, value) in pairs {
+                visitor.visit_literal(key);
+                visitor.visit_literal(value);
+            }
+        }
+        Literal::String(_) | Literal::Integer(_) | Literal::Float(_) | Literal::Bool(_) => {}
+    }
+}
+
+/// Visits the nodes of a template by mutable reference, so that they can be changed (or replaced)
+/// in place.
+pub trait VisitMut<'i> {
+    fn visit_template_mut(&mut self, template: &mut Template<'i>) {
+        walk_template_mut(self, template)
+    }
+
+    /// Visits a body (of a template or a statement) as a whole, so that blocks can be added or
+    /// removed. By default, visits each block.
+    fn visit_body_mut(&mut self, body: &mut Vec<Block<'i>>) {
+        walk_body_mut(self, body)
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block<'i>) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt<'i>) {
+        walk_stmt_mut(self, stmt)
+    }
+
+    fn visit_for_mut(&mut self, stmt: &mut ForStmt<'i>) {
+        walk_for_mut(self, stmt)
+    }
+
+    fn visit_else_mut(&mut self, r#else: &mut Else<'i>) {
+        walk_else_mut(self, r#else)
+    }
+
+    fn visit_if_mut(&mut self, r#if: &mut If<'i>) {
+        walk_if_mut(self, r#if)
+    }
+
+    fn visit_if_branch_mut(&mut self, branch: &mut IfBranch<'i>) {
+        walk_if_branch_mut(self, branch)
+    }
+
+    fn visit_macro_mut(&mut self, r#macro: &mut Macro<'i>) {
+        walk_macro_mut(self, r#macro)
+    }
+
+    fn visit_filter_mut(&mut self, filter: &mut Filter<'i>) {
+        walk_filter_mut(self, filter)
+    }
+
+    fn visit_set_mut(&mut self, set: &mut Set<'i>) {
+        walk_set_mut(self, set)
+    }
+
+    fn visit_set_target_mut(&mut self, target: &mut SetTarget<'i>) {
+        walk_set_target_mut(self, target)
+    }
+
+    fn visit_set_data_mut(&mut self, data: &mut SetData<'i>) {
+        walk_set_data_mut(self, data)
+    }
+
+    fn visit_include_mut(&mut self, include: &mut Include<'i>) {
+        walk_include_mut(self, include)
+    }
+
+    fn visit_import_mut(&mut self, import: &mut Import<'i>) {
+        walk_import_mut(self, import)
+    }
+
+    fn visit_with_mut(&mut self, with: &mut With<'i>) {
+        walk_with_mut(self, with)
+    }
+
+    fn visit_do_mut(&mut self, r#do: &mut Do<'i>) {
+        walk_do_mut(self, r#do)
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr<'i>) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_unary_op_mut(&mut self, expr: &mut UnaryOpExpr<'i>) {
+        walk_unary_op_mut(self, expr)
+    }
+
+    fn visit_bin_op_mut(&mut self, expr: &mut BinOpExpr<'i>) {
+        walk_bin_op_mut(self, expr)
+    }
+
+    fn visit_compare_mut(&mut self, expr: &mut CompareExpr<'i>) {
+        walk_compare_mut(self, expr)
+    }
+
+    fn visit_literal_mut(&mut self, literal: &mut Literal<'i>) {
+        walk_literal_mut(self, literal)
+    }
+
+    /// See [`Visit::visit_ident`].
+    fn visit_ident_mut(&mut self, _ident: &mut Ident<'i>) {}
+}
+
+pub fn walk_template_mut<'i, V: VisitMut<'i> + ?Sized>(
+    visitor: &mut V,
+    template: &mut Template<'i>,
+) {
+    visitor.visit_body_mut(&mut template.expressions);
+}
+
+pub fn walk_body_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, body: &mut Vec<Block<'i>>) {
+    for block in body {
+        visitor.visit_block_mut(block);
+    }
+}
+
+pub fn walk_block_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, block: &mut Block<'i>) {
+    match block {
+        Block::Expr(e) => visitor.visit_expr_mut(e),
+        Block::Stmt(s) => visitor.visit_stmt_mut(s),
+        Block::RawText(_) | Block::Comment(_) => {}
+    }
+}
+
+pub fn walk_stmt_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, stmt: &mut Stmt<'i>) {
+    match stmt {
+        Stmt::For(stmt, r#else) => {
+            visitor.visit_for_mut(stmt);
+            if let Some(r#else) = r#else {
+                visitor.visit_else_mut(r#else);
+            }
+        }
+        Stmt::If(i) => visitor.visit_if_mut(i),
+        Stmt::Macro(m) => visitor.visit_macro_mut(m),
+        Stmt::Filter(filter) => visitor.visit_filter_mut(filter),
+        Stmt::Set(set) => visitor.visit_set_mut(set),
+        Stmt::Include(i) => visitor.visit_include_mut(i),
+        Stmt::Import(i) => visitor.visit_import_mut(i),
+        Stmt::With(w) => visitor.visit_with_mut(w),
+        Stmt::Do(d) => visitor.visit_do_mut(d),
+    }
+}
+
+pub fn walk_for_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, stmt: &mut ForStmt<'i>) {
+    for ident in &mut stmt.idents_of_iter {
+        visitor.visit_ident_mut(ident);
+    }
+    visitor.visit_expr_mut(&mut stmt.in_expr);
+    visitor.visit_body_mut(&mut stmt.body);
+}
+
+pub fn walk_else_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, r#else: &mut Else<'i>) {
+    visitor.visit_body_mut(&mut r#else.body);
+}
+
+pub fn walk_if_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, r#if: &mut If<'i>) {
+    visitor.visit_if_branch_mut(&mut r#if.if_branch);
+    for branch in &mut r#if.elif_branches {
+        visitor.visit_if_branch_mut(branch);
+    }
+    if let Some(r#else) = &mut r#if.else_branch {
+        visitor.visit_else_mut(r#else);
+    }
+}
+
+pub fn walk_if_branch_mut<'i, V: VisitMut<'i> + ?Sized>(
+    visitor: &mut V,
+    branch: &mut IfBranch<'i>,
+) {
+    visitor.visit_expr_mut(&mut branch.condition);
+    visitor.visit_body_mut(&mut branch.body);
+}
+
+pub fn walk_macro_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, r#macro: &mut Macro<'i>) {
+    visitor.visit_ident_mut(&mut r#macro.name);
+    for arg in &mut r#macro.args {
+        visitor.visit_ident_mut(arg);
+    }
+    walk_kwargs_mut(visitor, &mut r#macro.kwargs);
+    visitor.visit_body_mut(&mut r#macro.body);
+}
+
+fn walk_kwargs_mut<'i, V: VisitMut<'i> + ?Sized>(
+    visitor: &mut V,
+    kwargs: &mut [(Ident<'i>, Expr<'i>)],
+) {
+    for (ident, expr) in kwargs {
+        visitor.visit_ident_mut(ident);
+        visitor.visit_expr_mut(expr);
+    }
+}
+
+pub fn walk_filter_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, filter: &mut Filter<'i>) {
+    visitor.visit_ident_mut(&mut filter.name);
+    visitor.visit_body_mut(&mut filter.body);
+}
+
+pub fn walk_set_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, set: &mut Set<'i>) {
+    for target in &mut set.targets {
+        visitor.visit_set_target_mut(target);
+    }
+    visitor.visit_set_data_mut(&mut set.data);
+}
+
+pub fn walk_set_target_mut<'i, V: VisitMut<'i> + ?Sized>(
+    visitor: &mut V,
+    target: &mut SetTarget<'i>,
+) {
+    match target {
+        SetTarget::Ident(ident) => visitor.visit_ident_mut(ident),
+        SetTarget::Attr(ident, attr) => {
+            visitor.visit_ident_mut(ident);
+            visitor.visit_ident_mut(attr);
+        }
+    }
+}
+
+pub fn walk_set_data_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, data: &mut SetData<'i>) {
+    match data {
+        SetData::Expr(expr) => visitor.visit_expr_mut(expr),
+        SetData::Block(body, filters) => {
+            visitor.visit_body_mut(body);
+            for filter in filters {
+                visitor.visit_expr_mut(filter);
+            }
+        }
+    }
+}
+
+pub fn walk_include_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, include: &mut Include<'i>) {
+    visitor.visit_expr_mut(&mut include.files);
+}
+
+pub fn walk_import_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, import: &mut Import<'i>) {
+    visitor.visit_expr_mut(&mut import.file);
+    match &mut import.items {
+        Items::All(alias) => visitor.visit_ident_mut(alias),
+        Items::List(items) => {
+            for (item, alias) in items {
+                visitor.visit_ident_mut(item);
+                if let Some(alias) = alias {
+                    visitor.visit_ident_mut(alias);
+                }
+            }
+        }
+    }
+}
+
+pub fn walk_with_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, with: &mut With<'i>) {
+    walk_kwargs_mut(visitor, &mut with.bindings);
+    visitor.visit_body_mut(&mut with.body);
+}
+
+pub fn walk_do_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, r#do: &mut Do<'i>) {
+    visitor.visit_expr_mut(&mut r#do.expr);
+}
+
+pub fn walk_expr_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, expr: &mut Expr<'i>) {
+    match expr {
+        Expr::UnaryOp(u) => visitor.visit_unary_op_mut(u),
+        Expr::BinOpExpr(b) => visitor.visit_bin_op_mut(b),
+        Expr::Compare(c) => visitor.visit_compare_mut(c),
+        Expr::Literal(l) => visitor.visit_literal_mut(l),
+        Expr::Ident(i) => visitor.visit_ident_mut(i),
+        Expr::FunctionCall(name, args, kwargs) => {
+            visitor.visit_ident_mut(name);
+            for arg in args {
+                visitor.visit_expr_mut(arg);
+            }
+            walk_kwargs_mut(visitor, kwargs);
+        }
+    }
+}
+
+pub fn walk_unary_op_mut<'i, V: VisitMut<'i> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut UnaryOpExpr<'i>,
+) {
+    visitor.visit_expr_mut(&mut expr.arg);
+}
+
+pub fn walk_bin_op_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, expr: &mut BinOpExpr<'i>) {
+    visitor.visit_expr_mut(&mut expr.arg1);
+    visitor.visit_expr_mut(&mut expr.arg2);
+}
+
+pub fn walk_compare_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, expr: &mut CompareExpr<'i>) {
+    visitor.visit_expr_mut(&mut expr.first);
+    for (_, arg) in &mut expr.rest {
+        visitor.visit_expr_mut(arg);
+    }
+}
+
+pub fn walk_literal_mut<'i, V: VisitMut<'i> + ?Sized>(visitor: &mut V, literal: &mut Literal<'i>) {
+    match literal {
+        Literal::List(items) | Literal::Tuple(items) => {
+            for item in items {
+                visitor.visit_literal_mut(item);
+            }
+        }
+        Literal::Dict(pairs) => {
+            for (key, value) in pairs {
+                visitor.visit_literal_mut(key);
+                visitor.visit_literal_mut(value);
+            }
+        }
+        Literal::String(_) | Literal::Integer(_) | Literal::Float(_) | Literal::Bool(_) => {}
+    }
+}
@@ -0,0 +1,94 @@
+use std::{borrow::Cow, collections::HashMap, sync::Arc, thread};
+
+use ophelia_logic::{
+    parse::{
+        ast::{
+            BinOp, BinOpExpr, Block, CompareExpr, Else, Expr, ForStmt, Ident, If, IfBranch,
+            Literal, Set, SetData, SetTarget, Stmt, Template,
+        },
+        Parse,
+    },
+    render::{Context, Render, Value},
+};
+
+fn ident(name: &str) -> Expr<'_> {
+    Expr::Ident(Ident::new(name))
+}
+
+fn string(value: &str) -> Expr<'_> {
+    Expr::Literal(Literal::String(Cow::Borrowed(value)))
+}
+
+#[test]
+fn templates_can_be_built_by_hand() {
+    let body = vec![Block::Stmt(Stmt::If(Box::new(If::new(
+        IfBranch::new(
+            Expr::Compare(Box::new(CompareExpr::new(
+                Expr::Literal(Literal::Integer(0)),
+                vec![
+                    (BinOp::Lt, ident("x")),
+                    (BinOp::Lt, Expr::Literal(Literal::Integer(3))),
+                ],
+            ))),
+            vec![Block::Expr(Expr::BinOpExpr(Box::new(BinOpExpr::new(
+                BinOp::Tilde,
+                ident("greeting"),
+                ident("x"),
+            ))))],
+        ),
+        vec![],
+        Some(Else::new(vec![Block::RawText(Cow::Borrowed("-"))])),
+    ))))];
+
+    let template = Template::new(vec![
+        Block::Stmt(Stmt::Set(Set::new(
+            vec![SetTarget::Ident(Ident::new("greeting"))],
+            SetData::Expr(string("hi")),
+        ))),
+        Block::Stmt(Stmt::For(
+            Box::new(ForStmt::new(vec![Ident::new("x")], ident("items"), body)),
+            None,
+        )),
+    ]);
+
+    let source =
+        "{% set greeting = 'hi' %}{% for x in items %}{% if 0 < x < 3 %}{{ greeting ~ x }}\
+                  {% else %}-{% endif %}{% endfor %}";
+    let (parsed, _) = Template::parse(source).unwrap();
+    assert_eq!(template, parsed);
+
+    let mut ctx = Context::new();
+    ctx.insert("items", Value::list(vec![1.into(), 2.into(), 3.into()]));
+    let mut output = String::new();
+    template.render(&mut ctx, &mut output).unwrap();
+    assert_eq!(output, "hi1hi2-");
+}
+
+#[test]
+fn owned_templates_outlive_their_source() {
+    fn parse_owned(source: String) -> Template<'static> {
+        let (template, _) = Template::parse(&source).unwrap();
+        template.into_owned()
+    }
+
+    let mut cache: HashMap<&str, Arc<Template<'static>>> = HashMap::new();
+    cache.insert(
+        "greeting",
+        Arc::new(parse_owned(
+            "{# hi #}hello {{ name | upper }}{% for x in [1, 2] %}{{ x }}{% endfor %}".to_string(),
+        )),
+    );
+
+    let template = cache["greeting"].clone();
+    let output = thread::spawn(move || {
+        let mut ctx = Context::new();
+        ctx.insert("name", "ophelia".into());
+        let mut output = String::new();
+        template.render(&mut ctx, &mut output).unwrap();
+        output
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(output, "hello OPHELIA12");
+}
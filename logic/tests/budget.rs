@@ -0,0 +1,175 @@
+mod common;
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use ophelia_logic::{
+    parse::{Parse, Template},
+    render::{Budget, BudgetError, CancellationToken, Context, Render, RenderError},
+};
+
+fn context(budget: Budget) -> Context {
+    let mut ctx = common::context();
+    ctx.set_budget(budget);
+    ctx
+}
+
+/// Renders `source` with `budget` (see `common::render`).
+fn render(source: &str, budget: Budget) -> Result<String, RenderError> {
+    let (template, _) = Template::parse(source).unwrap();
+    common::render(&template, || context(budget.clone()))
+}
+
+/// How much fuel rendering `source` spends (which is the same whether it is rendered by walking
+/// the tree or compiled).
+fn spent(source: &str) -> u64 {
+    let (template, _) = Template::parse(source).unwrap();
+    let [walked, compiled] = common::render_both(&template, || context(Budget::new()))
+        .map(|(_, ctx)| ctx.budget().unwrap().spent());
+    assert_eq!(walked, compiled, "while rendering {:?}", source);
+    compiled
+}
+
+fn budget_error(error: BudgetError) -> Result<String, RenderError> {
+    Err(RenderError::Budget(error))
+}
+
+#[test]
+fn fuel_is_spent_on_expressions_and_blocks() {
+    // two blocks, and the expression `1 + 2` (and its operands)
+    assert_eq!(spent("a{{ 1 + 2 }}"), 5);
+    assert_eq!(
+        render("a{{ 1 + 2 }}", Budget::new().fuel(5)),
+        Ok("a3".into())
+    );
+    assert_eq!(
+        render("a{{ 1 + 2 }}", Budget::new().fuel(4)),
+        budget_error(BudgetError::OutOfFuel(4))
+    );
+
+    // loops spend it on every iteration, and so do included templates
+    let source = "{% for item in items %}{% include 'item.html' %}{% endfor %}";
+    let fuel = spent(source);
+    assert_eq!(
+        render(source, Budget::new().fuel(fuel)),
+        Ok("<li>1</li><li>2</li><li>3</li>".into())
+    );
+    assert_eq!(
+        render(source, Budget::new().fuel(fuel - 1)),
+        budget_error(BudgetError::OutOfFuel(fuel - 1))
+    );
+    assert!(spent("{% for item in items %}{{ item }}{% endfor %}") > spent("{{ items }}"));
+}
+
+#[test]
+fn huge_ranges_run_out_of_fuel() {
+    let started = Instant::now();
+    assert_eq!(
+        render(
+            "{% for i in range(10**9) %}{{ i }}{% endfor %}",
+            Budget::new().fuel(100_000)
+        ),
+        budget_error(BudgetError::OutOfFuel(100_000))
+    );
+    // without building the range first
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    assert_eq!(
+        render("{{ range(3) }}", Budget::new().fuel(100)),
+        Ok("[0, 1, 2]".into())
+    );
+}
+
+#[test]
+fn loops_stop_at_the_deadline() {
+    let source = "{% for i in range(100000) %}{% for j in range(100) %}{% endfor %}{% endfor %}";
+    let started = Instant::now();
+    assert_eq!(
+        render(source, Budget::new().timeout(Duration::from_millis(10))),
+        budget_error(BudgetError::TimedOut)
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // even while a huge range is being built, without any fuel to run out of
+    let started = Instant::now();
+    assert_eq!(
+        render(
+            "{% for i in range(10**9) %}{% endfor %}",
+            Budget::new().timeout(Duration::from_millis(20))
+        ),
+        budget_error(BudgetError::TimedOut)
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    assert_eq!(
+        render("{{ 1 }}", Budget::new().deadline(Instant::now())),
+        Ok("1".into())
+    );
+    assert_eq!(
+        render(
+            "{% include 'item.html' %}",
+            Budget::new().deadline(Instant::now())
+        ),
+        budget_error(BudgetError::TimedOut)
+    );
+}
+
+#[test]
+fn renders_can_be_cancelled() {
+    let token = CancellationToken::new();
+    let source = "{% for i in range(100000) %}{% for j in range(100) %}{% endfor %}{% endfor %}";
+    let (template, _) = Template::parse(source).unwrap();
+
+    let cancel = {
+        let token = token.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            token.cancel();
+        })
+    };
+    let mut ctx = context(Budget::new().cancel_with(token.clone()));
+    assert_eq!(
+        template.render(&mut ctx, &mut String::new()),
+        Err(RenderError::Budget(BudgetError::Cancelled))
+    );
+    cancel.join().unwrap();
+
+    // including while a huge range is being built
+    let token = CancellationToken::new();
+    let (template, _) = Template::parse("{% for i in range(10**9) %}{% endfor %}").unwrap();
+    let cancel = {
+        let token = token.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            token.cancel();
+        })
+    };
+    let started = Instant::now();
+    let mut ctx = context(Budget::new().cancel_with(token.clone()));
+    assert_eq!(
+        template.render(&mut ctx, &mut String::new()),
+        Err(RenderError::Budget(BudgetError::Cancelled))
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+    cancel.join().unwrap();
+
+    // and stay cancelled
+    assert!(token.is_cancelled());
+    assert_eq!(
+        render(
+            "{% for item in items %}{% endfor %}",
+            Budget::new().cancel_with(token.clone())
+        ),
+        budget_error(BudgetError::Cancelled)
+    );
+    // (which is only noticed once a loop runs)
+    assert_eq!(
+        render(
+            "{% for item in [] %}{% endfor %}",
+            Budget::new().cancel_with(token)
+        ),
+        Ok("".into())
+    );
+}
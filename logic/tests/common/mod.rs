@@ -0,0 +1,104 @@
+//! Helpers shared by the integration tests.
+
+// each test file uses a different part of this
+#![allow(dead_code)]
+
+use std::{fs, path::Path};
+
+use ophelia_logic::{
+    compile::compile,
+    parse::Template,
+    render::{Context, DictLoader, Render, RenderError, Value},
+};
+
+pub fn context() -> Context {
+    let mut loader = DictLoader::new();
+    loader.insert(
+        "forms.html",
+        "{% macro input(name) %}<input name={{ name }}>{% endmacro %}",
+    );
+    loader.insert(
+        "card.html",
+        "{% macro card(title, body) %}{{ title }}{{ body }}{% endmacro %}",
+    );
+    loader.insert("greeting.html", "hello {{ visitor }}");
+    loader.insert("itself.html", "a{% include 'itself.html' %}");
+    loader.insert("item.html", "<li>{{ item }}</li>");
+
+    let mut ctx = Context::new();
+    ctx.set_loader(loader);
+    ctx.insert("name", Value::from("ophelia"));
+    ctx.insert(
+        "user",
+        Value::dict(vec![
+            (Value::from("name"), Value::from("ophelia")),
+            (Value::from("password"), Value::from("hunter2")),
+        ]),
+    );
+    ctx.insert("items", Value::from(vec![1, 2, 3]));
+    ctx.insert(
+        "pairs",
+        Value::list(vec![
+            Value::from(vec!["a", "b"]),
+            Value::from(vec!["c", "d"]),
+        ]),
+    );
+    ctx
+}
+
+/// Renders `template` by walking the tree and by running the compiled program, each in a fresh
+/// context from `context` (e.g. [`context`]), and returns both results along with the contexts
+/// they were rendered in.
+pub fn render_both(
+    template: &Template,
+    context: impl Fn() -> Context,
+) -> [(Result<String, RenderError>, Context); 2] {
+    let mut ctx = context();
+    let mut walked = String::new();
+    let walked = template.render(&mut ctx, &mut walked).map(|_| walked);
+    let walked = (walked, ctx);
+
+    let mut ctx = context();
+    let mut compiled = String::new();
+    let compiled = compile(template)
+        .render(&mut ctx, &mut compiled)
+        .map(|_| compiled);
+    [walked, (compiled, ctx)]
+}
+
+/// As [`render_both`], but checks that both give the same result, and returns it.
+///
+/// Errors are compared by what they say, because those in templates which were loaded refer to
+/// where the loaded source was in memory, which is different each time it is loaded.
+pub fn render(template: &Template, context: impl Fn() -> Context) -> Result<String, RenderError> {
+    let [(walked, _), (compiled, _)] = render_both(template, context);
+    assert_eq!(
+        walked.as_ref().map_err(ToString::to_string),
+        compiled.as_ref().map_err(ToString::to_string),
+        "while rendering {:?}",
+        template.to_string()
+    );
+    compiled
+}
+
+/// Every template in the tests' fixtures, with its path.
+pub fn fixtures() -> Vec<(String, String)> {
+    let mut fixtures = vec![];
+    let mut directories = vec![
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/templates"),
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../macros/tests/templates"),
+    ];
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                directories.push(path);
+            } else {
+                let source = fs::read_to_string(&path).unwrap();
+                fixtures.push((path.display().to_string(), source));
+            }
+        }
+    }
+    assert!(fixtures.len() > 2);
+    fixtures
+}
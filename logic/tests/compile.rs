@@ -0,0 +1,92 @@
+mod common;
+
+use ophelia_logic::{
+    compile::compile,
+    parse::{Parse, Template},
+    render::{Render, RenderError, Value},
+};
+
+use common::{context, render};
+
+/// Renders `input` by walking the tree and by running the compiled program, and checks that
+/// both give the same result.
+fn check(input: &str) -> Result<String, RenderError> {
+    let (template, _) = Template::parse(input).expect("parsing failed");
+    render(&template, context)
+}
+
+#[test]
+fn compiled_templates_render_like_the_tree() {
+    for input in &[
+        "hello {{ name }}!",
+        "{{ 1 + 2 * 3 }} {{ 7 // 2 }} {{ 2 ** 3 }} {{ 'a' ~ 1 }}",
+        "{{ missing }}{{ 0 and name }}{{ 0 or name }}{{ 1 and name }}",
+        "{{ not name }}{{ not missing }}{{ not 1 == 2 }}",
+        "{{ -1 }}{{ 1 - -1 }}{{ -2 ** 2 }}",
+        "{{ 1 == 1 == 1 }}{{ 1 < 2 < 2 }}{{ 2 < 1 < missing() }}{{ 1 < name | length < 10 }}",
+        "{% if missing %}a{% elif name %}b{% else %}c{% endif %}",
+        "{% for x in items %}{% if x > 1 %}{{ x }}{% else %}-{% endif %}{% endfor %}",
+        "{% for x in items %}<{{ x }}>{% endfor %}",
+        "{% for a, b in pairs %}{{ a ~ b }},{% endfor %}",
+        "{% for x in items %}{% for y in items %}{{ x * y }} {% endfor %}{% endfor %}",
+        "{% set a = 1 %}{% for x in items %}{% set a = x %}{% endfor %}{{ a }}",
+        "{% set ns = namespace(total=0) %}\
+         {% for x in items %}{% set ns.total = ns.total + x %}{% endfor %}{{ ns.total }}",
+        "{% set a, b = [1, 2] %}{{ a }}{{ b }}",
+        "{% set a | upper | replace('A', 'o') %}banana{% endset %}{{ a }}",
+        "{% with a = 1, b = name %}{{ a ~ b }}{% endwith %}{{ a }}",
+        "{% set l = [] %}{% do l.append(name) %}{{ l }}",
+        "{% filter upper %}{% for x in items %}{{ name }}{% endfor %}{% endfilter %}",
+        "{{ name | replace('o', '0') | upper }} {{ items | join(', ') }}",
+        "{% from 'forms.html' import input %}{{ input(name) }}",
+        "{{ name is defined }}{{ missing is defined }}{{ items | first is odd }}{{ 6 is divisibleby(3) }}",
+        "{% if name is string and name is lower and true is true %}{{ name is eq('ophelia') }}{% endif %}",
+    ] {
+        check(input).expect("rendering failed");
+    }
+}
+
+#[test]
+fn compiled_templates_fail_like_the_tree() {
+    for input in &[
+        "{{ 1 / 0 }}",
+        "{{ -name }}",
+        "{{ 1 < 2 < name }}",
+        "{{ name | nope }}",
+        "{{ name is nope }}",
+        "{{ name is even }}",
+        "{{ 1 is divisibleby }}",
+        "{% set d = {} %}{% set d.a = 1 %}",
+        "{% for a, b in items %}{% endfor %}",
+        "{% include 'missing.html' %}",
+    ] {
+        assert!(check(input).is_err(), "{:?} should fail", input);
+    }
+}
+
+#[test]
+fn literals_are_not_shared_between_renders() {
+    let (template, _) = Template::parse("{% set l = [] %}{% do l.append(1) %}{{ l }}").unwrap();
+    let program = compile(&template);
+
+    for _ in 0..2 {
+        let mut output = String::new();
+        program.render(&mut context(), &mut output).unwrap();
+        assert_eq!(output, "[1]");
+    }
+}
+
+#[test]
+fn failing_programs_leave_the_context_usable() {
+    let (template, _) = Template::parse("{% for x in items %}{{ x / 0 }}{% endfor %}").unwrap();
+
+    let mut ctx = context();
+    assert!(compile(&template)
+        .render(&mut ctx, &mut String::new())
+        .is_err());
+
+    // a scope left over from the loop would otherwise swallow this assignment
+    let (set, _) = Template::parse("{% set after = 1 %}").unwrap();
+    compile(&set).render(&mut ctx, &mut String::new()).unwrap();
+    assert_eq!(ctx.get("after"), Some(Value::Integer(1)));
+}
@@ -0,0 +1,174 @@
+mod common;
+
+use common::fixtures;
+use ophelia_logic::cst::{
+    self,
+    ast::{AstNode, Expr, Item},
+    GreenToken, SyntaxKind, SyntaxNode,
+};
+
+#[test]
+fn trees_keep_every_byte() {
+    let unusual = [
+        "",
+        "{{ x }}}",
+        "{{ {'a': {'b': 1}}}}",
+        "{{ x }}{# #}#}%}",
+        "{% extends 'base.html' %}",
+    ];
+    let malformed = [
+        "{{",
+        "{{ x }",
+        "{{ }}",
+        "{%%}",
+        "{% 1 %}",
+        "{% for x in %}{% endfor %}",
+        "{% if x %}{% for y in z %}{% endif %}",
+        "{% endif %}{% else %}",
+        "{% if x %}{% elif %}{% else %}{% else %}",
+        "{# unterminated",
+        "{{ 'unterminated }}",
+        "{% set a, = %}",
+        "{% set x | %}{% endset",
+        "{{ [1, 2 }}",
+        "{{ {1 2} }}",
+        "{{ f(a=, b)(c) }}",
+        "{{ (1, ) }} {{ (,) }} {{ ( }}",
+        "{% macro m %}{% endmacro %}",
+        "{% from x import %}{% import %}{% include 'a' ignore %}",
+        "{{ ü + 1.5e3 ** -x }} {% with a = 1, = 2 %}",
+        "text {{ x\n{% if y %}\tmore{#",
+    ];
+
+    let sources = fixtures()
+        .into_iter()
+        .map(|(_, source)| source)
+        .chain(unusual.iter().map(ToString::to_string))
+        .chain(malformed.iter().map(ToString::to_string));
+
+    for source in sources {
+        let tree = cst::parse(&source);
+        assert_eq!(tree.syntax().to_string(), source);
+    }
+
+    for source in unusual {
+        assert!(cst::parse(source).errors().is_empty(), "{:?}", source);
+    }
+    for source in malformed {
+        assert!(!cst::parse(source).errors().is_empty(), "{:?}", source);
+    }
+}
+
+#[test]
+fn valid_templates_have_no_errors() {
+    for (path, source) in fixtures() {
+        let tree = cst::parse(&source);
+        assert!(
+            tree.errors().is_empty(),
+            "{} has errors: {:?}",
+            path,
+            tree.errors()
+        );
+    }
+}
+
+#[test]
+fn errors_point_at_the_problem() {
+    let source = "{% if x %}\n{{ 1 + }}\n{% endfor %}";
+    let tree = cst::parse(source);
+    let messages = tree
+        .errors()
+        .iter()
+        .map(|error| (error.message(), &source[error.range()]))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        vec![
+            ("expected an expression", "}}"),
+            ("`endfor` does not end or divide any block", "{%"),
+            ("expected `{% endif %}`", ""),
+        ]
+    );
+}
+
+#[test]
+fn typed_views_find_the_parts_of_nodes() {
+    let tree = cst::parse("{% for x, y in pairs | sort %} {{ x ~ y }} {% else %}-{% endfor %}");
+
+    let block = match tree.root().items().next() {
+        Some(Item::Block(block)) => block,
+        other => panic!("expected a block, found {:?}", other),
+    };
+
+    let keywords = block
+        .tags()
+        .map(|tag| tag.keyword().unwrap().text().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(keywords, vec!["for", "else", "endfor"]);
+    assert_eq!(
+        block.closing().unwrap().syntax().to_string(),
+        "{% endfor %}"
+    );
+
+    let opening = block.opening().unwrap();
+    let exprs = opening.exprs().collect::<Vec<_>>();
+    assert_eq!(exprs.len(), 3);
+    assert!(matches!(&exprs[0], Expr::Name(name) if name.text() == "x"));
+    match &exprs[2] {
+        Expr::BinExpr(filter) => {
+            assert_eq!(filter.operator_token().unwrap().text(), "|");
+            assert_eq!(filter.rhs().unwrap().syntax().to_string(), "sort");
+        }
+        other => panic!("expected a filter, found {:?}", other),
+    }
+
+    let bodies = block.bodies().collect::<Vec<_>>();
+    assert_eq!(bodies.len(), 2);
+    let output = bodies[0]
+        .items()
+        .find_map(|item| match item {
+            Item::Output(output) => Some(output),
+            _ => None,
+        })
+        .unwrap();
+    match output.expr().unwrap() {
+        Expr::BinExpr(concat) => {
+            assert_eq!(concat.syntax().to_string(), "x ~ y");
+            assert!(matches!(concat.lhs(), Some(Expr::Name(name)) if name.text() == "x"));
+        }
+        other => panic!("expected `~`, found {:?}", other),
+    }
+}
+
+#[test]
+fn trees_can_be_edited() {
+    let source = "{%  for x in xs  %}{{x}}, {{ x.y | f(x=x) }}{% endfor %}{# x #}";
+
+    // the names which are variables (rather than attributes, or the names of arguments)
+    let is_variable = |name: &SyntaxNode| match name.parent() {
+        Some(parent) if parent.kind() == SyntaxKind::Kwarg => name.prev_sibling().is_some(),
+        Some(parent) if parent.kind() == SyntaxKind::BinExpr => name.prev_sibling().is_none(),
+        _ => true,
+    };
+
+    let mut root = cst::parse(source).syntax();
+    while let Some(token) = root
+        .descendants_with_tokens()
+        .filter_map(|element| element.into_token())
+        .find(|token| {
+            token.text() == "x"
+                && token
+                    .parent()
+                    .is_some_and(|name| name.kind() == SyntaxKind::Name && is_variable(&name))
+        })
+    {
+        root = SyntaxNode::new_root(
+            token.replace_with(GreenToken::new(SyntaxKind::Ident.into(), "item")),
+        );
+    }
+
+    assert_eq!(
+        root.to_string(),
+        "{%  for item in xs  %}{{item}}, {{ item.y | f(x=item) }}{% endfor %}{# x #}"
+    );
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::{context, fixtures, render};
+use ophelia_logic::{
+    format::{format, Formatter, Quote},
+    parse::{Parse, Template},
+};
+
+fn parse(source: &str) -> Template<'_> {
+    Template::parse(source).unwrap().0
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    let formatters = [
+        Formatter::new(),
+        Formatter::new().indent(2),
+        Formatter::new().indent(4).quote(Quote::Double),
+    ];
+
+    for (path, source) in fixtures() {
+        for formatter in &formatters {
+            let once = formatter.format(&parse(&source));
+            let twice = formatter.format(&parse(&once));
+            assert_eq!(
+                once, twice,
+                "while formatting {} with {:?}",
+                path, formatter
+            );
+        }
+    }
+}
+
+#[test]
+fn formatting_without_indenting_keeps_the_output() {
+    for (path, source) in fixtures() {
+        let formatted = format(&parse(&source));
+        assert_eq!(
+            render(&parse(&source), context),
+            render(&parse(&formatted), context),
+            "while formatting {}",
+            path
+        );
+    }
+}
+
+#[test]
+fn formatting_tidies_tags_up() {
+    let source = "<ul>\n{%for x in items%}\n      {%if x>1%}\n  <li>{{x}}</li>\n{%elif x==1%}\
+                  one{%else%}\n    {%set y=\"none\"%}\n    {%endif%}\n{%endfor%}\n</ul>{#  note#}";
+
+    assert_eq!(
+        Formatter::new().indent(2).format(&parse(source)),
+        "<ul>\n{% for x in items %}\n  {% if x > 1 %}\n  <li>{{ x }}</li>\n  {% elif x == 1 %}\
+         one{% else %}\n    {% set y = 'none' %}\n  {% endif %}\n{% endfor %}\n</ul>{# note #}"
+    );
+    assert_eq!(
+        Formatter::new().quote(Quote::Double).format(&parse(source)),
+        "<ul>\n{% for x in items %}\n      {% if x > 1 %}\n  <li>{{ x }}</li>\n{% elif x == 1 %}\
+         one{% else %}\n    {% set y = \"none\" %}\n    {% endif %}\n{% endfor %}\n</ul>{# note #}"
+    );
+}
+
+#[test]
+fn display_separates_items_with_commas() {
+    for (source, displayed) in [
+        ("{{ f(a,b,key=c) }}", "{{ f(a, b, key=c) }}"),
+        ("{{ f() }}", "{{ f() }}"),
+        ("{{ [1,2,] }}", "{{ [1, 2] }}"),
+        ("{{ [] }}", "{{ [] }}"),
+        ("{{ {'a':1,'b':2,} }}", "{{ {\"a\": 1, \"b\": 2} }}"),
+        ("{{ [(1,),(1,2),()] }}", "{{ [(1,), (1, 2), ()] }}"),
+        (
+            "{% filter upper %}x{% endfilter %}",
+            "{% filter upper %}x{% endfilter %}",
+        ),
+    ] {
+        let template = parse(source);
+        assert_eq!(template.to_string(), displayed);
+        assert_eq!(parse(displayed), template);
+    }
+}
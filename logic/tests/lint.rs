@@ -0,0 +1,165 @@
+use ophelia_logic::{
+    lint::{Lint, Linter, Rule, Severity},
+    parse::{Parse, Template},
+};
+
+fn lints(linter: &Linter, source: &str) -> Vec<Lint> {
+    let (template, _) = Template::parse(source).unwrap();
+    linter.lint(source, &template)
+}
+
+/// The rules which `source` breaks, with the text which each lint points at.
+fn broken(source: &str) -> Vec<(Rule, &str)> {
+    lints(&Linter::new(), source)
+        .into_iter()
+        .map(|lint| (lint.rule, &source[lint.span.unwrap()]))
+        .collect()
+}
+
+#[test]
+fn unused_variables_and_arguments() {
+    assert_eq!(
+        broken(
+            "{% set a, b = pair %}{% set _ignored = 1 %}{% set ns = namespace() %}\
+             {% set ns.total = 1 %}{{ b }}\
+             {% macro card(title, body, footer='') %}{{ title }}{{ footer }}{% endmacro %}"
+        ),
+        vec![(Rule::UnusedVariable, "a"), (Rule::UnusedArgument, "body")]
+    );
+
+    // reading a variable anywhere (including through a filter's arguments) uses it
+    assert_eq!(
+        broken(
+            "{% for x in items %}{{ x | replace('a', sep) }}{% set sep = x %}{% endfor %}\
+             {% macro m(p) %}{% if p is divisibleby(2) %}{% endif %}{% endmacro %}"
+        ),
+        vec![]
+    );
+}
+
+#[test]
+fn shadowed_loop_variables() {
+    assert_eq!(
+        broken(
+            "{% for row in rows %}{% for cell, row in row %}{{ cell }}\
+             {% for cell in row %}{{ cell }}{% endfor %}{% endfor %}{% endfor %}\
+             {% for row in rows %}{{ row }}{% endfor %}"
+        ),
+        vec![
+            (Rule::ShadowedLoopVariable, "row"),
+            (Rule::ShadowedLoopVariable, "cell")
+        ]
+    );
+}
+
+#[test]
+fn dynamic_includes() {
+    assert_eq!(
+        broken(
+            "{% include 'a.html' %}{% include ['a.html', 'b.html'] %}\
+             {% include page ~ '.html' %}{% include page ignore missing %}"
+        ),
+        vec![(Rule::DynamicInclude, "page")]
+    );
+}
+
+#[test]
+fn safe_on_user_input() {
+    assert_eq!(
+        broken(
+            "{% set html = '<b>' %}{{ html | safe }}{{ '<i>' | safe }}\
+             {% for item in items %}{{ item | safe }}{{ user.bio | safe }}{% endfor %}"
+        ),
+        vec![(Rule::SafeOnUserInput, "safe")]
+    );
+    let source = "{{ user.bio | safe }}";
+    let lints = lints(&Linter::new(), source);
+    assert_eq!(lints[0].span, Some(14..18));
+    assert!(
+        lints[0].message.contains("user.bio"),
+        "{}",
+        lints[0].message
+    );
+}
+
+#[test]
+fn deprecated_and_unknown_filters_and_tests() {
+    assert_eq!(
+        broken(
+            "{{ x | upper | shout }}{% if x is equalto(1) or x is prime %}{% endif %}\
+             {% filter whisper %}{% endfilter %}{% set y | upper | trimmed %}{% endset %}{{ y }}"
+        ),
+        vec![
+            (Rule::UnknownFilter, "shout"),
+            (Rule::Deprecated, "equalto"),
+            (Rule::UnknownTest, "prime"),
+            (Rule::UnknownFilter, "whisper"),
+            (Rule::UnknownFilter, "trimmed"),
+        ]
+    );
+
+    // filters and tests which were added are known
+    let linter = Linter::new().filter("shout").test("prime");
+    let found = lints(&linter, "{{ x | shout }}{% if x is prime %}{% endif %}");
+    assert_eq!(found, vec![]);
+
+    let found = lints(&Linter::new(), "{{ x | shout }}");
+    assert_eq!(found[0].severity, Severity::Error);
+    assert_eq!(found[0].message, "there is no filter called `shout`");
+}
+
+#[test]
+fn unreachable_branches() {
+    assert_eq!(
+        broken(
+            "{% if x %}{% elif 1 == 1 %}{% elif y %}a{% else %}b{% endif %}\
+             {% if true %}{% else %}c{% endif %}{% if false %}{% elif x %}{% endif %}"
+        ),
+        vec![
+            (Rule::UnreachableBranch, "y"),
+            (Rule::UnreachableBranch, "b"),
+            (Rule::UnreachableBranch, "c")
+        ]
+    );
+}
+
+#[test]
+fn rules_can_be_allowed() {
+    let source =
+        "{% set a = 1 %}{% for x in xs %}{# ophelia: allow(unknown-filter, unused-variable) #}\
+                  {{ x | shout }}{% set b = 2 %}{% endfor %}{{ y | shout }}";
+    assert_eq!(
+        broken(source),
+        vec![(Rule::UnusedVariable, "a"), (Rule::UnknownFilter, "shout")]
+    );
+    let after_loop = source.find("{{ y").unwrap();
+    assert!(
+        lints(&Linter::new(), source)[1]
+            .span
+            .as_ref()
+            .unwrap()
+            .start
+            > after_loop
+    );
+
+    let source = "{#ophelia: allow( unused-variable )#}{% set a = 1 %}{{ x is prime }}";
+    assert_eq!(broken(source), vec![(Rule::UnknownTest, "prime")]);
+
+    // rules can also be allowed (or made more severe) for every template
+    let linter = Linter::new()
+        .allow(Rule::UnknownTest)
+        .severity(Rule::UnusedVariable, Severity::Error);
+    let found = lints(&linter, "{% set a = 1 %}{{ x is prime }}");
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].rule, Rule::UnusedVariable);
+    assert_eq!(found[0].severity, Severity::Error);
+}
+
+#[test]
+fn rules_have_ids() {
+    for rule in Rule::ALL {
+        assert_eq!(Rule::from_id(rule.id()), Some(*rule));
+        assert_eq!(rule.to_string(), rule.id());
+    }
+    assert_eq!(Rule::from_id("unused"), None);
+}
@@ -0,0 +1,36 @@
+use std::{env, fs, path::PathBuf, process};
+
+use ophelia_logic::render::{FileSystemLoader, Loader};
+
+/// A fresh directory holding `templates/page.html`, next to a file which is outside it.
+fn directory(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("ophelia-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("templates/nested")).unwrap();
+    fs::write(dir.join("templates/page.html"), "page").unwrap();
+    fs::write(dir.join("templates/nested/part.html"), "part").unwrap();
+    fs::write(dir.join("secret.txt"), "secret").unwrap();
+    dir
+}
+
+#[test]
+fn file_system_loader_loads_relative_names() {
+    let dir = directory("relative");
+    let loader = FileSystemLoader::new(dir.join("templates"));
+
+    assert_eq!(loader.load("page.html").as_deref(), Some("page"));
+    assert_eq!(loader.load("nested/part.html").as_deref(), Some("part"));
+    assert_eq!(loader.load("./page.html").as_deref(), Some("page"));
+    assert_eq!(loader.load("missing.html"), None);
+}
+
+#[test]
+fn file_system_loader_stays_inside_its_root() {
+    let dir = directory("escape");
+    let loader = FileSystemLoader::new(dir.join("templates"));
+
+    assert_eq!(loader.load("../secret.txt"), None);
+    assert_eq!(loader.load("nested/../../secret.txt"), None);
+    let absolute = dir.join("secret.txt");
+    assert_eq!(loader.load(absolute.to_str().unwrap()), None);
+}
@@ -0,0 +1,80 @@
+use ophelia_logic::{
+    meta::{referenced_templates, undeclared_variables},
+    parse::{Parse, Template},
+};
+
+fn undeclared(source: &str) -> Vec<String> {
+    let (template, _) = Template::parse(source).unwrap();
+    let names = undeclared_variables(&template);
+    names.into_iter().map(String::from).collect()
+}
+
+#[test]
+fn undeclared_variables_follow_scopes() {
+    // variables which are read before they are set are expected from the context
+    assert_eq!(
+        undeclared("{{ a }}{% set a = 1 %}{{ a }}{% set b = b %}"),
+        ["a", "b"]
+    );
+
+    // loop variables, and variables set inside loops, are not visible after them
+    assert_eq!(
+        undeclared("{% for x, y in pairs %}{% set z = x %}{{ y ~ z }}{% endfor %}{{ x ~ z }}"),
+        ["pairs", "x", "z"]
+    );
+
+    // macros see their parameters, and everything set around them (even after them)
+    assert_eq!(
+        undeclared(
+            "{% macro card(title, size=title | length, colour=default) %}\
+             {{ title ~ size ~ colour ~ theme ~ card ~ body }}{% endmacro %}\
+             {% set theme = 'dark' %}{{ title }}"
+        ),
+        ["body", "default", "title"]
+    );
+
+    assert_eq!(
+        undeclared(
+            "{% import 'forms.html' as forms %}{% from 'util.html' import a, b as c %}\
+             {{ forms.input(a, c, b) }}{% with w = w, v = 1 %}{{ w ~ v }}{% endwith %}{{ v }}"
+        ),
+        ["b", "v", "w"]
+    );
+}
+
+#[test]
+fn undeclared_variables_skip_names_which_are_not_variables() {
+    // filters, tests, attributes and methods are not variables, but their arguments are; and
+    // `namespace` is a builtin
+    assert_eq!(
+        undeclared(
+            "{{ user.name | replace(old, 'x') }}{% if n is divisibleby(k) %}{% endif %}\
+             {{ page.url(absolute=full) }}{% set ns = namespace() %}{% set ns.count = 1 %}\
+             {% set stats.total = 1 %}{% filter upper %}{{ text }}{% endfilter %}"
+        ),
+        ["full", "k", "n", "old", "page", "stats", "text", "user"]
+    );
+}
+
+#[test]
+fn referenced_templates_are_listed_once() {
+    let (template, _) = Template::parse(
+        "{% include 'header.html' %}{% for item in items %}{% include ['a.html', 'b.html'] %}\
+         {% include item %}{% endfor %}{% macro m() %}{% from 'forms.html' import input %}{% endmacro %}\
+         {% import name as module %}{% include 'header.html' %}{% include page ~ '.html' %}",
+    )
+    .unwrap();
+    assert_eq!(
+        referenced_templates(&template),
+        [
+            Some("header.html"),
+            Some("a.html"),
+            Some("b.html"),
+            None,
+            Some("forms.html")
+        ]
+    );
+
+    let (template, _) = Template::parse("{{ 'page.html' }}").unwrap();
+    assert_eq!(referenced_templates(&template), []);
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use ophelia_logic::{
+    optimise::optimise,
+    parse::{Parse, Template},
+};
+
+use common::{context, render};
+
+/// Checks that optimising `input` does not change what it renders (whether rendered by walking
+/// the tree, or compiled), and returns the optimised template.
+fn check(input: &str) -> Template<'_> {
+    let (template, _) = Template::parse(input).expect("parsing failed");
+    let optimised = optimise(template.clone());
+
+    assert_eq!(
+        render(&optimised, context),
+        render(&template, context),
+        "while rendering {:?}",
+        input
+    );
+    optimised
+}
+
+/// Checks that `input` optimises to the same template as `expected` parses to.
+fn check_optimises_to(input: &str, expected: &str) {
+    let optimised = check(input);
+    let (expected, _) = Template::parse(expected).expect("parsing failed");
+    assert_eq!(optimised, expected, "while optimising {:?}", input);
+}
+
+#[test]
+fn constant_expressions_are_folded() {
+    check_optimises_to("{{ 60 * 60 * 24 }}", "{{ 86400 }}");
+    check_optimises_to("{{ 1 + 2 * name }}", "{{ 1 + 2 * name }}");
+    check_optimises_to("{{ name ~ (1 + 2) }}", "{{ name ~ 3 }}");
+    check_optimises_to("{{ 'a' ~ 1 ~ 2.5 }}", "{{ 'a12.5' }}");
+    check_optimises_to("{{ 7 / 2 }}{{ 2 ** 10 }}", "{{ 3.5 }}{{ 1024 }}");
+    check_optimises_to("{{ 1 < 2 and 3 in [1, 2, 3] }}", "{{ true }}");
+    check_optimises_to("{{ 1 < 2 < 3 }}{{ 1 < 2 < 2 }}", "{{ true }}{{ false }}");
+    check_optimises_to("{{ 1 < 1 + 1 < name }}", "{{ 1 < 2 < name }}");
+    check_optimises_to("{{ not 0 }}{{ not name }}", "{{ true }}{{ not name }}");
+    check_optimises_to("{{ 0 or name }}{{ 1 and name }}", "{{ name }}{{ name }}");
+    check_optimises_to("{{ 0 and name }}{{ 'a' or name }}", "{{ 0 }}{{ 'a' }}");
+    check_optimises_to(
+        "{% set x = 2 * 3 %}{% for i in items ~ (1 + 1) %}{{ i }}{% endfor %}{% do items.append(1 - 1) %}",
+        "{% set x = 6 %}{% for i in items ~ 2 %}{{ i }}{% endfor %}{% do items.append(0) %}",
+    );
+}
+
+#[test]
+fn failing_expressions_are_left_alone() {
+    // these fail when rendered, so they have to be rendered to fail in the same way
+    check_optimises_to("{{ 1 / 0 }}", "{{ 1 / 0 }}");
+    check_optimises_to("{{ 'a' - 1 }}", "{{ 'a' - 1 }}");
+    check_optimises_to("{{ 'a' | nope }}", "{{ 'a' | nope }}");
+}
+
+#[test]
+fn negative_results_are_folded_into_negations() {
+    // there are no negative literals, so a negative constant is written with `-`
+    check_optimises_to("{{ 1 - 2 }}", "{{ -1 }}");
+    check_optimises_to("{{ -(2 * 3) }}{{ 1.5 - 2 }}", "{{ -6 }}{{ -0.5 }}");
+    check_optimises_to("{{ -1 * -1 }}{{ - -1 }}", "{{ 1 }}{{ 1 }}");
+    check_optimises_to("{{ +'a' }}{{ -'a' }}", "{{ +'a' }}{{ -'a' }}");
+}
+
+#[test]
+fn dead_branches_are_removed() {
+    check_optimises_to("{% if 1 > 2 %}a{% else %}b{% endif %}", "b");
+    check_optimises_to("{% if true %}a{% else %}b{% endif %}", "a");
+    check_optimises_to("{% if false %}a{% endif %}", "");
+    check_optimises_to(
+        "{% if 0 %}a{% elif name %}b{% elif 1 %}c{% elif items %}d{% endif %}",
+        "{% if name %}b{% else %}c{% endif %}",
+    );
+    check_optimises_to(
+        "x{% if name %}{% if 1 %}a{% endif %}{% endif %}y",
+        "x{% if name %}a{% endif %}y",
+    );
+}
+
+#[test]
+fn macros_are_optimised() {
+    check_optimises_to(
+        "{% macro m(x=1 + 1) %}{% if 0 %}a{% endif %}{{ x * (2 + 3) }}{% endmacro %}{{ m() }}",
+        "{% macro m(x=2) %}{{ x * 5 }}{% endmacro %}{{ m() }}",
+    );
+}
+
+#[test]
+fn raw_text_is_merged() {
+    check_optimises_to("a{# comment #}b", "ab");
+    check_optimises_to("a{% if 1 %}b{{ 'c' }}{% endif %}d", "ab{{ 'c' }}d");
+    check_optimises_to(
+        "{% for x in items %}<{% if not 0 %}li{% endif %}>{% endfor %}",
+        "{% for x in items %}<li>{% endfor %}",
+    );
+}
+
+#[test]
+fn optimised_templates_render_like_the_original() {
+    for input in &[
+        "hello {{ name }}!",
+        "{% set a = 1 %}{% if a %}{% set b = 2 %}{% endif %}{{ b }}",
+        "{% with a = 2 * 2 %}{{ a }}{% endwith %}{{ a }}",
+        "{% set a | upper %}{{ 'x' ~ 1 }}{% endset %}{{ a }}",
+        "{% filter upper %}{% if 1 %}a{# b #}c{% endif %}{% endfilter %}",
+        "{% macro m(x=1 + 1) %}{{ x * 2 }}{% endmacro %}{{ m() }}",
+        "{% for x in items %}{% if x > 1 %}{{ x ~ (1 + 1) }}{% endif %}{% endfor %}",
+    ] {
+        check(input);
+    }
+}
@@ -0,0 +1,173 @@
+use std::{cell::RefCell, fmt, io, rc::Rc};
+
+use ophelia_logic::{
+    compile::compile,
+    parse::{Parse, Template},
+    render::{
+        Context, FmtOutput, Function, IoOutput, Render, RenderError, Sandbox, SecurityError, Value,
+    },
+};
+
+const SOURCE: &str = "{% for item in items %}<li>{{ item }}</li>{% endfor %}";
+
+fn context() -> Context {
+    let mut ctx = Context::new();
+    ctx.insert("items", Value::from(vec![1, 2, 3]));
+    ctx
+}
+
+/// A writer which keeps each write separately, and can be made to fail after some of them.
+#[derive(Default)]
+struct Writes {
+    writes: Vec<String>,
+    fail_after: Option<usize>,
+}
+
+impl io::Write for Writes {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.fail_after == Some(self.writes.len()) {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "the pipe broke"));
+        }
+        self.writes.push(String::from_utf8(buf.to_vec()).unwrap());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn output_is_written_in_chunks() {
+    let (template, _) = Template::parse(SOURCE).unwrap();
+    let program = compile(&template);
+
+    for template in [&template as &dyn Render, &program] {
+        let mut output = IoOutput::with_capacity(Writes::default(), 8);
+        template.render(&mut context(), &mut output).unwrap();
+        assert_eq!(
+            output.into_inner().unwrap().writes,
+            ["<li>1</li>", "<li>2</li>", "<li>3</li>"]
+        );
+
+        // without buffering, every piece is written as soon as it is rendered
+        let mut output = IoOutput::with_capacity(Writes::default(), 0);
+        template.render(&mut context(), &mut output).unwrap();
+        assert_eq!(output.into_inner().unwrap().writes.len(), 9);
+
+        // and by default, small templates are written all at once
+        let mut output = IoOutput::new(Writes::default());
+        template.render(&mut context(), &mut output).unwrap();
+        assert_eq!(
+            output.into_inner().unwrap().writes,
+            ["<li>1</li><li>2</li><li>3</li>"]
+        );
+    }
+}
+
+#[test]
+fn output_is_written_before_rendering_fails() {
+    let (template, _) = Template::parse("{{ items }}{{ 1 / 0 }}").unwrap();
+    let mut output = IoOutput::with_capacity(Writes::default(), 0);
+    assert!(template.render(&mut context(), &mut output).is_err());
+    assert_eq!(output.into_inner().unwrap().writes, ["[1, 2, 3]"]);
+}
+
+#[test]
+fn io_errors_are_render_errors() {
+    let (template, _) = Template::parse(SOURCE).unwrap();
+    let writer = Writes {
+        fail_after: Some(1),
+        ..Writes::default()
+    };
+    let mut output = IoOutput::with_capacity(writer, 8);
+    let error = template.render(&mut context(), &mut output).unwrap_err();
+    assert_eq!(
+        error,
+        RenderError::Io {
+            kind: io::ErrorKind::BrokenPipe,
+            message: "the pipe broke".into()
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "the output could not be written: the pipe broke"
+    );
+}
+
+#[test]
+fn output_can_be_formatted() {
+    struct Rendered<'a>(&'a Template<'a>);
+
+    impl fmt::Display for Rendered<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let mut output = FmtOutput::with_capacity(f, 4);
+            self.0
+                .render(&mut context(), &mut output)
+                .map_err(|_| fmt::Error)?;
+            output.into_inner().map_err(|_| fmt::Error)?;
+            Ok(())
+        }
+    }
+
+    let (template, _) = Template::parse(SOURCE).unwrap();
+    assert_eq!(
+        Rendered(&template).to_string(),
+        "<li>1</li><li>2</li><li>3</li>"
+    );
+}
+
+#[test]
+fn chunks_are_written_while_rendering() {
+    /// A writer whose writes can still be seen while it is being written to.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<String>>>);
+
+    impl io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0
+                .borrow_mut()
+                .push(String::from_utf8(buf.to_vec()).unwrap());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let source = format!("{}{{{{ writes() }}}}", SOURCE);
+    let (template, _) = Template::parse(&source).unwrap();
+    let program = compile(&template);
+
+    for template in [&template as &dyn Render, &program] {
+        let writer = Shared::default();
+        let mut ctx = context();
+        ctx.insert("writes", {
+            let writer = writer.clone();
+            Value::from(Function::new(move |_, _| {
+                Ok(Value::from(writer.0.borrow().len() as i32))
+            }))
+        });
+
+        // the first chunk was written by the time `writes()` is called, at the end of the render
+        let mut output = IoOutput::with_capacity(writer.clone(), 16);
+        template.render(&mut ctx, &mut output).unwrap();
+        assert_eq!(*writer.0.borrow(), ["<li>1</li><li>2</li>"]);
+        output.flush().unwrap();
+        assert_eq!(*writer.0.borrow(), ["<li>1</li><li>2</li>", "<li>3</li>1"]);
+    }
+}
+
+#[test]
+fn sandboxes_count_what_was_already_written() {
+    let (template, _) = Template::parse(SOURCE).unwrap();
+    let mut ctx = context();
+    ctx.set_sandbox(Sandbox::new().max_output(25));
+
+    let mut output = IoOutput::with_capacity(Writes::default(), 0);
+    assert_eq!(
+        template.render(&mut ctx, &mut output),
+        Err(RenderError::Security(SecurityError::OutputTooLarge(25)))
+    );
+}
@@ -0,0 +1,524 @@
+use std::{cell::Cell, rc::Rc};
+
+use ophelia_logic::{
+    parse::{Parse, Template},
+    render::{
+        Context, DictLoader, Function, Render, RenderError, Value, BUILTIN_FILTERS, BUILTIN_TESTS,
+    },
+};
+
+fn render(input: &str, ctx: &mut Context) -> String {
+    let (template, _) = Template::parse(input).expect("parsing failed");
+
+    let mut output = String::new();
+    template.render(ctx, &mut output).expect("rendering failed");
+    output
+}
+
+#[test]
+fn render_expressions() {
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::from("world"));
+
+    assert_eq!(render("hello {{ name }}!", &mut ctx), "hello world!");
+    assert_eq!(render("{{ 1 + 2 * 3 }}", &mut ctx), "7");
+    assert_eq!(
+        render("{{ 7 // 2 }} {{ 7 % 3 }} {{ 2 ** 3 }}", &mut ctx),
+        "3 1 8"
+    );
+    assert_eq!(render("{{ 1 / 2 }}", &mut ctx), "0.5");
+    assert_eq!(render("{{ 'a' ~ 1 }}", &mut ctx), "a1");
+    assert_eq!(render("{{ missing }}", &mut ctx), "");
+    assert_eq!(
+        render("{{ (1 + 2) * 3 }} {{ 2 ** 3 ** 2 }}", &mut ctx),
+        "9 512"
+    );
+    assert_eq!(
+        render("{{ 10 - 4 - 3 }} {{ 'a' ~ 1 + 2 }}", &mut ctx),
+        "3 a3"
+    );
+    assert_eq!(
+        render("{{ not 1 == 2 }} {{ 0 or 2 and 3 }}", &mut ctx),
+        "True 3"
+    );
+    assert_eq!(
+        render("{{ -1 }} {{ 1 - -1 }} {{ -2 ** 2 }} {{ +2.5 }}", &mut ctx),
+        "-1 2 -4 2.5"
+    );
+
+    // comparisons chain, and stop at the first which does not hold
+    assert_eq!(
+        render(
+            "{{ 1 == 1 == 1 }} {{ 1 < 2 < 2 }} {{ 1 < 3 > 2 }} {{ 2 < 1 < missing() }}",
+            &mut ctx
+        ),
+        "True False True False"
+    );
+    assert_eq!(render("{{ (1 == 1) == (2 == 2) }}", &mut ctx), "True");
+
+    let (template, _) = Template::parse("{{ -name }}").unwrap();
+    assert!(template.render(&mut ctx, &mut String::new()).is_err());
+}
+
+#[test]
+fn render_if() {
+    let mut ctx = Context::new();
+    ctx.insert("n", Value::Integer(2));
+
+    let input = "{% if n == 1 %}one{% elif n == 2 %}two{% else %}many{% endif %}";
+    assert_eq!(render(input, &mut ctx), "two");
+    assert_eq!(render("{% if n > 5 %}big{% endif %}", &mut ctx), "");
+    assert_eq!(
+        render("{% if not n %}no{% else %}yes{% endif %}", &mut ctx),
+        "yes"
+    );
+    // an `if` does not introduce a scope
+    assert_eq!(
+        render("{% if n %}{% set m = 1 %}{% endif %}{{ m }}", &mut ctx),
+        "1"
+    );
+}
+
+#[test]
+fn render_with() {
+    let mut ctx = Context::new();
+    ctx.insert("foo", Value::from("outer"));
+
+    assert_eq!(
+        render(
+            "{% with a = 1, b = foo %}{{ a ~ b }}{% endwith %}",
+            &mut ctx
+        ),
+        "1outer"
+    );
+}
+
+#[test]
+fn with_bindings_do_not_leak() {
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::from("outer"));
+
+    assert_eq!(
+        render(
+            "{% with a = 'inner' %}{{ a }}{% endwith %}{{ a }}",
+            &mut ctx
+        ),
+        "innerouter"
+    );
+    assert_eq!(
+        render("{% with b = 1 %}{{ b }}{% endwith %}{{ b }}", &mut ctx),
+        "1"
+    );
+}
+
+#[test]
+fn with_values_are_evaluated_in_the_outer_scope() {
+    let mut ctx = Context::new();
+    ctx.insert("a", Value::from(1));
+
+    assert_eq!(
+        render("{% with a = 2, b = a %}{{ b }}{% endwith %}", &mut ctx),
+        "1"
+    );
+}
+
+#[test]
+fn do_discards_its_result() {
+    let calls = Rc::new(Cell::new(0));
+    let mut ctx = Context::new();
+    ctx.insert("count", {
+        let calls = calls.clone();
+        Value::from(Function::new(move |_, _| {
+            calls.set(calls.get() + 1);
+            Ok(Value::from("ignored"))
+        }))
+    });
+
+    assert_eq!(render("a{% do count() %}b", &mut ctx), "ab");
+    assert_eq!(calls.get(), 1);
+}
+
+#[test]
+fn do_mutates_shared_values() {
+    let mut ctx = Context::new();
+    ctx.insert("items", Value::from(vec![1]));
+
+    assert_eq!(
+        render("{% do items.append(2) %}{{ items }}", &mut ctx),
+        "[1, 2]"
+    );
+    assert_eq!(ctx.get("items"), Some(Value::from(vec![1, 2])));
+}
+
+#[test]
+fn set_assigns_in_the_current_scope() {
+    let mut ctx = Context::new();
+
+    assert_eq!(render("{% set a = 1 + 1 %}{{ a }}", &mut ctx), "2");
+    assert_eq!(render("{% set a, b = [1, 2] %}{{ a ~ b }}", &mut ctx), "12");
+    assert_eq!(
+        render("{% set a %}captured{% endset %}{{ a }}", &mut ctx),
+        "captured"
+    );
+}
+
+#[test]
+fn set_inside_a_loop_does_not_leak() {
+    let mut ctx = Context::new();
+    ctx.insert("items", Value::from(vec![1, 2, 3]));
+
+    assert_eq!(
+        render(
+            "{% set x = 0 %}{% for i in items %}{% set x = i %}{% endfor %}{{ x }}",
+            &mut ctx
+        ),
+        "0"
+    );
+}
+
+#[test]
+fn namespaces_carry_values_out_of_loops() {
+    let mut ctx = Context::new();
+    ctx.insert("items", Value::from(vec![1, 2, 3]));
+
+    assert_eq!(
+        render(
+            "{% set ns = namespace(total=0) %}\
+             {% for i in items %}{% set ns.total = ns.total + i %}{% endfor %}\
+             {{ ns.total }}",
+            &mut ctx
+        ),
+        "6"
+    );
+}
+
+#[test]
+fn attribute_assignment_requires_a_namespace() {
+    let (template, _) = Template::parse("{% set d = {'a': 1} %}{% set d.a = 2 %}").unwrap();
+
+    assert!(template
+        .render(&mut Context::new(), &mut String::new())
+        .is_err());
+}
+
+#[test]
+fn render_filters() {
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::from("ophelia"));
+    ctx.insert("items", Value::from(vec![1, 2, 3]));
+
+    assert_eq!(render("{{ name | upper }}", &mut ctx), "OPHELIA");
+    assert_eq!(render("{{ name | capitalize }}", &mut ctx), "Ophelia");
+    assert_eq!(render("{{ items | join(', ') }}", &mut ctx), "1, 2, 3");
+    assert_eq!(render("{{ items | length }}", &mut ctx), "3");
+    assert_eq!(render("{{ missing | default('x') }}", &mut ctx), "x");
+    assert_eq!(
+        render("{% filter upper %}shout{% endfilter %}", &mut ctx),
+        "SHOUT"
+    );
+}
+
+#[test]
+fn set_block_applies_filters() {
+    let mut ctx = Context::new();
+
+    assert_eq!(
+        render("{% set a | upper %}captured{% endset %}{{ a }}", &mut ctx),
+        "CAPTURED"
+    );
+    assert_eq!(
+        render(
+            "{% set a | replace('a', 'o') | capitalize %}banana{% endset %}{{ a }}",
+            &mut ctx
+        ),
+        "Bonono"
+    );
+}
+
+#[test]
+fn render_macros() {
+    let mut ctx = Context::new();
+
+    assert_eq!(
+        render(
+            "{% macro input(name, type='text') %}<input name={{ name }} type={{ type }}>{% endmacro %}\
+             {{ input('a') }} {{ input('b', type='password') }}",
+            &mut ctx
+        ),
+        "<input name=a type=text> <input name=b type=password>"
+    );
+}
+
+#[test]
+fn macros_outlive_their_source() {
+    let mut ctx = Context::new();
+
+    let source = String::from("{% macro twice(x) %}{{ x }}{{ x }}{% endmacro %}");
+    render(&source, &mut ctx);
+    drop(source);
+
+    let twice = ctx.get("twice").unwrap();
+    assert_eq!(
+        twice.call(vec![Value::from("ab")], vec![]),
+        Ok(Value::from("abab"))
+    );
+}
+
+#[test]
+fn macros_see_variables_as_they_are_when_called() {
+    let mut ctx = Context::new();
+
+    assert_eq!(
+        render(
+            "{% set x = 1 %}{% macro m() %}{{ x }}{% endmacro %}{% set x = 2 %}{{ m() }}",
+            &mut ctx
+        ),
+        "2"
+    );
+    assert_eq!(
+        render(
+            "{% macro a() %}{{ b() }}{% endmacro %}{% macro b() %}B{% endmacro %}{{ a() }}",
+            &mut ctx
+        ),
+        "B"
+    );
+}
+
+fn forms() -> Context {
+    let mut loader = DictLoader::new();
+    loader.insert(
+        "forms.html",
+        "{% set kind = 'form' %}{% set _private = 1 %}\
+         {% macro input(name) %}[{{ name }}]{% endmacro %}\
+         {% macro textarea(name) %}({{ name }} {{ user }}){% endmacro %}\
+         {% macro row(name) %}{{ label(name) }}{{ input(name) }}{% endmacro %}\
+         {% macro label(name) %}{{ name }}:{% endmacro %}",
+    );
+
+    let mut ctx = Context::new();
+    ctx.set_loader(loader);
+    ctx.insert("user", Value::from("ophelia"));
+    ctx
+}
+
+#[test]
+fn import_binds_a_module() {
+    let mut ctx = forms();
+
+    assert_eq!(
+        render(
+            "{% import 'forms.html' as forms %}{{ forms.input('a') }} {{ forms.kind }}",
+            &mut ctx
+        ),
+        "[a] form"
+    );
+    assert_eq!(
+        render(
+            "{% import 'forms.html' as forms %}{{ forms._private }}",
+            &mut ctx
+        ),
+        ""
+    );
+}
+
+#[test]
+fn from_import_aliases_items() {
+    let mut ctx = forms();
+
+    assert_eq!(
+        render(
+            "{% from 'forms.html' import input as field, kind %}{{ field('a') }} {{ kind }}",
+            &mut ctx
+        ),
+        "[a] form"
+    );
+
+    // `row` calls the module's other macros, which were not imported
+    assert_eq!(
+        render("{% from 'forms.html' import row %}{{ row('a') }}", &mut ctx),
+        "a:[a]"
+    );
+
+    let (template, _) = Template::parse("{% from 'forms.html' import missing %}").unwrap();
+    assert!(template.render(&mut ctx, &mut String::new()).is_err());
+}
+
+#[test]
+fn imports_only_see_the_context_when_asked_to() {
+    let mut ctx = forms();
+
+    assert_eq!(
+        render(
+            "{% from 'forms.html' import textarea %}{{ textarea('a') }}",
+            &mut ctx
+        ),
+        "(a )"
+    );
+    assert_eq!(
+        render(
+            "{% from 'forms.html' import textarea with context %}{{ textarea('a') }}",
+            &mut ctx
+        ),
+        "(a ophelia)"
+    );
+}
+
+#[test]
+fn importing_a_missing_template_fails() {
+    let (template, _) = Template::parse("{% import 'nope.html' as nope %}").unwrap();
+
+    assert_eq!(
+        template.render(&mut forms(), &mut String::new()),
+        Err(RenderError::TemplateNotFound("nope.html".to_string()))
+    );
+}
+
+fn pages() -> Context {
+    let mut loader = DictLoader::new();
+    loader.insert("header.html", "<h1>{{ title }}</h1>{% set leaked = 1 %}");
+    loader.insert("fallback.html", "fallback");
+
+    let mut ctx = Context::new();
+    ctx.set_loader(loader);
+    ctx.insert("title", Value::from("hello"));
+    ctx
+}
+
+#[test]
+fn include_renders_with_the_context() {
+    let mut ctx = pages();
+
+    assert_eq!(
+        render("{% include 'header.html' %}{{ leaked }}", &mut ctx),
+        "<h1>hello</h1>"
+    );
+    assert_eq!(
+        render("{% include 'header.html' without context %}", &mut ctx),
+        "<h1></h1>"
+    );
+}
+
+#[test]
+fn include_picks_the_first_template_which_exists() {
+    let mut ctx = pages();
+
+    assert_eq!(
+        render(
+            "{% include ['missing.html', 'fallback.html', 'header.html'] %}",
+            &mut ctx
+        ),
+        "fallback"
+    );
+}
+
+#[test]
+fn include_ignore_missing() {
+    let mut ctx = pages();
+
+    assert_eq!(
+        render("a{% include 'missing.html' ignore missing %}b", &mut ctx),
+        "ab"
+    );
+
+    let (template, _) = Template::parse("{% include ['x.html', 'y.html'] %}").unwrap();
+    assert_eq!(
+        template.render(&mut ctx, &mut String::new()),
+        Err(RenderError::TemplateNotFound("x.html, y.html".to_string()))
+    );
+}
+
+#[test]
+fn tests_check_values() {
+    let mut ctx = Context::new();
+    ctx.insert("name", Value::from("ophelia"));
+    ctx.insert("items", Value::from(vec![1, 2, 3]));
+
+    assert_eq!(
+        render(
+            "{{ name is defined }} {{ missing is defined }} {{ missing is undefined }}",
+            &mut ctx
+        ),
+        "True False True"
+    );
+    assert_eq!(
+        render(
+            "{{ items is iterable }} {{ name is string }} {{ name is lower }} {{ 1 is number }}",
+            &mut ctx
+        ),
+        "True True True True"
+    );
+    assert_eq!(
+        render(
+            "{% for x in items %}{% if x is even %}{{ x }}{% endif %}{% endfor %} \
+             {{ 9 is divisibleby(3) }} {{ 2 is gt(1) }} {{ false is false }}",
+            &mut ctx
+        ),
+        "2 True True True"
+    );
+
+    for failing in &["{{ name is nope }}", "{{ name is odd }}", "{{ 1 is eq }}"] {
+        let (template, _) = Template::parse(failing).unwrap();
+        assert!(template.render(&mut ctx, &mut String::new()).is_err());
+    }
+}
+
+#[test]
+fn filters_and_tests_can_be_added() {
+    let mut loader = DictLoader::new();
+    loader.insert("shout.html", "{{ name | shout(3) }}");
+
+    let mut ctx = Context::new();
+    ctx.set_loader(loader);
+    ctx.insert("name", Value::from("ophelia"));
+    ctx.add_filter(
+        "shout",
+        Function::new(|args, _| {
+            let times = match args.get(1) {
+                Some(Value::Integer(times)) => *times as usize,
+                _ => 1,
+            };
+            Ok(Value::from(
+                args[0].to_string().to_uppercase() + &"!".repeat(times),
+            ))
+        }),
+    );
+    // added filters and tests take precedence over the builtin ones
+    ctx.add_filter("upper", Function::new(|_, _| Ok(Value::from("up"))));
+    ctx.add_test(
+        "long",
+        Function::new(|args, _| Ok(Value::from(args[0].to_string().len() > 5))),
+    );
+
+    assert_eq!(
+        render("{{ name | shout }} {{ name | upper }}", &mut ctx),
+        "OPHELIA! up"
+    );
+    assert_eq!(
+        render("{{ name is long }} {{ 'a' is long }}", &mut ctx),
+        "True False"
+    );
+    // they are visible in templates which are included
+    assert_eq!(render("{% include 'shout.html' %}", &mut ctx), "OPHELIA!!!");
+}
+
+#[test]
+fn documented_builtins_exist() {
+    let mut ctx = Context::new();
+    for filter in BUILTIN_FILTERS {
+        assert!(filter.signature.starts_with(filter.name));
+        let source = format!("{{{{ 1 | {} }}}}", filter.name);
+        let (template, _) = Template::parse(&source).unwrap();
+        let result = template.render(&mut ctx, &mut String::new());
+        assert_ne!(
+            result,
+            Err(RenderError::UnknownFilter(filter.name.to_string()))
+        );
+    }
+    for test in BUILTIN_TESTS {
+        assert!(test.signature.starts_with(test.name));
+        let source = format!("{{{{ 1 is {} }}}}", test.name);
+        let (template, _) = Template::parse(&source).unwrap();
+        let result = template.render(&mut ctx, &mut String::new());
+        assert_ne!(result, Err(RenderError::UnknownTest(test.name.to_string())));
+    }
+}
@@ -0,0 +1,196 @@
+mod common;
+
+use ophelia_logic::{
+    parse::{Parse, Template},
+    render::{RenderError, Sandbox, SecurityError},
+};
+
+/// Renders `source` in `sandbox`, or without one (see `common::render`).
+fn render(source: &str, sandbox: impl Into<Option<Sandbox>>) -> Result<String, RenderError> {
+    let sandbox = sandbox.into();
+    let (template, _) = Template::parse(source).unwrap();
+    common::render(&template, || {
+        let mut ctx = common::context();
+        if let Some(sandbox) = sandbox.clone() {
+            ctx.set_sandbox(sandbox);
+        }
+        ctx
+    })
+}
+
+fn security_error(error: SecurityError) -> Result<String, RenderError> {
+    Err(RenderError::Security(error))
+}
+
+#[test]
+fn attributes_and_methods_have_to_be_allowed() {
+    let attribute = |name: &str| security_error(SecurityError::Attribute(name.into()));
+    let method = |name: &str| security_error(SecurityError::Method(name.into()));
+
+    assert_eq!(render("{{ user.name }}", Sandbox::new()), attribute("name"));
+    assert_eq!(
+        render("{{ user.password is defined }}", Sandbox::new()),
+        attribute("password")
+    );
+    assert_eq!(
+        render("{% do items.append(4) %}", Sandbox::new()),
+        method("append")
+    );
+    assert_eq!(
+        render(
+            "{% import 'forms.html' as forms %}{{ forms.input('a') }}",
+            Sandbox::new()
+        ),
+        method("input")
+    );
+
+    // only the ones which were allowed can be used
+    let sandbox = Sandbox::new()
+        .allow_attribute("name")
+        .allow_method("append");
+    assert_eq!(
+        render(
+            "{{ user.name }}{% do items.append(4) %}{{ items }}",
+            sandbox.clone()
+        ),
+        Ok("ophelia[1, 2, 3, 4]".to_string())
+    );
+    assert_eq!(
+        render("{{ user.password }}", sandbox),
+        attribute("password")
+    );
+
+    // namespaces belong to the template
+    assert_eq!(
+        render(
+            "{% set ns = namespace(count=1) %}{% set ns.count = ns.count + 1 %}{{ ns.count }}",
+            Sandbox::new()
+        ),
+        Ok("2".to_string())
+    );
+}
+
+#[test]
+fn output_is_limited() {
+    let too_large = security_error(SecurityError::OutputTooLarge(8));
+    let sandbox = || Sandbox::new().max_output(8);
+
+    assert_eq!(
+        render("{% for i in items %}abc{% endfor %}", sandbox()),
+        too_large
+    );
+    assert_eq!(render("{{ 'abc' ~ 'defghi' }}", sandbox()), too_large);
+    assert_eq!(
+        render("{{ 'a' | replace('a', 'aaaaaaaaa') }}", sandbox()),
+        too_large
+    );
+    // which is found out without building the string
+    assert_eq!(render("{{ 'a' * 2147483647 }}", sandbox()), too_large);
+    assert_eq!(
+        render(
+            "{% set s = 'x' * 10000000 %}{{ s | replace('x', s) }}",
+            Sandbox::new()
+        ),
+        security_error(SecurityError::OutputTooLarge(10 << 20))
+    );
+    assert_eq!(
+        render("{{ 'abc' | replace('', '--') }}", sandbox()),
+        too_large
+    );
+    assert_eq!(render("{{ items | join('1234') }}", sandbox()), too_large);
+    assert_eq!(
+        render(
+            "{{ 'abcabc' | replace('abc', 'x') }} {{ items | join }}",
+            sandbox()
+        ),
+        Ok("xx 123".to_string())
+    );
+    // and strings which are not output are limited too
+    assert_eq!(render("{% set s = 'a' * 9 %}", sandbox()), too_large);
+
+    assert_eq!(
+        render("{{ 'ab' * 4 }}", sandbox()),
+        Ok("abababab".to_string())
+    );
+}
+
+#[test]
+fn loops_are_limited() {
+    let sandbox = || Sandbox::new().max_iterations(3);
+    assert_eq!(
+        render("{% for i in range(4) %}{% endfor %}", sandbox()),
+        security_error(SecurityError::TooManyIterations(3))
+    );
+    assert_eq!(
+        render(
+            "{% for i in items %}{% for j in items %}{{ i * j }}{% endfor %}{% endfor %}",
+            sandbox()
+        ),
+        Ok("123246369".to_string())
+    );
+}
+
+#[test]
+fn nesting_is_limited() {
+    // a template which includes itself would otherwise overflow the stack
+    assert_eq!(
+        render("{% include 'itself.html' %}", None),
+        security_error(SecurityError::TooDeep(64))
+    );
+    assert_eq!(
+        render("{% include 'itself.html' %}", Sandbox::new()),
+        security_error(SecurityError::TooDeep(32))
+    );
+    assert_eq!(
+        render(
+            "{% macro forever() %}{{ forever() }}{% endmacro %}{{ forever() }}",
+            None
+        ),
+        security_error(SecurityError::TooDeep(64))
+    );
+
+    let countdown = |n: i32| {
+        format!(
+            "{{% macro countdown(n) %}}{{{{ n }}}}{{% if n > 0 %}}{{{{ countdown(n - 1) }}}}\
+             {{% endif %}}{{% endmacro %}}{{{{ countdown({}) }}}}",
+            n
+        )
+    };
+    let sandbox = || Sandbox::new().max_depth(4);
+    assert_eq!(render(&countdown(3), sandbox()), Ok("3210".to_string()));
+    assert_eq!(
+        render(&countdown(4), sandbox()),
+        security_error(SecurityError::TooDeep(4))
+    );
+}
+
+#[test]
+fn ranges_are_limited() {
+    assert_eq!(
+        render(
+            "{{ range(3) }} {{ range(1, 10, 3) }} {{ range(5, 0, -2) }} {{ range(2, 1) }}",
+            None
+        ),
+        Ok("[0, 1, 2] [1, 4, 7] [5, 3, 1] []".to_string())
+    );
+
+    let sandbox = || Sandbox::new().max_range(10);
+    assert_eq!(
+        render("{{ range(10) | length }}", sandbox()),
+        Ok("10".to_string())
+    );
+    assert_eq!(
+        render("{{ range(-5, 10) }}", sandbox()),
+        security_error(SecurityError::RangeTooLarge(10))
+    );
+    assert_eq!(
+        render("{{ range(11) | length }}", None),
+        Ok("11".to_string())
+    );
+
+    let error = render("{{ range(100000000) }}", Sandbox::new()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "a range is larger than the limit of 100000 numbers"
+    );
+}
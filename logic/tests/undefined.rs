@@ -0,0 +1,127 @@
+mod common;
+
+use std::ops::Range;
+
+use ophelia_logic::{
+    parse::{Parse, Template},
+    render::{Context, RenderError, Undefined},
+};
+
+/// Renders `source` with the `undefined` policy (see `common::render`).
+fn render(source: &str, undefined: Undefined) -> Result<String, RenderError> {
+    let (template, _) = Template::parse(source).unwrap();
+    common::render(&template, || context(undefined))
+}
+
+fn context(undefined: Undefined) -> Context {
+    let mut ctx = common::context();
+    ctx.set_undefined(undefined);
+    ctx
+}
+
+/// What `source` fails to render because of under the `Strict` policy, and where that is (which
+/// is the same whether it was rendered by walking the tree or compiled).
+fn strict_error(source: &str) -> (String, Option<Range<usize>>) {
+    let (template, _) = Template::parse(source).unwrap();
+    let [walked, compiled] = common::render_both(&template, || context(Undefined::Strict)).map(
+        |(result, _)| match result.unwrap_err() {
+            RenderError::Undefined { expr, origin } => (expr, origin.span(source)),
+            error => panic!("{:?} is not about something undefined", error),
+        },
+    );
+    assert_eq!(walked, compiled, "while rendering {:?}", source);
+    compiled
+}
+
+#[test]
+fn lenient_undefined_values_are_empty() {
+    assert_eq!(
+        render(
+            "[{{ missing }}]{% for x in missing %}{{ x }}{% endfor %}{% if not missing %}no{% endif %}\
+             [{{ user.age }}][{{ missing | default('x') }}]",
+            Undefined::Lenient
+        ),
+        Ok("[]no[][x]".to_string())
+    );
+
+    // but they do not have attributes
+    let source = "{{ missing.name }}";
+    let error = render(source, Undefined::Lenient).unwrap_err();
+    assert_eq!(error.to_string(), "`missing` is undefined");
+    assert_eq!(error.span(source), Some(3..10));
+}
+
+#[test]
+fn chainable_undefined_values_have_undefined_attributes() {
+    assert_eq!(
+        render(
+            "[{{ missing.name }}][{{ missing.a.b | default('x') }}][{{ user.age.years }}]",
+            Undefined::Chainable
+        ),
+        Ok("[][x][]".to_string())
+    );
+    assert_eq!(Context::new().undefined(), Undefined::Chainable);
+}
+
+#[test]
+fn debug_undefined_values_are_written_out() {
+    assert_eq!(
+        render(
+            "{{ user.name }} {{ missing }} {{ user.age }} {{ missing.a }} {{ missing ~ '!' }}",
+            Undefined::Debug
+        ),
+        Ok("ophelia {{ missing }} {{ user.age }} {{ missing.a }} !".to_string())
+    );
+}
+
+#[test]
+fn strict_undefined_values_are_errors() {
+    assert_eq!(
+        strict_error("a {{ missing }}"),
+        ("missing".into(), Some(5..12))
+    );
+    assert_eq!(
+        strict_error("{% if user.name %}{{ user.age + 1 }}{% endif %}"),
+        ("user.age".into(), Some(21..29))
+    );
+    assert_eq!(
+        strict_error("{% for x in rows %}{% endfor %}"),
+        ("rows".into(), Some(12..16))
+    );
+    assert_eq!(strict_error("{{ f(1) }}"), ("f".into(), Some(3..4)));
+    assert_eq!(
+        strict_error("{{ user.name | replace(old, 'x') }}"),
+        ("old".into(), Some(23..26))
+    );
+    assert_eq!(
+        render("{{ missing }}", Undefined::Strict)
+            .unwrap_err()
+            .to_string(),
+        "`missing` is undefined"
+    );
+
+    // they can still be tested for, or given defaults
+    assert_eq!(
+        render(
+            "{{ missing is defined }} {{ user.age is undefined }} {{ missing.a is defined }} \
+             {{ user.age | default(1) }} {{ missing | d('x') | upper }}",
+            Undefined::Strict
+        ),
+        Ok("False True False 1 X".to_string())
+    );
+
+    // the policy carries on into imported templates, and arguments which were not given are not
+    // undefined variables
+    assert_eq!(
+        render(
+            "{% from 'card.html' import card %}{{ card('a') }}",
+            Undefined::Strict
+        ),
+        Ok("a".to_string())
+    );
+    let source = "{% include 'greeting.html' %}";
+    let error = render(source, Undefined::Strict).unwrap_err();
+    assert_eq!(error.to_string(), "`visitor` is undefined");
+    // the error is in the included template, not this one
+    assert_eq!(error.span(source), None);
+}
@@ -0,0 +1,166 @@
+use std::borrow::Cow;
+
+use ophelia_logic::{
+    parse::{
+        ast::{Block, Expr, ForStmt, Ident, Literal, Macro},
+        Parse, Template,
+    },
+    render::{Context, Render},
+    visit::{walk_body_mut, walk_expr, walk_expr_mut, walk_for, Visit, VisitMut},
+};
+
+/// Collects the names of the variables which are read, in order.
+#[derive(Default)]
+struct Reads<'ast> {
+    names: Vec<&'ast str>,
+}
+
+impl<'ast> Visit<'ast> for Reads<'ast> {
+    fn visit_expr(&mut self, expr: &'ast Expr<'ast>) {
+        match expr {
+            Expr::Ident(ident) | Expr::FunctionCall(ident, ..) => self.names.push(ident.name()),
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[test]
+fn visitors_see_every_expression() {
+    let (template, _) = Template::parse(
+        "{% for x in items %}{% if x > limit %}{{ x | upper }}{% elif y %}{% else %}{{ z }}\
+         {% endif %}{% endfor %}\
+         {% set a, b = pair %}{% set c | replace(old, 'new') %}{{ d }}{% endset %}\
+         {% macro m(p, q=default) %}{{ p ~ q }}{% endmacro %}\
+         {% with w = 1 + e %}{% do f(w, key=g) %}{% endwith %}\
+         {% include h %}{% from i import j as k %}{% filter upper %}{{ -l < m < n }}{% endfilter %}",
+    )
+    .unwrap();
+
+    let mut reads = Reads::default();
+    reads.visit_template(&template);
+    assert_eq!(
+        reads.names,
+        vec![
+            "items", "x", "limit", "x", "upper", "y", "z", "pair", "d", "replace", "old",
+            "default", "p", "q", "e", "f", "w", "g", "h", "i", "l", "m", "n"
+        ]
+    );
+}
+
+#[test]
+fn visitors_can_stop_descending() {
+    /// Counts the loops which are not nested in other loops, and the macros.
+    #[derive(Default)]
+    struct Outermost {
+        loops: usize,
+        macros: Vec<String>,
+    }
+
+    impl<'ast> Visit<'ast> for Outermost {
+        fn visit_for(&mut self, _: &'ast ForStmt<'ast>) {
+            self.loops += 1;
+        }
+
+        fn visit_macro(&mut self, r#macro: &'ast Macro<'ast>) {
+            self.macros.push(r#macro.name().name().to_string());
+        }
+    }
+
+    let (template, _) = Template::parse(
+        "{% for x in a %}{% for y in x %}{% macro inner() %}{% endmacro %}{% endfor %}{% endfor %}\
+         {% if b %}{% for z in b %}{% endfor %}{% endif %}{% macro outer() %}{% endmacro %}",
+    )
+    .unwrap();
+
+    let mut outermost = Outermost::default();
+    outermost.visit_template(&template);
+    assert_eq!(outermost.loops, 2);
+    assert_eq!(outermost.macros, vec!["outer"]);
+
+    /// As `Outermost`, but descends into loops.
+    #[derive(Default)]
+    struct All(usize);
+
+    impl<'ast> Visit<'ast> for All {
+        fn visit_for(&mut self, stmt: &'ast ForStmt<'ast>) {
+            self.0 += 1;
+            walk_for(self, stmt);
+        }
+    }
+
+    let mut all = All::default();
+    all.visit_template(&template);
+    assert_eq!(all.0, 3);
+}
+
+#[test]
+fn visitors_see_every_identifier() {
+    #[derive(Default)]
+    struct Idents(Vec<String>);
+
+    impl<'ast> Visit<'ast> for Idents {
+        fn visit_ident(&mut self, ident: &'ast Ident<'ast>) {
+            self.0.push(ident.name().to_string());
+        }
+    }
+
+    let (template, _) = Template::parse(
+        "{% set ns.total = [1] %}{% macro m(a, b=1) %}{% endmacro %}\
+         {% import 'x' as y %}{{ y.z(k=1) }}",
+    )
+    .unwrap();
+
+    let mut idents = Idents::default();
+    idents.visit_template(&template);
+    assert_eq!(
+        idents.0,
+        vec!["ns", "total", "m", "a", "b", "y", "y", "z", "k"]
+    );
+}
+
+#[test]
+fn mutable_visitors_can_replace_nodes() {
+    /// Replaces every read of a variable with a constant.
+    struct Inline<'a> {
+        name: &'a str,
+        value: &'a str,
+    }
+
+    impl<'i> VisitMut<'i> for Inline<'_> {
+        fn visit_expr_mut(&mut self, expr: &mut Expr<'i>) {
+            match expr {
+                Expr::Ident(ident) if ident.name() == self.name => {
+                    *expr = Expr::Literal(Literal::String(Cow::Owned(self.value.to_string())));
+                }
+                _ => walk_expr_mut(self, expr),
+            }
+        }
+
+        fn visit_body_mut(&mut self, body: &mut Vec<Block<'i>>) {
+            body.retain(|block| !matches!(block, Block::Comment(_)));
+            walk_body_mut(self, body);
+        }
+    }
+
+    let (mut template, _) = Template::parse(
+        "{# greeting #}hello {{ name | upper }}{% for x in [1] %}{# {{ name }} #}{{ name }}\
+         {% endfor %}",
+    )
+    .unwrap();
+
+    Inline {
+        name: "name",
+        value: "ophelia",
+    }
+    .visit_template_mut(&mut template);
+
+    assert!(!template
+        .blocks()
+        .iter()
+        .any(|block| matches!(block, Block::Comment(_))));
+
+    let mut output = String::new();
+    template.render(&mut Context::new(), &mut output).unwrap();
+    assert_eq!(output, "hello OPHELIAophelia");
+}
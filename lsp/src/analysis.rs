@@ -0,0 +1,533 @@
+//! What the server knows about templates, worked out from their lossless trees (so that it works
+//! while a template is being typed, and so has errors in it).
+//!
+//! Nothing here knows about the protocol: offsets are byte offsets into the source.
+
+use ophelia_logic::{
+    cst::{
+        self,
+        ast::{AstNode, BinExpr, Block, Call, Expr, Item, Name, Root, Tag},
+        SyntaxKind, SyntaxNode, SyntaxToken, TextRange, TextSize,
+    },
+    format::Formatter,
+    parse::{ast::BinOp, Parse, Template},
+    render::{Builtin, BUILTIN_FILTERS, BUILTIN_TESTS},
+};
+
+/// The problems with `source`, and where they are.
+pub(crate) fn diagnostics(source: &str) -> Vec<(TextRange, String)> {
+    // the lossless tree finds (and recovers from) more errors at once, but accepts some templates
+    // which the parser does not, so those are checked with the parser too
+    let tree = cst::parse(source);
+    if !tree.errors().is_empty() {
+        return tree
+            .errors()
+            .iter()
+            .map(|error| (error.range(), error.message().to_string()))
+            .collect();
+    }
+    match Template::parse(source) {
+        Ok(_) => vec![],
+        Err(error) => {
+            let start = error.offset(source);
+            let end = start + source[start..].chars().next().map_or(0, char::len_utf8);
+            vec![(range(start, end), error.to_string())]
+        }
+    }
+}
+
+/// `source` formatted, or `None` if it cannot be parsed.
+pub(crate) fn format(source: &str) -> Option<String> {
+    let (template, _) = Template::parse(source).ok()?;
+    Some(Formatter::new().format(&template))
+}
+
+/// Where something which is used in a template is defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Definition {
+    /// In the same template.
+    Here(TextRange),
+    /// In another template: the template itself, or the macro called `item` in it.
+    Template { name: String, item: Option<String> },
+}
+
+/// The definition of what is at `offset`: a macro (defined here, or imported from another
+/// template), a module which was imported, or the template which a string in an `include`,
+/// `import`, `from` or `extends` tag names.
+pub(crate) fn definition(source: &str, offset: usize) -> Option<Definition> {
+    let root = cst::parse(source).root();
+    let token = token_at(root.syntax(), offset)?;
+
+    if token.kind() == SyntaxKind::String {
+        let tag = token.parent_ancestors().find_map(Tag::cast)?;
+        let keyword = tag.keyword()?;
+        if !["include", "import", "from", "extends"].contains(&keyword.text()) {
+            return None;
+        }
+        return Some(Definition::Template {
+            name: unquote(token.text()),
+            item: None,
+        });
+    }
+
+    let name = token.parent().and_then(Name::cast)?;
+    let text = name.text();
+    let before = name.syntax().text_range().start();
+
+    // `module.item`, where `module` was imported (and `module.item(...)`, which is parsed as
+    // `module.(item(...))`)
+    let operand = match name.syntax().parent().and_then(Call::cast) {
+        Some(call) => call.syntax().clone(),
+        None => name.syntax().clone(),
+    };
+    if let Some(dot) = operand.parent().and_then(BinExpr::cast) {
+        if dot.operator() == Some(BinOp::Dot)
+            && dot.rhs().as_ref().map(Expr::syntax) == Some(&operand)
+        {
+            return match dot.lhs()? {
+                Expr::Name(module) => match import(&root, &module.text(), before)? {
+                    Import::Module(file) => Some(Definition::Template {
+                        name: file,
+                        item: Some(text),
+                    }),
+                    Import::Item(..) => None,
+                },
+                _ => None,
+            };
+        }
+    }
+
+    if let Some(range) = find_macro(&root, &text) {
+        return Some(Definition::Here(range));
+    }
+    match import(&root, &text, before)? {
+        Import::Module(file) => Some(Definition::Template {
+            name: file,
+            item: None,
+        }),
+        Import::Item(file, item) => Some(Definition::Template {
+            name: file,
+            item: Some(item),
+        }),
+    }
+}
+
+/// Where the name of the macro called `name` is, in `source`.
+pub(crate) fn find_macro_in(source: &str, name: &str) -> Option<TextRange> {
+    find_macro(&cst::parse(source).root(), name)
+}
+
+fn find_macro(root: &Root, name: &str) -> Option<TextRange> {
+    root.syntax()
+        .descendants()
+        .filter_map(Block::cast)
+        .filter_map(|block| block.opening())
+        .filter(|tag| keyword(tag).as_deref() == Some("macro"))
+        .find_map(|tag| {
+            let macro_name = tag.exprs().next().and_then(name_of)?;
+            (macro_name.text() == name).then(|| macro_name.syntax().text_range())
+        })
+}
+
+/// What a name was imported as.
+enum Import {
+    /// `{% import 'file' as name %}`
+    Module(String),
+    /// `{% from 'file' import item as name %}`
+    Item(String, String),
+}
+
+/// What the last `import` or `from` tag before `offset` which binds `name` imported.
+fn import(root: &Root, name: &str, offset: TextSize) -> Option<Import> {
+    let tags = root
+        .syntax()
+        .descendants()
+        .filter_map(Tag::cast)
+        .filter(|tag| tag.syntax().text_range().end() <= offset);
+
+    let mut found = None;
+    for tag in tags {
+        let file = match tag.exprs().next() {
+            Some(Expr::Literal(literal)) => match literal.token() {
+                Some(token) if token.kind() == SyntaxKind::String => unquote(token.text()),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        match keyword(&tag).as_deref() {
+            Some("import") => {
+                if tag
+                    .exprs()
+                    .nth(1)
+                    .and_then(name_of)
+                    .map(|n| n.text())
+                    .as_deref()
+                    == Some(name)
+                {
+                    found = Some(Import::Module(file));
+                }
+            }
+            Some("from") => {
+                for (item, alias) in imported_names(&tag) {
+                    if alias.text() == name {
+                        found = Some(Import::Item(file.clone(), item.text()));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// The names which a `from` tag imports, and the names they are bound to.
+fn imported_names(tag: &Tag) -> Vec<(Name, Name)> {
+    let mut names: Vec<(Name, Name)> = vec![];
+    let mut aliased = false;
+    for element in tag.syntax().children_with_tokens().skip_while(|element| {
+        !(element.kind() == SyntaxKind::Keyword && element.to_string() == "import")
+    }) {
+        match element {
+            cst::NodeOrToken::Token(token) if token.kind() == SyntaxKind::Keyword => {
+                aliased = token.text() == "as";
+            }
+            cst::NodeOrToken::Node(node) => {
+                if let Some(name) = Name::cast(node) {
+                    match names.last_mut() {
+                        Some((_, alias)) if aliased => *alias = name,
+                        _ => names.push((name.clone(), name)),
+                    }
+                    aliased = false;
+                }
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+/// An explanation of the filter or test at `offset`, and where its name is.
+pub(crate) fn hover(source: &str, offset: usize) -> Option<(TextRange, String)> {
+    let root = cst::parse(source).root();
+    let token = token_at(root.syntax(), offset)?;
+    let name = token.parent().and_then(Name::cast)?;
+
+    let (kind, builtins) = match role(&name)? {
+        Role::Filter => ("filter", BUILTIN_FILTERS),
+        Role::Test => ("test", BUILTIN_TESTS),
+    };
+    let builtin = builtins
+        .iter()
+        .find(|builtin| builtin.name == name.text())?;
+    Some((
+        name.syntax().text_range(),
+        format!(
+            "```jinja\n({}) {}\n```\n\n{}",
+            kind, builtin.signature, builtin.description
+        ),
+    ))
+}
+
+/// What a name which is not a variable is the name of.
+enum Role {
+    Filter,
+    Test,
+}
+
+fn role(name: &Name) -> Option<Role> {
+    // filters and tests which are given arguments are calls
+    let mut expr = name.syntax().clone();
+    if let Some(call) = expr.parent().and_then(Call::cast) {
+        expr = call.syntax().clone();
+    }
+    let parent = expr.parent()?;
+
+    if let Some(bin_expr) = BinExpr::cast(parent.clone()) {
+        if bin_expr.rhs().as_ref().map(Expr::syntax) != Some(&expr) {
+            return None;
+        }
+        return match bin_expr.operator()? {
+            BinOp::Pipe => Some(Role::Filter),
+            BinOp::Is => Some(Role::Test),
+            _ => None,
+        };
+    }
+
+    // the filters of `{% filter name %}` and `{% set x | name %}`
+    let tag = Tag::cast(parent)?;
+    let previous = previous_token(&expr)?;
+    let filters = previous.kind() == SyntaxKind::Operator && previous.text() == "|";
+    let filter_tag =
+        previous.kind() == SyntaxKind::Keyword && keyword(&tag).as_deref() == Some("filter");
+    (filters || filter_tag).then_some(Role::Filter)
+}
+
+/// Something which can be written at the place completions were asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Completion {
+    pub(crate) label: String,
+    pub(crate) kind: CompletionKind,
+    pub(crate) detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CompletionKind {
+    Variable,
+    Macro,
+    Module,
+    Filter,
+    Test,
+}
+
+/// What can be written at `offset`: filters after `|`, tests after `is`, and otherwise the
+/// variables (and macros and modules) which are in scope there.
+pub(crate) fn completions(source: &str, offset: usize) -> Vec<Completion> {
+    let root = cst::parse(source).root();
+    let offset = TextSize::from(offset as u32);
+
+    // the token before the cursor, and the one before that if the cursor is on a name which is
+    // being typed
+    let mut token = match root.syntax().token_at_offset(offset).left_biased() {
+        Some(token) => token,
+        None => return vec![],
+    };
+    // (which is outside of any tag if it is text, or if the cursor is before the first token)
+    if token.text_range().start() == offset
+        || matches!(
+            token.kind(),
+            SyntaxKind::Text
+                | SyntaxKind::CommentOpen
+                | SyntaxKind::CommentText
+                | SyntaxKind::OutputClose
+                | SyntaxKind::TagClose
+        )
+    {
+        return vec![];
+    }
+    if token.kind() == SyntaxKind::Ident && token.text_range().end() == offset {
+        match token.prev_token() {
+            Some(previous) => token = previous,
+            None => return vec![],
+        }
+    }
+    while token.kind() == SyntaxKind::Whitespace {
+        match token.prev_token() {
+            Some(previous) => token = previous,
+            None => return vec![],
+        }
+    }
+
+    let builtins = |builtins: &[Builtin], kind| {
+        builtins
+            .iter()
+            .map(|builtin| Completion {
+                label: builtin.name.to_string(),
+                kind,
+                detail: Some(builtin.signature.to_string()),
+            })
+            .collect()
+    };
+    match token.text() {
+        "|" => return builtins(BUILTIN_FILTERS, CompletionKind::Filter),
+        "is" => return builtins(BUILTIN_TESTS, CompletionKind::Test),
+        _ => {}
+    }
+
+    let mut scope = Scope::default();
+    scope.items(root.items(), offset);
+    scope.bindings.push(Completion {
+        label: "namespace".to_string(),
+        kind: CompletionKind::Macro,
+        detail: Some("namespace(attrs, **kwargs)".to_string()),
+    });
+    scope.bindings.push(Completion {
+        label: "range".to_string(),
+        kind: CompletionKind::Macro,
+        detail: Some("range(start, stop, step)".to_string()),
+    });
+
+    // later bindings of a name replace earlier ones
+    let mut completions: Vec<Completion> = vec![];
+    for binding in scope.bindings.into_iter().rev() {
+        if !completions
+            .iter()
+            .any(|completion| completion.label == binding.label)
+        {
+            completions.push(binding);
+        }
+    }
+    completions.reverse();
+    completions
+}
+
+/// The names which are bound at a place in a template.
+#[derive(Default)]
+struct Scope {
+    bindings: Vec<Completion>,
+}
+
+impl Scope {
+    fn bind(&mut self, name: Option<Name>, kind: CompletionKind, detail: Option<String>) {
+        if let Some(name) = name {
+            self.bindings.push(Completion {
+                label: name.text(),
+                kind,
+                detail,
+            });
+        }
+    }
+
+    /// Adds what `items` bind before `offset`, and (if `offset` is inside one of them) what is
+    /// bound inside the item.
+    fn items(&mut self, items: impl Iterator<Item = Item>, offset: TextSize) {
+        for item in items {
+            match item {
+                Item::Tag(tag) if tag.syntax().text_range().end() <= offset => self.tag(&tag),
+                Item::Block(block) if block.syntax().text_range().end() <= offset => {
+                    // of the blocks, only `set` and `macro` bind names outside of themselves
+                    if let Some(tag) = block.opening() {
+                        if matches!(keyword(&tag).as_deref(), Some("set" | "macro")) {
+                            self.tag(&tag);
+                        }
+                    }
+                }
+                Item::Block(block) if block.syntax().text_range().contains_inclusive(offset) => {
+                    self.block(&block, offset);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Adds what is bound inside `block`, at `offset`.
+    fn block(&mut self, block: &Block, offset: TextSize) {
+        let opening = match block.opening() {
+            Some(opening) => opening,
+            None => return,
+        };
+        let first_body = block.bodies().next();
+        let body = block
+            .bodies()
+            .find(|body| body.syntax().text_range().contains_inclusive(offset));
+        let body = match body {
+            Some(body) => body,
+            None => return,
+        };
+        let in_first_body = first_body.as_ref() == Some(&body);
+
+        match keyword(&opening).as_deref() {
+            Some("for") if in_first_body => {
+                for name in targets(&opening, "in") {
+                    self.bind(Some(name), CompletionKind::Variable, None);
+                }
+            }
+            Some("macro") => {
+                if let Some(params) = opening.params() {
+                    for param in params.args() {
+                        self.bind(name_of(param), CompletionKind::Variable, None);
+                    }
+                    for kwarg in params.kwargs() {
+                        self.bind(kwarg.name(), CompletionKind::Variable, None);
+                    }
+                }
+                // macros can call themselves
+                self.tag(&opening);
+            }
+            Some("with") => {
+                for kwarg in opening.kwargs() {
+                    self.bind(kwarg.name(), CompletionKind::Variable, None);
+                }
+            }
+            _ => {}
+        }
+        self.items(body.items(), offset);
+    }
+
+    /// Adds the names which a tag binds.
+    fn tag(&mut self, tag: &Tag) {
+        match keyword(tag).as_deref() {
+            Some("set") => {
+                for name in targets(tag, "=") {
+                    self.bind(Some(name), CompletionKind::Variable, None);
+                }
+            }
+            Some("macro") => {
+                let name = tag.exprs().next().and_then(name_of);
+                let detail = name.as_ref().map(|name| {
+                    let params = tag
+                        .params()
+                        .map_or_else(String::new, |params| params.syntax().to_string());
+                    format!("{}{}", name.text(), params)
+                });
+                self.bind(name, CompletionKind::Macro, detail);
+            }
+            Some("import") => {
+                self.bind(
+                    tag.exprs().nth(1).and_then(name_of),
+                    CompletionKind::Module,
+                    tag.exprs().next().map(|file| file.syntax().to_string()),
+                );
+            }
+            Some("from") => {
+                let file = tag.exprs().next().map(|file| file.syntax().to_string());
+                for (_, alias) in imported_names(tag) {
+                    self.bind(Some(alias), CompletionKind::Macro, file.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The names which a `for` or `set` tag binds (the ones before `until`, which is `in` or `=`).
+fn targets(tag: &Tag, until: &str) -> Vec<Name> {
+    tag.syntax()
+        .children_with_tokens()
+        .take_while(|element| {
+            !(matches!(element.kind(), SyntaxKind::Keyword | SyntaxKind::Eq)
+                && element.to_string() == until)
+        })
+        .filter_map(|element| element.into_node().and_then(Name::cast))
+        .collect()
+}
+
+/// The keyword of `tag`, as a string.
+fn keyword(tag: &Tag) -> Option<String> {
+    tag.keyword().map(|keyword| keyword.text().to_string())
+}
+
+/// The name which `expr` is, if it is just a name.
+fn name_of(expr: Expr) -> Option<Name> {
+    match expr {
+        Expr::Name(name) => Some(name),
+        _ => None,
+    }
+}
+
+/// The name or string at `offset` (preferring the one after it, if it is between two).
+fn token_at(root: &SyntaxNode, offset: usize) -> Option<SyntaxToken> {
+    root.token_at_offset(TextSize::from(offset as u32))
+        .filter(|token| matches!(token.kind(), SyntaxKind::Ident | SyntaxKind::String))
+        .last()
+}
+
+/// The token before `node`, other than whitespace.
+fn previous_token(node: &SyntaxNode) -> Option<SyntaxToken> {
+    let mut token = node.first_token()?.prev_token()?;
+    while token.kind() == SyntaxKind::Whitespace {
+        token = token.prev_token()?;
+    }
+    Some(token)
+}
+
+/// The contents of a string literal (which does not have escapes).
+fn unquote(literal: &str) -> String {
+    literal
+        .get(1..literal.len().saturating_sub(1))
+        .unwrap_or("")
+        .to_string()
+}
+
+fn range(start: usize, end: usize) -> TextRange {
+    TextRange::new(TextSize::from(start as u32), TextSize::from(end as u32))
+}
@@ -0,0 +1,357 @@
+//! A language server for templates.
+//!
+//! It offers:
+//!
+//! - diagnostics (from the lossless tree, and then from the parser), whenever a template changes
+//! - go to definition, for macros (defined in the template, or imported from another one), for
+//!   modules which were imported, and for the templates named by `include`, `import`, `from` and
+//!   `extends` tags
+//! - hover, for the builtin filters and tests
+//! - completion of the variables, macros and modules which are in scope (and of filters after
+//!   `|`, and tests after `is`)
+//! - formatting (with [`ophelia_logic::format::Formatter`])
+//!
+//! Templates named by other templates are looked for (as `FileSystemLoader` would look for them)
+//! next to the template which names them, and then in each of the workspace's folders.
+
+use std::{collections::HashMap, error::Error, fs, path::PathBuf};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+        PublishDiagnostics,
+    },
+    request::{Completion, Formatting, GotoDefinition, HoverRequest, Request as _},
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DocumentFormattingParams, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability,
+    InitializeParams, Location, MarkupContent, MarkupKind, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Url,
+};
+use ophelia_logic::cst::TextRange;
+
+mod analysis;
+
+use analysis::{CompletionKind, Definition};
+
+pub type ServerResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Serves `connection` until the client asks the server to shut down.
+pub fn run(connection: &Connection) -> ServerResult<()> {
+    let params = connection.initialize(serde_json::to_value(capabilities())?)?;
+    let params: InitializeParams = serde_json::from_value(params)?;
+    let mut server = Server::new(&params);
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = server.request(request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                for notification in server.notification(notification) {
+                    connection
+                        .sender
+                        .send(Message::Notification(notification))?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["|".to_string(), " ".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    }
+}
+
+#[derive(Debug)]
+struct Server {
+    /// The text of the documents which the client has open.
+    documents: HashMap<Url, String>,
+    /// The workspace's folders.
+    roots: Vec<PathBuf>,
+}
+
+impl Server {
+    fn new(params: &InitializeParams) -> Self {
+        #[allow(deprecated)]
+        let root_uri = params.root_uri.iter();
+        let roots = params
+            .workspace_folders
+            .iter()
+            .flatten()
+            .map(|folder| &folder.uri)
+            .chain(root_uri)
+            .filter_map(|uri| uri.to_file_path().ok())
+            .collect();
+
+        Self {
+            documents: HashMap::new(),
+            roots,
+        }
+    }
+
+    fn request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            GotoDefinition::METHOD => {
+                respond::<GotoDefinition>(request, |params| self.definition(params))
+            }
+            HoverRequest::METHOD => respond::<HoverRequest>(request, |params| self.hover(params)),
+            Completion::METHOD => respond::<Completion>(request, |params| self.completion(params)),
+            Formatting::METHOD => respond::<Formatting>(request, |params| self.format(params)),
+            _ => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unknown request `{}`", request.method),
+            ),
+        }
+    }
+
+    /// Handles a notification, returning the notifications which the client should be sent.
+    fn notification(&mut self, notification: Notification) -> Vec<Notification> {
+        let uri = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = match params::<DidOpenTextDocument>(notification) {
+                    Some(params) => params,
+                    None => return vec![],
+                };
+                let document = params.text_document;
+                self.documents.insert(document.uri.clone(), document.text);
+                document.uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = match params::<DidChangeTextDocument>(notification) {
+                    Some(params) => params,
+                    None => return vec![],
+                };
+                // the server asks for the whole text of the document on every change
+                let text = match params.content_changes.into_iter().last() {
+                    Some(change) => change.text,
+                    None => return vec![],
+                };
+                let uri = params.text_document.uri;
+                self.documents.insert(uri.clone(), text);
+                uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = match params::<DidCloseTextDocument>(notification) {
+                    Some(params) => params,
+                    None => return vec![],
+                };
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                uri
+            }
+            _ => return vec![],
+        };
+        vec![self.diagnostics(uri)]
+    }
+
+    /// The notification which publishes the diagnostics for the document at `uri` (which are
+    /// cleared if it has been closed).
+    fn diagnostics(&self, uri: Url) -> Notification {
+        let diagnostics = match self.documents.get(&uri) {
+            Some(source) => {
+                let index = LineIndex::new(source);
+                analysis::diagnostics(source)
+                    .into_iter()
+                    .map(|(range, message)| Diagnostic {
+                        range: index.range(range),
+                        severity: Some(DiagnosticSeverity::ERROR),
+                        source: Some("ophelia".to_string()),
+                        message,
+                        ..Diagnostic::default()
+                    })
+                    .collect()
+            }
+            None => vec![],
+        };
+        Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            PublishDiagnosticsParams {
+                uri,
+                diagnostics,
+                version: None,
+            },
+        )
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let source = self.documents.get(&uri)?;
+        let index = LineIndex::new(source);
+
+        let location = match analysis::definition(source, index.offset(position.position))? {
+            Definition::Here(range) => Location {
+                uri,
+                range: index.range(range),
+            },
+            Definition::Template { name, item } => {
+                let (target, target_source) = self.load(&uri, &name)?;
+                let range = item
+                    .and_then(|item| analysis::find_macro_in(&target_source, &item))
+                    .map_or_else(Range::default, |range| {
+                        LineIndex::new(&target_source).range(range)
+                    });
+                Location { uri: target, range }
+            }
+        };
+        Some(GotoDefinitionResponse::Scalar(location))
+    }
+
+    /// Finds the template called `name`, which the document at `from` refers to.
+    fn load(&self, from: &Url, name: &str) -> Option<(Url, String)> {
+        let next_to = from
+            .to_file_path()
+            .ok()
+            .and_then(|path| path.parent().map(PathBuf::from));
+        next_to
+            .iter()
+            .chain(&self.roots)
+            .map(|directory| directory.join(name))
+            .find_map(|path| {
+                let uri = Url::from_file_path(&path).ok()?;
+                match self.documents.get(&uri) {
+                    Some(source) => Some((uri, source.clone())),
+                    None => fs::read_to_string(&path).ok().map(|source| (uri, source)),
+                }
+            })
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let source = self.documents.get(&position.text_document.uri)?;
+        let index = LineIndex::new(source);
+
+        let (range, markdown) = analysis::hover(source, index.offset(position.position))?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: markdown,
+            }),
+            range: Some(index.range(range)),
+        })
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let source = self.documents.get(&position.text_document.uri)?;
+        let index = LineIndex::new(source);
+
+        let items = analysis::completions(source, index.offset(position.position))
+            .into_iter()
+            .map(|completion| CompletionItem {
+                label: completion.label,
+                kind: Some(match completion.kind {
+                    CompletionKind::Variable => CompletionItemKind::VARIABLE,
+                    CompletionKind::Macro | CompletionKind::Filter | CompletionKind::Test => {
+                        CompletionItemKind::FUNCTION
+                    }
+                    CompletionKind::Module => CompletionItemKind::MODULE,
+                }),
+                detail: completion.detail,
+                ..CompletionItem::default()
+            })
+            .collect();
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn format(&self, params: DocumentFormattingParams) -> Option<Vec<TextEdit>> {
+        let source = self.documents.get(&params.text_document.uri)?;
+        let formatted = analysis::format(source)?;
+        if formatted == *source {
+            return Some(vec![]);
+        }
+        let end = TextRange::up_to((source.len() as u32).into());
+        Some(vec![TextEdit {
+            range: LineIndex::new(source).range(end),
+            new_text: formatted,
+        }])
+    }
+}
+
+/// Responds to `request` with what `handle` returns for its parameters.
+fn respond<R: lsp_types::request::Request>(
+    request: Request,
+    handle: impl FnOnce(R::Params) -> R::Result,
+) -> Response {
+    match serde_json::from_value(request.params) {
+        Ok(params) => Response::new_ok(request.id, handle(params)),
+        Err(error) => Response::new_err(
+            request.id,
+            ErrorCode::InvalidParams as i32,
+            error.to_string(),
+        ),
+    }
+}
+
+/// The parameters of `notification` (or `None` if they are not what they should be, in which
+/// case the notification is ignored, as the protocol says it should be).
+fn params<N: lsp_types::notification::Notification>(
+    notification: Notification,
+) -> Option<N::Params> {
+    serde_json::from_value(notification.params).ok()
+}
+
+/// Converts between byte offsets and the protocol's positions (whose characters are UTF-16 code
+/// units).
+struct LineIndex<'s> {
+    source: &'s str,
+    /// Where each line starts.
+    lines: Vec<usize>,
+}
+
+impl<'s> LineIndex<'s> {
+    fn new(source: &'s str) -> Self {
+        let lines = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        Self { source, lines }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.lines.partition_point(|start| *start <= offset) - 1;
+        let character = self.source[self.lines[line]..offset].encode_utf16().count();
+        Position::new(line as u32, character as u32)
+    }
+
+    fn range(&self, range: TextRange) -> Range {
+        Range::new(
+            self.position(range.start().into()),
+            self.position(range.end().into()),
+        )
+    }
+
+    /// The offset of `position` (or of the end of its line, if it is past it).
+    fn offset(&self, position: Position) -> usize {
+        let start = match self.lines.get(position.line as usize) {
+            Some(start) => *start,
+            None => return self.source.len(),
+        };
+        let mut units = 0;
+        for (index, c) in self.source[start..].char_indices() {
+            if units >= position.character as usize || c == '\n' {
+                return start + index;
+            }
+            units += c.len_utf16();
+        }
+        self.source.len()
+    }
+}
@@ -0,0 +1,18 @@
+use std::process::ExitCode;
+
+use lsp_server::Connection;
+
+fn main() -> ExitCode {
+    // editors talk to the server over its standard input and output
+    let (connection, io_threads) = Connection::stdio();
+    let result = ophelia_lsp::run(&connection);
+    drop(connection);
+
+    match result.and_then(|()| io_threads.join().map_err(Into::into)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("ophelia-lsp: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
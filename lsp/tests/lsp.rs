@@ -0,0 +1,398 @@
+//! Talks to the server over an in-memory connection, as an editor would.
+
+use std::{env, fs, path::PathBuf, process, thread};
+
+use lsp_server::{Connection, Message, Notification, Request, RequestId};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Exit, Initialized,
+        Notification as _, PublishDiagnostics,
+    },
+    request::{Completion, Formatting, GotoDefinition, HoverRequest, Initialize, Shutdown},
+    CompletionParams, CompletionResponse, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentFormattingParams, FormattingOptions, GotoDefinitionParams,
+    GotoDefinitionResponse, HoverContents, HoverParams, InitializeParams, Location, Position,
+    PublishDiagnosticsParams, Range, TextDocumentContentChangeEvent, TextDocumentIdentifier,
+    TextDocumentItem, TextDocumentPositionParams, Url, VersionedTextDocumentIdentifier,
+    WorkspaceFolder,
+};
+
+/// An editor, which is connected to a server running on another thread.
+struct Client {
+    connection: Connection,
+    server: Option<thread::JoinHandle<()>>,
+    next_id: i32,
+}
+
+impl Client {
+    /// Starts a server for a workspace in `root`.
+    fn new(root: &PathBuf) -> Self {
+        let (client, server) = Connection::memory();
+        let server = thread::spawn(move || ophelia_lsp::run(&server).unwrap());
+        let mut client = Self {
+            connection: client,
+            server: Some(server),
+            next_id: 0,
+        };
+
+        #[allow(deprecated)]
+        let params = InitializeParams {
+            workspace_folders: Some(vec![WorkspaceFolder {
+                uri: Url::from_directory_path(root).unwrap(),
+                name: "templates".to_string(),
+            }]),
+            ..InitializeParams::default()
+        };
+        client.request::<Initialize>(params);
+        client.notify::<Initialized>(lsp_types::InitializedParams {});
+        client
+    }
+
+    fn request<R: lsp_types::request::Request>(&mut self, params: R::Params) -> R::Result {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        let request = Request::new(id.clone(), R::METHOD.to_string(), params);
+        self.connection.sender.send(request.into()).unwrap();
+
+        for message in &self.connection.receiver {
+            if let Message::Response(response) = message {
+                assert_eq!(response.id, id);
+                assert!(response.error.is_none(), "{:?}", response.error);
+                return serde_json::from_value(response.result.unwrap()).unwrap();
+            }
+        }
+        panic!("the server stopped");
+    }
+
+    fn notify<N: lsp_types::notification::Notification>(&self, params: N::Params) {
+        let notification = Notification::new(N::METHOD.to_string(), params);
+        self.connection.sender.send(notification.into()).unwrap();
+    }
+
+    /// Opens a document, returning the diagnostics which the server publishes for it.
+    fn open(&self, uri: &Url, text: &str) -> PublishDiagnosticsParams {
+        self.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(
+                uri.clone(),
+                "jinja".to_string(),
+                1,
+                text.to_string(),
+            ),
+        });
+        self.diagnostics()
+    }
+
+    fn diagnostics(&self) -> PublishDiagnosticsParams {
+        for message in &self.connection.receiver {
+            if let Message::Notification(notification) = message {
+                if notification.method == PublishDiagnostics::METHOD {
+                    return serde_json::from_value(notification.params).unwrap();
+                }
+            }
+        }
+        panic!("the server stopped");
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        if let Some(server) = self.server.take() {
+            self.request::<Shutdown>(());
+            self.notify::<Exit>(());
+            server.join().unwrap();
+        }
+    }
+}
+
+/// A fresh directory, which holds the given templates.
+fn workspace(name: &str, templates: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join(format!("ophelia-lsp-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    for (path, source) in templates {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+    dir
+}
+
+fn at(uri: &Url, line: u32, character: u32) -> TextDocumentPositionParams {
+    TextDocumentPositionParams::new(
+        TextDocumentIdentifier::new(uri.clone()),
+        Position::new(line, character),
+    )
+}
+
+fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+    Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+}
+
+#[test]
+fn diagnostics_follow_the_document() {
+    let dir = workspace("diagnostics", &[]);
+    let mut client = Client::new(&dir);
+    let uri = Url::from_file_path(dir.join("page.html")).unwrap();
+
+    // positions count UTF-16 code units
+    let diagnostics = client.open(&uri, "é😀 {% if x %}\n{{ 1 + }}").diagnostics;
+    let found = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.range, diagnostic.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        vec![
+            (range((1, 7), (1, 9)), "expected an expression"),
+            (range((1, 9), (1, 9)), "expected `{% endif %}`"),
+        ]
+    );
+
+    // errors which only the parser finds are reported too
+    client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+        text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+        content_changes: vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "{% extends 'base.html' %}".to_string(),
+        }],
+    });
+    assert_eq!(client.diagnostics().diagnostics.len(), 1);
+
+    client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+        text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 3),
+        content_changes: vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "{% if x %}{{ 1 + 2 }}{% endif %}".to_string(),
+        }],
+    });
+    assert!(client.diagnostics().diagnostics.is_empty());
+
+    client.notify::<DidCloseTextDocument>(DidCloseTextDocumentParams {
+        text_document: TextDocumentIdentifier::new(uri.clone()),
+    });
+    let closed = client.diagnostics();
+    assert_eq!(closed.uri, uri);
+    assert!(closed.diagnostics.is_empty());
+
+    // requests about documents which are not open have no answer
+    assert_eq!(
+        client.request::<HoverRequest>(HoverParams {
+            text_document_position_params: at(&uri, 0, 0),
+            work_done_progress_params: Default::default(),
+        }),
+        None
+    );
+}
+
+#[test]
+fn definitions_can_be_found() {
+    let dir = workspace(
+        "definitions",
+        &[
+            (
+                "forms.html",
+                "{# forms #}\n{% macro input(name) %}<input name='{{ name }}'>{% endmacro %}",
+            ),
+            ("base.html", "<html></html>"),
+            ("pages/header.html", "<h1></h1>"),
+        ],
+    );
+    let mut client = Client::new(&dir);
+    let uri = Url::from_file_path(dir.join("pages/page.html")).unwrap();
+    let forms = Url::from_file_path(dir.join("forms.html")).unwrap();
+    let source = "{% extends 'base.html' %}\n\
+                  {% import 'forms.html' as forms %}{% from 'forms.html' import input as field %}\n\
+                  {% macro row(x) %}{{ x }}{% endmacro %}\n\
+                  {{ row(1) }}{{ forms.input('a') }}{{ field('b') }}{% include 'header.html' %}";
+    client.open(&uri, source);
+
+    let mut definition = |line, character| {
+        let response = client.request::<GotoDefinition>(GotoDefinitionParams {
+            text_document_position_params: at(&uri, line, character),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        match response {
+            Some(GotoDefinitionResponse::Scalar(location)) => Some(location),
+            None => None,
+            other => panic!("{:?}", other),
+        }
+    };
+    let start = Range::default();
+
+    // macros defined in the template
+    assert_eq!(
+        definition(3, 4),
+        Some(Location::new(uri.clone(), range((2, 9), (2, 12))))
+    );
+    // macros in modules, and macros which were imported by name
+    let input = range((1, 9), (1, 14));
+    assert_eq!(definition(3, 22), Some(Location::new(forms.clone(), input)));
+    assert_eq!(definition(3, 37), Some(Location::new(forms.clone(), input)));
+    // the modules themselves
+    assert_eq!(definition(3, 15), Some(Location::new(forms.clone(), start)));
+    assert_eq!(definition(1, 12), Some(Location::new(forms.clone(), start)));
+    // templates are looked for next to the template, and then in the workspace
+    let header = Url::from_file_path(dir.join("pages/header.html")).unwrap();
+    assert_eq!(definition(3, 63), Some(Location::new(header, start)));
+    let base = Url::from_file_path(dir.join("base.html")).unwrap();
+    assert_eq!(definition(0, 13), Some(Location::new(base, start)));
+
+    // variables and text have no definitions
+    assert_eq!(definition(2, 23), None);
+    assert_eq!(definition(0, 0), None);
+}
+
+#[test]
+fn filters_and_tests_have_hovers() {
+    let dir = workspace("hover", &[]);
+    let mut client = Client::new(&dir);
+    let uri = Url::from_file_path(dir.join("page.html")).unwrap();
+    client.open(
+        &uri,
+        "{{ x | replace('a', 'b') }}{% if x is eq(3) %}{% endif %}\
+         {% filter upper %}{% endfilter %}{{ upper }}",
+    );
+
+    let mut hover = |character| {
+        client
+            .request::<HoverRequest>(HoverParams {
+                text_document_position_params: at(&uri, 0, character),
+                work_done_progress_params: Default::default(),
+            })
+            .map(|hover| match hover.contents {
+                HoverContents::Markup(markup) => (hover.range.unwrap(), markup.value),
+                other => panic!("{:?}", other),
+            })
+    };
+
+    let (found, text) = hover(9).unwrap();
+    assert_eq!(found, range((0, 7), (0, 14)));
+    assert!(text.contains("(filter) replace(old, new)"), "{}", text);
+    let (_, text) = hover(38).unwrap();
+    assert!(text.contains("(test) eq(other)"), "{}", text);
+    let (_, text) = hover(69).unwrap();
+    assert!(text.contains("(filter) upper()"), "{}", text);
+
+    // `upper` is only a filter after `|`
+    assert_eq!(hover(94), None);
+    assert_eq!(hover(3), None);
+}
+
+#[test]
+fn completions_offer_what_is_in_scope() {
+    let dir = workspace("completion", &[]);
+    let mut client = Client::new(&dir);
+    let uri = Url::from_file_path(dir.join("page.html")).unwrap();
+    let lines = [
+        "{% set title = 'a' %}{% from 'forms.html' import input as field %}",
+        "{% macro card(body, footer='') %}{{  }}{% endmacro %}",
+        "{% for item, count in items %}{{ it }}{% else %}{{  }}{% endfor %}",
+        "{{ title | up }}{% if title is  %}{% endif %}{{  }}",
+    ];
+    client.open(&uri, &lines.join("\n"));
+
+    let mut complete = |line, character| {
+        let response = client.request::<Completion>(CompletionParams {
+            text_document_position: at(&uri, line, character),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        });
+        let mut labels = match response {
+            Some(CompletionResponse::Array(items)) => {
+                items.into_iter().map(|item| item.label).collect::<Vec<_>>()
+            }
+            other => panic!("{:?}", other),
+        };
+        labels.sort();
+        labels
+    };
+
+    // inside a macro, its parameters (and the macro itself)
+    assert_eq!(
+        complete(1, 36),
+        [
+            "body",
+            "card",
+            "field",
+            "footer",
+            "namespace",
+            "range",
+            "title"
+        ]
+    );
+    // inside a loop, its variables (but not in its `else`)
+    assert_eq!(
+        complete(2, 35),
+        [
+            "card",
+            "count",
+            "field",
+            "item",
+            "namespace",
+            "range",
+            "title"
+        ]
+    );
+    assert_eq!(
+        complete(2, 51),
+        ["card", "field", "namespace", "range", "title"]
+    );
+    // filters after `|`, and tests after `is`
+    assert!(complete(3, 13).contains(&"upper".to_string()));
+    assert!(complete(3, 13).contains(&"replace".to_string()));
+    assert!(complete(3, 31).contains(&"divisibleby".to_string()));
+    assert!(!complete(3, 31).contains(&"replace".to_string()));
+    // and nothing outside of tags
+    assert!(complete(0, 0).is_empty());
+}
+
+#[test]
+fn documents_are_formatted() {
+    let dir = workspace("format", &[]);
+    let mut client = Client::new(&dir);
+    let uri = Url::from_file_path(dir.join("page.html")).unwrap();
+
+    let format = |client: &mut Client| {
+        client.request::<Formatting>(DocumentFormattingParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            options: FormattingOptions {
+                tab_size: 4,
+                insert_spaces: true,
+                ..FormattingOptions::default()
+            },
+            work_done_progress_params: Default::default(),
+        })
+    };
+
+    client.open(&uri, "{%if x%}\n{{x|upper}}{%endif%}");
+    let edits = format(&mut client).unwrap();
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0].range, range((0, 0), (1, 20)));
+    assert_eq!(edits[0].new_text, "{% if x %}\n{{ x | upper }}{% endif %}");
+
+    client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+        text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+        content_changes: vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: edits[0].new_text.clone(),
+        }],
+    });
+    client.diagnostics();
+    assert_eq!(format(&mut client), Some(vec![]));
+
+    // templates which cannot be parsed are left alone
+    client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+        text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 3),
+        content_changes: vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "{% if %}".to_string(),
+        }],
+    });
+    client.diagnostics();
+    assert_eq!(format(&mut client), None);
+}
@@ -0,0 +1,140 @@
+//! Rendering templates without parsing them at runtime.
+//!
+//! ```ignore
+//! use ophelia_logic::{codegen::GeneratedTemplate, render::{Context, Render}};
+//! use ophelia_macros::template;
+//!
+//! static PAGE: GeneratedTemplate = template!("templates/page.html");
+//!
+//! let mut output = String::new();
+//! PAGE.render(&mut Context::new(), &mut output)?;
+//! ```
+//!
+//! The template is read (relative to the directory of the crate's `Cargo.toml`), parsed and
+//! optimised while the crate is compiled, and becomes Rust code which renders it (see
+//! `ophelia_logic::codegen`). A template which does not parse is a compile error.
+//!
+//! Given a struct as well, the template's variables are checked against its fields, and it is
+//! rendered with one instead of with the variables of a `Context`:
+//!
+//! ```ignore
+//! use ophelia_logic::{codegen::TypedTemplate, render::Context};
+//! use ophelia_macros::template;
+//!
+//! struct Page {
+//!     title: String,
+//!     items: Vec<i32>,
+//! }
+//!
+//! // a compile error if `page.html` reads any variable other than `title` and `items`
+//! static PAGE: TypedTemplate<Page> = template!("templates/page.html", Page);
+//!
+//! let page = Page { title: "Home".into(), items: vec![1, 2, 3] };
+//! let mut output = String::new();
+//! PAGE.render(&page, &mut Context::new(), &mut output)?;
+//! ```
+
+#![deny(
+    missing_debug_implementations,
+    missing_copy_implementations,
+    unused_must_use
+)]
+
+use std::{env, fs, path::Path};
+
+use ophelia_logic::{
+    codegen,
+    parse::{Parse as _, ParseError, Template},
+};
+use proc_macro::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input, LitStr, Token, Type,
+};
+
+/// Turns the template at the given path into a `GeneratedTemplate`, or (given the type of a
+/// struct after the path) into a `TypedTemplate` of that struct.
+#[proc_macro]
+pub fn template(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as Input);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// What `template!` is given: `"path"` or `"path", Context`.
+struct Input {
+    path: LitStr,
+    context: Option<Type>,
+}
+
+impl Parse for Input {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path = input.parse()?;
+        let context = if input.is_empty() {
+            None
+        } else {
+            input.parse::<Token![,]>()?;
+            Some(input.parse()?)
+        };
+        Ok(Self { path, context })
+    }
+}
+
+fn expand(Input { path, context }: &Input) -> syn::Result<proc_macro2::TokenStream> {
+    let root = env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let full_path = Path::new(&root).join(path.value());
+
+    let source = fs::read_to_string(&full_path).map_err(|error| {
+        syn::Error::new(
+            path.span(),
+            format!("could not read `{}`: {}", full_path.display(), error),
+        )
+    })?;
+
+    let template = match Template::parse(&source) {
+        Ok((template, _)) => template,
+        Err(error) => {
+            return Err(syn::Error::new(
+                path.span(),
+                format!(
+                    "`{}` could not be parsed: {}",
+                    path.value(),
+                    describe(&source, &error)
+                ),
+            ))
+        }
+    };
+
+    let generated = match context {
+        Some(context) => codegen::generate_typed(template, context.to_token_stream()),
+        None => codegen::generate(template),
+    };
+    let full_path = full_path.to_string_lossy();
+    Ok(quote! {{
+        // makes cargo rebuild the crate when the template changes
+        const _: &[u8] = include_bytes!(#full_path);
+        #generated
+    }})
+}
+
+/// Describes a parse error, with the line and column where it happened.
+fn describe(source: &str, error: &ParseError) -> String {
+    match error {
+        ParseError::UnexpectedToken(token) => {
+            // the token is a slice of the source
+            let offset = (token.as_ptr() as usize)
+                .saturating_sub(source.as_ptr() as usize)
+                .min(source.len());
+            let before = &source[..offset];
+            let line = before.matches('\n').count() + 1;
+            let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+            let token = token.lines().next().unwrap_or("");
+            format!("unexpected `{}` at line {}, column {}", token, line, column)
+        }
+        ParseError::UnexpectedEndOfInput => "unexpected end of input".to_string(),
+        other => format!("{:?}", other),
+    }
+}
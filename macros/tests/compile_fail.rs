@@ -0,0 +1,8 @@
+/// Templates which do not parse, and typed templates whose structs are missing some of their
+/// variables, do not compile (with the errors in `compile_fail/*.stderr`).
+#[test]
+fn broken_templates_do_not_compile() {
+    // (the cases are compiled as a crate in `target/tests/trybuild/ophelia_macros`, so the paths of
+    // their templates are relative to that)
+    trybuild::TestCases::new().compile_fail("tests/compile_fail/*.rs");
+}
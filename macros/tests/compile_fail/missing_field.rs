@@ -0,0 +1,11 @@
+use ophelia_logic::codegen::TypedTemplate;
+use ophelia_macros::template;
+
+// `typed.html` reads `items` too
+struct Page {
+    title: String,
+}
+
+static TYPED: TypedTemplate<Page> = template!("../../../../macros/tests/templates/typed.html", Page);
+
+fn main() {}
@@ -0,0 +1,6 @@
+use ophelia_logic::codegen::GeneratedTemplate;
+use ophelia_macros::template;
+
+static BROKEN: GeneratedTemplate = template!("../../../../macros/tests/compile_fail/broken.html");
+
+fn main() {}
@@ -0,0 +1,229 @@
+use std::time::Instant;
+
+use ophelia_logic::{
+    codegen::{GeneratedTemplate, TypedTemplate},
+    parse::{Parse, Template},
+    render::{
+        Budget, BudgetError, CancellationToken, Context, DictLoader, IoOutput, Render, RenderError,
+        Sandbox, SecurityError, Undefined, Value,
+    },
+};
+use ophelia_macros::template;
+
+static EXPRESSIONS: GeneratedTemplate = template!("tests/templates/expressions.html");
+
+fn context() -> Context {
+    let mut loader = DictLoader::new();
+    loader.insert("forms.html", include_str!("templates/forms.html"));
+    loader.insert("header.html", include_str!("templates/header.html"));
+
+    let mut ctx = Context::new();
+    ctx.set_loader(loader);
+    ctx.insert("name", Value::from("ophelia"));
+    ctx.insert("count", Value::Integer(3));
+    ctx.insert("items", Value::from(vec![1, 2, 3]));
+    ctx.insert(
+        "pairs",
+        Value::list(vec![
+            Value::from(vec!["a", "b"]),
+            Value::from(vec!["c", "d"]),
+        ]),
+    );
+    ctx
+}
+
+/// Renders `generated`, and checks that it renders like `source` (the template it was generated
+/// from) does when its tree is walked.
+fn check(generated: GeneratedTemplate, source: &str) -> Result<String, RenderError> {
+    let (template, _) = Template::parse(source).expect("parsing failed");
+    let mut walked = String::new();
+    let walked = template.render(&mut context(), &mut walked).map(|_| walked);
+
+    let mut output = String::new();
+    let output = generated
+        .render(&mut context(), &mut output)
+        .map(|_| output);
+
+    assert_eq!(walked, output, "while rendering {:?}", source);
+    output
+}
+
+#[test]
+fn generated_templates_render_like_the_tree() {
+    for (generated, source) in &[
+        (EXPRESSIONS, include_str!("templates/expressions.html")),
+        (
+            template!("tests/templates/statements.html"),
+            include_str!("templates/statements.html"),
+        ),
+        (
+            template!("tests/templates/forms.html"),
+            include_str!("templates/forms.html"),
+        ),
+    ] {
+        check(*generated, source).expect("rendering failed");
+    }
+}
+
+#[test]
+fn generated_templates_fail_like_the_tree() {
+    for (generated, source) in &[
+        (
+            template!("tests/templates/failing/division.html"),
+            include_str!("templates/failing/division.html"),
+        ),
+        (
+            template!("tests/templates/failing/unpacking.html"),
+            include_str!("templates/failing/unpacking.html"),
+        ),
+        (
+            template!("tests/templates/failing/filter.html"),
+            include_str!("templates/failing/filter.html"),
+        ),
+        (
+            template!("tests/templates/failing/macro.html"),
+            include_str!("templates/failing/macro.html"),
+        ),
+    ] {
+        assert!(
+            check(*generated, source).is_err(),
+            "{:?} should fail",
+            source
+        );
+    }
+}
+
+#[test]
+fn generated_templates_keep_what_they_assign() {
+    let mut ctx = context();
+    template!("tests/templates/statements.html")
+        .render(&mut ctx, &mut String::new())
+        .unwrap();
+
+    assert_eq!(ctx.get("total"), Some(Value::Integer(0)));
+    assert_eq!(ctx.get("a"), None);
+    assert!(matches!(ctx.get("field"), Some(Value::Function(_))));
+}
+
+#[test]
+fn generated_templates_follow_the_undefined_policy() {
+    let generated = template!("tests/templates/undefined.html");
+    let (template, _) = Template::parse(include_str!("templates/undefined.html")).unwrap();
+
+    for (undefined, expected) in [
+        (Undefined::Lenient, Err("`missing` is undefined")),
+        (Undefined::Chainable, Ok("ophelia  False x \n")),
+        (
+            Undefined::Debug,
+            Ok("ophelia {{ missing }} False x {{ missing.a }}\n"),
+        ),
+        (Undefined::Strict, Err("`missing` is undefined")),
+    ] {
+        let results = [&generated as &dyn Render, &template].map(|template| {
+            let mut ctx = context();
+            ctx.set_undefined(undefined);
+            let mut output = String::new();
+            template
+                .render(&mut ctx, &mut output)
+                .map(|_| output)
+                .map_err(|error| error.to_string())
+        });
+        for result in results {
+            assert_eq!(
+                result.as_deref(),
+                expected.map_err(String::from).as_deref(),
+                "with {:?}",
+                undefined
+            );
+        }
+    }
+}
+
+#[test]
+fn generated_templates_are_sandboxed() {
+    let generated = template!("tests/templates/undefined.html");
+    let (template, _) = Template::parse(include_str!("templates/undefined.html")).unwrap();
+
+    for (sandbox, error) in [
+        (Sandbox::new(), SecurityError::Attribute("nope".into())),
+        (
+            Sandbox::new().allow_attribute("nope").max_output(5),
+            SecurityError::OutputTooLarge(5),
+        ),
+    ] {
+        for template in [&generated as &dyn Render, &template] {
+            let mut ctx = context();
+            ctx.set_sandbox(sandbox.clone());
+            assert_eq!(
+                template.render(&mut ctx, &mut String::new()),
+                Err(RenderError::Security(error.clone()))
+            );
+        }
+    }
+}
+
+#[test]
+fn generated_templates_spend_their_budget() {
+    let generated = template!("tests/templates/statements.html");
+    let (template, _) = Template::parse(include_str!("templates/statements.html")).unwrap();
+
+    let cancelled = CancellationToken::new();
+    cancelled.cancel();
+    for (budget, expected) in [
+        (Budget::new().fuel(1_000_000), None),
+        (Budget::new().fuel(10), Some(BudgetError::OutOfFuel(10))),
+        (
+            Budget::new().deadline(Instant::now()),
+            Some(BudgetError::TimedOut),
+        ),
+        (
+            Budget::new().cancel_with(cancelled.clone()),
+            Some(BudgetError::Cancelled),
+        ),
+    ] {
+        for template in [&generated as &dyn Render, &template] {
+            let mut ctx = context();
+            ctx.set_budget(budget.clone());
+            let result = template.render(&mut ctx, &mut String::new());
+            assert_eq!(result.err(), expected.clone().map(RenderError::Budget));
+        }
+    }
+}
+
+#[test]
+fn generated_templates_can_be_written_in_chunks() {
+    let mut expected = String::new();
+    EXPRESSIONS.render(&mut context(), &mut expected).unwrap();
+
+    let mut output = IoOutput::with_capacity(vec![], 16);
+    EXPRESSIONS.render(&mut context(), &mut output).unwrap();
+    assert_eq!(output.into_inner().unwrap(), expected.as_bytes());
+}
+
+/// The variables of `templates/typed.html` (and one which it does not read).
+struct Page {
+    title: String,
+    items: Vec<i32>,
+    #[allow(dead_code)]
+    unread: bool,
+}
+
+static TYPED: TypedTemplate<Page> = template!("tests/templates/typed.html", Page);
+
+#[test]
+fn typed_templates_render_the_fields() {
+    let page = Page {
+        title: "home".into(),
+        items: vec![1, 2],
+        unread: true,
+    };
+    // the context still provides the loader, and the variables of included templates
+    let mut ctx = context();
+    let mut output = String::new();
+    TYPED.render(&page, &mut ctx, &mut output).unwrap();
+    assert_eq!(output, "<h1>HOME</h1>12<h1>ophelia</h1>\n\n");
+
+    // which are bound in a scope of their own
+    assert_eq!(ctx.get("title"), None);
+    assert_eq!(ctx.get("items"), Some(Value::from(vec![1, 2, 3])));
+}